@@ -0,0 +1,61 @@
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main, Throughput};
+
+use poo::bench::{aggregate, generate_comments, serialize, tokenize};
+use poo::serializer::deserialize;
+use poo::text::text_item::TextItem;
+
+fn bench_tokenize(c: &mut Criterion) {
+    let corpus = generate_comments(1_000, 10_000, 1);
+    let bytes = corpus.iter().map(|(_, text)| text.len()).sum::<usize>();
+
+    let mut group = c.benchmark_group("tokenize");
+    group.throughput(Throughput::Bytes(bytes as u64));
+
+    group.bench_function("process_alt", |b| {
+        b.iter(|| {
+            corpus
+                .iter()
+                .map(|(_, text)| TextItem::process_alt(text).len())
+                .sum::<usize>()
+        })
+    });
+
+    group.bench_function("parallel", |b| b.iter(|| tokenize(&corpus)));
+
+    group.finish();
+}
+
+fn bench_aggregate(c: &mut Criterion) {
+    let corpus = generate_comments(1_000, 10_000, 2);
+    let tokenized = tokenize(&corpus);
+
+    let mut group = c.benchmark_group("aggregate");
+    group.throughput(Throughput::Elements(tokenized.len() as u64));
+
+    group.bench_function("merge", |b| {
+        b.iter_batched(
+            || tokenized.clone(),
+            aggregate,
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+fn bench_serializer(c: &mut Criterion) {
+    let corpus = generate_comments(1_000, 10_000, 3);
+    let map = aggregate(tokenize(&corpus));
+    let buf = serialize(&map);
+
+    let mut group = c.benchmark_group("serializer");
+    group.throughput(Throughput::Bytes(buf.len() as u64));
+
+    group.bench_function("serialize", |b| b.iter(|| serialize(&map)));
+    group.bench_function("deserialize", |b| b.iter(|| deserialize(&buf, |_| {})));
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_tokenize, bench_aggregate, bench_serializer);
+criterion_main!(benches);
@@ -39,4 +39,7 @@ fn main() {
     }
 
     println!("cargo:rustc-env=ASSET_DIR={}", &dir);
+
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/poo.proto").unwrap();
 }
\ No newline at end of file
@@ -0,0 +1,171 @@
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::analysis::{global_freqs, merge_freqs};
+use crate::anonymity::{Suppressed, Thresholds};
+use crate::segment::{segment, Assignments, SegmentOptions};
+use crate::text::interner::WordKey;
+use crate::text::text_item::{PooMap, PooMapBase, PooMapInner, PooMapRoot};
+
+/// Key of the whole corpus' counts in an aggregate file.
+pub const CORPUS_KEY: &[u8] = b"(corpus)";
+
+/// Key of a segment's counts in an aggregate file.
+pub fn segment_key(segment: u32) -> Box<[u8]> {
+    format!("(segment {})", segment).into_bytes().into_boxed_slice()
+}
+
+/// Resolves interned words, for code that only works on plain maps.
+pub fn resolve<K: WordKey + Sync + Send>(data: PooMapBase<PooMapRoot<K, u64>>) -> PooMap {
+    data.into_par_iter()
+        .map(|(author, freqs)| {
+            let freqs =
+                freqs.into_iter()
+                    .map(|(word, freq)| (Box::from(word.word()), freq))
+                    .collect::<PooMapInner>();
+
+            (author, freqs)
+        })
+        .collect()
+}
+
+/// Sums the profiles in `poo` under the keys `keys_of` gives each author.
+fn sum_by<F>(poo: &PooMap, keys_of: F) -> PooMap
+where
+    F: Fn(&[u8], &PooMapInner) -> Vec<Box<[u8]>> + Sync,
+{
+    poo.par_iter()
+        .fold(
+            PooMap::default,
+            |mut acc, (author, freqs)| {
+                for key in keys_of(author, freqs) {
+                    let sums = acc.entry(key).or_default();
+
+                    for (word, freq) in freqs {
+                        *sums.entry(word.clone()).or_default() += freq;
+                    }
+                }
+
+                acc
+            },
+        )
+        .reduce(
+            PooMap::default,
+            |mut acc, other| {
+                for (key, freqs) in other {
+                    let sums = acc.remove(&key).unwrap_or_default();
+
+                    acc.insert(key, merge_freqs(sums, freqs));
+                }
+
+                acc
+            },
+        )
+}
+
+/// Segments published by `aggregates` have at least this many members
+/// unless thresholds say otherwise.
+pub const MIN_SEGMENT_AUTHORS: u64 = 10;
+
+/// Key the counts of segments too small to publish are folded into.
+pub const OTHER_KEY: &[u8] = b"(segment other)";
+
+/// What `aggregates` kept out of its output.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Withheld {
+    /// Below `Thresholds`, as in per-user outputs.
+    pub suppressed: Suppressed,
+    /// Segments folded into `OTHER_KEY` and their members.
+    pub folded_segments: usize,
+    pub folded_authors: usize,
+}
+
+/// Sums `poo` into corpus and, with `segments`, segment-level counts, keyed
+/// by `CORPUS_KEY` and `segment_key`. The result has the shape of a corpus,
+/// so it's saved and read like one, but holds no individual's profile:
+/// `thresholds` are applied first, and segments with fewer members than
+/// `thresholds.min_authors` are summed together under `OTHER_KEY`, along
+/// with the smallest publishable segment if that's still too few. Leaving
+/// them out instead would let the corpus minus the published segments give
+/// them away.
+///
+/// Every author counts towards a segment, including those too small for
+/// the segmentation itself.
+pub fn aggregates(mut poo: PooMap, segments: Option<usize>, thresholds: Thresholds) -> (PooMap, Withheld) {
+    let mut withheld = Withheld::default();
+
+    if !thresholds.is_noop() {
+        withheld.suppressed = thresholds.apply(&mut poo);
+    }
+
+    let mut aggregates = PooMap::default();
+
+    aggregates.insert(Box::from(CORPUS_KEY), global_freqs(&poo));
+
+    if let Some(k) = segments {
+        let segmentation = segment(&poo, &SegmentOptions { segments: k, ..Default::default() });
+
+        let assigned =
+            poo.par_iter()
+                .map(|(author, freqs)| (author.clone(), segmentation.assign(freqs).0))
+                .collect::<FxHashMap<_, _>>();
+
+        let mut members = FxHashMap::<u32, u64>::default();
+
+        for segment in assigned.values() {
+            *members.entry(*segment).or_default() += 1;
+        }
+
+        let mut folded =
+            members.iter()
+                .filter(|(_, n)| **n < thresholds.min_authors)
+                .map(|(segment, _)| *segment)
+                .collect::<FxHashSet<_>>();
+
+        let folded_members = |folded: &FxHashSet<u32>| folded.iter().map(|s| members[s]).sum::<u64>();
+
+        if folded_members(&folded) > 0 && folded_members(&folded) < thresholds.min_authors {
+            let smallest =
+                members.iter()
+                    .filter(|(segment, _)| !folded.contains(*segment))
+                    .min_by_key(|(segment, n)| (**n, **segment))
+                    .map(|(segment, _)| *segment);
+
+            folded.extend(smallest);
+        }
+
+        withheld.folded_segments = folded.len();
+        withheld.folded_authors = folded_members(&folded) as usize;
+
+        // everything folded would just repeat the corpus
+        if folded.len() < members.len() {
+            aggregates.extend(sum_by(&poo, |author, _| {
+                let segment = assigned[author];
+
+                match folded.contains(&segment) {
+                    true => vec![Box::from(OTHER_KEY)],
+                    false => vec![segment_key(segment)],
+                }
+            }));
+        }
+    }
+
+    (aggregates, withheld)
+}
+
+/// Sums the profiles of each segment's members, keyed by `segment_key`.
+/// Segments that others split also count their sub-segments' members.
+/// Authors of `poo` missing from `assignments` aren't counted.
+pub fn segment_freqs(poo: &PooMap, assignments: &Assignments) -> PooMap {
+    sum_by(poo, |author, _| {
+        let mut keys = Vec::new();
+        let mut segment = assignments.segment_of(author).map(|(segment, _)| segment);
+
+        while let Some(s) = segment {
+            keys.push(segment_key(s));
+            segment = assignments.segments[s as usize].parent;
+        }
+
+        keys
+    })
+}
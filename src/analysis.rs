@@ -0,0 +1,189 @@
+use std::ops::AddAssign;
+
+use rayon::prelude::*;
+
+use crate::domains::is_domain;
+use crate::text::text_item::{PooMap, PooMapInner};
+
+/// Sums the frequencies of all authors. Like everything here that works on
+/// words, it leaves out linked domains, see `domains`.
+pub fn global_freqs(poo: &PooMap) -> PooMapInner {
+    poo
+        .par_iter()
+        .fold(
+            || PooMapInner::default(),
+            |mut acc, (_, freqs)| {
+                for (word, freq) in freqs.iter().filter(|(word, _)| !is_domain(word)) {
+                    acc
+                        .entry(word.clone())
+                        .or_insert(0)
+                        .add_assign(*freq);
+                }
+
+                acc
+            },
+        )
+        .reduce(
+            || PooMapInner::default(),
+            merge_freqs,
+        )
+}
+
+/// Number of authors using each word.
+pub fn document_freqs(poo: &PooMap) -> PooMapInner {
+    poo
+        .par_iter()
+        .fold(
+            || PooMapInner::default(),
+            |mut acc, (_, freqs)| {
+                for word in freqs.keys().filter(|word| !is_domain(word)) {
+                    acc
+                        .entry(word.clone())
+                        .or_insert(0)
+                        .add_assign(1);
+                }
+
+                acc
+            },
+        )
+        .reduce(
+            || PooMapInner::default(),
+            merge_freqs,
+        )
+}
+
+pub fn merge_freqs(mut acc: PooMapInner, mut other: PooMapInner) -> PooMapInner {
+    if acc.len() < other.len() {
+        std::mem::swap(&mut acc, &mut other);
+    }
+
+    for (word, freq) in other {
+        acc
+            .entry(word)
+            .or_insert(0)
+            .add_assign(freq);
+    }
+
+    acc
+}
+
+pub fn total_tokens(freqs: &PooMapInner) -> u64 {
+    freqs.iter()
+        .filter(|(word, _)| !is_domain(word))
+        .map(|(_, freq)| freq)
+        .sum()
+}
+
+/// TF-IDF of every word in `freqs`, given the document frequencies of the
+/// corpus it belongs to and the number of authors in it.
+pub fn tf_idf<'a>(
+    freqs: &'a PooMapInner,
+    doc_freqs: &PooMapInner,
+    authors: usize,
+) -> Vec<(&'a [u8], f64)> {
+    let total = total_tokens(freqs).max(1) as f64;
+
+    freqs
+        .iter()
+        .filter(|(word, _)| !is_domain(word))
+        .map(|(word, freq)| {
+            let df = doc_freqs.get(word).copied().unwrap_or(1).max(1) as f64;
+            let idf = (authors.max(1) as f64 / df).ln();
+
+            (&word[..], *freq as f64 / total * idf)
+        })
+        .collect()
+}
+
+/// The words of `freqs` and their counts, for `top_n`.
+pub fn word_freqs(freqs: &PooMapInner) -> impl Iterator<Item=(&[u8], u64)> {
+    freqs.iter()
+        .filter(|(word, _)| !is_domain(word))
+        .map(|(word, freq)| (&word[..], *freq))
+}
+
+/// The `n` highest scoring entries, ties broken by word.
+pub fn top_n<'a, S: PartialOrd + Copy>(scores: impl IntoIterator<Item=(&'a [u8], S)>, n: usize) -> Vec<(&'a [u8], S)> {
+    let mut scores = scores.into_iter().collect::<Vec<_>>();
+
+    scores.sort_by(|a, b|
+        b.1.partial_cmp(&a.1)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.0.cmp(b.0))
+    );
+
+    scores.truncate(n);
+    scores
+}
+
+/// Log-odds ratio with an informative Dirichlet prior (Monroe et al., 2008)
+/// of every word used by `a` or `b`, as z-scores. Positive scores are words
+/// over-represented in `a`, negative ones in `b`.
+///
+/// The prior is the combined usage of both, scaled to `prior_weight` pseudo
+/// counts, which keeps rare words from dominating the ranking.
+pub fn log_odds<'a>(
+    a: &'a PooMapInner,
+    b: &'a PooMapInner,
+    prior_weight: f64,
+) -> Vec<(&'a [u8], f64)> {
+    let n_a = total_tokens(a) as f64;
+    let n_b = total_tokens(b) as f64;
+    let n_prior = (n_a + n_b).max(1.0);
+
+    a.keys()
+        .chain(b.keys().filter(|w| !a.contains_key(*w)))
+        .map(|word| {
+            let y_a = a.get(word).copied().unwrap_or(0) as f64;
+            let y_b = b.get(word).copied().unwrap_or(0) as f64;
+
+            let alpha = (y_a + y_b) / n_prior * prior_weight;
+
+            let delta =
+                ((y_a + alpha) / (n_a + prior_weight - y_a - alpha)).ln()
+                    - ((y_b + alpha) / (n_b + prior_weight - y_b - alpha)).ln();
+
+            let variance = 1.0 / (y_a + alpha) + 1.0 / (y_b + alpha);
+
+            (&word[..], delta / variance.sqrt())
+        })
+        .collect()
+}
+
+/// Cosine similarity of two frequency profiles.
+pub fn cosine_similarity(a: &PooMapInner, b: &PooMapInner) -> f64 {
+    let (small, large) = if a.len() < b.len() { (a, b) } else { (b, a) };
+
+    let dot =
+        small
+            .iter()
+            .filter(|(word, _)| !is_domain(word))
+            .filter_map(|(word, freq)| large.get(word).map(|other| *freq as f64 * *other as f64))
+            .sum::<f64>();
+
+    let norm = |m: &PooMapInner| {
+        m.iter()
+            .filter(|(word, _)| !is_domain(word))
+            .map(|(_, v)| (*v as f64).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    };
+
+    let denominator = norm(a) * norm(b);
+
+    if denominator == 0.0 {
+        0.0
+    } else {
+        dot / denominator
+    }
+}
+
+/// The `n` authors whose profiles are most similar to `profile`.
+pub fn most_similar<'a>(poo: &'a PooMap, profile: &PooMapInner, n: usize) -> Vec<(&'a [u8], f64)> {
+    let scores =
+        poo.par_iter()
+            .map(|(author, freqs)| (&author[..], cosine_similarity(profile, freqs)))
+            .collect::<Vec<_>>();
+
+    top_n(scores, n)
+}
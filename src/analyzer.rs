@@ -1,31 +1,29 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs::{DirEntry, File};
-use std::hash::BuildHasherDefault;
-use std::io::Read;
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use num::complex::ComplexFloat;
 use num::Float;
 use rayon::iter::IndexedParallelIterator;
 use rayon::iter::IntoParallelRefIterator;
 use rayon::iter::ParallelIterator;
-use twox_hash::XxHash;
-use zstd::Decoder;
-
-use serializer::deserialize;
-
-use crate::serializer::{extract_user, FnFeedback};
-use crate::text::STOPWORDS;
-use crate::text::text_item::PooMapInner;
-
-mod text;
-mod serializer;
-
-fn std_deviation(values: &[f32]) -> f32 {
-    let mean = values.iter().sum::<f32>() / values.len() as f32;
-    let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / values.len() as f32;
-    variance.sqrt()
-}
+use serde::{Deserialize, Serialize};
+
+use poo::aggregate::{self, segment_key};
+use poo::analysis::merge_freqs;
+use poo::args::Args;
+use poo::audit;
+use poo::cache::{content_hash, hash_path};
+use poo::crypto::{is_encrypted_file, Sealed};
+use poo::fingerprint;
+use poo::manifest::Manifest;
+use poo::segment::Assignments;
+use poo::matcher::{AuthorFold, AuthorMatcher};
+use poo::optout;
+use poo::serializer::{deserialize, deserialize_salvaging, extract_matching, extract_users_with, Damage, FnFeedback, read_assignments, read_file, serialize_with_writer};
+use poo::text::STOPWORDS;
+use poo::text::text_item::{PooMap, PooMapInner};
 
 // double y;
 //
@@ -55,157 +53,264 @@ fn linear_to_srgb(c: f32) -> f32 {
     }
 }
 
-fn save_fingerpint(poo_map: &PooMapInner, name: &str, fp_type: &str) -> Option<()> {
-    let gwf = {
-        let mut f =
-            poo_map
-                .iter()
-                .collect::<Vec<_>>();
+/// Returns where the image went, if it could be rendered.
+fn save_fingerpint(poo_map: &PooMapInner, name: &str, fp_type: &str) -> Option<String> {
+    let path = format!("./fps/{}.{}.png", name, fp_type);
 
-        f.sort_by(|a, b| b.1.cmp(a.1));
+    // a fingerprint is user-level data like the profile it's drawn from
+    if let Err(e) = audit::write(&path) {
+        eprintln!("Not writing {}: {}", path, e);
+        return None;
+    }
 
-        f
-            .par_iter()
-            .take(128 * 128)
-            .fold(
-                || HashMap::<Vec<u8>, u64, BuildHasherDefault<XxHash>>::default(),
-                |mut acc, (k, v)| {
-                    acc.insert((*k).clone(), **v);
-                    acc
-                },
-            )
-            .reduce(
-                || HashMap::<Vec<u8>, u64, BuildHasherDefault<XxHash>>::default(),
-                |mut acc, freqs| {
-                    for (word, freq) in freqs.iter() {
-                        acc.insert(word.clone(), *freq);
-                    }
+    fingerprint::save(poo_map, &path)?;
 
-                    acc
-                },
-            )
-    };
+    Some(path)
+}
 
-    let f =
-        gwf
-            .par_iter()
-            .map(|(_k, v)| *v as u32)
-            .collect::<Vec<_>>();
+#[derive(Debug, Default)]
+struct FileSummary {
+    authors: usize,
+    words: usize,
+    fingerprints: usize,
+    /// Summed profiles of the segments' members in this file, with
+    /// `--segments`.
+    segments: PooMap,
+    /// Parts of the file skipped as unreadable.
+    damage: Vec<Damage>,
+    /// Files written for it.
+    outputs: Vec<String>,
+}
 
-    let f_stddev = std_deviation(&f.iter().map(|x| *x as f32).collect::<Vec<_>>());
-    let f_mean = f.iter().sum::<u32>() as f32 / f.len() as f32;
+/// What the last run made of each file, kept in `analyzer.state.json` in
+/// the output directory so files unchanged since are skipped.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RunState {
+    files: BTreeMap<String, FileState>,
+}
 
-    let f_min = f_mean / 2.0;
-    let f_max = f_mean * 2.0;
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FileState {
+    /// Hash of the file's content and the options it was processed with.
+    key: String,
+    authors: usize,
+    words: usize,
+    fingerprints: usize,
+    damage: Vec<Damage>,
+    #[serde(default)]
+    outputs: Vec<String>,
+}
 
-    println!("f_min: {}, f_max: {}", f_min, f_max);
-    println!("f_mean: {}, f_stddev: {}", f_mean, f_stddev);
+impl RunState {
+    fn path(out_dir: &Path) -> std::path::PathBuf {
+        out_dir.join("analyzer.state.json")
+    }
 
-    let f =
-        f.par_iter()
-            //.filter(|b| **b < f_min)
-            .cloned()
-            .map(|x| (((x as f32 - f_min) / (f_max - f_min)) * 255.0) as u32)
-            .collect::<Vec<_>>();
+    fn load(out_dir: &Path) -> Self {
+        std::fs::read(Self::path(out_dir))
+            .ok()
+            .and_then(|buf| serde_json::from_slice(&buf).ok())
+            .unwrap_or_default()
+    }
 
-    //use rustfft::{FftPlanner, num_complex::Complex};
-
-    //let mut planner = FftPlanner::<f32>::new();
-    //let fft = planner.plan_fft_forward(16384);
-
-    //let mut buffer = vec![Complex { re: 0.0, im: 0.0 }; 16384];
-
-    //for (i, v) in f.iter().enumerate() {
-    //    buffer[i] = Complex { re: *v as f32, im: 1.0 };
-    //}
-
-    //fft.process(&mut buffer);
-
-    cortical_io::image::generate_height_image_from_vec(
-        //&buffer.iter().map(|x| x.norm() as u32).collect::<Vec<_>>(),
-        &f,
-        10,
-        |p, _i|
-            match p {
-                0 => [0, 0, 0],
-                //_ if densest_points.contains(&i) => [255, 0, 0],
-                _ => {
-                    // normalize p
-                    let r1 = 255.0 / 255.0;
-                    let g1 = 20.0 / 255.0;
-                    let b1 = 147.0 / 255.0;
-
-                    let r2 = 255.0 / 255.0;
-                    let g2 = 0.0 / 255.0;
-                    let b2 = 255.0 / 255.0;
-
-                    let _r = (((r2 - r1) * p as f32 + r1) * 255.0) as u8;
-                    let _g = (((g2 - g1) * p as f32 + g1) * 255.0) as u8;
-                    let _b = (((b2 - b1) * p as f32 + b1) * 255.0) as u8;
-
-                    //[255 - p, (20 + p).max(255), (147 - p).min(0)]
-                    [
-                        p / 3,
-                        p / 2,
-                        p,
-                    ]
-                },
-            },
-    )?.save(&format!("./fps/{}.{}.png", name, fp_type)).unwrap();
+    fn save(&self, out_dir: &Path) -> std::io::Result<()> {
+        std::fs::write(Self::path(out_dir), serde_json::to_vec_pretty(self)?)
+    }
+}
+
+/// Where a file's segment totals are kept between runs, as they're needed
+/// for the segment fingerprints even when the file itself is skipped.
+fn cached_segments_path(out_dir: &Path, name: &str) -> std::path::PathBuf {
+    out_dir.join(".analyzer").join(format!("{}.segments", name))
+}
+
+fn save_cached_segments(out_dir: &Path, name: &str, segments: &PooMap) -> std::io::Result<()> {
+    let path = cached_segments_path(out_dir, name);
+
+    std::fs::create_dir_all(path.parent().unwrap())?;
+
+    let mut file = std::io::BufWriter::new(File::create(path)?);
+
+    serialize_with_writer(segments, &mut file, |_| {})
+}
+
+/// The summary the last run recorded for a file, if it's unchanged since.
+fn cached_summary(state: &FileState, out_dir: &Path, name: &str, with_segments: bool) -> Option<FileSummary> {
+    let segments =
+        if with_segments {
+            deserialize(&std::fs::read(cached_segments_path(out_dir, name)).ok()?, |_| {})
+        } else {
+            PooMap::default()
+        };
+
+    Some(FileSummary {
+        authors: state.authors,
+        words: state.words,
+        fingerprints: state.fingerprints,
+        segments,
+        damage: state.damage.clone(),
+        outputs: state.outputs.clone(),
+    })
+}
 
-    Some(())
+/// What a run couldn't process, written next to its outputs when anything
+/// failed so it's clear which results are incomplete.
+#[derive(Debug, Default, Serialize)]
+struct SalvageReport {
+    /// Files processed, possibly with damaged parts skipped.
+    completed: Vec<String>,
+    /// Files that couldn't be read at all, with why.
+    failed: Vec<(String, String)>,
+    /// Damaged parts, by file.
+    damaged: Vec<(String, Vec<Damage>)>,
 }
 
-fn run_for_file(path: &Path, username: Option<String>) {
+fn is_stopword(word: &[u8]) -> bool {
+    STOPWORDS.contains(word.iter().map(|&b| b as char).collect::<String>().as_str())
+}
+
+/// Renders a fingerprint per segment from the segment totals of all files,
+/// laid out by the words of all first-level segments together so the
+/// images can be compared with each other. Returns where they went.
+fn save_segment_fingerprints(segments: &PooMap, assignments: &Assignments) -> Vec<String> {
+    let total =
+        assignments.segments
+            .iter()
+            .enumerate()
+            .filter(|(_, info)| info.parent.is_none())
+            .filter_map(|(i, _)| segments.get(&segment_key(i as u32)))
+            .fold(PooMapInner::default(), |acc, freqs| merge_freqs(acc, freqs.clone()));
+
+    let total =
+        total.into_iter()
+            .filter(|(word, _)| !is_stopword(word))
+            .collect::<PooMapInner>();
+
+    let layout = fingerprint::layout(&total);
+
+    assignments.segments
+        .par_iter()
+        .enumerate()
+        .filter_map(|(i, info)| {
+            let freqs = segments.get(&segment_key(i as u32))?;
+
+            let name =
+                if info.label.is_empty() {
+                    format!("segment-{}", i)
+                } else {
+                    format!("segment-{}-{}", i, info.label.replace(|c: char| !c.is_alphanumeric(), "_"))
+                };
+
+            save_fingerpint(&fingerprint::project(freqs, &layout), &name, "segment")
+        })
+        .collect()
+}
+
+/// Writes `poo` zstd-compressed to `out`, sealed to the age key if `encrypt`.
+fn write_freqs(poo: &PooMap, out: &Path, encrypt: bool) -> std::io::Result<()> {
+    audit::write(&out.to_string_lossy())?;
+
+    let mut file = File::create(out)?;
+    let mut sealed = Sealed::new(&mut file, encrypt)?;
+
+    let mut encoder = zstd::stream::Encoder::new(&mut sealed, 10)?;
+
+    serialize_with_writer(poo, &mut encoder, |_| {})?;
+    encoder.finish()?;
+    sealed.finish()?;
+
+    file.sync_all()
+}
+
+/// Writes every author matching `matcher` into `{name}.matched.freqs` in
+/// `out_dir`, encrypted if the input was or `encrypt` is set.
+fn extract_matching_from_file(path: &Path, matcher: &AuthorMatcher, out_dir: &Path, encrypt: bool) -> std::io::Result<FileSummary> {
     let name = path.file_name().unwrap().to_str().unwrap().to_string();
 
-    println!("name: {}", name);
+    println!("[{}] loading", name);
+
+    let buf = read_file(path)?;
+
+    let found =
+        extract_matching(
+            &buf,
+            |author| matcher.matches(author),
+            |_| {},
+        );
 
-    let mut file = File::open(path).unwrap();
+    println!("[{}] {} matching authors", name, found.len());
 
-    let mut decoder =
-        Decoder::new(&mut file).unwrap();
+    let summary = FileSummary {
+        authors: found.len(),
+        words: found.values().map(|f| f.len()).sum(),
+        ..Default::default()
+    };
 
-    let mut buf = Vec::new();
-    decoder.read_to_end(&mut buf).unwrap();
-    //file.read_to_end(&mut buf).unwrap();
+    if found.is_empty() {
+        return Ok(summary);
+    }
 
-    if username.is_some() {
-        dbg!(
-            extract_user(
-                &mut buf,
-                &username.unwrap(),
+    let out = out_dir.join(format!("{}.matched.freqs", &name));
+
+    write_freqs(&found, &out, encrypt || is_encrypted_file(path)?)?;
+
+    Ok(FileSummary { outputs: vec![out.to_string_lossy().to_string()], ..summary })
+}
+
+fn run_for_file(path: &Path, usernames: &[&str], fold: AuthorFold, assignments: Option<&Assignments>) -> std::io::Result<FileSummary> {
+    let name = path.file_name().unwrap().to_str().unwrap().to_string();
+
+    println!("[{}] loading", name);
+
+    let buf = read_file(path)?;
+
+    if !usernames.is_empty() {
+        let found =
+            extract_users_with(
+                &buf,
+                usernames,
+                fold,
                 |_| {},
-            )
-        );
+            );
+
+        for (author, freqs) in found.iter() {
+            let mut freqs = freqs.iter().collect::<Vec<_>>();
+            freqs.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+            println!("[{}] found user: {}", name, String::from_utf8_lossy(author));
+
+            for (word, freq) in freqs {
+                println!("{}: {}", String::from_utf8_lossy(word), freq);
+            }
+        }
 
-        return;
+        return Ok(FileSummary {
+            authors: found.len(),
+            words: found.values().map(|f| f.len()).sum(),
+            ..Default::default()
+        });
     }
 
-    let poo =
-        deserialize(
+    // files are processed concurrently, so only report coarse progress
+    // prefixed with the file name
+    let (poo, damage) =
+        deserialize_salvaging(
             &buf,
             |x|
                 match x {
                     FnFeedback::Message(m) => {
-                        println!("message: {}", m);
-                    },
-                    FnFeedback::Total(p) => {
-                        println!("items: {}", p);
-                    },
-                    FnFeedback::Progress(p) => {
-                        println!("\rprogress: {}\t", p);
+                        println!("[{}] {}", name, m);
                     },
                     _ => {},
                 },
         );
 
-    dbg!(poo.len());
+    println!("[{}] {} authors", name, poo.len());
 
     let _author_count = poo.len();
 
     // create a PooMap merging the frequencies of all comments by the same author
-    let poo_map = PooMapInner::new();
+    let poo_map = PooMapInner::default();
 
     poo
         .par_iter()
@@ -213,22 +318,22 @@ fn run_for_file(path: &Path, username: Option<String>) {
             freqs
                 .par_iter()
                 .filter_map(|(word, freq)| {
-                    if STOPWORDS.contains(word.iter().map(|&b| b as char).collect::<String>().as_str()) {
+                    if is_stopword(word) {
                         None
                     } else {
                         Some((word, freq))
                     }
                 })
                 .fold(
-                    || PooMapInner::new(),
-                    |mut acc, (word, freq): (&Vec<u8>, &u64)| {
+                    || PooMapInner::default(),
+                    |mut acc, (word, freq): (&Box<[u8]>, &u64)| {
                         acc.insert(word.clone(), *freq);
 
                         acc
                     }
                 )
                 .reduce(
-                    || PooMapInner::new(),
+                    || PooMapInner::default(),
                     |acc, freqs| {
                         for (_word, _freq) in freqs.iter() {}
 
@@ -237,7 +342,7 @@ fn run_for_file(path: &Path, username: Option<String>) {
                 )
         );
 
-    save_fingerpint(&poo_map, "global", "global");
+    let global = save_fingerpint(&poo_map, &name, "global");
 
     let mut authors = poo
         .iter()
@@ -250,47 +355,117 @@ fn run_for_file(path: &Path, username: Option<String>) {
         .take(100)
         .collect::<Vec<_>>();
 
-    authors
-        .par_iter()
-        .for_each(|(author, comments)| {
-            let mut xy = poo_map.clone();
+    let fingerprints =
+        authors
+            .par_iter()
+            .filter_map(|(author, comments)| {
+                let mut xy = poo_map.clone();
 
-            xy.iter_mut()
-                .for_each(|(_, v)| *v = 0);
+                xy.iter_mut()
+                    .for_each(|(_, v)| *v = 0);
 
-            for (word, ref mut freq) in comments.iter() {
-                if xy.contains_key(word) {
-                    xy.insert(word.clone(), **freq);
+                for (word, ref mut freq) in comments.iter() {
+                    if xy.contains_key(word) {
+                        xy.insert(word.clone(), **freq);
+                    }
                 }
-            }
 
-            let author =
-                String::from_utf8_lossy(
-                    author
-                        .iter()
-                        .filter(|&b| *b != 0)
-                        .cloned()
-                        .collect::<Vec<_>>()
-                        .as_slice(),
-                ).to_string();
-
-            // count zeros in xy
-            let not_zero_count = xy.iter().filter(|(_, v)| **v > 0).count();
-
-            if not_zero_count < 128 {
-                return;
-            }
+                let author =
+                    String::from_utf8_lossy(
+                        author
+                            .iter()
+                            .filter(|&b| *b != 0)
+                            .cloned()
+                            .collect::<Vec<_>>()
+                            .as_slice(),
+                    ).to_string();
+
+                // count zeros in xy
+                let not_zero_count = xy.iter().filter(|(_, v)| **v > 0).count();
+
+                if not_zero_count < 128 {
+                    return None;
+                }
 
-            save_fingerpint(&xy, &author, "norm");
-        });
+                save_fingerpint(&xy, &author, "norm")
+            })
+            .collect::<Vec<_>>();
+
+    Ok(FileSummary {
+        authors: poo.len(),
+        words: poo.values().map(|v| v.len()).sum(),
+        fingerprints: fingerprints.len(),
+        segments: assignments.map(|a| aggregate::segment_freqs(&poo, a)).unwrap_or_default(),
+        damage,
+        outputs: global.into_iter().chain(fingerprints).collect(),
+    })
 }
 
 fn main() {
+    let args = Args::from_env();
+
+    if let Err(e) = optout::init(&args) {
+        eprintln!("Failed to read opt-out list: {}", e);
+        std::process::exit(1);
+    }
+
     // find folder located at first argument
-    let path = std::env::args().nth(1).expect("No path provided");
-    let path = std::path::Path::new(&path);
+    let path = args.positional(0).expect("No path provided");
+    let path = std::path::Path::new(path);
+
+    // any further arguments are usernames to extract
+    let usernames =
+        args.positionals()
+            .iter()
+            .skip(1)
+            .map(|u| u.as_str())
+            .collect::<Vec<_>>();
 
-    let username = std::env::args().nth(2);
+    // --ignore-case / --normalize relax how usernames are compared
+    let fold =
+        if args.flag("normalize") {
+            AuthorFold::Normalized
+        } else if args.flag("ignore-case") {
+            AuthorFold::CaseInsensitive
+        } else {
+            AuthorFold::Exact
+        };
+
+    // --match=<regex> or --glob=<glob> extract all matching authors into new files
+    let matcher =
+        match (args.value("match"), args.value("glob")) {
+            (Some(regex), _) => Some(AuthorMatcher::from_regex(regex)),
+            (None, Some(glob)) => Some(AuthorMatcher::from_glob(glob)),
+            (None, None) => None,
+        }
+            .map(|m| m.expect("invalid author pattern"));
+
+    // --segments=<file> adds a fingerprint per segment of a saved segmentation
+    let assignments =
+        args.value("segments")
+            .map(|path| {
+                read_file(Path::new(path))
+                    .and_then(|buf| read_assignments(&buf))
+                    .unwrap_or_else(|e| panic!("failed to read segments {}: {}", path, e))
+            });
+
+    let out_dir =
+        args.value("out")
+            .map(Path::new)
+            .unwrap_or(path);
+
+    // --encrypt seals extracted authors even when their input wasn't
+    let encrypt = args.flag("encrypt");
+
+    let mut manifest = Manifest::start("analyzer");
+
+    manifest.option("match", args.value("match"));
+    manifest.option("glob", args.value("glob"));
+    manifest.option("encrypted", encrypt);
+
+    if let Some(segments) = args.value("segments") {
+        manifest.input_path(segments);
+    }
 
     // find all files in folder
     let files = std::fs::read_dir(path).expect("Could not read directory");
@@ -309,9 +484,220 @@ fn main() {
 
     files.sort_by(|a, b| a.path().file_name().cmp(&b.path().file_name()));
 
-    files
-        .iter()
-        .for_each(|f| {
-            run_for_file(&f.path(), username.clone());
-        });
+    // files are skipped when neither they nor the options that shape their
+    // outputs changed since the last run; lookups by username only print,
+    // so they always run
+    let skipping = usernames.is_empty() && !args.flag("force");
+
+    let state = RunState::load(out_dir);
+
+    let hash_of = |option: &str| args.value(option).map(|p| hash_path(p).unwrap_or_default()).unwrap_or_default();
+
+    let options_key =
+        format!(
+            "{}\0{}\0{}\0{}",
+            args.value("match").unwrap_or(""),
+            args.value("glob").unwrap_or(""),
+            hash_of("segments"),
+            hash_of("opt-out"),
+        );
+
+    // shards are independent, so analyze them concurrently
+    let done = AtomicUsize::new(0);
+
+    // a file that can't be read is recorded and skipped, the others still
+    // count
+    let results =
+        files
+            .par_iter()
+            .map(|f| {
+                let name = f.file_name().to_string_lossy().to_string();
+
+                let key =
+                    hash_path(&f.path().to_string_lossy())
+                        .map(|hash| format!("{:016x}", content_hash(format!("{}\0{}", hash, options_key).as_bytes())));
+
+                let cached =
+                    match &key {
+                        Ok(key) if skipping => {
+                            state.files
+                                .get(&name)
+                                .filter(|file| file.key == *key)
+                                .and_then(|file| cached_summary(file, out_dir, &name, assignments.is_some()))
+                        }
+                        _ => None,
+                    };
+
+                let unchanged = cached.is_some();
+
+                let result =
+                    match (cached, matcher.as_ref()) {
+                        (Some(summary), _) => Ok(summary),
+                        (None, Some(matcher)) => extract_matching_from_file(&f.path(), matcher, out_dir, encrypt),
+                        (None, None) => run_for_file(&f.path(), &usernames, fold, assignments.as_ref()),
+                    };
+
+                let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+
+                match &result {
+                    Ok(summary) => {
+                        println!(
+                            "[{}/{}] {}: {} authors, {} words, {} fingerprints{}",
+                            done,
+                            files.len(),
+                            name,
+                            summary.authors,
+                            summary.words,
+                            summary.fingerprints,
+                            if unchanged { " (unchanged, skipped)" } else { "" },
+                        );
+                    }
+                    Err(e) => {
+                        eprintln!("[{}/{}] {}: failed: {}", done, files.len(), name, e);
+                    }
+                }
+
+                // recorded only once the outputs are written
+                let file_state =
+                    match (&result, key) {
+                        (Ok(summary), Ok(key)) if skipping && !unchanged => {
+                            let cached =
+                                if assignments.is_some() {
+                                    save_cached_segments(out_dir, &name, &summary.segments)
+                                } else {
+                                    Ok(())
+                                };
+
+                            match cached {
+                                Ok(()) => {
+                                    Some(FileState {
+                                        key,
+                                        authors: summary.authors,
+                                        words: summary.words,
+                                        fingerprints: summary.fingerprints,
+                                        damage: summary.damage.clone(),
+                                        outputs: summary.outputs.clone(),
+                                    })
+                                }
+                                Err(e) => {
+                                    eprintln!("[{}] Failed to cache segment totals: {}", name, e);
+                                    None
+                                }
+                            }
+                        }
+                        _ => None,
+                    };
+
+                (name, result, file_state)
+            })
+            .collect::<Vec<_>>();
+
+    let mut salvage = SalvageReport::default();
+    let mut summaries = Vec::new();
+    let mut state = state;
+
+    for f in files.iter() {
+        manifest.input_path(&f.path().to_string_lossy());
+    }
+
+    manifest.stage("analyze");
+
+    for (name, result, file_state) in results {
+        if let Some(file_state) = file_state {
+            state.files.insert(name.clone(), file_state);
+        }
+
+        match result {
+            Ok(mut summary) => {
+                if !summary.damage.is_empty() {
+                    salvage.damaged.push((name.clone(), std::mem::take(&mut summary.damage)));
+                }
+
+                salvage.completed.push(name);
+                summaries.push(summary);
+            }
+            Err(e) => {
+                // never skipped next time
+                state.files.remove(&name);
+                salvage.failed.push((name, e.to_string()));
+            }
+        }
+    }
+
+    if skipping {
+        if let Err(e) = state.save(out_dir) {
+            eprintln!("Failed to save run state: {}", e);
+        }
+    }
+
+    if let Some(assignments) = assignments.as_ref() {
+        let mut segments = PooMap::default();
+
+        for summary in summaries.iter_mut() {
+            for (key, freqs) in std::mem::take(&mut summary.segments) {
+                let sums = segments.remove(&key).unwrap_or_default();
+
+                segments.insert(key, merge_freqs(sums, freqs));
+            }
+        }
+
+        let saved = save_segment_fingerprints(&segments, assignments);
+
+        println!("{} segment fingerprints", saved.len());
+
+        for path in saved {
+            manifest.output(&path);
+        }
+    }
+
+    if !salvage.failed.is_empty() || !salvage.damaged.is_empty() {
+        let report_path = out_dir.join("salvage.json");
+
+        let written =
+            serde_json::to_vec_pretty(&salvage)
+                .map_err(std::io::Error::from)
+                .and_then(|json| std::fs::write(&report_path, json));
+
+        match written {
+            Ok(()) => {
+                manifest.output(&report_path.to_string_lossy());
+
+                eprintln!(
+                    "{} files failed and {} had damaged parts skipped, see {}",
+                    salvage.failed.len(),
+                    salvage.damaged.len(),
+                    report_path.display(),
+                );
+            }
+            Err(e) => eprintln!("Failed to write salvage report: {}", e),
+        }
+    }
+
+    for summary in summaries.iter() {
+        for path in summary.outputs.iter() {
+            manifest.output(path);
+        }
+    }
+
+    manifest.count("files", summaries.len() as u64);
+    manifest.count("failed_files", salvage.failed.len() as u64);
+    manifest.count("authors", summaries.iter().map(|s| s.authors as u64).sum());
+    manifest.count("fingerprints", summaries.iter().map(|s| s.fingerprints as u64).sum());
+
+    // lookups by username only print
+    if usernames.is_empty() {
+        let location = out_dir.join("analyzer");
+
+        if let Err(e) = manifest.write(&location.to_string_lossy()) {
+            eprintln!("Failed to write manifest: {}", e);
+        }
+    }
+
+    println!(
+        "total: {} files, {} authors, {} words, {} fingerprints",
+        summaries.len(),
+        summaries.iter().map(|s| s.authors).sum::<usize>(),
+        summaries.iter().map(|s| s.words).sum::<usize>(),
+        summaries.iter().map(|s| s.fingerprints).sum::<usize>(),
+    );
 }
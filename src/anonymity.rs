@@ -0,0 +1,111 @@
+use rayon::prelude::*;
+use rustc_hash::FxHashSet;
+
+use crate::analysis::{document_freqs, total_tokens};
+use crate::text::text_item::PooMap;
+
+/// Thresholds applied before per-user data is published, so low-activity
+/// authors and their rare words can't be singled out.
+///
+/// Ragegun files don't record how many comments an author wrote, so
+/// activity is measured in tokens.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Thresholds {
+    /// Authors with fewer tokens are left out entirely.
+    pub min_tokens: u64,
+    /// Words used by fewer authors are dropped from every profile, a word
+    /// only a handful of people use identifies them about as well as a name.
+    pub min_authors: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Suppressed {
+    pub authors: usize,
+    pub words: usize,
+}
+
+impl Thresholds {
+    pub fn is_noop(&self) -> bool {
+        self.min_tokens == 0 && self.min_authors <= 1
+    }
+
+    /// Removes what falls below the thresholds from `poo`. Authors are
+    /// removed first, so word counts are of the authors actually published.
+    pub fn apply(&self, poo: &mut PooMap) -> Suppressed {
+        let mut suppressed = Suppressed::default();
+        let authors = poo.len();
+
+        if self.min_tokens > 0 {
+            poo.retain(|_, freqs| total_tokens(freqs) >= self.min_tokens);
+        }
+
+        if self.min_authors > 1 {
+            let doc_freqs = document_freqs(poo);
+
+            suppressed.words = doc_freqs.values().filter(|n| **n < self.min_authors).count();
+
+            poo.par_iter_mut()
+                .for_each(|(_, freqs)| freqs.retain(|word, _| doc_freqs[word] >= self.min_authors));
+
+            // authors left without any word have nothing to publish
+            poo.retain(|_, freqs| !freqs.is_empty());
+        }
+
+        suppressed.authors = authors - poo.len();
+        suppressed
+    }
+}
+
+/// Drops words that work as identifiers from per-user outputs: words only a
+/// few authors ever used, and tokens shaped like handles, hashes or the
+/// remains of email addresses and URLs once punctuation is stripped.
+#[derive(Debug, Clone, Copy)]
+pub struct RareWords {
+    /// Words used by at most this many authors are dropped.
+    pub max_authors: u64,
+    /// Words mixing letters and digits at least this long are dropped
+    /// however common, e.g. `jdoe1984`.
+    pub mixed_len: usize,
+    /// Words at least this long are dropped, e.g. `johndoeexamplecom`.
+    pub max_len: usize,
+}
+
+impl Default for RareWords {
+    fn default() -> Self {
+        Self {
+            max_authors: 1,
+            mixed_len: 8,
+            max_len: 24,
+        }
+    }
+}
+
+impl RareWords {
+    fn looks_like_identifier(&self, word: &[u8]) -> bool {
+        let len = String::from_utf8_lossy(word).chars().count();
+
+        let mixed =
+            word.iter().any(u8::is_ascii_digit)
+                && word.iter().any(|b| !b.is_ascii_digit());
+
+        len >= self.max_len || (mixed && len >= self.mixed_len)
+    }
+
+    /// Removes the words from every profile in `poo`, returning how many
+    /// distinct words were dropped.
+    pub fn apply(&self, poo: &mut PooMap) -> usize {
+        let doc_freqs = document_freqs(poo);
+
+        let dropped =
+            doc_freqs
+                .par_iter()
+                .filter(|(word, authors)| **authors <= self.max_authors || self.looks_like_identifier(word))
+                .map(|(word, _)| word.clone())
+                .collect::<FxHashSet<_>>();
+
+        poo.par_iter_mut()
+            .for_each(|(_, freqs)| freqs.retain(|word, _| !dropped.contains(word)));
+
+        dropped.len()
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Minimal command line parser.
+///
+/// Options are passed as `--name=value` or as bare `--flag`s. Single-letter
+/// options (`-n 50`) always take the following argument as their value.
+/// Everything else is treated as a positional argument.
+#[derive(Debug, Clone, Default)]
+pub struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, Option<String>>,
+}
+
+impl Args {
+    pub fn from_env() -> Self {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse(mut args: impl Iterator<Item=String>) -> Self {
+        let mut parsed = Self::default();
+
+        while let Some(arg) = args.next() {
+            if arg.len() == 2 && arg.starts_with('-') && arg != "--" {
+                parsed.options.insert(arg[1..].to_string(), args.next());
+
+                continue;
+            }
+
+            match arg.strip_prefix("--") {
+                Some(opt) => {
+                    match opt.split_once('=') {
+                        Some((name, value)) => {
+                            parsed.options.insert(name.to_string(), Some(value.to_string()));
+                        }
+                        None => {
+                            parsed.options.insert(opt.to_string(), None);
+                        }
+                    }
+                }
+                None => {
+                    parsed.positional.push(arg);
+                }
+            }
+        }
+
+        parsed
+    }
+
+    pub fn positionals(&self) -> &[String] {
+        &self.positional
+    }
+
+    pub fn positional(&self, index: usize) -> Option<&str> {
+        self.positional.get(index).map(|v| v.as_str())
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.options.get(name)?.as_deref()
+    }
+
+    /// Parses the value of `--name=value`, panicking with a readable message
+    /// if it is present but malformed.
+    pub fn parse_value<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.value(name).map(|v|
+            v.parse::<T>()
+                .unwrap_or_else(|_| panic!("invalid value for --{}: {}", name, v))
+        )
+    }
+}
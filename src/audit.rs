@@ -0,0 +1,84 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+
+lazy_static! {
+    /// The audit log named by `POO_AUDIT_LOG`, opened for appending only.
+    static ref LOG: Option<Mutex<File>> =
+        std::env::var("POO_AUDIT_LOG")
+            .ok()
+            .map(|path| {
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .unwrap_or_else(|e| panic!("failed to open audit log {}: {}", path, e))
+            })
+            .map(Mutex::new);
+}
+
+/// One line of the audit log.
+#[derive(Serialize)]
+struct Entry<'a> {
+    /// Unix seconds.
+    time: u64,
+    user: Option<String>,
+    host: Option<String>,
+    pid: u32,
+    command: Vec<String>,
+    /// `read` or `write`.
+    action: &'a str,
+    location: &'a str,
+}
+
+fn host() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+}
+
+/// Appends a JSON line recording that this process reads or writes
+/// `location`, when `POO_AUDIT_LOG` is set. Every reader and writer of
+/// user-level data calls this before touching it; an entry that can't be
+/// written fails the access rather than letting it go unrecorded.
+pub fn record(action: &str, location: &str) -> std::io::Result<()> {
+    let log =
+        match LOG.as_ref() {
+            Some(log) => log,
+            None => return Ok(()),
+        };
+
+    let entry =
+        Entry {
+            time: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            user: std::env::var("USER").or_else(|_| std::env::var("USERNAME")).ok(),
+            host: host(),
+            pid: std::process::id(),
+            command: std::env::args().collect(),
+            action,
+            location,
+        };
+
+    let mut line = serde_json::to_vec(&entry)?;
+
+    line.push(b'\n');
+
+    // a single write per line, so concurrent writers don't interleave
+    let mut log = log.lock().unwrap();
+
+    log.write_all(&line)?;
+    log.sync_data()
+}
+
+pub fn read(location: &str) -> std::io::Result<()> {
+    record("read", location)
+}
+
+pub fn write(location: &str) -> std::io::Result<()> {
+    record("write", location)
+}
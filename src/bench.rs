@@ -0,0 +1,118 @@
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+
+use crate::rng::Rng;
+use crate::serializer::{deserialize, serialize_with_writer};
+use crate::text::text_item::{SymMap, SymMapInner, TextItem};
+
+const SYLLABLES: [&str; 24] = [
+    "ka", "lo", "mi", "ne", "ru", "sa", "ti", "vo",
+    "ba", "de", "fi", "go", "hu", "ja", "ke", "li",
+    "ma", "no", "pi", "qu", "re", "si", "to", "xe",
+];
+
+fn synthetic_word(mut index: u64) -> String {
+    let mut word = String::new();
+
+    loop {
+        word.push_str(SYLLABLES[(index % SYLLABLES.len() as u64) as usize]);
+        index /= SYLLABLES.len() as u64;
+
+        if index == 0 {
+            return word;
+        }
+    }
+}
+
+/// Generates `comments` comments spread over `authors` authors.
+///
+/// Word choice is skewed towards low indices so the vocabulary roughly
+/// follows the long-tailed distribution of real comments.
+pub fn generate_comments(authors: usize, comments: usize, seed: u64) -> Vec<(Box<[u8]>, String)> {
+    let vocabulary = 50_000u64;
+    let mut rng = Rng::new(seed);
+
+    (0..comments)
+        .map(|_| {
+            let author = format!("user{}", rng.below(authors.max(1) as u64));
+            let length = 5 + rng.below(80);
+
+            let text =
+                (0..length)
+                    .map(|_| {
+                        let r = rng.below(vocabulary);
+
+                        synthetic_word(r * r / vocabulary)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ");
+
+            (author.into_bytes().into_boxed_slice(), text)
+        })
+        .collect()
+}
+
+pub fn tokenize(comments: &[(Box<[u8]>, String)]) -> Vec<(Box<[u8]>, SymMapInner)> {
+    comments
+        .par_iter()
+        .map(|(author, text)| (author.clone(), TextItem::process_alt(text)))
+        .collect()
+}
+
+pub fn aggregate(tokenized: Vec<(Box<[u8]>, SymMapInner)>) -> SymMap {
+    TextItem::aggregate(tokenized.into_par_iter())
+}
+
+pub fn serialize(map: &SymMap) -> Vec<u8> {
+    let mut buf = Vec::new();
+
+    serialize_with_writer(map, &mut buf, |_| {})
+        .expect("serializing into memory can't fail");
+
+    buf
+}
+
+fn report(stage: &str, elapsed: Duration, amount: usize, unit: &str) {
+    println!(
+        "{:<12} {:>10.2?} {:>14.0} {}/s",
+        stage,
+        elapsed,
+        amount as f64 / elapsed.as_secs_f64(),
+        unit,
+    );
+}
+
+/// Measures each stage of the pipeline on a generated corpus.
+pub fn run(authors: usize, comments: usize) {
+    println!("generating {} comments by {} authors..", comments, authors);
+
+    let corpus = generate_comments(authors, comments, 0x5eed);
+    let bytes = corpus.iter().map(|(_, text)| text.len()).sum::<usize>();
+
+    let start = Instant::now();
+    let tokenized = tokenize(&corpus);
+    report("tokenize", start.elapsed(), bytes, "B");
+
+    let start = Instant::now();
+    let map = aggregate(tokenized);
+    report("aggregate", start.elapsed(), comments, "comments");
+
+    let words = map.values().map(|v| v.len()).sum::<usize>();
+
+    let start = Instant::now();
+    let buf = serialize(&map);
+    report("serialize", start.elapsed(), buf.len(), "B");
+
+    let start = Instant::now();
+    let poo = deserialize(&buf, |_| {});
+    report("deserialize", start.elapsed(), buf.len(), "B");
+
+    println!(
+        "{} authors, {} words, {} bytes serialized, {} authors read back",
+        map.len(),
+        words,
+        buf.len(),
+        poo.len(),
+    );
+}
@@ -0,0 +1,148 @@
+use std::hash::Hasher;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use lru::LruCache;
+use serde_json::Value;
+use twox_hash::XxHash64;
+
+/// Hash of a corpus file, so cached results of one corpus are never served
+/// for another.
+pub fn content_hash(data: &[u8]) -> u64 {
+    let mut hasher = XxHash64::with_seed(0);
+
+    hasher.write(data);
+    hasher.finish()
+}
+
+/// Content hash of a file, as hex. Directories, like RocksDB databases,
+/// hash their listing with sizes and modification times instead, as their
+/// files are only ever added, replaced or removed.
+pub fn hash_path(path: &str) -> std::io::Result<String> {
+    let path = Path::new(path);
+
+    if !path.is_dir() {
+        return Ok(format!("{:016x}", content_hash(&std::fs::read(path)?)));
+    }
+
+    let mut listing = Vec::new();
+
+    for entry in walk(path)? {
+        let metadata = entry.metadata()?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+
+        listing.extend_from_slice(format!("{}\t{}\t{}\n", entry.display(), metadata.len(), modified).as_bytes());
+    }
+
+    Ok(format!("{:016x}", content_hash(&listing)))
+}
+
+/// Files under `dir`, sorted.
+fn walk(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            files.extend(walk(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+/// Caches expensive query results, in memory and optionally in Redis so
+/// they survive restarts and are shared between server instances.
+pub struct ResultCache {
+    namespace: String,
+    lru: Mutex<LruCache<String, Value>>,
+    #[cfg(feature = "redis")]
+    redis: Option<Mutex<redis::Connection>>,
+    // seconds until entries expire in Redis
+    #[cfg(feature = "redis")]
+    ttl: usize,
+}
+
+impl ResultCache {
+    pub fn new(corpus_hash: u64, capacity: usize) -> Self {
+        Self {
+            namespace: format!("poo:{:016x}", corpus_hash),
+            lru: Mutex::new(LruCache::new(NonZeroUsize::new(capacity.max(1)).unwrap())),
+            #[cfg(feature = "redis")]
+            redis: None,
+            #[cfg(feature = "redis")]
+            ttl: 24 * 60 * 60,
+        }
+    }
+
+    #[cfg(feature = "redis")]
+    pub fn with_redis(mut self, url: &str) -> redis::RedisResult<Self> {
+        let connection = redis::Client::open(url)?.get_connection()?;
+
+        self.redis = Some(Mutex::new(connection));
+
+        Ok(self)
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}:{}", self.namespace, key)
+    }
+
+    pub fn get(&self, key: &str) -> Option<Value> {
+        let key = self.key(key);
+
+        if let Some(value) = self.lru.lock().unwrap().get(&key) {
+            return Some(value.clone());
+        }
+
+        #[cfg(feature = "redis")]
+        if let Some(redis) = &self.redis {
+            let found =
+                redis::cmd("GET")
+                    .arg(&key)
+                    .query::<Option<String>>(&mut *redis.lock().unwrap());
+
+            match found {
+                Ok(Some(json)) => {
+                    if let Ok(value) = serde_json::from_str::<Value>(&json) {
+                        self.lru.lock().unwrap().put(key, value.clone());
+
+                        return Some(value);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("Redis lookup failed: {}", e),
+            }
+        }
+
+        None
+    }
+
+    pub fn insert(&self, key: &str, value: &Value) {
+        let key = self.key(key);
+
+        #[cfg(feature = "redis")]
+        if let Some(redis) = &self.redis {
+            let stored =
+                redis::cmd("SET")
+                    .arg(&key)
+                    .arg(value.to_string())
+                    .arg("EX")
+                    .arg(self.ttl)
+                    .query::<()>(&mut *redis.lock().unwrap());
+
+            if let Err(e) = stored {
+                eprintln!("Redis store failed: {}", e);
+            }
+        }
+
+        self.lru.lock().unwrap().put(key, value.clone());
+    }
+}
@@ -0,0 +1,138 @@
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use rustc_hash::FxHashMap;
+
+use crate::analysis::total_tokens;
+use crate::args::Args;
+use crate::text::text_item::PooMapInner;
+
+lazy_static! {
+    /// The built-in dictionary unless `init` loaded another.
+    static ref DICTIONARY: RwLock<CategoryDictionary> =
+        RwLock::new(CategoryDictionary::parse(include_str!("./text/categories.dic")).expect("built-in dictionary is valid"));
+}
+
+/// Word categories in the LIWC `.dic` format: a `%`-delimited header of
+/// `id<TAB>name` lines, then one `word<TAB>id...` line per word. A trailing
+/// `*` makes a word match everything starting with it.
+///
+/// ```text
+/// %
+/// 1	posemo
+/// 2	negemo
+/// %
+/// happ*	1
+/// hate*	2
+/// ```
+#[derive(Debug, Clone)]
+pub struct CategoryDictionary {
+    /// Category names, in the order of the feature vector.
+    pub categories: Vec<String>,
+    /// Word to the positions of its categories.
+    words: FxHashMap<Box<[u8]>, Vec<usize>>,
+    /// Like `words`, for the `*` entries, without the `*`.
+    prefixes: FxHashMap<Box<[u8]>, Vec<usize>>,
+}
+
+impl CategoryDictionary {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines =
+            text.lines()
+                .enumerate()
+                .map(|(i, line)| (i + 1, line.trim()))
+                .filter(|(_, line)| !line.is_empty());
+
+        if lines.next().map(|(_, line)| line) != Some("%") {
+            return Err("expected a `%` header".to_string());
+        }
+
+        let mut categories = Vec::new();
+        let mut position_of = FxHashMap::default();
+
+        for (i, line) in lines.by_ref() {
+            if line == "%" {
+                break;
+            }
+
+            let (id, name) =
+                line.split_once(char::is_whitespace)
+                    .ok_or_else(|| format!("line {}: expected `id<TAB>name`", i))?;
+
+            position_of.insert(id.to_string(), categories.len());
+            categories.push(name.trim().to_string());
+        }
+
+        let mut dictionary = Self { categories, words: FxHashMap::default(), prefixes: FxHashMap::default() };
+
+        for (i, line) in lines {
+            // entries are tab separated, some dictionaries have phrases
+            let (word, ids) =
+                line.split_once('\t')
+                    .or_else(|| line.split_once(' '))
+                    .ok_or_else(|| format!("line {}: expected `word<TAB>id...`", i))?;
+
+            let word = word.trim().to_lowercase();
+
+            let positions =
+                ids.split_whitespace()
+                    .map(|id| position_of.get(id).copied().ok_or_else(|| format!("line {}: unknown category {}", i, id)))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+            match word.strip_suffix('*') {
+                Some(prefix) => dictionary.prefixes.insert(Box::from(prefix.as_bytes()), positions),
+                None => dictionary.words.insert(Box::from(word.as_bytes()), positions),
+            };
+        }
+
+        Ok(dictionary)
+    }
+
+    /// The categories of `word`: those of its entry, or else of the longest
+    /// prefix entry it starts with.
+    fn lookup(&self, word: &[u8]) -> Option<&[usize]> {
+        self.words.get(word)
+            .or_else(|| (1..=word.len()).rev().find_map(|len| self.prefixes.get(&word[..len])))
+            .map(Vec::as_slice)
+    }
+
+    /// Share of the tokens of `freqs` in each category, in the order of
+    /// `categories`. A word can count towards several.
+    pub fn vector(&self, freqs: &PooMapInner) -> Vec<f64> {
+        let mut counts = vec![0u64; self.categories.len()];
+
+        for (word, freq) in freqs {
+            for category in self.lookup(word).unwrap_or_default() {
+                counts[*category] += freq;
+            }
+        }
+
+        let tokens = total_tokens(freqs).max(1) as f64;
+
+        counts.into_iter().map(|count| count as f64 / tokens).collect()
+    }
+}
+
+/// Replaces the built-in dictionary with the one given by
+/// `--categories=<file.dic>`, if any. Returns the number of categories in
+/// use.
+pub fn init(args: &Args) -> std::io::Result<usize> {
+    if let Some(location) = args.value("categories") {
+        let text = std::fs::read_to_string(location)?;
+        let dictionary = CategoryDictionary::parse(&text).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+        *DICTIONARY.write().unwrap() = dictionary;
+    }
+
+    Ok(DICTIONARY.read().unwrap().categories.len())
+}
+
+/// The names of the categories in use.
+pub fn names() -> Vec<String> {
+    DICTIONARY.read().unwrap().categories.clone()
+}
+
+/// `freqs` against the dictionary in use, see `CategoryDictionary::vector`.
+pub fn vector(freqs: &PooMapInner) -> Vec<f64> {
+    DICTIONARY.read().unwrap().vector(freqs)
+}
@@ -0,0 +1,256 @@
+use std::fmt::Write;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::global_freqs;
+use crate::diff::CorpusDiff;
+use crate::report::{escape, md_escape, table, STYLE};
+use crate::segment::Segmentation;
+use crate::text::text_item::PooMap;
+
+/// How one segment of the first corpus is represented in both.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SegmentShift {
+    pub segment: u32,
+    pub terms: Vec<String>,
+    /// Share of each corpus' authors in the segment.
+    pub share: (f64, f64),
+}
+
+/// Everything a two-corpus comparison shows, independent of the output
+/// format.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComparisonData {
+    pub names: (String, String),
+    pub authors: (usize, usize),
+    pub tokens: (u64, u64),
+    pub vocabulary: (usize, usize),
+    /// Words only used in the second corpus, and only in the first.
+    pub new_words: usize,
+    pub lost_words: usize,
+    /// Shared words over all words used in either.
+    pub vocabulary_overlap: f64,
+    pub new_users: usize,
+    pub vanished_users: usize,
+    pub continuing_users: usize,
+    /// Median `1 - cosine similarity` of continuing users' two profiles.
+    pub median_shift: f64,
+    pub rising: Vec<(String, f64)>,
+    pub falling: Vec<(String, f64)>,
+    /// Largest change in share first.
+    pub segments: Vec<SegmentShift>,
+}
+
+fn lossy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).to_string()
+}
+
+impl ComparisonData {
+    /// `segmentation` is of the first corpus; authors of the second are
+    /// assigned to its segments so the compositions are comparable.
+    pub fn build(
+        names: (&str, &str),
+        a: &PooMap,
+        b: &PooMap,
+        segmentation: Option<&Segmentation>,
+        terms: usize,
+    ) -> Self {
+        let diff = CorpusDiff::compute(a, b, terms);
+
+        let (global_a, global_b) = rayon::join(|| global_freqs(a), || global_freqs(b));
+
+        let shared = global_a.keys().filter(|w| global_b.contains_key(*w)).count();
+
+        let mut shifts = diff.shifts.iter().map(|(_, s)| *s).collect::<Vec<_>>();
+
+        shifts.sort_by(|x, y| x.partial_cmp(y).unwrap_or(std::cmp::Ordering::Equal));
+
+        let segments =
+            segmentation
+                .map(|s| {
+                    let k = s.centroids.len();
+
+                    // every author of both is assigned, the segmentation
+                    // itself may have left out small accounts
+                    let shares = |poo: &PooMap| {
+                        let mut sizes = vec![0usize; k];
+
+                        for segment in poo.par_iter().map(|(_, freqs)| s.assign(freqs).0).collect::<Vec<_>>() {
+                            sizes[segment as usize] += 1;
+                        }
+
+                        sizes
+                            .into_iter()
+                            .map(|size| size as f64 / poo.len().max(1) as f64)
+                            .collect::<Vec<_>>()
+                    };
+
+                    let (share_a, share_b) = rayon::join(|| shares(a), || shares(b));
+
+                    let mut segments =
+                        (0..k)
+                            .map(|i| SegmentShift {
+                                segment: i as u32,
+                                terms:
+                                    s.top_terms(i as u32, 8)
+                                        .into_iter()
+                                        .map(|(word, _)| lossy(word))
+                                        .collect(),
+                                share: (share_a[i], share_b[i]),
+                            })
+                            .collect::<Vec<_>>();
+
+                    segments.sort_by(|x, y| {
+                        (y.share.1 - y.share.0).abs()
+                            .partial_cmp(&(x.share.1 - x.share.0).abs())
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    });
+
+                    segments
+                })
+                .unwrap_or_default();
+
+        let scores = |scores: &[(Box<[u8]>, f64)]| {
+            scores
+                .iter()
+                .map(|(word, z)| (lossy(word), *z))
+                .collect()
+        };
+
+        Self {
+            names: (names.0.to_string(), names.1.to_string()),
+            authors: diff.authors,
+            tokens: diff.tokens,
+            vocabulary: diff.vocabulary,
+            new_words: global_b.len() - shared,
+            lost_words: global_a.len() - shared,
+            vocabulary_overlap: shared as f64 / (global_a.len() + global_b.len() - shared).max(1) as f64,
+            new_users: diff.new_users.len(),
+            vanished_users: diff.vanished_users.len(),
+            continuing_users: diff.shifts.len(),
+            median_shift: shifts.get(shifts.len() / 2).copied().unwrap_or(0.0),
+            rising: scores(&diff.rising),
+            falling: scores(&diff.falling),
+            segments,
+        }
+    }
+
+    /// `(metric, first, second)` rows of the overview table.
+    fn overview(&self) -> Vec<(&'static str, String, String)> {
+        vec![
+            ("authors", self.authors.0.to_string(), self.authors.1.to_string()),
+            ("tokens", self.tokens.0.to_string(), self.tokens.1.to_string()),
+            ("distinct words", self.vocabulary.0.to_string(), self.vocabulary.1.to_string()),
+            ("words only here", self.lost_words.to_string(), self.new_words.to_string()),
+            ("authors only here", self.vanished_users.to_string(), self.new_users.to_string()),
+        ]
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "{:.1}% of the combined vocabulary is shared. {} authors appear in both, their median profile shift (1 - cosine similarity) is {:.3}.",
+            self.vocabulary_overlap * 100.0,
+            self.continuing_users,
+            self.median_shift,
+        )
+    }
+}
+
+/// Renders a self-contained HTML comparison page.
+pub fn render_html(data: &ComparisonData) -> String {
+    let title = format!("{} vs {}", data.names.0, data.names.1);
+
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title><style>{}</style></head><body>",
+        escape(&title),
+        STYLE,
+    ).ok();
+
+    writeln!(out, "<h1>{}</h1>", escape(&title)).ok();
+    writeln!(out, "<table><tr><th></th><th>{}</th><th>{}</th></tr>", escape(&data.names.0), escape(&data.names.1)).ok();
+
+    for (metric, a, b) in data.overview() {
+        writeln!(out, "<tr><td>{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td></tr>", metric, a, b).ok();
+    }
+
+    writeln!(out, "</table><p>{}</p>", data.summary()).ok();
+
+    writeln!(out, "<h2>Rising words</h2>").ok();
+    table(&mut out, ("word", "z-score"), &data.rising.iter().map(|(w, z)| (w, format!("{:.2}", z))).collect::<Vec<_>>());
+
+    writeln!(out, "<h2>Falling words</h2>").ok();
+    table(&mut out, ("word", "z-score"), &data.falling.iter().map(|(w, z)| (w, format!("{:.2}", z))).collect::<Vec<_>>());
+
+    if !data.segments.is_empty() {
+        writeln!(out, "<h2>Segment composition</h2>").ok();
+        writeln!(
+            out,
+            "<table><tr><th>segment</th><th>terms</th><th>{}</th><th>{}</th><th>change</th></tr>",
+            escape(&data.names.0),
+            escape(&data.names.1),
+        ).ok();
+
+        for segment in data.segments.iter() {
+            writeln!(
+                out,
+                "<tr><td class=\"n\">{}</td><td>{}</td><td class=\"n\">{:.1}%</td><td class=\"n\">{:.1}%</td><td class=\"n\">{:+.1}</td></tr>",
+                segment.segment,
+                escape(&segment.terms.join(", ")),
+                segment.share.0 * 100.0,
+                segment.share.1 * 100.0,
+                (segment.share.1 - segment.share.0) * 100.0,
+            ).ok();
+        }
+
+        writeln!(out, "</table>").ok();
+    }
+
+    writeln!(out, "</body></html>").ok();
+
+    out
+}
+
+/// Renders the comparison as Markdown.
+pub fn render_markdown(data: &ComparisonData) -> String {
+    let mut out = String::new();
+
+    writeln!(out, "# {} vs {}\n", md_escape(&data.names.0), md_escape(&data.names.1)).ok();
+    writeln!(out, "| | {} | {} |\n|---|---:|---:|", md_escape(&data.names.0), md_escape(&data.names.1)).ok();
+
+    for (metric, a, b) in data.overview() {
+        writeln!(out, "| {} | {} | {} |", metric, a, b).ok();
+    }
+
+    writeln!(out, "\n{}", data.summary()).ok();
+
+    for (title, words) in [("Rising words", &data.rising), ("Falling words", &data.falling)] {
+        writeln!(out, "\n## {}\n\n| word | z-score |\n|---|---:|", title).ok();
+
+        for (word, z) in words.iter() {
+            writeln!(out, "| {} | {:.2} |", md_escape(word), z).ok();
+        }
+    }
+
+    if !data.segments.is_empty() {
+        writeln!(out, "\n## Segment composition\n").ok();
+        writeln!(out, "| segment | terms | {} | {} | change |\n|---:|---|---:|---:|---:|", md_escape(&data.names.0), md_escape(&data.names.1)).ok();
+
+        for segment in data.segments.iter() {
+            writeln!(
+                out,
+                "| {} | {} | {:.1}% | {:.1}% | {:+.1} |",
+                segment.segment,
+                md_escape(&segment.terms.join(", ")),
+                segment.share.0 * 100.0,
+                segment.share.1 * 100.0,
+                (segment.share.1 - segment.share.0) * 100.0,
+            ).ok();
+        }
+    }
+
+    out
+}
@@ -0,0 +1,342 @@
+use std::io::{self, BufRead, Write};
+
+use dashmap::DashMap;
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+
+use crate::text::text_item::PooMapInner;
+
+/// Words on either side of a word that count as its context.
+pub const DEFAULT_WINDOW: usize = 4;
+
+/// Smooths the context distribution, so rare contexts don't get the
+/// highest PMI just for being rare.
+const CONTEXT_ALPHA: f64 = 0.75;
+
+/// Pairs of positions in a vocabulary, the smaller first.
+pub type Pairs = Vec<(u32, u32)>;
+
+/// How often words of a fixed vocabulary appear within `window` words of
+/// each other, summed over the corpus. A sidecar written next to a .freqs
+/// file by `poo --cooccurrence`.
+#[derive(Debug)]
+pub struct Cooccurrence {
+    window: usize,
+    words: Vec<Box<[u8]>>,
+    index: FxHashMap<Box<[u8]>, u32>,
+    /// Per pair of positions in `words`, the smaller first.
+    counts: DashMap<(u32, u32), u64>,
+}
+
+impl Cooccurrence {
+    /// Counts only `words`, usually the most used of an earlier run's
+    /// vocabulary; the others still take up room in windows.
+    pub fn new(words: Vec<Box<[u8]>>, window: usize) -> Self {
+        let index =
+            words.iter()
+                .enumerate()
+                .map(|(i, word)| (word.clone(), i as u32))
+                .collect();
+
+        Self { window, words, index, counts: DashMap::default() }
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// The pairs in `text`, tokenized like `TextItem::process_alt`, for
+    /// `add`.
+    pub fn pairs(&self, text: &str) -> Pairs {
+        let text =
+            text.chars()
+                .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+                .flat_map(char::to_lowercase)
+                .collect::<String>();
+
+        let words =
+            text.split_whitespace()
+                .map(|word| self.index.get(word.as_bytes()).copied())
+                .collect::<Vec<_>>();
+
+        let mut pairs = Vec::new();
+
+        for (i, word) in words.iter().enumerate() {
+            let Some(word) = word else { continue };
+
+            for other in words[i + 1..].iter().take(self.window).flatten() {
+                // a word repeated nearby says nothing about its meaning
+                if other != word {
+                    pairs.push((*word.min(other), *word.max(other)));
+                }
+            }
+        }
+
+        pairs
+    }
+
+    /// Counts `pairs`. Safe to call from many threads.
+    pub fn add(&self, pairs: &[(u32, u32)]) {
+        for pair in pairs {
+            *self.counts.entry(*pair).or_insert(0) += 1;
+        }
+    }
+
+    /// Adds the counts of `other`, e.g. of another shard.
+    pub fn merge(&mut self, other: Self) {
+        for ((a, b), count) in other.counts {
+            let (a, b) = (self.position(&other.words[a as usize]), self.position(&other.words[b as usize]));
+
+            *self.counts.entry((a.min(b), a.max(b))).or_insert(0) += count;
+        }
+    }
+
+    fn position(&mut self, word: &[u8]) -> u32 {
+        match self.index.get(word) {
+            Some(i) => *i,
+            None => {
+                let i = self.words.len() as u32;
+
+                self.words.push(word.into());
+                self.index.insert(word.into(), i);
+
+                i
+            }
+        }
+    }
+
+    /// `corpus.freqs` keeps its counts in `corpus.freqs.cooc`.
+    pub fn sidecar_path(location: &str) -> String {
+        format!("{}.cooc", location)
+    }
+
+    /// Tab separated `word, word, count` lines, sorted by word, after a
+    /// `#window` line.
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut pairs =
+            self.counts.iter()
+                .map(|entry| {
+                    let (a, b) = *entry.key();
+
+                    (&self.words[a as usize], &self.words[b as usize], *entry.value())
+                })
+                .map(|(a, b, count)| if a <= b { (a, b, count) } else { (b, a, count) })
+                .collect::<Vec<_>>();
+
+        pairs.par_sort_unstable();
+
+        writeln!(writer, "#window\t{}", self.window)?;
+
+        for (a, b, count) in pairs {
+            // words never contain whitespace, the tokenizer splits on it
+            writer.write_all(a)?;
+            writer.write_all(b"\t")?;
+            writer.write_all(b)?;
+            writeln!(writer, "\t{}", count)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read(reader: impl BufRead) -> io::Result<Self> {
+        let invalid = |line: usize| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("malformed co-occurrence line {}", line + 1))
+        };
+
+        let mut cooccurrence = Self::new(Vec::new(), DEFAULT_WINDOW);
+
+        for (i, line) in reader.split(b'\n').enumerate() {
+            let line = line?;
+
+            if let Some(window) = line.strip_prefix(b"#window\t") {
+                cooccurrence.window =
+                    std::str::from_utf8(window)
+                        .ok()
+                        .and_then(|w| w.trim().parse().ok())
+                        .ok_or_else(|| invalid(i))?;
+
+                continue;
+            }
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut fields = line.split(|b| *b == b'\t');
+
+            let (a, b, count) =
+                match (fields.next(), fields.next(), fields.next()) {
+                    (Some(a), Some(b), Some(count)) => (a, b, count),
+                    _ => return Err(invalid(i)),
+                };
+
+            let count =
+                std::str::from_utf8(count)
+                    .ok()
+                    .and_then(|c| c.parse::<u64>().ok())
+                    .ok_or_else(|| invalid(i))?;
+
+            let (a, b) = (cooccurrence.position(a), cooccurrence.position(b));
+
+            *cooccurrence.counts.entry((a.min(b), a.max(b))).or_insert(0) += count;
+        }
+
+        Ok(cooccurrence)
+    }
+}
+
+/// The counts of the .freqs file at `location`, `None` if it was ingested
+/// without `--cooccurrence`.
+#[cfg(feature = "native")]
+pub fn load(location: &str) -> Option<Cooccurrence> {
+    let path = Cooccurrence::sidecar_path(location);
+    let buf = crate::storage::read_file(&path).ok()?;
+
+    match Cooccurrence::read(&buf[..]) {
+        Ok(cooccurrence) => Some(cooccurrence),
+        Err(e) => {
+            eprintln!("Ignoring unreadable {}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Positive pointwise mutual information word vectors: a word is described
+/// by the contexts it appears in more often than chance. Words used in the
+/// same contexts get similar vectors, even if they never appear together.
+#[derive(Debug, Clone)]
+pub struct Ppmi {
+    words: Vec<Box<[u8]>>,
+    index: FxHashMap<Box<[u8]>, usize>,
+    /// Per word, `(context, ppmi)` sorted by context, of unit length.
+    vectors: Vec<Vec<(u32, f64)>>,
+}
+
+impl Ppmi {
+    pub fn fit(cooccurrence: &Cooccurrence) -> Self {
+        let words = cooccurrence.words.clone();
+        let mut rows = vec![0.0; words.len()];
+
+        for entry in cooccurrence.counts.iter() {
+            let (a, b) = *entry.key();
+
+            rows[a as usize] += *entry.value() as f64;
+            rows[b as usize] += *entry.value() as f64;
+        }
+
+        let contexts = rows.iter().map(|row: &f64| row.powf(CONTEXT_ALPHA)).collect::<Vec<_>>();
+        let contexts_total = contexts.iter().sum::<f64>();
+
+        let mut vectors = vec![Vec::new(); words.len()];
+
+        for entry in cooccurrence.counts.iter() {
+            let (a, b) = *entry.key();
+            let count = *entry.value() as f64;
+
+            // the matrix is symmetric, but the smoothed context side isn't
+            for (word, context) in [(a, b), (b, a)] {
+                let pmi = (count * contexts_total / (rows[word as usize] * contexts[context as usize])).log2();
+
+                if pmi > 0.0 {
+                    vectors[word as usize].push((context, pmi));
+                }
+            }
+        }
+
+        vectors.par_iter_mut().for_each(|vector| {
+            vector.sort_unstable_by_key(|(context, _)| *context);
+
+            let norm = vector.iter().map(|(_, value)| value * value).sum::<f64>().sqrt();
+
+            for (_, value) in vector.iter_mut() {
+                *value /= norm;
+            }
+        });
+
+        let index =
+            words.iter()
+                .enumerate()
+                .map(|(i, word)| (word.clone(), i))
+                .collect();
+
+        Self { words, index, vectors }
+    }
+
+    /// Words with a vector.
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// The `n` words with the most similar vectors to that of `word`, by
+    /// cosine, `None` if it has none.
+    pub fn neighbors(&self, word: &[u8], n: usize) -> Option<Vec<(&[u8], f64)>> {
+        let target = &self.vectors[*self.index.get(word)?];
+
+        let mut similar =
+            self.words.par_iter()
+                .zip(self.vectors.par_iter())
+                .filter(|(other, _)| other.as_ref() != word)
+                .map(|(other, vector)| (other.as_ref(), sparse_dot(target, vector)))
+                .filter(|(_, similarity)| *similarity > 0.0)
+                .collect::<Vec<_>>();
+
+        similar.par_sort_unstable_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        similar.truncate(n);
+
+        Some(similar)
+    }
+
+    /// A profile as the sum of its words' vectors, weighted by `1 + ln(freq)`
+    /// so its most used words don't drown out the rest. Dense over the
+    /// contexts, of unit length, all zeros if no word has a vector.
+    pub fn embed(&self, freqs: &PooMapInner) -> Vec<f64> {
+        let mut embedding = vec![0.0; self.words.len()];
+
+        for (word, freq) in freqs {
+            if let Some(i) = self.index.get(word) {
+                let weight = 1.0 + (*freq as f64).ln();
+
+                for (context, value) in self.vectors[*i].iter() {
+                    embedding[*context as usize] += weight * value;
+                }
+            }
+        }
+
+        let norm = embedding.iter().map(|value| value * value).sum::<f64>().sqrt();
+
+        if norm > 0.0 {
+            for value in embedding.iter_mut() {
+                *value /= norm;
+            }
+        }
+
+        embedding
+    }
+}
+
+/// Cosine of two embeddings from `Ppmi::embed`.
+pub fn similarity(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+fn sparse_dot(a: &[(u32, f64)], b: &[(u32, f64)]) -> f64 {
+    let (mut i, mut j, mut dot) = (0, 0, 0.0);
+
+    while i < a.len() && j < b.len() {
+        match a[i].0.cmp(&b[j].0) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                dot += a[i].1 * b[j].1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+
+    dot
+}
@@ -0,0 +1,126 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use age::x25519::Identity;
+
+const AGE_MAGIC: &[u8] = b"age-encryption.org/v1";
+
+fn to_io<E: std::error::Error + Send + Sync + 'static>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+/// The age identity from `POO_AGE_KEY`, or from the file named by
+/// `POO_AGE_KEY_FILE`, e.g. one written by `age-keygen`. Files are encrypted
+/// to its public key and decrypted with it.
+pub fn identity() -> io::Result<Option<Identity>> {
+    let key =
+        match (std::env::var("POO_AGE_KEY"), std::env::var("POO_AGE_KEY_FILE")) {
+            (Ok(key), _) => key,
+            (Err(_), Ok(path)) => {
+                std::fs::read_to_string(path)?
+                    .lines()
+                    .map(str::trim)
+                    .find(|l| l.starts_with("AGE-SECRET-KEY-"))
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no AGE-SECRET-KEY in key file"))?
+                    .to_string()
+            }
+            _ => return Ok(None),
+        };
+
+    Identity::from_str(key.trim())
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn is_encrypted(buf: &[u8]) -> bool {
+    buf.starts_with(AGE_MAGIC)
+}
+
+/// Whether the file at `path` is age-encrypted, from its first bytes, so
+/// what's derived from it can be sealed as well.
+pub fn is_encrypted_file(path: &Path) -> io::Result<bool> {
+    let mut magic = Vec::with_capacity(AGE_MAGIC.len());
+
+    File::open(path)?.take(AGE_MAGIC.len() as u64).read_to_end(&mut magic)?;
+
+    Ok(is_encrypted(&magic))
+}
+
+/// Decrypts `buf` if it is age-encrypted, otherwise returns it as is.
+pub fn decrypt(buf: Vec<u8>) -> io::Result<Vec<u8>> {
+    if !is_encrypted(&buf) {
+        return Ok(buf);
+    }
+
+    let identity =
+        identity()?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::PermissionDenied, "file is encrypted, set POO_AGE_KEY or POO_AGE_KEY_FILE"))?;
+
+    let mut reader =
+        match age::Decryptor::new(buf.as_slice()).map_err(to_io)? {
+            age::Decryptor::Recipients(decryptor) => {
+                decryptor
+                    .decrypt(std::iter::once(&identity as &dyn age::Identity))
+                    .map_err(to_io)?
+            }
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "passphrase-encrypted files aren't supported")),
+        };
+
+    let mut out = Vec::new();
+
+    reader.read_to_end(&mut out)?;
+
+    Ok(out)
+}
+
+/// A writer that encrypts what it's given when a key is set up. Nothing is
+/// complete until `finish`, which writes the final chunk.
+pub enum Sealed<W: Write> {
+    Plain(W),
+    Encrypted(age::stream::StreamWriter<W>),
+}
+
+impl<W: Write> Sealed<W> {
+    /// Encrypts to the configured identity if `encrypt`, failing if there is
+    /// none rather than silently writing plaintext.
+    pub fn new(writer: W, encrypt: bool) -> io::Result<Self> {
+        if !encrypt {
+            return Ok(Self::Plain(writer));
+        }
+
+        let identity =
+            identity()?
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "encryption needs POO_AGE_KEY or POO_AGE_KEY_FILE"))?;
+
+        let encryptor =
+            age::Encryptor::with_recipients(vec![Box::new(identity.to_public())])
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no recipient"))?;
+
+        Ok(Self::Encrypted(encryptor.wrap_output(writer)?))
+    }
+
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Self::Plain(writer) => Ok(writer),
+            Self::Encrypted(writer) => writer.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Sealed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(writer) => writer.write(buf),
+            Self::Encrypted(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(writer) => writer.flush(),
+            Self::Encrypted(writer) => writer.flush(),
+        }
+    }
+}
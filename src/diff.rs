@@ -0,0 +1,85 @@
+use rayon::prelude::*;
+
+use crate::analysis::{cosine_similarity, global_freqs, log_odds, top_n, total_tokens};
+use crate::text::text_item::PooMap;
+
+/// Differences between two corpora, e.g. two months of comments.
+pub struct CorpusDiff<'a> {
+    pub authors: (usize, usize),
+    pub tokens: (u64, u64),
+    pub vocabulary: (usize, usize),
+    /// Authors only present in the second corpus.
+    pub new_users: Vec<&'a [u8]>,
+    /// Authors only present in the first corpus.
+    pub vanished_users: Vec<&'a [u8]>,
+    /// Authors present in both, with `1 - cosine similarity` of their two
+    /// profiles, largest shift first.
+    pub shifts: Vec<(&'a [u8], f64)>,
+    /// Words over-represented in the second corpus, as log-odds z-scores.
+    pub rising: Vec<(Box<[u8]>, f64)>,
+    /// Words over-represented in the first corpus.
+    pub falling: Vec<(Box<[u8]>, f64)>,
+}
+
+impl<'a> CorpusDiff<'a> {
+    /// `terms` limits the number of rising and falling words kept.
+    pub fn compute(a: &'a PooMap, b: &'a PooMap, terms: usize) -> Self {
+        let global_a = global_freqs(a);
+        let global_b = global_freqs(b);
+
+        let scores = log_odds(&global_b, &global_a, 1000.0);
+
+        let owned = |scores: Vec<(&[u8], f64)>| {
+            scores
+                .into_iter()
+                .map(|(word, score)| (Box::<[u8]>::from(word), score))
+                .collect::<Vec<_>>()
+        };
+
+        let rising = owned(top_n(scores.iter().map(|(w, z)| (*w, *z)), terms));
+        let falling = owned(top_n(scores.iter().map(|(w, z)| (*w, -*z)), terms));
+
+        let mut new_users =
+            b.keys()
+                .filter(|author| !a.contains_key(*author))
+                .map(|author| &author[..])
+                .collect::<Vec<_>>();
+
+        let mut vanished_users =
+            a.keys()
+                .filter(|author| !b.contains_key(*author))
+                .map(|author| &author[..])
+                .collect::<Vec<_>>();
+
+        new_users.sort_unstable();
+        vanished_users.sort_unstable();
+
+        let mut shifts =
+            a.par_iter()
+                .filter_map(|(author, freqs)| {
+                    b.get(author)
+                        .map(|other| (&author[..], 1.0 - cosine_similarity(freqs, other)))
+                })
+                .collect::<Vec<_>>();
+
+        shifts.sort_by(|x, y|
+            y.1.partial_cmp(&x.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| x.0.cmp(y.0))
+        );
+
+        Self {
+            authors: (a.len(), b.len()),
+            tokens: (
+                a.values().map(total_tokens).sum(),
+                b.values().map(total_tokens).sum(),
+            ),
+            vocabulary: (global_a.len(), global_b.len()),
+            new_users,
+            vanished_users,
+            shifts,
+            rising,
+            falling,
+        }
+    }
+}
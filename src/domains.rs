@@ -0,0 +1,73 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::analysis::{log_odds, merge_freqs, top_n};
+use crate::text::text_item::{PooMap, PooMapInner};
+
+/// Prefix of the tokens that hold linked domains. Tokenized words are
+/// alphanumeric, so none of them starts with it. They share profiles with
+/// words, but token counts, vocabularies and everything else that works on
+/// words leave them out.
+pub const PREFIX: &[u8] = b"@";
+
+lazy_static! {
+    // HN comments are HTML with every link in an anchor, slashes escaped
+    static ref HREF: Regex =
+        Regex::new(r#"href="https?:(?://|&#x2F;&#x2F;)([^/"&:?#]+)"#).unwrap();
+}
+
+/// The domains `text` links to, lowercased and without `www.`, once per
+/// link.
+pub fn linked_domains(text: &str) -> impl Iterator<Item = String> + '_ {
+    HREF.captures_iter(text)
+        .map(|c| {
+            let domain = c[1].to_lowercase();
+
+            domain.strip_prefix("www.").map(str::to_string).unwrap_or(domain)
+        })
+        .filter(|domain| domain.contains('.'))
+}
+
+/// The token a linked `domain` is counted under.
+pub fn token(domain: &str) -> Vec<u8> {
+    [PREFIX, domain.as_bytes()].concat()
+}
+
+pub fn is_domain(word: &[u8]) -> bool {
+    word.starts_with(PREFIX)
+}
+
+/// The linked domains among `freqs`, without the prefix.
+pub fn domain_freqs(freqs: &PooMapInner) -> PooMapInner {
+    freqs.iter()
+        .filter(|(word, _)| is_domain(word))
+        .map(|(word, freq)| (Box::from(&word[PREFIX.len()..]), *freq))
+        .collect()
+}
+
+/// Links per domain over all of `poo`, which `analysis::global_freqs` leaves
+/// out.
+pub fn corpus_domains(poo: &PooMap) -> PooMapInner {
+    poo.iter()
+        .map(|(_, freqs)| domain_freqs(freqs))
+        .fold(PooMapInner::default(), merge_freqs)
+}
+
+/// The `n` domains `domains` links to most disproportionately compared to
+/// the corpus, by log-odds with the corpus as the prior. Empty for corpora
+/// ingested without `--domains`.
+pub fn affinity(domains: &PooMapInner, corpus: &PooMapInner, n: usize) -> Vec<(String, f64)> {
+    if domains.is_empty() {
+        return Vec::new();
+    }
+
+    let scores =
+        log_odds(domains, corpus, 10.0)
+            .into_iter()
+            .filter(|(domain, score)| *score > 0.0 && domains.contains_key(*domain));
+
+    top_n(scores, n)
+        .into_iter()
+        .map(|(domain, score)| (String::from_utf8_lossy(domain).to_string(), score))
+        .collect()
+}
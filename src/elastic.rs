@@ -0,0 +1,99 @@
+use serde_json::{json, Value};
+
+use crate::audit;
+use crate::profile::UserProfile;
+use crate::serializer::{FnFeedback, ProgressSink};
+
+#[derive(Debug)]
+pub enum ExportError {
+    Http(Box<ureq::Error>),
+    Io(std::io::Error),
+    /// The bulk request went through, but some documents were rejected.
+    Rejected { failed: usize, first: Value },
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportError::Http(e) => write!(f, "request failed: {}", e),
+            ExportError::Io(e) => write!(f, "reading response failed: {}", e),
+            ExportError::Rejected { failed, first } => {
+                write!(f, "{} documents rejected, first error: {}", failed, first)
+            }
+        }
+    }
+}
+
+impl From<ureq::Error> for ExportError {
+    fn from(e: ureq::Error) -> Self {
+        ExportError::Http(Box::new(e))
+    }
+}
+
+impl From<std::io::Error> for ExportError {
+    fn from(e: std::io::Error) -> Self {
+        ExportError::Io(e)
+    }
+}
+
+/// Newline delimited `_bulk` body indexing `profiles` by author.
+fn bulk_body(index: &str, profiles: &[UserProfile]) -> String {
+    let mut body = String::new();
+
+    for profile in profiles {
+        body.push_str(&json!({ "index": { "_index": index, "_id": profile.author } }).to_string());
+        body.push('\n');
+        body.push_str(&serde_json::to_string(profile).unwrap_or_default());
+        body.push('\n');
+    }
+
+    body
+}
+
+/// Bulk-indexes `profiles` into `index` on the Elasticsearch or OpenSearch
+/// cluster at `url`, `batch` documents per request.
+pub fn export(
+    url: &str,
+    index: &str,
+    profiles: &[UserProfile],
+    batch: usize,
+    fn_feedback: impl FnMut(FnFeedback) -> (),
+) -> Result<(), ExportError> {
+    let endpoint = format!("{}/_bulk", url.trim_end_matches('/'));
+
+    audit::write(&format!("{}/{}", url.trim_end_matches('/'), index))?;
+
+    let mut sink = ProgressSink::new(fn_feedback);
+
+    sink.message(format!("Export: Indexing {} profiles into {}..", profiles.len(), index));
+    sink.total(profiles.len() as u64);
+
+    let mut done = 0u64;
+
+    for chunk in profiles.chunks(batch.max(1)) {
+        let response =
+            ureq::post(&endpoint)
+                .set("Content-Type", "application/x-ndjson")
+                .send_string(&bulk_body(index, chunk))?
+                .into_json::<Value>()?;
+
+        if response["errors"].as_bool() == Some(true) {
+            let failed =
+                response["items"]
+                    .as_array()
+                    .map(|items| items.iter().filter(|i| i["index"]["error"].is_object()).collect::<Vec<_>>())
+                    .unwrap_or_default();
+
+            return Err(ExportError::Rejected {
+                failed: failed.len(),
+                first: failed.first().map(|i| i["index"]["error"].clone()).unwrap_or(Value::Null),
+            });
+        }
+
+        done += chunk.len() as u64;
+
+        sink.finish(done);
+    }
+
+    Ok(())
+}
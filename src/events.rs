@@ -0,0 +1,105 @@
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+
+use crate::serializer::FnFeedback;
+
+// events buffered per subscriber before further ones are dropped for it
+const SUBSCRIBER_BUFFER: usize = 1024;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Message { text: String },
+    Total { total: u64 },
+    Progress { progress: u64 },
+    /// One k-means pass; `changed` authors moved to another segment.
+    SegmentIteration { iteration: usize, changed: usize, sizes: Vec<usize> },
+    Segmented { assigned: usize, sizes: Vec<usize> },
+}
+
+impl Event {
+    pub fn from_feedback(fb: &FnFeedback) -> Option<Self> {
+        match fb {
+            FnFeedback::Message(text) => Some(Event::Message { text: text.clone() }),
+            FnFeedback::Total(total) => Some(Event::Total { total: *total }),
+            FnFeedback::Progress(progress) => Some(Event::Progress { progress: *progress }),
+            FnFeedback::Tick => None,
+        }
+    }
+}
+
+/// Fans events out to every subscriber. Subscribers that fall behind miss
+/// events rather than slowing down the pipeline.
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Mutex<Vec<SyncSender<String>>>,
+}
+
+lazy_static! {
+    pub static ref EVENTS: EventBus = EventBus::default();
+}
+
+impl EventBus {
+    pub fn subscribe(&self) -> Receiver<String> {
+        let (sender, receiver) = sync_channel(SUBSCRIBER_BUFFER);
+
+        self.subscribers.lock().unwrap().push(sender);
+
+        receiver
+    }
+
+    pub fn publish(&self, event: &Event) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+
+        if subscribers.is_empty() {
+            return;
+        }
+
+        let json = serde_json::to_string(event).unwrap_or_default();
+
+        subscribers.retain(|subscriber| {
+            !matches!(subscriber.try_send(json.clone()), Err(TrySendError::Disconnected(_)))
+        });
+    }
+
+    pub fn publish_feedback(&self, fb: &FnFeedback) {
+        if let Some(event) = Event::from_feedback(fb) {
+            self.publish(&event);
+        }
+    }
+}
+
+/// Streams `EVENTS` as JSON text messages to WebSocket clients on `addr`.
+#[cfg(feature = "native")]
+pub fn spawn_websocket(addr: &str) -> std::io::Result<()> {
+    use tungstenite::Message;
+
+    let listener = std::net::TcpListener::bind(addr)?;
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().filter_map(|s| s.ok()) {
+            std::thread::spawn(move || {
+                let mut socket =
+                    match tungstenite::accept(stream) {
+                        Ok(socket) => socket,
+                        Err(e) => {
+                            eprintln!("WebSocket handshake failed: {}", e);
+
+                            return;
+                        }
+                    };
+
+                for event in EVENTS.subscribe() {
+                    if socket.write_message(Message::Text(event)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,278 @@
+use std::io::Write;
+use std::path::Path;
+
+use rocksdb::{DB, IteratorMode};
+use serde::Deserialize;
+
+use poo::anonymity::{RareWords, Thresholds};
+use poo::args::Args;
+use poo::audit;
+use poo::categories;
+use poo::elastic;
+use poo::manifest::Manifest;
+use poo::optout;
+use poo::profile::{profiles, TimeProfile, UserDetail};
+use poo::rocks;
+use poo::segment::{segment, SegmentOptions};
+use poo::serializer::{decompress, deserialize, FnFeedback};
+use poo::storage::{self, read_file};
+use poo::text::text_item::PooMap;
+use poo::textstats;
+use poo::toxicity;
+
+const USAGE: &str = "usage:
+  export elasticsearch <file> <url> [--index=<name>] [--segments=<k>] [--terms=<count>] [--min-tokens=<count>] [--min-authors=<k>] [--redact-rare[=<authors>]] [--batch=<docs>]
+  export user <file> <name> [--out=<file>] [--db=<rocksdb> [--author-index=<db>]] [--segments=<k>] [--terms=<count>] [--min-tokens=<count>] [--min-authors=<k>] [--redact-rare[=<authors>]]   (--db adds a time profile with monthly sentiment)
+
+  --min-tokens leaves out authors with fewer tokens, --min-authors drops words used by fewer than k authors,
+  --redact-rare drops words used by at most that many authors (1) and words shaped like handles or addresses";
+
+/// The fields of a stored item needed for time profiles.
+#[derive(Deserialize)]
+struct ItemTime {
+    by: Option<String>,
+    time: Option<i64>,
+    text: Option<String>,
+}
+
+fn print_feedback(fb: FnFeedback) {
+    match fb {
+        FnFeedback::Message(msg) => eprintln!("{}", msg),
+        FnFeedback::Progress(progress) => eprint!("\r{}", progress),
+        _ => {}
+    }
+}
+
+fn thresholds(args: &Args) -> Thresholds {
+    Thresholds {
+        min_tokens: args.parse_value("min-tokens").unwrap_or(0),
+        min_authors: args.parse_value("min-authors").unwrap_or(0),
+    }
+}
+
+/// `--redact-rare[=<authors>]`, dropping words used by at most that many
+/// authors, one by default, and identifier-like words.
+fn rare_words(args: &Args) -> Option<RareWords> {
+    args.flag("redact-rare")
+        .then(|| RareWords {
+            max_authors: args.parse_value("redact-rare").unwrap_or(1),
+            ..Default::default()
+        })
+}
+
+/// Loads the corpus with everything below the `thresholds` and every rare
+/// word already removed, so nothing downstream can publish it.
+fn load(path: &str, thresholds: Thresholds, rare: Option<RareWords>, mut manifest: Option<&mut Manifest>) -> PooMap {
+    eprintln!("Loading {}..", path);
+
+    let buf = read_file(path).expect("failed to read file");
+
+    if let Some(manifest) = manifest.as_mut() {
+        manifest.input(path, &buf);
+    }
+
+    let mut corpus = deserialize(&buf, |_| {});
+
+    if !thresholds.is_noop() {
+        let suppressed = thresholds.apply(&mut corpus);
+
+        eprintln!("Suppressed {} authors and {} words", suppressed.authors, suppressed.words);
+
+        if let Some(manifest) = manifest.as_mut() {
+            manifest.option("min_tokens", thresholds.min_tokens);
+            manifest.option("min_authors", thresholds.min_authors);
+            manifest.count("suppressed_authors", suppressed.authors as u64);
+            manifest.count("suppressed_words", suppressed.words as u64);
+        }
+    }
+
+    if let Some(rare) = rare {
+        let redacted = rare.apply(&mut corpus);
+
+        eprintln!("Redacted {} rare words", redacted);
+
+        if let Some(manifest) = manifest {
+            manifest.option("redact_rare", rare.max_authors);
+            manifest.count("redacted_words", redacted as u64);
+        }
+    }
+
+    corpus
+}
+
+fn elasticsearch(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+    let url = args.positional(2).expect(USAGE);
+    let index = args.value("index").unwrap_or("hn-users");
+
+    let corpus = load(path, thresholds(args), rare_words(args), None);
+
+    let segmentation =
+        args.parse_value::<usize>("segments")
+            .map(|k| segment(&corpus, &SegmentOptions { segments: k, ..Default::default() }));
+
+    let profiles =
+        profiles(
+            &corpus,
+            segmentation.as_ref(),
+            args.parse_value("terms").unwrap_or(50),
+            0,
+            textstats::load(path).as_ref(),
+        );
+
+    let exported =
+        elastic::export(
+            url,
+            index,
+            &profiles,
+            args.parse_value("batch").unwrap_or(1000),
+            print_feedback,
+        );
+
+    eprintln!();
+
+    if let Err(e) = exported {
+        eprintln!("Export failed: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Collects the timestamps of `author`'s items. With an author index only
+/// their items are read, otherwise the whole database is scanned.
+fn time_profile(db_path: &str, index_path: Option<&str>, author: &str) -> TimeProfile {
+    if let Err(e) = audit::read(db_path) {
+        eprintln!("Failed to write audit log: {}", e);
+        std::process::exit(1);
+    }
+
+    let db = match DB::open_for_read_only(&rocksdb::Options::default(), db_path, false) {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("Failed to open database: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut profile = TimeProfile::default();
+
+    let mut add = |v: &[u8]| {
+        let v = decompress(v.to_vec()).unwrap_or_default();
+
+        if let Ok(ItemTime { by: Some(by), time: Some(time), text }) = serde_json::from_slice(&v) {
+            if by == author {
+                profile.add(time);
+
+                if let Some(text) = text {
+                    profile.add_text(time, &text);
+                }
+            }
+        }
+    };
+
+    match index_path {
+        Some(index_path) => {
+            let ids =
+                rocks::open(Path::new(index_path))
+                    .and_then(|index| rocks::items_of(&index, author.as_bytes()))
+                    .unwrap_or_else(|e| panic!("failed to read author index: {:?}", e));
+
+            eprintln!("Reading {} items by {}..", ids.len(), author);
+
+            for v in db.multi_get(ids.iter().map(|id| id.to_be_bytes())).into_iter().flatten().flatten() {
+                add(&v);
+            }
+        }
+        None => {
+            eprintln!("Scanning {} for items by {}..", db_path, author);
+
+            for (_, v) in db.iterator(IteratorMode::Start).filter_map(|v| v.ok()) {
+                add(&v);
+            }
+        }
+    }
+
+    profile
+}
+
+fn user(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+    let name = args.positional(2).expect(USAGE);
+
+    let mut manifest = Manifest::start("export");
+
+    let corpus = load(path, thresholds(args), rare_words(args), Some(&mut manifest));
+
+    let segmentation =
+        args.parse_value::<usize>("segments")
+            .map(|k| segment(&corpus, &SegmentOptions { segments: k, ..Default::default() }));
+
+    let times =
+        args.value("db")
+            .map(|db| {
+                manifest.input_path(db);
+
+                time_profile(db, args.value("author-index"), name)
+            });
+
+    let text_stats = textstats::load(path).and_then(|mut stats| stats.remove(name.as_bytes()));
+
+    let detail =
+        match UserDetail::build(name.as_bytes(), &corpus, segmentation.as_ref(), args.parse_value("terms").unwrap_or(50), times, text_stats.as_ref()) {
+            Some(detail) => detail,
+            None => {
+                eprintln!("No user named {}", name);
+                std::process::exit(1);
+            }
+        };
+
+    let json = serde_json::to_string_pretty(&detail).expect("failed to serialize profile");
+
+    let written =
+        match args.value("out") {
+            Some(out) => {
+                storage::create(out)
+                    .and_then(|mut output| {
+                        output.write_all(json.as_bytes())?;
+                        output.finish()
+                    })
+                    .and_then(|_| {
+                        manifest.output(out);
+                        manifest.write(out)
+                    })
+            }
+            None => writeln!(std::io::stdout(), "{}", json),
+        };
+
+    if let Err(e) = written {
+        eprintln!("Failed to write profile: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn main() {
+    let args = Args::from_env();
+
+    if let Err(e) = optout::init(&args) {
+        eprintln!("Failed to read opt-out list: {}", e);
+        std::process::exit(1);
+    }
+
+    if let Err(e) = toxicity::init(&args) {
+        eprintln!("Failed to read toxicity lexicon: {}", e);
+        std::process::exit(1);
+    }
+
+    if let Err(e) = categories::init(&args) {
+        eprintln!("Failed to read category dictionary: {}", e);
+        std::process::exit(1);
+    }
+
+    match args.positional(0) {
+        Some("elasticsearch") | Some("opensearch") => elasticsearch(&args),
+        Some("user") => user(&args),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+
+use twox_hash::XxHash;
+
+use crate::text::text_item::PooMapInner;
+
+/// Fingerprints are `SIDE * SIDE` cells, one per word.
+pub const SIDE: usize = 128;
+
+/// Heights of the fingerprint of a profile: the frequencies of its most used
+/// words, laid out by word hash and scaled so the mean lands mid-range.
+pub fn heights(freqs: &PooMapInner) -> Vec<u32> {
+    let mut top =
+        freqs
+            .iter()
+            .collect::<Vec<_>>();
+
+    top.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    top.truncate(SIDE * SIDE);
+
+    // the hash order spreads words over the image independent of frequency
+    let cells =
+        top
+            .into_iter()
+            .map(|(word, freq)| (word.clone(), *freq))
+            .collect::<HashMap<Box<[u8]>, u64, BuildHasherDefault<XxHash>>>();
+
+    let f =
+        cells
+            .values()
+            .map(|v| *v as u32)
+            .collect::<Vec<_>>();
+
+    let f_mean = f.iter().sum::<u32>() as f32 / f.len() as f32;
+
+    let f_min = f_mean / 2.0;
+    let f_max = f_mean * 2.0;
+
+    f.iter()
+        .map(|x| (((*x as f32 - f_min) / (f_max - f_min)) * 255.0) as u32)
+        .collect()
+}
+
+/// The `SIDE * SIDE` most used words of `freqs`, all counts zeroed. Profiles
+/// `project`ed onto the same layout put every word in the same cell, so
+/// their fingerprints can be compared.
+pub fn layout(freqs: &PooMapInner) -> PooMapInner {
+    let mut top = freqs.iter().collect::<Vec<_>>();
+
+    top.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    top.truncate(SIDE * SIDE);
+
+    top.into_iter().map(|(word, _)| (word.clone(), 0)).collect()
+}
+
+/// The counts of `freqs` for the words of `layout`.
+pub fn project(freqs: &PooMapInner, layout: &PooMapInner) -> PooMapInner {
+    layout
+        .keys()
+        .map(|word| (word.clone(), freqs.get(word).copied().unwrap_or(0)))
+        .collect()
+}
+
+#[inline(always)]
+pub fn color(p: u8) -> [u8; 3] {
+    match p {
+        0 => [0, 0, 0],
+        _ => [p / 3, p / 2, p],
+    }
+}
+
+/// Renders the fingerprint of `freqs` as a PNG at `path`.
+#[cfg(feature = "native")]
+pub fn save(freqs: &PooMapInner, path: &str) -> Option<()> {
+    cortical_io::image::generate_height_image_from_vec(
+        &heights(freqs),
+        10,
+        |p, _i| color(p),
+    )?
+        .save(path)
+        .ok()
+}
+
+/// Raw RGBA pixels of a `SIDE` x `SIDE` fingerprint, one pixel per cell.
+///
+/// Unlike `save` this doesn't go through cortical's renderer, which needs
+/// threads, so it also works in the browser.
+pub fn rgba(freqs: &PooMapInner) -> Vec<u8> {
+    let heights = heights(freqs);
+    let max = heights.iter().copied().max().unwrap_or(0).max(1) as u64;
+
+    let mut pixels = vec![0u8; SIDE * SIDE * 4];
+
+    for (i, h) in heights.iter().enumerate() {
+        let [r, g, b] = color((*h as u64 * 255 / max).min(255) as u8);
+
+        pixels[i * 4..i * 4 + 4].copy_from_slice(&[r, g, b, 255]);
+    }
+
+    pixels
+}
+
+/// PNG encoded `rgba`, e.g. for embedding in reports.
+#[cfg(feature = "native")]
+pub fn png(freqs: &PooMapInner) -> Option<Vec<u8>> {
+    let image = image::RgbaImage::from_raw(SIDE as u32, SIDE as u32, rgba(freqs))?;
+
+    let mut buf = Vec::new();
+
+    image
+        .write_to(&mut std::io::Cursor::new(&mut buf), image::ImageOutputFormat::Png)
+        .ok()?;
+
+    Some(buf)
+}
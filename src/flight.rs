@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use arrow::array::{Float32Builder, ListBuilder, StringBuilder, UInt32Builder, UInt64Builder};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::error::ArrowError;
+use arrow::ipc::writer::IpcWriteOptions;
+use arrow::record_batch::RecordBatch;
+use arrow_flight::flight_service_server::{FlightService, FlightServiceServer};
+use arrow_flight::utils::flight_data_from_arrow_batch;
+use arrow_flight::{
+    Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightEndpoint, FlightInfo,
+    HandshakeRequest, HandshakeResponse, IpcMessage, PutResult, SchemaAsIpc, SchemaResult, Ticket,
+};
+use futures::stream::{self, Stream, StreamExt};
+use rayon::prelude::*;
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::analysis::total_tokens;
+use crate::segment::Vectorizer;
+use crate::text::text_item::PooMap;
+
+type BoxStream<T> = Pin<Box<dyn Stream<Item=Result<T, Status>> + Send + 'static>>;
+
+// authors per record batch
+const BATCH_ROWS: usize = 65536;
+
+/// Tickets served, `vectors` is the sparse tf-idf matrix and `vocabulary`
+/// maps its column indices back to words.
+const TICKETS: [&str; 2] = ["vectors", "vocabulary"];
+
+pub fn vectors_schema(dims: usize) -> SchemaRef {
+    let item = |data_type| Box::new(Field::new("item", data_type, true));
+
+    Arc::new(
+        Schema::new(vec![
+            Field::new("author", DataType::Utf8, false),
+            Field::new("tokens", DataType::UInt64, false),
+            Field::new("indices", DataType::List(item(DataType::UInt32)), false),
+            Field::new("values", DataType::List(item(DataType::Float32)), false),
+        ])
+            .with_metadata(HashMap::from([("dims".to_string(), dims.to_string())]))
+    )
+}
+
+pub fn vocabulary_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("index", DataType::UInt32, false),
+        Field::new("word", DataType::Utf8, false),
+        Field::new("idf", DataType::Float32, false),
+    ]))
+}
+
+/// Serves the tf-idf vectors of a corpus over Arrow Flight.
+pub struct VectorService {
+    poo: Arc<PooMap>,
+    vectorizer: Arc<Vectorizer>,
+    // sorted, so batches come out in a stable order
+    authors: Arc<Vec<Box<[u8]>>>,
+}
+
+impl VectorService {
+    pub fn new(poo: PooMap, vocabulary: usize) -> Self {
+        let vectorizer = Vectorizer::fit(&poo, vocabulary);
+
+        let mut authors = poo.keys().cloned().collect::<Vec<_>>();
+
+        authors.par_sort_unstable();
+
+        Self {
+            poo: Arc::new(poo),
+            vectorizer: Arc::new(vectorizer),
+            authors: Arc::new(authors),
+        }
+    }
+
+    fn schema(&self, ticket: &[u8]) -> Result<SchemaRef, Status> {
+        match ticket {
+            b"vectors" => Ok(vectors_schema(self.vectorizer.dims())),
+            b"vocabulary" => Ok(vocabulary_schema()),
+            _ => Err(Status::not_found(format!(
+                "unknown ticket, expected one of {}",
+                TICKETS.join(", "),
+            ))),
+        }
+    }
+
+    fn flight_info(&self, ticket: &[u8]) -> Result<FlightInfo, Status> {
+        let schema = self.schema(ticket)?;
+
+        let message: IpcMessage =
+            SchemaAsIpc::new(&schema, &IpcWriteOptions::default())
+                .try_into()
+                .map_err(to_status)?;
+
+        Ok(FlightInfo::new(
+            message,
+            Some(FlightDescriptor::new_cmd(ticket.to_vec())),
+            vec![FlightEndpoint {
+                ticket: Some(Ticket { ticket: ticket.to_vec().into() }),
+                location: vec![],
+            }],
+            self.rows(ticket) as i64,
+            -1,
+        ))
+    }
+
+    fn rows(&self, ticket: &[u8]) -> usize {
+        match ticket {
+            b"vectors" => self.authors.len(),
+            _ => self.vectorizer.dims(),
+        }
+    }
+
+    fn vocabulary_batch(&self) -> Result<RecordBatch, ArrowError> {
+        let mut indices = UInt32Builder::new();
+        let mut words = StringBuilder::new();
+        let mut idfs = Float32Builder::new();
+
+        for (i, (word, idf)) in self.vectorizer.vocabulary().iter().zip(self.vectorizer.idf()).enumerate() {
+            indices.append_value(i as u32);
+            words.append_value(String::from_utf8_lossy(word));
+            idfs.append_value(*idf);
+        }
+
+        RecordBatch::try_new(
+            vocabulary_schema(),
+            vec![
+                Arc::new(indices.finish()),
+                Arc::new(words.finish()),
+                Arc::new(idfs.finish()),
+            ],
+        )
+    }
+
+    fn vectors_batch(
+        poo: &PooMap,
+        vectorizer: &Vectorizer,
+        authors: &[Box<[u8]>],
+    ) -> Result<RecordBatch, ArrowError> {
+        let rows =
+            authors
+                .par_iter()
+                .map(|author| {
+                    let freqs = &poo[author];
+
+                    (author, total_tokens(freqs), vectorizer.vectorize(freqs))
+                })
+                .collect::<Vec<_>>();
+
+        let mut names = StringBuilder::new();
+        let mut tokens = UInt64Builder::new();
+        let mut indices = ListBuilder::new(UInt32Builder::new());
+        let mut values = ListBuilder::new(Float32Builder::new());
+
+        for (author, total, vector) in rows {
+            names.append_value(String::from_utf8_lossy(author));
+            tokens.append_value(total);
+
+            for (i, x) in vector {
+                indices.values().append_value(i);
+                values.values().append_value(x);
+            }
+
+            indices.append(true);
+            values.append(true);
+        }
+
+        RecordBatch::try_new(
+            vectors_schema(vectorizer.dims()),
+            vec![
+                Arc::new(names.finish()),
+                Arc::new(tokens.finish()),
+                Arc::new(indices.finish()),
+                Arc::new(values.finish()),
+            ],
+        )
+    }
+}
+
+fn to_status(e: ArrowError) -> Status {
+    Status::internal(e.to_string())
+}
+
+/// Schema message followed by the dictionaries and data of each batch.
+fn encode(
+    schema: SchemaRef,
+    batches: impl Iterator<Item=Result<RecordBatch, ArrowError>> + Send + 'static,
+) -> BoxStream<FlightData> {
+    let options = IpcWriteOptions::default();
+
+    let header = FlightData::from(SchemaAsIpc::new(&schema, &options));
+
+    let body =
+        stream::iter(batches)
+            .flat_map(move |batch| {
+                let messages: Vec<Result<FlightData, Status>> =
+                    match batch {
+                        Ok(batch) => {
+                            let (mut messages, data) = flight_data_from_arrow_batch(&batch, &options);
+
+                            messages.push(data);
+                            messages.into_iter().map(Ok).collect()
+                        }
+                        Err(e) => vec![Err(to_status(e))],
+                    };
+
+                stream::iter(messages)
+            });
+
+    Box::pin(stream::once(async { Ok(header) }).chain(body))
+}
+
+#[tonic::async_trait]
+impl FlightService for VectorService {
+    type HandshakeStream = BoxStream<HandshakeResponse>;
+    type ListFlightsStream = BoxStream<FlightInfo>;
+    type DoGetStream = BoxStream<FlightData>;
+    type DoPutStream = BoxStream<PutResult>;
+    type DoActionStream = BoxStream<arrow_flight::Result>;
+    type ListActionsStream = BoxStream<ActionType>;
+    type DoExchangeStream = BoxStream<FlightData>;
+
+    async fn handshake(
+        &self,
+        _request: Request<Streaming<HandshakeRequest>>,
+    ) -> Result<Response<Self::HandshakeStream>, Status> {
+        Err(Status::unimplemented("no authentication"))
+    }
+
+    async fn list_flights(
+        &self,
+        _request: Request<Criteria>,
+    ) -> Result<Response<Self::ListFlightsStream>, Status> {
+        let infos =
+            TICKETS
+                .iter()
+                .map(|ticket| self.flight_info(ticket.as_bytes()))
+                .collect::<Vec<_>>();
+
+        Ok(Response::new(Box::pin(stream::iter(infos))))
+    }
+
+    async fn get_flight_info(
+        &self,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        self.flight_info(&request.into_inner().cmd).map(Response::new)
+    }
+
+    async fn get_schema(
+        &self,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<SchemaResult>, Status> {
+        let schema = self.schema(&request.into_inner().cmd)?;
+
+        SchemaAsIpc::new(&schema, &IpcWriteOptions::default())
+            .try_into()
+            .map(Response::new)
+            .map_err(to_status)
+    }
+
+    async fn do_get(
+        &self,
+        request: Request<Ticket>,
+    ) -> Result<Response<Self::DoGetStream>, Status> {
+        let ticket = request.into_inner().ticket;
+        let schema = self.schema(&ticket)?;
+
+        if &ticket[..] == b"vocabulary" {
+            let batch = self.vocabulary_batch();
+
+            return Ok(Response::new(encode(schema, std::iter::once(batch))));
+        }
+
+        let poo = self.poo.clone();
+        let vectorizer = self.vectorizer.clone();
+        let authors = self.authors.clone();
+
+        // batches are vectorized as the client pulls them
+        let batches =
+            (0..authors.len())
+                .step_by(BATCH_ROWS)
+                .map(move |start| {
+                    let end = (start + BATCH_ROWS).min(authors.len());
+
+                    Self::vectors_batch(&poo, &vectorizer, &authors[start..end])
+                });
+
+        Ok(Response::new(encode(schema, batches)))
+    }
+
+    async fn do_put(
+        &self,
+        _request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoPutStream>, Status> {
+        Err(Status::unimplemented("read only"))
+    }
+
+    async fn do_action(
+        &self,
+        _request: Request<Action>,
+    ) -> Result<Response<Self::DoActionStream>, Status> {
+        Err(Status::unimplemented("no actions"))
+    }
+
+    async fn list_actions(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<Self::ListActionsStream>, Status> {
+        Ok(Response::new(Box::pin(stream::empty())))
+    }
+
+    async fn do_exchange(
+        &self,
+        _request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoExchangeStream>, Status> {
+        Err(Status::unimplemented("read only"))
+    }
+}
+
+/// Serves `poo` over Arrow Flight on `addr` until the process exits.
+pub async fn serve(poo: PooMap, vocabulary: usize, addr: SocketAddr) -> Result<(), tonic::transport::Error> {
+    let service = VectorService::new(poo, vocabulary);
+
+    tonic::transport::Server::builder()
+        .add_service(FlightServiceServer::new(service))
+        .serve(addr)
+        .await
+}
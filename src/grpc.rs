@@ -0,0 +1,132 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tonic::{Request, Response, Status};
+
+use crate::analysis::{most_similar, top_n, word_freqs};
+use crate::matcher::AuthorFold;
+use crate::server::State;
+
+pub mod proto {
+    tonic::include_proto!("poo");
+}
+
+use proto::poo_server::{Poo, PooServer};
+
+impl From<proto::AuthorFold> for AuthorFold {
+    fn from(fold: proto::AuthorFold) -> Self {
+        match fold {
+            proto::AuthorFold::Exact => AuthorFold::Exact,
+            proto::AuthorFold::CaseInsensitive => AuthorFold::CaseInsensitive,
+            proto::AuthorFold::Normalized => AuthorFold::Normalized,
+        }
+    }
+}
+
+fn lossy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).to_string()
+}
+
+/// Answers gRPC calls from the same state as the HTTP server.
+pub struct PooService {
+    state: Arc<State>,
+}
+
+impl PooService {
+    pub fn new(state: Arc<State>) -> Self {
+        Self { state }
+    }
+}
+
+#[tonic::async_trait]
+impl Poo for PooService {
+    async fn extract(
+        &self,
+        request: Request<proto::ExtractRequest>,
+    ) -> Result<Response<proto::ExtractResponse>, Status> {
+        let request = request.into_inner();
+        let fold = AuthorFold::from(request.fold());
+
+        let limit =
+            match request.limit {
+                0 => usize::MAX,
+                n => n as usize,
+            };
+
+        let users =
+            request.users
+                .iter()
+                .filter_map(|user| self.state.find(user.as_bytes(), fold))
+                .map(|(author, freqs)| proto::UserFreqs {
+                    author: lossy(author),
+                    freqs:
+                        top_n(word_freqs(freqs), limit)
+                            .into_iter()
+                            .map(|(word, freq)| proto::WordFreq { word: lossy(word), freq })
+                            .collect(),
+                })
+                .collect();
+
+        Ok(Response::new(proto::ExtractResponse { users }))
+    }
+
+    async fn similar(
+        &self,
+        request: Request<proto::SimilarRequest>,
+    ) -> Result<Response<proto::SimilarResponse>, Status> {
+        let request = request.into_inner();
+        let n = if request.n == 0 { 20 } else { request.n as usize };
+
+        let (author, freqs) =
+            self.state
+                .find(request.user.as_bytes(), AuthorFold::Exact)
+                .ok_or_else(|| Status::not_found(format!("user {} not found", request.user)))?;
+
+        let similar =
+            most_similar(&self.state.poo, freqs, n + 1)
+                .into_iter()
+                .filter(|(other, _)| *other != author)
+                .take(n)
+                .map(|(other, score)| proto::Similarity { author: lossy(other), score })
+                .collect();
+
+        Ok(Response::new(proto::SimilarResponse { similar }))
+    }
+
+    async fn assign_segment(
+        &self,
+        request: Request<proto::SegmentRequest>,
+    ) -> Result<Response<proto::SegmentAssignment>, Status> {
+        let request = request.into_inner();
+
+        let segmentation =
+            self.state
+                .segmentation
+                .as_ref()
+                .ok_or_else(|| Status::failed_precondition("server was started without segments"))?;
+
+        let (author, freqs) =
+            self.state
+                .find(request.user.as_bytes(), AuthorFold::Exact)
+                .ok_or_else(|| Status::not_found(format!("user {} not found", request.user)))?;
+
+        let (segment, similarity) =
+            segmentation
+                .segment_of(author)
+                .unwrap_or_else(|| segmentation.assign(freqs));
+
+        Ok(Response::new(proto::SegmentAssignment {
+            author: lossy(author),
+            segment,
+            similarity,
+        }))
+    }
+}
+
+/// Serves `state` over gRPC on `addr` until the process exits.
+pub async fn serve(state: Arc<State>, addr: SocketAddr) -> Result<(), tonic::transport::Error> {
+    tonic::transport::Server::builder()
+        .add_service(PooServer::new(PooService::new(state)))
+        .serve(addr)
+        .await
+}
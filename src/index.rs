@@ -0,0 +1,126 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::total_tokens;
+use crate::crypto::Sealed;
+use crate::optout;
+use crate::storage;
+use crate::text::text_item::{PooMap, PooMapBase};
+
+/// Word → authors index, persisted next to the .freqs file it was built from.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct InverseIndex {
+    /// Author names and their total token count, indexed by author id.
+    authors: Vec<(Box<[u8]>, u64)>,
+    /// Per word, the ids of the authors using it and how often.
+    postings: PooMapBase<Vec<(u32, u64)>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WordUse<'a> {
+    pub author: &'a [u8],
+    pub freq: u64,
+    pub total: u64,
+}
+
+impl WordUse<'_> {
+    /// Share of the author's output that is this word.
+    pub fn share(&self) -> f64 {
+        self.freq as f64 / self.total.max(1) as f64
+    }
+}
+
+impl InverseIndex {
+    pub fn build(poo: &PooMap) -> Self {
+        let mut authors = poo.iter().collect::<Vec<_>>();
+        authors.sort_unstable_by(|a, b| a.0.cmp(b.0));
+
+        let mut index = Self::default();
+
+        for (id, (author, freqs)) in authors.into_iter().enumerate() {
+            index.authors.push((author.clone(), total_tokens(freqs)));
+
+            for (word, freq) in freqs.iter() {
+                index.postings
+                    .entry(word.clone())
+                    .or_default()
+                    .push((id as u32, *freq));
+            }
+        }
+
+        index
+    }
+
+    /// `corpus.freqs` is indexed into `corpus.freqs.index`.
+    pub fn sidecar_path(path: &Path) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".index");
+
+        PathBuf::from(name)
+    }
+
+    /// Sealed when `encrypt`, which it should be whenever the corpus is:
+    /// postings list who used every word.
+    pub fn save(&self, path: &Path, encrypt: bool) -> std::io::Result<()> {
+        let mut output = storage::create(&path.to_string_lossy())?;
+        let mut sealed = Sealed::new(&mut output, encrypt)?;
+        let mut encoder = zstd::stream::Encoder::new(&mut sealed, 3)?;
+
+        bincode::serialize_into(&mut encoder, self)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+
+        encoder.finish()?;
+        sealed.finish()?;
+        output.finish()
+    }
+
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let buf = storage::read_file(&path.to_string_lossy())?;
+
+        bincode::deserialize(&buf)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn authors(&self) -> usize {
+        self.authors.len()
+    }
+
+    pub fn words(&self) -> usize {
+        self.postings.len()
+    }
+
+    /// Everyone using `word`, highest share of their output first. Authors
+    /// who opted out after the index was built are left out here, as the
+    /// index is only rebuilt when the corpus changes.
+    pub fn users_of(&self, word: &[u8]) -> Vec<WordUse> {
+        let mut uses =
+            self.postings
+                .get(word)
+                .map(|postings|
+                    postings
+                        .iter()
+                        .map(|(id, freq)| {
+                            let (author, total) = &self.authors[*id as usize];
+
+                            WordUse {
+                                author,
+                                freq: *freq,
+                                total: *total,
+                            }
+                        })
+                        .filter(|word_use| !optout::is_opted_out(word_use.author))
+                        .collect::<Vec<_>>()
+                )
+                .unwrap_or_default();
+
+        uses.sort_by(|a, b|
+            b.share()
+                .partial_cmp(&a.share())
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| b.freq.cmp(&a.freq))
+        );
+
+        uses
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::manifest::dir_size;
+use crate::serializer::decompress;
+use crate::source::{decode_id, IdRange, KvSource};
+
+// malformed values kept to show
+const SAMPLES: usize = 5;
+const PREVIEW_LEN: usize = 120;
+
+#[derive(Deserialize)]
+struct ItemType {
+    r#type: Option<String>,
+}
+
+/// A value that didn't decompress or parse.
+#[derive(Debug, Clone, Serialize)]
+pub struct Malformed {
+    /// The item id, or the key in hex if it isn't one.
+    pub key: String,
+    pub error: String,
+    /// Start of the raw value, lossily decoded.
+    pub preview: String,
+}
+
+/// What's in a source database, to sanity-check a dump before ingesting it.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DbStats {
+    pub items: u64,
+    pub value_bytes: u64,
+    pub first_id: Option<i64>,
+    pub last_id: Option<i64>,
+    /// Items by `type`, `none` for items without one.
+    pub types: BTreeMap<String, u64>,
+    pub malformed: u64,
+    pub malformed_samples: Vec<Malformed>,
+    pub disk_bytes: u64,
+}
+
+fn malformed(key: &[u8], value: &[u8], error: String) -> Malformed {
+    Malformed {
+        key:
+            decode_id(key)
+                .filter(|_| key.len() == 8)
+                .map(|id| id.to_string())
+                .unwrap_or_else(|| key.iter().map(|b| format!("{:02x}", b)).collect()),
+        error,
+        preview: String::from_utf8_lossy(&value[..value.len().min(PREVIEW_LEN)]).to_string(),
+    }
+}
+
+impl DbStats {
+    fn add(mut self, key: &[u8], value: &[u8]) -> Self {
+        self.items += 1;
+        self.value_bytes += value.len() as u64;
+
+        let parsed =
+            match (key.len(), decode_id(key)) {
+                (8, Some(id)) => {
+                    self.first_id = Some(self.first_id.map_or(id, |first| first.min(id)));
+                    self.last_id = Some(self.last_id.map_or(id, |last| last.max(id)));
+
+                    decompress(value.to_vec())
+                        .map_err(|e| format!("decompression failed: {}", e))
+                        .and_then(|v| serde_json::from_slice::<ItemType>(&v).map_err(|e| e.to_string()))
+                }
+                _ => Err("key isn't a big-endian item id".to_string()),
+            };
+
+        match parsed {
+            Ok(item) => {
+                *self.types.entry(item.r#type.unwrap_or_else(|| "none".to_string())).or_default() += 1;
+            }
+            Err(error) => {
+                self.malformed += 1;
+
+                if self.malformed_samples.len() < SAMPLES {
+                    self.malformed_samples.push(malformed(key, value, error));
+                }
+            }
+        }
+
+        self
+    }
+
+    fn merge(mut self, other: Self) -> Self {
+        self.items += other.items;
+        self.value_bytes += other.value_bytes;
+        self.first_id = self.first_id.into_iter().chain(other.first_id).min();
+        self.last_id = self.last_id.into_iter().chain(other.last_id).max();
+
+        for (kind, count) in other.types {
+            *self.types.entry(kind).or_default() += count;
+        }
+
+        self.malformed += other.malformed;
+        self.malformed_samples.extend(other.malformed_samples);
+        self.malformed_samples.truncate(SAMPLES);
+
+        self
+    }
+
+    pub fn print(&self) {
+        let id = |id: Option<i64>| id.map(|id| id.to_string()).unwrap_or_else(|| "-".to_string());
+
+        println!("items        {}", self.items);
+        println!("id range     {} to {}", id(self.first_id), id(self.last_id));
+        println!("values       {:.1} MiB", self.value_bytes as f64 / 1024.0 / 1024.0);
+        println!("on disk      {:.1} MiB", self.disk_bytes as f64 / 1024.0 / 1024.0);
+        println!("malformed    {}", self.malformed);
+
+        println!("\ntypes:");
+
+        for (kind, count) in self.types.iter() {
+            println!("  {:<12} {:>12} {:>6.2}%", kind, count, *count as f64 / self.items.max(1) as f64 * 100.0);
+        }
+
+        if !self.malformed_samples.is_empty() {
+            println!("\nmalformed values:");
+
+            for sample in self.malformed_samples.iter() {
+                println!("  {}: {}\n    {:?}", sample.key, sample.error, sample.preview);
+            }
+        }
+    }
+}
+
+/// Scans every item in `range`.
+pub fn inspect(path: &Path, source: &dyn KvSource, range: IdRange) -> std::io::Result<DbStats> {
+    let stats =
+        source.items(range)
+            .par_bridge()
+            .try_fold(DbStats::default, |stats, entry| entry.map(|(k, v)| stats.add(&k, &v)))
+            .try_reduce(DbStats::default, |a, b| Ok(a.merge(b)))?;
+
+    let disk_bytes =
+        if path.is_dir() {
+            dir_size(path)?
+        } else {
+            path.metadata()?.len()
+        };
+
+    Ok(DbStats { disk_bytes, ..stats })
+}
@@ -0,0 +1,122 @@
+use lazy_static::lazy_static;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::text::text_item::PooMapInner;
+
+/// Pseudo-tokens at the corpus rate added to every profile before scoring,
+/// so a single mention in a short history doesn't top the ranking.
+const PRIOR_TOKENS: f64 = 1000.0;
+
+lazy_static! {
+    /// Programming languages, frameworks and databases.
+    pub static ref TECH: Lexicon =
+        Lexicon::parse(include_str!("./text/techwords.txt")).expect("built-in lexicon is valid");
+}
+
+/// Named terms in categories, each term known under one or more tokens.
+#[derive(Debug, Clone)]
+pub struct Lexicon {
+    /// `(category, name)`, in file order.
+    pub terms: Vec<(String, String)>,
+    /// Token to position in `terms`.
+    tokens: FxHashMap<Box<[u8]>, usize>,
+}
+
+/// The terms of one category a profile uses more than the corpus does.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategoryAffinity {
+    pub category: String,
+    /// With how many times the corpus rate they're used at, as log2,
+    /// highest first.
+    pub terms: Vec<(String, f64)>,
+}
+
+impl Lexicon {
+    /// One `category,name,tokens` line per term, tokens separated by spaces;
+    /// blank lines and `#` comments are ignored.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lexicon = Self { terms: Vec::new(), tokens: FxHashMap::default() };
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.splitn(3, ',').map(str::trim);
+
+            let (category, name, tokens) =
+                match (fields.next(), fields.next(), fields.next()) {
+                    (Some(category), Some(name), Some(tokens)) if !tokens.is_empty() => (category, name, tokens),
+                    _ => return Err(format!("line {}: expected category,name,tokens", i + 1)),
+                };
+
+            let term = lexicon.terms.len();
+
+            lexicon.terms.push((category.to_string(), name.to_string()));
+
+            for token in tokens.split_whitespace() {
+                if lexicon.tokens.insert(Box::from(token.as_bytes()), term).is_some() {
+                    return Err(format!("line {}: {} is listed twice", i + 1, token));
+                }
+            }
+        }
+
+        Ok(lexicon)
+    }
+
+    /// Uses of each term in `freqs`, by position in `terms`.
+    pub fn counts(&self, freqs: &PooMapInner) -> Vec<u64> {
+        let mut counts = vec![0; self.terms.len()];
+
+        for (token, term) in self.tokens.iter() {
+            if let Some(freq) = freqs.get(token) {
+                counts[*term] += freq;
+            }
+        }
+
+        counts
+    }
+
+    /// Per category, the `n` terms `counts` out of `tokens` uses most above
+    /// the rate of `corpus_counts` out of `corpus_tokens`. Categories
+    /// without any are left out.
+    pub fn affinity(
+        &self,
+        counts: &[u64],
+        tokens: u64,
+        corpus_counts: &[u64],
+        corpus_tokens: u64,
+        n: usize,
+    ) -> Vec<CategoryAffinity> {
+        let mut categories = Vec::<CategoryAffinity>::new();
+
+        for (term, (category, name)) in self.terms.iter().enumerate() {
+            if counts[term] == 0 {
+                continue;
+            }
+
+            let corpus_rate = (corpus_counts[term] as f64).max(1.0) / (corpus_tokens as f64).max(1.0);
+            let rate = (counts[term] as f64 + PRIOR_TOKENS * corpus_rate) / (tokens as f64 + PRIOR_TOKENS);
+            let score = (rate / corpus_rate).log2();
+
+            if score <= 0.0 {
+                continue;
+            }
+
+            match categories.iter_mut().find(|c| c.category == *category) {
+                Some(affinity) => affinity.terms.push((name.clone(), score)),
+                None => categories.push(CategoryAffinity { category: category.clone(), terms: vec![(name.clone(), score)] }),
+            }
+        }
+
+        for affinity in categories.iter_mut() {
+            affinity.terms.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+            affinity.terms.truncate(n);
+        }
+
+        categories
+    }
+}
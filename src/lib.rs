@@ -0,0 +1,77 @@
+pub mod aggregate;
+pub mod analysis;
+pub mod anonymity;
+pub mod args;
+#[cfg(feature = "native")]
+pub mod audit;
+pub mod bench;
+#[cfg(feature = "native")]
+pub mod cache;
+pub mod categories;
+pub mod cooccurrence;
+#[cfg(feature = "native")]
+pub mod compare;
+#[cfg(feature = "native")]
+pub mod crypto;
+pub mod diff;
+pub mod domains;
+#[cfg(feature = "native")]
+pub mod elastic;
+pub mod events;
+pub mod fingerprint;
+#[cfg(feature = "flight")]
+pub mod flight;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "native")]
+pub mod index;
+#[cfg(feature = "native")]
+pub mod inspect;
+pub mod lexicon;
+#[cfg(feature = "native")]
+pub mod manifest;
+pub mod matcher;
+pub mod metrics;
+pub mod optout;
+#[cfg(feature = "native")]
+pub mod pipeline;
+pub mod profile;
+#[cfg(feature = "native")]
+pub mod pseudonym;
+pub mod quality;
+#[cfg(feature = "native")]
+pub mod queue;
+#[cfg(feature = "native")]
+pub mod report;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod rng;
+#[cfg(feature = "native")]
+pub mod rocks;
+pub mod scrub;
+pub mod segment;
+pub mod sentiment;
+pub mod serializer;
+#[cfg(feature = "native")]
+pub mod server;
+#[cfg(feature = "native")]
+pub mod site;
+#[cfg(feature = "native")]
+pub mod source;
+#[cfg(feature = "native")]
+pub mod spill;
+pub mod stability;
+#[cfg(feature = "sql")]
+pub mod sql;
+#[cfg(feature = "native")]
+pub mod storage;
+pub mod text;
+pub mod textstats;
+pub mod toxicity;
+pub mod vocabulary;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "native")]
+pub mod wordcloud;
+#[cfg(feature = "native")]
+pub mod zipf;
@@ -2,22 +2,50 @@
 
 extern crate core;
 
-use std::fs::File;
+use std::borrow::Cow;
 use std::io::{BufRead, Error, Write};
-use std::ops::AddAssign;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use dashmap::DashMap;
+use rustc_hash::FxHashMap;
 use kdam::{BarExt, Column, RichProgress, tqdm};
 use kdam::term::Colorizer;
 use rayon::prelude::*;
-use rocksdb::DB;
+use rocksdb::{BlockBasedOptions, Cache, DB, Options};
 use serde::{Deserialize, Serialize};
 
-use crate::serializer::{FnFeedback, serialize_with_writer};
-use crate::text::text_item::{PooMap, PooMapInner, TextItem};
+use poo::aggregate;
+use poo::anonymity::Thresholds;
+use poo::args::Args;
+use poo::audit;
+use poo::bench;
+use poo::cooccurrence::{self, Cooccurrence, Pairs};
+use poo::crypto::Sealed;
+use poo::domains;
+use poo::events::{spawn_websocket, EVENTS};
+use poo::inspect;
+use poo::manifest::Manifest;
+use poo::metrics::{METRICS, spawn_exporter};
+use poo::optout;
+use poo::pipeline::{Pipeline, PipelineConfig};
+use poo::pseudonym::Pseudonymizer;
+use poo::queue::{self, WorkQueue};
+use poo::rocks::{self, ProfileWriter};
+use poo::scrub::Scrubber;
+use poo::serializer::{decompress, FnFeedback, serialize_with_writer};
+#[cfg(feature = "lmdb")]
+use poo::source::LmdbSource;
+use poo::source::{decode_id, IdRange, KvSource};
+use poo::spill::Spiller;
+use poo::storage;
+use poo::text::interner::INTERNER;
+use poo::text::text_item::{SymMap, SymMapInner, TextItem};
+use poo::textstats::{self, TextStats};
+use poo::vocabulary::Vocabulary;
 
-pub mod text;
-pub mod serializer;
+// number of items aggregated between memory budget checks
+const SPILL_BATCH: usize = 250_000;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct Item {
@@ -60,16 +88,601 @@ fn read_until<R: BufRead + ?Sized>(r: &mut R, delim: u8, buf: &mut Vec<u8>) -> R
     }
 }
 
+fn parse_item((k, v): (Box<[u8]>, Box<[u8]>), ingest: &Ingest) -> Option<(i64, Box<[u8]>, SymMapInner, Option<TextStats>, Option<Pairs>)> {
+    let k = decode_id(&k)?;
+
+    print!("\r{}", k as usize);
+
+    METRICS.bytes_ingested.add(v.len() as u64);
+
+    // some dumps store every value zstd-compressed
+    let mut v =
+        match decompress(v.into_vec()) {
+            Ok(v) => v,
+            Err(_) => {
+                METRICS.parse_failures.inc();
+
+                return None;
+            }
+        };
+
+    let i: Item =
+        match simd_json::from_slice(&mut v[..]) {
+            Ok(i) => i,
+            Err(_) => {
+                METRICS.parse_failures.inc();
+
+                return None;
+            }
+        };
+
+    // text-less items count too, they carry threads on
+    if let Some(threads) = ingest.threads {
+        threads.parents.insert(k, i.parent);
+    }
+
+    let replies_received = i.kids.as_ref().map_or(0, Vec::len) as u64;
+
+    match (i.by, i.text) {
+        (Some(by), Some(text)) => {
+            METRICS.items_ingested.inc();
+
+            let text =
+                match ingest.scrubber {
+                    Some(scrubber) => scrubber.scrub(&text),
+                    None => Cow::Borrowed(text.as_str()),
+                };
+
+            let mut freqs = TextItem::process_alt(&text);
+
+            // linked domains are counted alongside the words, under tokens
+            // no word can collide with
+            if ingest.domains {
+                for domain in domains::linked_domains(&text) {
+                    *freqs.entry(INTERNER.intern(&domains::token(&domain))).or_insert(0) += 1;
+                }
+            }
+
+            let stats = ingest.text_stats.map(|_| TextStats::of_item(&text, replies_received));
+            let pairs = ingest.cooccurrence.map(|cooccurrence| cooccurrence.pairs(&text));
+
+            Some((
+                k,
+                by.into_bytes().into_boxed_slice(),
+                freqs,
+                stats,
+                pairs,
+            ))
+        }
+        _ => {
+            METRICS.items_skipped.inc();
+
+            None
+        }
+    }
+}
+
+/// Where items sit in their threads. Items are ingested in parallel, so a
+/// reply can come before its parent; depths are only resolved once every
+/// item has been seen.
+#[derive(Default)]
+struct Threads {
+    /// Parent of every item seen, `None` for posts.
+    parents: DashMap<i64, Option<i64>>,
+    /// Author of every item counted in the text statistics.
+    authors: DashMap<i64, Box<[u8]>>,
+}
+
+impl Threads {
+    /// Depth of `id` in its thread, 0 for a post, `None` if the thread
+    /// starts before the ingested range. `known` caches depths across calls.
+    fn depth(&self, id: i64, known: &mut FxHashMap<i64, u64>) -> Option<u64> {
+        let mut chain = Vec::new();
+        let mut current = id;
+
+        let base =
+            loop {
+                if let Some(depth) = known.get(&current) {
+                    break Some(*depth);
+                }
+
+                match self.parents.get(&current).map(|parent| *parent) {
+                    Some(Some(parent)) => {
+                        chain.push(current);
+                        current = parent;
+                    }
+                    Some(None) => {
+                        known.insert(current, 0);
+                        break Some(0);
+                    }
+                    None => break None,
+                }
+            }?;
+
+        Some(
+            chain.iter()
+                .rev()
+                .fold(base, |depth, item| {
+                    known.insert(*item, depth + 1);
+                    depth + 1
+                })
+        )
+    }
+
+    /// Adds the depth of every authored item to its author's statistics.
+    fn resolve(&self, text_stats: &DashMap<Box<[u8]>, TextStats>) {
+        let mut known = FxHashMap::default();
+
+        for entry in self.authors.iter() {
+            if let Some(depth) = self.depth(*entry.key(), &mut known) {
+                text_stats.entry(entry.value().clone()).or_default().count_depth(depth);
+            }
+        }
+    }
+}
+
+/// What happens to each item besides parsing it.
+#[derive(Clone, Copy)]
+struct Ingest<'a> {
+    /// Records each item under its author.
+    index: Option<&'a DB>,
+    pseudonyms: Option<&'a Pseudonymizer>,
+    scrubber: Option<&'a Scrubber>,
+    /// Counts linked domains, see `domains`.
+    domains: bool,
+    /// Sums each author's raw text statistics.
+    text_stats: Option<&'a DashMap<Box<[u8]>, TextStats>>,
+    /// Where items sit in their threads, for `text_stats`.
+    threads: Option<&'a Threads>,
+    /// Counts word pairs near each other.
+    cooccurrence: Option<&'a Cooccurrence>,
+}
+
+impl Ingest<'_> {
+    fn process(&self, item: (Box<[u8]>, Box<[u8]>)) -> Option<(Box<[u8]>, SymMapInner)> {
+        let (id, author, freqs, stats, pairs) = parse_item(item, self)?;
+
+        if optout::is_opted_out(&author) {
+            return None;
+        }
+
+        // pseudonymized before anything is stored, the index included
+        let author =
+            match self.pseudonyms {
+                Some(pseudonyms) => pseudonyms.pseudonym(&author),
+                None => author,
+            };
+
+        if let Some(db) = self.index {
+            if let Err(e) = rocks::index_item(db, &author, id) {
+                panic!("failed to index item {}: {:?}", id, e)
+            }
+        }
+
+        if let (Some(text_stats), Some(stats)) = (self.text_stats, stats) {
+            *text_stats.entry(author.clone()).or_default() += stats;
+
+            if let Some(threads) = self.threads {
+                threads.authors.insert(id, author.clone());
+            }
+        }
+
+        if let (Some(cooccurrence), Some(pairs)) = (self.cooccurrence, pairs) {
+            cooccurrence.add(&pairs);
+        }
+
+        Some((author, freqs))
+    }
+}
+
+/// Aggregates the database in batches, spilling to disk whenever the
+/// in-memory aggregation grows past the budget. Returns what's left in memory.
+fn aggregate_with_budget(source: &dyn KvSource, range: IdRange, ingest: Ingest, spiller: &mut Spiller) -> SymMap {
+    let mut acc = SymMap::default();
+    let mut batch = Vec::with_capacity(SPILL_BATCH);
+
+    let mut items =
+        source.items(range)
+            .filter_map(|v| v.ok());
+
+    loop {
+        batch.extend(items.by_ref().take(SPILL_BATCH));
+
+        if batch.is_empty() {
+            break;
+        }
+
+        acc = TextItem::merge(
+            acc,
+            TextItem::aggregate(batch.par_drain(..).filter_map(|item| ingest.process(item))),
+        );
+
+        if let Err(e) = spiller.maybe_spill(&mut acc) {
+            panic!("failed to spill to disk: {:?}", e)
+        }
+    }
+
+    acc
+}
+
+/// Merges `range` into the profiles of `db` a batch at a time, each batch
+/// written together with its last item id so a crashed run can resume.
+fn merge_in_batches(source: &dyn KvSource, range: IdRange, ingest: Ingest, db: &DB) -> Result<(), rocksdb::Error> {
+    let mut batch = Vec::with_capacity(SPILL_BATCH);
+
+    let mut items =
+        source.items(range)
+            .filter_map(|v| v.ok());
+
+    loop {
+        batch.extend(items.by_ref().take(SPILL_BATCH));
+
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let through = batch.iter().rev().find_map(|(k, _)| decode_id(k));
+
+        let merged =
+            batch.par_drain(..)
+                .filter_map(|item| ingest.process(item))
+                .collect::<Vec<_>>();
+
+        rocks::merge_items(db, &merged, through)?;
+    }
+}
+
+/// RocksDB settings for the source database; the defaults are tuned for
+/// point lookups, not for one big sequential scan.
+#[derive(Debug, Clone, Copy, Default)]
+struct Tuning {
+    /// In MiB.
+    block_cache: Option<usize>,
+    background_jobs: Option<i32>,
+}
+
+impl Tuning {
+    fn options(&self) -> Result<Options, rocksdb::Error> {
+        let mut opts = Options::default();
+
+        if let Some(mib) = self.block_cache {
+            let mut table = BlockBasedOptions::default();
+
+            table.set_block_cache(&Cache::new_lru_cache(mib * 1024 * 1024)?);
+
+            opts.set_block_based_table_factory(&table);
+        }
+
+        if let Some(jobs) = self.background_jobs {
+            opts.increase_parallelism(jobs);
+            opts.set_max_background_jobs(jobs);
+        }
+
+        Ok(opts)
+    }
+}
+
+/// Opens the source database read-only unless `writable`, so it can be
+/// ingested while another process still writes to it and archival dumps are
+/// never modified or compacted.
+fn open_db(path: &Path, writable: bool, tuning: Tuning) -> Result<DB, rocksdb::Error> {
+    let opts = tuning.options()?;
+
+    if writable {
+        return DB::open(&opts, path);
+    }
+
+    // a writer may hold an active WAL, that's fine when reading
+    DB::open_for_read_only(&opts, path, false)
+}
+
+/// Opens a database profiles are written to, recording that in the audit
+/// log first.
+fn open_output_db(location: &str, what: &str) -> DB {
+    if let Err(e) = audit::write(location) {
+        panic!("failed to write audit log: {:?}", e)
+    }
+
+    match rocks::open(Path::new(location)) {
+        Ok(db) => db,
+        Err(e) => panic!("failed to open {} database: {:?}", what, e),
+    }
+}
+
+/// Claims shards from the queue and ingests each with a run of this binary
+/// limited to its ids, passing the other options through, until none are
+/// left. A failed shard is put back for another worker.
+fn run_worker(args: &Args) {
+    let location = args.value("queue").expect("--queue is required");
+    let out_dir = args.value("out-dir").unwrap_or(".");
+
+    let mut queue =
+        WorkQueue::open(location)
+            .unwrap_or_else(|e| panic!("failed to open queue {}: {:?}", location, e));
+
+    let mut passed =
+        std::env::args()
+            .skip(1)
+            .filter(|arg| {
+                !["--queue=", "--out-dir=", "--out=", "--from-id=", "--to-id="]
+                    .iter()
+                    .any(|prefix| arg.starts_with(prefix))
+            })
+            .collect::<Vec<_>>();
+
+    if let Some(i) = passed.iter().position(|arg| arg == "work") {
+        passed.remove(i);
+    }
+
+    let exe = std::env::current_exe().expect("failed to locate own binary");
+
+    loop {
+        let task =
+            match queue.claim() {
+                Ok(Some(task)) => task,
+                Ok(None) => break,
+                Err(e) => panic!("failed to claim a shard: {:?}", e),
+            };
+
+        let out = format!("{}/shard-{:05}.users.freqs", out_dir.trim_end_matches('/'), task.shard);
+
+        println!("Shard {}: ids {}..{} to {}", task.shard, task.from, task.to, out);
+
+        let status =
+            std::process::Command::new(&exe)
+                .args(&passed)
+                .arg(format!("--from-id={}", task.from))
+                .arg(format!("--to-id={}", task.to))
+                .arg(format!("--out={}", out))
+                .status();
+
+        match status {
+            Ok(status) if status.success() => {
+                if let Err(e) = queue.complete(&task, &out) {
+                    panic!("failed to mark shard {} done: {:?}", task.shard, e)
+                }
+            }
+            failed => {
+                if let Err(e) = queue.release(&task) {
+                    eprintln!("Failed to put shard {} back: {:?}", task.shard, e);
+                }
+
+                panic!("shard {} failed: {:?}", task.shard, failed)
+            }
+        }
+    }
+
+    match queue.counts() {
+        Ok((pending, claimed, done)) => {
+            println!("No shards left: {} pending, {} in progress, {} done", pending, claimed, done)
+        }
+        Err(e) => eprintln!("Failed to read queue: {:?}", e),
+    }
+}
+
 fn main() {
+    let args = Args::from_env();
+
+    if args.positional(0) == Some("bench") {
+        bench::run(
+            args.parse_value("authors").unwrap_or(10_000),
+            args.parse_value("comments").unwrap_or(200_000),
+        );
+
+        return;
+    }
+
+    // `poo pipeline <config.json> [--force]` runs every out-of-date stage
+    if args.positional(0) == Some("pipeline") {
+        let path = args.positional(1).expect("No pipeline config provided");
+
+        let config =
+            PipelineConfig::read(path)
+                .unwrap_or_else(|e| panic!("failed to read pipeline config {}: {}", path, e));
+
+        let mut pipeline =
+            Pipeline::new(config)
+                .unwrap_or_else(|e| panic!("failed to set up pipeline: {:?}", e));
+
+        match pipeline.run(args.flag("force")) {
+            Ok(ran) if ran.is_empty() => println!("Everything up to date"),
+            Ok(ran) => println!("Ran {}", ran.join(", ")),
+            Err(e) => panic!("pipeline failed: {}", e),
+        }
+
+        return;
+    }
+
+    // `poo work <path> --queue=<queue> [--out-dir=<dir>]` ingests queued shards
+    if args.positional(0) == Some("work") {
+        run_worker(&args);
+
+        return;
+    }
+
+    // `poo requeue --queue=<queue> [--stale-after=<minutes>]` puts back the
+    // shards of workers that died holding them, claimed over two hours ago
+    // by default
+    if args.positional(0) == Some("requeue") {
+        let location = args.value("queue").expect("--queue is required");
+        let stale_after = Duration::from_secs(args.parse_value::<u64>("stale-after").unwrap_or(120) * 60);
+
+        match WorkQueue::open(location).and_then(|mut queue| queue.requeue(stale_after)) {
+            Ok(tasks) => {
+                for task in tasks.iter() {
+                    println!("Shard {}: ids {}..{} back in the queue", task.shard, task.from, task.to);
+                }
+
+                println!("Requeued {} shards", tasks.len());
+            }
+            Err(e) => panic!("failed to requeue shards: {:?}", e),
+        }
+
+        return;
+    }
+
+    // `poo inspect-db <path>` reports what a dump holds instead of ingesting
+    // it, `poo enqueue <path> --queue=<queue> [--shards=<n>]` splits it into
+    // shards for `poo work`
+    let inspecting = args.positional(0) == Some("inspect-db");
+    let enqueueing = args.positional(0) == Some("enqueue");
+
     // find folder located at first argument
-    let path = std::env::args().nth(1).expect("No path provided");
-    let path = Path::new(&path);
+    let path = args.positional((inspecting || enqueueing) as usize).expect("No path provided");
+    let path = Path::new(path);
     let name = path.file_name().unwrap().to_str().unwrap();
 
-    let db = match DB::open_default(path) {
-        Ok(db) => { db }
-        Err(e) => { panic!("failed to open database: {:?}", e) }
-    };
+    if let Some(addr) = args.value("events-addr") {
+        if let Err(e) = spawn_websocket(addr) {
+            eprintln!("Failed to start event stream: {}", e);
+        }
+    }
+
+    if let Some(addr) = args.value("metrics-addr") {
+        if let Err(e) = spawn_exporter(addr) {
+            eprintln!("Failed to start metrics exporter: {}", e);
+        }
+    }
+
+    if let Err(e) = audit::read(&path.to_string_lossy()) {
+        panic!("failed to write audit log: {:?}", e)
+    }
+
+    let mut manifest = Manifest::start("poo");
+
+    match optout::init(&args) {
+        Ok(names) => manifest.option("opted_out", names),
+        Err(e) => panic!("failed to read opt-out list: {:?}", e),
+    }
+
+    manifest.input_path(&path.to_string_lossy());
+
+    let range =
+        IdRange {
+            from: args.parse_value("from-id"),
+            to: args.parse_value("to-id"),
+            // --readahead is in KiB
+            readahead: args.parse_value::<usize>("readahead").map(|kib| kib * 1024),
+        };
+
+    let tuning =
+        Tuning {
+            block_cache: args.parse_value("block-cache"),
+            background_jobs: args.parse_value("background-jobs"),
+        };
+
+    manifest.option("from_id", range.from);
+    manifest.option("to_id", range.to);
+
+    let writable = args.flag("writable");
+
+    let db;
+    let snapshot;
+    #[cfg(feature = "sled")]
+    let sled_db;
+    #[cfg(feature = "lmdb")]
+    let lmdb_env;
+
+    let backend = args.value("backend").unwrap_or("rocksdb");
+
+    manifest.option("backend", backend);
+
+    let source: &dyn KvSource =
+        match backend {
+            "rocksdb" => {
+                db = match open_db(path, writable, tuning) {
+                    Ok(db) => { db }
+                    Err(e) => { panic!("failed to open database: {:?}", e) }
+                };
+
+                // everything is read from one snapshot, items written while
+                // the run goes on don't leak in and the cutoff below holds
+                // for the whole corpus
+                snapshot = db.snapshot();
+
+                &snapshot
+            }
+            #[cfg(feature = "sled")]
+            "sled" => {
+                sled_db = match sled::open(path) {
+                    Ok(db) => { db }
+                    Err(e) => { panic!("failed to open database: {:?}", e) }
+                };
+
+                &sled_db
+            }
+            #[cfg(feature = "lmdb")]
+            "lmdb" => {
+                lmdb_env = match LmdbSource::open(path, writable) {
+                    Ok(env) => { env }
+                    Err(e) => { panic!("failed to open database: {:?}", e) }
+                };
+
+                &lmdb_env
+            }
+            other => panic!("unsupported backend {}, sled and lmdb need their features enabled", other),
+        };
+
+    if inspecting {
+        match inspect::inspect(path, source, range) {
+            Ok(stats) => stats.print(),
+            Err(e) => panic!("failed to read database: {:?}", e),
+        }
+
+        return;
+    }
+
+    if enqueueing {
+        let location = args.value("queue").expect("--queue is required");
+
+        let last =
+            match source.last_id(range) {
+                Ok(Some(last)) => last,
+                Ok(None) => panic!("no items to enqueue"),
+                Err(e) => panic!("failed to read database: {:?}", e),
+            };
+
+        let tasks = queue::split(range.from.unwrap_or(0), last, args.parse_value("shards").unwrap_or(16));
+
+        match WorkQueue::open(location).and_then(|mut queue| queue.push(&tasks)) {
+            Ok(()) => println!("Queued {} shards up to id {} in {}", tasks.len(), last, location),
+            Err(e) => panic!("failed to queue shards: {:?}", e),
+        }
+
+        return;
+    }
+
+    match source.last_id(range) {
+        Ok(cutoff) => manifest.option("cutoff_id", cutoff),
+        Err(e) => panic!("failed to read database: {:?}", e),
+    }
+
+    // --aggregate-only promises nothing per author reaches the disk, so it
+    // rules out whatever would persist profiles, item ids or text statistics
+    // along the way, checked before any of it is opened
+    let aggregate_only = args.flag("aggregate-only");
+
+    if aggregate_only {
+        for option in ["merge-db", "profiles-db", "author-index", "text-stats"] {
+            if args.flag(option) {
+                panic!("--aggregate-only can't be combined with --{}", option);
+            }
+        }
+    }
+
+    // --memory-budget is in MiB. Spill runs are profiles on disk, so
+    // --aggregate-only keeps everything in memory instead
+    let mut spiller =
+        args.parse_value::<usize>("memory-budget")
+            .filter(|_| !aggregate_only)
+            .map(|budget| {
+                let dir =
+                    args.value("spill-dir")
+                        .map(PathBuf::from)
+                        .unwrap_or_else(std::env::temp_dir);
+
+                Spiller::new(budget * 1024 * 1024, &dir)
+            });
 
     let mut ti = TextItem::new();
 
@@ -103,68 +716,151 @@ fn main() {
 
     pb.write(format!("Processing {}...", name).colorize("green"));
 
-    ti.ingest(
-        &db.iterator(rocksdb::IteratorMode::Start)
-            .par_bridge()
-            .filter_map(|v| {
-                v
-                    .ok()
-                    .map(|(k, mut v)| {
-                        let mut kbuf = [0u8; 8];
-                        kbuf.copy_from_slice(&k[..8]);
-                        let k = i64::from_be_bytes(kbuf);
-
-                        print!("\r{}", k as usize);
-
-                        simd_json::from_slice(&mut v[..]).ok()
-                    })
-                    .flatten()
+    // aggregating through the merge operator leaves nothing in memory, the
+    // profiles column family is the aggregation
+    let merge_db =
+        args.value("merge-db")
+            .map(|location| open_output_db(location, "merge"));
+
+    // profiles also go into a column family for keyed lookups
+    let profiles_db =
+        args.value("profiles-db")
+            .filter(|_| merge_db.is_none())
+            .map(|location| open_output_db(location, "profiles"));
+
+    // --author-index keeps author → item ids next to the profiles
+    let author_index =
+        Some(merge_db.as_ref().or(profiles_db.as_ref()))
+            .filter(|_| args.flag("author-index"))
+            .map(|db| db.expect("--author-index needs --merge-db or --profiles-db"));
+
+    // --pseudonymize replaces names with salted hashes; the salt is
+    // discarded unless --salt-file says where to keep it
+    let pseudonyms =
+        Some(args.value("salt-file"))
+            .filter(|_| args.flag("pseudonymize"))
+            .map(|salt_file| match salt_file {
+                Some(salt_file) => Pseudonymizer::from_salt_file(Path::new(salt_file)),
+                None => Pseudonymizer::random(),
             })
-            .filter_map(|i: Item|
-                Some((
-                    i.by?.as_bytes().to_vec(),
-                    TextItem::process_alt(&(i.text?)),
-                ))
-            )
-            .fold(
-                || PooMap::new(),
-                |mut acc, (author, freqs)| {
-                    let author_map =
-                        &mut acc
-                            .entry(author.clone())
-                            .or_insert_with(PooMapInner::new);
-
-                    for (word, freq) in freqs.iter() {
-                        author_map
-                            .entry(word.clone())
-                            .or_insert(0)
-                            .add_assign(*freq);
-                    }
+            .map(|pseudonyms| pseudonyms.unwrap_or_else(|e| panic!("failed to set up pseudonymization: {:?}", e)));
 
-                    acc
-                },
-            )
-            .reduce(
-                || PooMap::new(),
-                |mut acc, all_freqs| {
-                    for (author, freqs) in all_freqs.iter() {
-                        let author_map =
-                            &mut acc
-                                .entry(author.clone())
-                                .or_insert_with(PooMapInner::new);
-
-                        for (word, freq) in freqs.iter() {
-                            author_map
-                                .entry(word.clone())
-                                .or_insert(0)
-                                .add_assign(*freq);
+    manifest.option("pseudonymized", pseudonyms.is_some());
+
+    // --scrub removes emails, phone numbers and long digit strings from the
+    // text before tokenizing, --scrub-patterns=<file> adds regexes to those
+    let scrubber =
+        match args.value("scrub-patterns") {
+            Some(location) => {
+                let patterns = std::fs::read_to_string(location).expect("failed to read scrub patterns");
+
+                match Scrubber::with_patterns(&patterns) {
+                    Ok(scrubber) => Some(scrubber),
+                    Err(e) => panic!("invalid scrub pattern: {}", e),
+                }
+            }
+            None => args.flag("scrub").then(Scrubber::default),
+        };
+
+    manifest.option("scrubbed", scrubber.is_some());
+
+    // --text-stats also sums sentence, word, syllable and question counts of
+    // the raw text per author, saved in a .textstats sidecar for readability
+    // scores and behavior
+    let text_stats = args.flag("text-stats").then(DashMap::default);
+
+    manifest.option("text_stats", text_stats.is_some());
+
+    // --reply-depth adds how deep in their threads authors comment. It keeps
+    // the parent of every item in memory, and a range misses the threads
+    // started before it
+    let threads = (text_stats.is_some() && args.flag("reply-depth")).then(Threads::default);
+
+    manifest.option("reply_depth", threads.is_some());
+
+    // --cooccurrence=<file.vocab> counts how often the most used words of an
+    // earlier run's vocabulary appear within --window=<words> of each other,
+    // saved in a .cooc sidecar for PPMI word vectors. --cooccurrence-words
+    // sets how many words, memory grows with its square
+    let cooccurrence =
+        args.value("cooccurrence").map(|location| {
+            let vocabulary =
+                storage::read_file(location)
+                    .and_then(|buf| Vocabulary::read(&buf[..]))
+                    .unwrap_or_else(|e| panic!("failed to read vocabulary {}: {:?}", location, e));
+
+            let words =
+                vocabulary.top(args.parse_value("cooccurrence-words").unwrap_or(10_000))
+                    .into_iter()
+                    .filter(|word| !domains::is_domain(word))
+                    .collect();
+
+            Cooccurrence::new(words, args.parse_value("window").unwrap_or(cooccurrence::DEFAULT_WINDOW))
+        });
+
+    manifest.option("cooccurrence", cooccurrence.as_ref().map(Cooccurrence::window));
+
+    let ingest =
+        Ingest {
+            index: author_index,
+            pseudonyms: pseudonyms.as_ref(),
+            scrubber: scrubber.as_ref(),
+            domains: args.flag("domains"),
+            text_stats: text_stats.as_ref(),
+            threads: threads.as_ref(),
+            cooccurrence: cooccurrence.as_ref(),
+        };
+
+    // --domains also counts the domains comments link to, for affinity
+    // scores in profiles and reports
+    manifest.option("domains", args.flag("domains"));
+
+    let aggregated =
+        match (&merge_db, spiller.as_mut()) {
+            (Some(merge_db), _) => {
+                // a database an earlier run got partway through resumes after
+                // its last merged batch
+                let range =
+                    match rocks::merged_through(merge_db) {
+                        Ok(Some(through)) => {
+                            pb.write(format!("Resuming merge after item {}", through).colorize("green"));
+                            manifest.option("resumed_after", through);
+
+                            IdRange { from: range.from.max(Some(through + 1)), ..range }
                         }
-                    }
+                        Ok(None) => range,
+                        Err(e) => panic!("failed to read merge checkpoint: {:?}", e),
+                    };
+
+                if let Err(e) = merge_in_batches(source, range, ingest, merge_db) {
+                    panic!("failed to merge into database: {:?}", e)
+                }
+
+                SymMap::default()
+            }
+            (None, Some(spiller)) => aggregate_with_budget(source, range, ingest, spiller),
+            (None, None) => {
+                TextItem::aggregate(
+                    source.items(range)
+                        .par_bridge()
+                        .filter_map(|v| v.ok())
+                        .filter_map(|item| ingest.process(item))
+                )
+            }
+        };
+
+    // every item has been seen, parents included
+    if let (Some(threads), Some(text_stats)) = (threads.as_ref(), text_stats.as_ref()) {
+        threads.resolve(text_stats);
+    }
+
+    manifest.stage("aggregate");
+
+    ti.ingest(
+        aggregated,
+        |fb| {
+            EVENTS.publish_feedback(&fb);
 
-                    acc
-                },
-            ),
-        |fb|
             match fb {
                 FnFeedback::Message(msg) => {
                     pb.write(format!("{}", msg).colorize("green"));
@@ -172,30 +868,80 @@ fn main() {
                 FnFeedback::Total(total) => {
                     pb.pb.set_total(total as usize);
                 },
-                FnFeedback::Tick => {
-                    pb.pb.update(1);
+                FnFeedback::Progress(progress) => {
+                    pb.update_to(progress as usize);
                 },
                 _ => {},
-            },
+            }
+        },
     );
 
-    let mut file =
-        File::create(
-            path
-                .clone()
-                .with_file_name(
-                    format!("{}.users.freqs", &name),
-                )
-        ).unwrap();
+    // a local path or an object store URI, defaults to next to the database
+    let out =
+        args.value("out")
+            .map(str::to_string)
+            .unwrap_or_else(|| {
+                path.with_file_name(format!("{}.users.freqs", &name))
+                    .to_string_lossy()
+                    .to_string()
+            });
+
+    manifest.stage("ingest");
+    manifest.ingest_counts();
 
-    let mut encoder = zstd::stream::Encoder::new(&mut file, 10).unwrap();
+    // --aggregate-only keeps corpus and, with --segments, segment totals;
+    // profiles only ever exist in memory and are dropped before saving
+    let aggregates =
+        aggregate_only
+            .then(|| {
+                let segments = args.parse_value::<usize>("segments");
+
+                // --min-tokens and --min-authors as in export, the latter
+                // also the fewest members a published segment may have
+                let thresholds =
+                    Thresholds {
+                        min_tokens: args.parse_value("min-tokens").unwrap_or(0),
+                        min_authors: args.parse_value("min-authors").unwrap_or(aggregate::MIN_SEGMENT_AUTHORS),
+                    };
+
+                pb.write(format!("Aggregating {} authors..", ti.word_freqs.len()).colorize("green"));
+
+                manifest.option("aggregate_only", true);
+                manifest.option("segments", segments);
+                manifest.option("min_tokens", thresholds.min_tokens);
+                manifest.option("min_authors", thresholds.min_authors);
+
+                let (aggregates, withheld) =
+                    aggregate::aggregates(aggregate::resolve(std::mem::take(&mut ti.word_freqs)), segments, thresholds);
+
+                manifest.count("suppressed_authors", withheld.suppressed.authors as u64);
+                manifest.count("suppressed_words", withheld.suppressed.words as u64);
+                manifest.count("folded_segments", withheld.folded_segments as u64);
+                manifest.count("folded_authors", withheld.folded_authors as u64);
+
+                aggregates
+            });
+
+    let mut output = storage::create(&out).unwrap();
+
+    // --encrypt seals the file to the age key set up in the environment,
+    // compressed first as ciphertext doesn't compress
+    let mut sealed =
+        match Sealed::new(&mut output, args.flag("encrypt")) {
+            Ok(sealed) => sealed,
+            Err(e) => panic!("failed to set up encryption: {:?}", e),
+        };
+
+    manifest.option("encrypted", args.flag("encrypt"));
+
+    let mut encoder = zstd::stream::Encoder::new(&mut sealed, 10).unwrap();
 
     pb.pb.set_total(ti.word_freqs.len());
 
-    serialize_with_writer(
-        &ti.word_freqs,
-        &mut encoder,
-        |fb|
+    let save_feedback =
+        |fb: FnFeedback| {
+            EVENTS.publish_feedback(&fb);
+
             match fb {
                 FnFeedback::Message(msg) => {
                     pb.write(format!("{}", msg).colorize("green"));
@@ -207,13 +953,131 @@ fn main() {
                     pb.update_to(progress as usize);
                 },
                 _ => {},
-            },
-    )
-        .map_err(|x|
-            eprintln!("Error serializing: {}", x)
-        );
+            }
+        };
+
+    let mut profile_writer = profiles_db.as_ref().map(ProfileWriter::new);
+    let mut profile_error = None;
+
+    let mut vocabulary = Vocabulary::default();
+
+    let saved =
+        match (&merge_db, spiller.as_mut(), &aggregates) {
+            (Some(merge_db), _, _) => {
+                rocks::write_freqs(merge_db, &mut encoder, |_, freqs| vocabulary.observe(freqs), save_feedback)
+            }
+            (None, Some(spiller), _) if spiller.runs() > 0 => {
+                let on_author = |author: &[u8], freqs: &SymMapInner| {
+                    vocabulary.observe(freqs);
+
+                    if let Some(Err(e)) = profile_writer.as_mut().map(|w| w.put(author, freqs)) {
+                        profile_error.get_or_insert(e);
+                    }
+                };
+
+                spiller
+                    .spill(std::mem::take(&mut ti.word_freqs))
+                    .and_then(|_| spiller.merge_into(&mut encoder, on_author, save_feedback))
+            }
+            (None, _, Some(aggregates)) => {
+                vocabulary = Vocabulary::build(aggregates);
+
+                serialize_with_writer(aggregates, &mut encoder, save_feedback)
+            }
+            _ => {
+                vocabulary = Vocabulary::build(&ti.word_freqs);
+
+                if let Some(writer) = profile_writer.as_mut() {
+                    profile_error =
+                        ti.word_freqs
+                            .iter()
+                            .find_map(|(author, freqs)| writer.put(author, freqs).err());
+                }
+
+                serialize_with_writer(&ti.word_freqs, &mut encoder, save_feedback)
+            }
+        };
+
+    // a truncated file must not look like a finished run to the pipeline
+    if let Err(e) = saved {
+        panic!("failed to serialize {}: {:?}", out, e)
+    }
+
+    if let Some(writer) = profile_writer {
+        match profile_error.map_or_else(|| writer.finish(), Err) {
+            Ok(written) => manifest.count("profiles_written", written),
+            Err(e) => eprintln!("Error writing profiles: {}", e),
+        }
+    }
+
+    let finished =
+        encoder.finish().map(drop)
+            .and_then(|_| sealed.finish().map(drop));
+
+    if let Err(e) = finished.and_then(|_| output.finish()) {
+        panic!("failed to finalize {}: {:?}", out, e)
+    }
+
+    let vocab_out = Vocabulary::sidecar_path(&out);
+
+    // document frequencies of rare words point at their few users, so the
+    // vocabulary is sealed with the corpus
+    let written =
+        storage::create(&vocab_out)
+            .and_then(|mut output| {
+                let mut sealed = Sealed::new(&mut output, args.flag("encrypt"))?;
+
+                vocabulary.write(&mut sealed)?;
+
+                sealed.finish()?;
+                output.finish()
+            });
+
+    if let Err(e) = written {
+        eprintln!("Error writing vocabulary {}: {}", vocab_out, e);
+    }
+
+    // per author like the profiles, so sealed like them
+    if let Some(text_stats) = text_stats {
+        let stats_out = textstats::sidecar_path(&out);
+
+        match textstats::save(&text_stats.into_iter().collect(), &out, args.flag("encrypt")) {
+            Ok(()) => manifest.output(&stats_out),
+            Err(e) => eprintln!("Error writing text statistics {}: {}", stats_out, e),
+        }
+    }
+
+    if let Some(cooccurrence) = cooccurrence {
+        let cooc_out = Cooccurrence::sidecar_path(&out);
+
+        let written =
+            storage::create(&cooc_out)
+                .and_then(|mut output| {
+                    let mut sealed = Sealed::new(&mut output, args.flag("encrypt"))?;
+
+                    cooccurrence.write(&mut sealed)?;
+
+                    sealed.finish()?;
+                    output.finish()
+                });
+
+        match written {
+            Ok(()) => manifest.output(&cooc_out),
+            Err(e) => eprintln!("Error writing co-occurrence counts {}: {}", cooc_out, e),
+        }
+    }
+
+    manifest.stage("save");
+    manifest.count("authors", vocabulary.authors() as u64);
+    manifest.count("words", vocabulary.len() as u64);
+    manifest.output(&out);
+    manifest.output(&vocab_out);
+
+    if let Some(location) = args.value("merge-db").or(args.value("profiles-db")) {
+        manifest.output(location);
+    }
 
-    if let Err(e) = encoder.finish() {
-        eprintln!("Error finalizing file: {}", e);
+    if let Err(e) = manifest.write(&out) {
+        eprintln!("Error writing manifest: {}", e);
     }
 }
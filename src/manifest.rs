@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::hash::Hasher;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use serde_json::Value;
+use twox_hash::XxHash64;
+
+use crate::cache::content_hash;
+use crate::metrics::METRICS;
+use crate::storage::{self, is_remote};
+
+/// An input or output of a run.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileRecord {
+    pub location: String,
+    /// Unknown for objects in remote storage.
+    pub bytes: Option<u64>,
+    /// XxHash64 of the content, of the decompressed content for inputs read
+    /// through `storage::read_file`. Directories aren't hashed.
+    pub xxhash64: Option<String>,
+}
+
+/// Provenance of a run, written as JSON next to what it produced so results
+/// can be reproduced and audited.
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    pub tool: String,
+    pub version: String,
+    pub arguments: Vec<String>,
+    /// Unix seconds.
+    pub started_at: u64,
+    pub duration_secs: f64,
+    pub inputs: Vec<FileRecord>,
+    pub outputs: Vec<FileRecord>,
+    pub options: BTreeMap<String, Value>,
+    pub counts: BTreeMap<String, u64>,
+    /// `(stage, seconds)` in the order they ran.
+    pub stages: Vec<(String, f64)>,
+    #[serde(skip)]
+    start: Instant,
+    #[serde(skip)]
+    stage_start: Instant,
+}
+
+fn hash_file(path: &Path) -> std::io::Result<(u64, u64)> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = XxHash64::with_seed(0);
+    let mut buf = vec![0u8; 1 << 20];
+    let mut bytes = 0u64;
+
+    loop {
+        let read = file.read(&mut buf)?;
+
+        if read == 0 {
+            return Ok((bytes, hasher.finish()));
+        }
+
+        hasher.write(&buf[..read]);
+        bytes += read as u64;
+    }
+}
+
+pub(crate) fn dir_size(path: &Path) -> std::io::Result<u64> {
+    let mut total = 0;
+
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+
+        total += if meta.is_dir() { dir_size(&entry.path())? } else { meta.len() };
+    }
+
+    Ok(total)
+}
+
+/// Size and hash of a file, or total size of a directory.
+fn record(location: &str) -> FileRecord {
+    let path = Path::new(location);
+
+    let (bytes, hash) =
+        if is_remote(location) {
+            (None, None)
+        } else if path.is_dir() {
+            (dir_size(path).ok(), None)
+        } else {
+            hash_file(path)
+                .map(|(bytes, hash)| (Some(bytes), Some(hash)))
+                .unwrap_or((None, None))
+        };
+
+    FileRecord {
+        location: location.to_string(),
+        bytes,
+        xxhash64: hash.map(|h| format!("{:016x}", h)),
+    }
+}
+
+impl Manifest {
+    pub fn start(tool: &str) -> Self {
+        Self {
+            tool: tool.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            arguments: std::env::args().skip(1).collect(),
+            started_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            duration_secs: 0.0,
+            inputs: vec![],
+            outputs: vec![],
+            options: BTreeMap::new(),
+            counts: BTreeMap::new(),
+            stages: vec![],
+            start: Instant::now(),
+            stage_start: Instant::now(),
+        }
+    }
+
+    /// An input already read into memory.
+    pub fn input(&mut self, location: &str, data: &[u8]) {
+        self.inputs.push(FileRecord {
+            location: location.to_string(),
+            bytes: Some(data.len() as u64),
+            xxhash64: Some(format!("{:016x}", content_hash(data))),
+        });
+    }
+
+    /// An input read from disk in place, e.g. a database directory.
+    pub fn input_path(&mut self, location: &str) {
+        self.inputs.push(record(location));
+    }
+
+    /// Call once `location` has been written completely.
+    pub fn output(&mut self, location: &str) {
+        self.outputs.push(record(location));
+    }
+
+    pub fn option(&mut self, name: &str, value: impl Serialize) {
+        self.options.insert(name.to_string(), serde_json::to_value(value).unwrap_or(Value::Null));
+    }
+
+    pub fn count(&mut self, name: &str, count: u64) {
+        self.counts.insert(name.to_string(), count);
+    }
+
+    /// Ends the current stage as `name`, the next one starts now.
+    pub fn stage(&mut self, name: &str) {
+        self.stages.push((name.to_string(), self.stage_start.elapsed().as_secs_f64()));
+        self.stage_start = Instant::now();
+    }
+
+    /// Ingestion counters: items read, skipped and unparseable.
+    pub fn ingest_counts(&mut self) {
+        self.count("items_ingested", METRICS.items_ingested.get());
+        self.count("bytes_ingested", METRICS.bytes_ingested.get());
+        self.count("items_skipped", METRICS.items_skipped.get());
+        self.count("parse_failures", METRICS.parse_failures.get());
+    }
+
+    /// `report.html` is described by `report.html.manifest.json`.
+    pub fn sidecar_path(location: &str) -> String {
+        format!("{}.manifest.json", location.trim_end_matches('/'))
+    }
+
+    /// Writes the manifest next to `location`, the run's main output.
+    pub fn write(mut self, location: &str) -> std::io::Result<()> {
+        self.duration_secs = self.start.elapsed().as_secs_f64();
+
+        let mut output = storage::create(&Self::sidecar_path(location))?;
+
+        serde_json::to_writer_pretty(&mut output, &self)?;
+        output.write_all(b"\n")?;
+        output.finish()
+    }
+}
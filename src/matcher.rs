@@ -0,0 +1,84 @@
+use std::borrow::Cow;
+
+use regex::bytes::Regex;
+use unicode_normalization::UnicodeNormalization;
+
+/// How author names are compared when looking users up.
+///
+/// HN logins are case-insensitive, but the data keeps whatever casing the
+/// account was created with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AuthorFold {
+    /// Byte-for-byte comparison.
+    #[default]
+    Exact,
+    /// Unicode lowercase comparison.
+    CaseInsensitive,
+    /// NFKC normalization followed by lowercasing.
+    Normalized,
+}
+
+impl AuthorFold {
+    #[inline(always)]
+    pub fn fold<'a>(&self, author: &'a [u8]) -> Cow<'a, [u8]> {
+        match self {
+            Self::Exact => Cow::Borrowed(author),
+            Self::CaseInsensitive => {
+                Cow::Owned(
+                    String::from_utf8_lossy(author)
+                        .to_lowercase()
+                        .into_bytes()
+                )
+            }
+            Self::Normalized => {
+                Cow::Owned(
+                    String::from_utf8_lossy(author)
+                        .nfkc()
+                        .collect::<String>()
+                        .to_lowercase()
+                        .into_bytes()
+                )
+            }
+        }
+    }
+}
+
+/// Selects authors by regular expression or shell-style glob.
+pub struct AuthorMatcher {
+    pattern: Regex,
+}
+
+impl AuthorMatcher {
+    pub fn from_regex(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            pattern: Regex::new(pattern)?,
+        })
+    }
+
+    /// Globs match the whole name, `*` matches any run of characters and
+    /// `?` a single one.
+    pub fn from_glob(glob: &str) -> Result<Self, regex::Error> {
+        Self::from_regex(&glob_to_regex(glob))
+    }
+
+    #[inline(always)]
+    pub fn matches(&self, author: &[u8]) -> bool {
+        self.pattern.is_match(author)
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::from("^");
+
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    pattern.push('$');
+
+    pattern
+}
@@ -0,0 +1,177 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+
+#[derive(Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    #[inline(always)]
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    #[inline(always)]
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// upper bounds of the latency buckets, in seconds
+const LATENCY_BUCKETS: [f64; 10] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0];
+
+#[derive(Default)]
+pub struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    pub fn observe(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+
+        // buckets are cumulative
+        for (bucket, le) in self.buckets.iter().zip(LATENCY_BUCKETS) {
+            if secs <= le {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Process wide metrics, rendered in the Prometheus text format.
+#[derive(Default)]
+pub struct Metrics {
+    pub items_ingested: Counter,
+    pub bytes_ingested: Counter,
+    /// Items without an author or text, e.g. stories and deleted comments.
+    pub items_skipped: Counter,
+    pub parse_failures: Counter,
+    /// Request latencies by route.
+    pub query_latency: DashMap<&'static str, Histogram>,
+}
+
+lazy_static! {
+    pub static ref METRICS: Metrics = Metrics::default();
+}
+
+/// Resident set size of this process, where the platform exposes it.
+fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+
+    let kib =
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))?
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse::<u64>()
+            .ok()?;
+
+    Some(kib * 1024)
+}
+
+impl Metrics {
+    pub fn observe(&self, route: &'static str, elapsed: Duration) {
+        self.query_latency
+            .entry(route)
+            .or_default()
+            .observe(elapsed);
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let counters = [
+            ("poo_items_ingested_total", "Items aggregated into the corpus.", &self.items_ingested),
+            ("poo_bytes_ingested_total", "Bytes of raw items read.", &self.bytes_ingested),
+            ("poo_items_skipped_total", "Items without an author or text.", &self.items_skipped),
+            ("poo_parse_failures_total", "Items that failed to parse.", &self.parse_failures),
+        ];
+
+        for (name, help, counter) in counters {
+            writeln!(out, "# HELP {} {}", name, help).ok();
+            writeln!(out, "# TYPE {} counter", name).ok();
+            writeln!(out, "{} {}", name, counter.get()).ok();
+        }
+
+        if let Some(bytes) = resident_memory() {
+            writeln!(out, "# HELP process_resident_memory_bytes Resident memory size in bytes.").ok();
+            writeln!(out, "# TYPE process_resident_memory_bytes gauge").ok();
+            writeln!(out, "process_resident_memory_bytes {}", bytes).ok();
+        }
+
+        writeln!(out, "# HELP poo_query_duration_seconds Request latencies by route.").ok();
+        writeln!(out, "# TYPE poo_query_duration_seconds histogram").ok();
+
+        let mut routes =
+            self.query_latency
+                .iter()
+                .map(|entry| *entry.key())
+                .collect::<Vec<_>>();
+
+        routes.sort_unstable();
+
+        for route in routes {
+            let Some(histogram) = self.query_latency.get(route) else {
+                continue;
+            };
+
+            for (bucket, le) in histogram.buckets.iter().zip(LATENCY_BUCKETS) {
+                writeln!(
+                    out,
+                    "poo_query_duration_seconds_bucket{{route=\"{}\",le=\"{}\"}} {}",
+                    route,
+                    le,
+                    bucket.load(Ordering::Relaxed),
+                ).ok();
+            }
+
+            let count = histogram.count.load(Ordering::Relaxed);
+
+            writeln!(out, "poo_query_duration_seconds_bucket{{route=\"{}\",le=\"+Inf\"}} {}", route, count).ok();
+            writeln!(
+                out,
+                "poo_query_duration_seconds_sum{{route=\"{}\"}} {}",
+                route,
+                histogram.sum_micros.load(Ordering::Relaxed) as f64 / 1e6,
+            ).ok();
+            writeln!(out, "poo_query_duration_seconds_count{{route=\"{}\"}} {}", route, count).ok();
+        }
+
+        out
+    }
+}
+
+/// Serves `METRICS` on `addr` from a background thread, for processes
+/// without an HTTP server of their own.
+#[cfg(feature = "native")]
+pub fn spawn_exporter(addr: &str) -> std::io::Result<()> {
+    let server =
+        tiny_http::Server::http(addr)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let response = tiny_http::Response::from_string(METRICS.render());
+
+            if let Err(e) = request.respond(response) {
+                eprintln!("Failed to respond: {}", e);
+            }
+        }
+    });
+
+    Ok(())
+}
@@ -1,48 +1,273 @@
 use std::collections::BTreeMap;
 use std::fs::{DirEntry, File};
-use std::io::{Read, Write};
-use std::path::Path;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU16, Ordering};
 
 use kdam::{BarExt, Column, RichProgress, tqdm};
 use kdam::term::Colorizer;
 use rayon::iter::IntoParallelRefIterator;
 use rayon::iter::ParallelIterator;
+use serde::{Deserialize, Serialize};
 
-use serializer::deserialize;
+use poo::args::Args;
+use poo::audit;
+use poo::cache::content_hash;
+use poo::crypto::{is_encrypted_file, Sealed};
+use poo::manifest::Manifest;
+use poo::optout;
+use poo::serializer::{damage_totals, deserialize, deserialize_salvaging, format_version, read_file, serialize_indexed_with_writer, FnFeedback, serialize_with_writer};
+use poo::text::text_item::{PooMap, PooMapInner};
 
-use crate::serializer::{FnFeedback, serialize_with_writer};
+/// How far the migration of each input got, kept in `migrate.state.json`
+/// next to the inputs so an interrupted run continues where it left off.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct MigrateState {
+    files: BTreeMap<String, FileProgress>,
+}
 
-mod text;
-mod serializer;
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct FileProgress {
+    /// Content hash of the input the shards were cut from, and how.
+    input: String,
+    /// Content hash of every shard written and verified, by index.
+    shards: BTreeMap<usize, String>,
+    /// Number of shards, once all of them are done.
+    total: Option<usize>,
+}
 
-fn run_for_file(path: &Path, pb: &mut RichProgress) {
-    let name = path.file_name().unwrap().to_str().unwrap().to_string();
+impl MigrateState {
+    fn path(dir: &Path) -> PathBuf {
+        dir.join("migrate.state.json")
+    }
 
-    println!("name: {}", name);
+    fn load(dir: &Path) -> Self {
+        std::fs::read(Self::path(dir))
+            .ok()
+            .and_then(|buf| serde_json::from_slice(&buf).ok())
+            .unwrap_or_default()
+    }
 
-    let mut file = File::open(path).unwrap();
+    fn save(&self, dir: &Path) -> std::io::Result<()> {
+        // written aside and moved over, an interruption never leaves it torn
+        let tmp = Self::path(dir).with_extension("json.tmp");
 
-    pb.write(format!("Reading: loading {}..", &name).colorize("green"));
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(tmp, Self::path(dir))
+    }
+}
+
+fn hash_file(path: &Path) -> std::io::Result<String> {
+    Ok(format!("{:016x}", content_hash(&std::fs::read(path)?)))
+}
+
+/// Whether the shard at `out` is still the one recorded as `hash`.
+fn is_intact(out: &Path, hash: Option<&String>) -> bool {
+    match (hash, hash_file(out)) {
+        (Some(recorded), Ok(current)) => *recorded == current,
+        _ => false,
+    }
+}
 
-    let use_zstd = false;
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Partition {
+    /// Ranges of authors in name order.
+    Contiguous,
+    /// Authors spread by a hash of their name, evening out shard sizes when
+    /// names cluster.
+    AuthorHash,
+}
+
+/// How an input is cut into shards.
+#[derive(Debug, Clone, Copy)]
+struct Sharding {
+    /// Number of shards.
+    shards: Option<usize>,
+    /// Target bytes of input per shard, used when `shards` isn't set.
+    shard_size: Option<u64>,
+    partition: Partition,
+}
 
-    let buf =
-        if use_zstd {
-            match zstd::decode_all(&mut file) {
-                Ok(buf) => buf,
-                Err(e) => {
-                    pb.write(format!("Error: {}", e).colorize("red"));
-                    return;
+impl Sharding {
+    const DEFAULT_SHARDS: usize = 20;
+
+    fn from_args(args: &Args) -> Self {
+        let partition =
+            match args.value("partition") {
+                None | Some("contiguous") => Partition::Contiguous,
+                Some("hash") | Some("author-hash") => Partition::AuthorHash,
+                Some(other) => panic!("unknown partitioning {}, expected contiguous or hash", other),
+            };
+
+        Self {
+            shards: args.parse_value("shards"),
+            shard_size: args.parse_value("shard-size"),
+            partition,
+        }
+    }
+
+    /// Number of shards for `authors` authors in `bytes` of input, at least
+    /// one and never more than there are authors.
+    fn count(&self, authors: usize, bytes: usize) -> usize {
+        let count =
+            match (self.shards, self.shard_size) {
+                (Some(shards), _) => shards,
+                (None, Some(size)) => (bytes as u64).div_ceil(size.max(1)) as usize,
+                (None, None) => Self::DEFAULT_SHARDS,
+            };
+
+        count.clamp(1, authors.max(1))
+    }
+
+    /// Recorded with the progress, resuming with other options starts over.
+    fn key(&self) -> String {
+        format!("{:?}/{:?}/{:?}", self.shards, self.shard_size, self.partition)
+    }
+
+    /// Cuts authors sorted by name into shards, returned with their index.
+    fn split(&self, authors: Vec<(Box<[u8]>, PooMapInner)>, bytes: usize) -> Vec<(PooMap, usize)> {
+        let count = self.count(authors.len(), bytes);
+        let mut shards = (0..count).map(|i| (PooMap::default(), i)).collect::<Vec<_>>();
+
+        match self.partition {
+            Partition::Contiguous => {
+                let per_shard = authors.len().div_ceil(count);
+
+                for (i, author) in authors.into_iter().enumerate() {
+                    shards[i / per_shard].0.insert(author.0, author.1);
                 }
             }
-        } else {
-            let mut buf = Vec::new();
+            Partition::AuthorHash => {
+                for (author, freqs) in authors {
+                    let shard = (content_hash(&author) % count as u64) as usize;
+
+                    shards[shard].0.insert(author, freqs);
+                }
+            }
+        }
+
+        shards
+    }
+}
+
+/// Whether `name` is one of the shards `input` is migrated to.
+fn is_shard_of(name: &str, input: &str) -> bool {
+    name.strip_prefix(input)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .and_then(|rest| rest.strip_suffix(".users.freqs"))
+        .map_or(false, |index| !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Writes `poo` zstd-compressed to `out` in format `version`, sealed to the
+/// age key if `encrypt`.
+fn write_freqs(poo: &PooMap, out: &Path, version: u32, encrypt: bool) -> std::io::Result<()> {
+    audit::write(&out.to_string_lossy())?;
+
+    let mut file = File::create(out)?;
+    let mut sealed = Sealed::new(&mut file, encrypt)?;
+
+    let mut encoder = zstd::stream::Encoder::new(&mut sealed, 10)?;
+
+    match version {
+        1 => serialize_with_writer(poo, &mut encoder, |_| {})?,
+        _ => serialize_indexed_with_writer(poo, &mut encoder, |_| {})?,
+    }
+
+    encoder.finish()?;
+    sealed.finish()?;
+
+    file.sync_all()
+}
+
+/// Reads a written shard back, checking it holds `authors` authors, and
+/// returns its content hash.
+fn verify_shard(out: &Path, authors: usize) -> std::io::Result<String> {
+    let read = deserialize(&read_file(out)?, |_| {}).len();
+
+    if read != authors {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} holds {} authors, expected {}", out.display(), read, authors),
+        ));
+    }
+
+    hash_file(out)
+}
+
+fn progress_bar(position: u16, name: &str) -> RichProgress {
+    RichProgress::new(
+        tqdm!(
+            total = 0,
+            unit_scale = true,
+            unit_divisor = 1000,
+            position = position
+        ),
+        vec![
+            Column::Spinner(
+                "⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏"
+                    .chars()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<String>>(),
+                80.0,
+                1.0,
+            ),
+            Column::text(&format!("[bold blue]{}", name)),
+            Column::Bar,
+            Column::Percentage(1),
+            Column::text("•"),
+            Column::CountTotal,
+            Column::text("•"),
+            Column::Rate,
+            Column::text("•"),
+            Column::RemainingTime,
+        ],
+    )
+}
 
-            file.read_to_end(&mut buf).unwrap();
+/// Cuts `path` into shards next to it, encrypted if it was or `encrypt` is
+/// set. Returns the shards done, kept from earlier runs or written now.
+fn run_for_file(path: &Path, pb: &mut RichProgress, sharding: &Sharding, state: &Mutex<MigrateState>, state_dir: &Path, encrypt: bool) -> Vec<PathBuf> {
+    let name = path.file_name().unwrap().to_str().unwrap().to_string();
+
+    pb.write(format!("Reading: loading {}..", &name).colorize("green"));
 
-            buf.to_vec()
+    let loaded =
+        read_file(path)
+            .and_then(|buf| Ok((buf, encrypt || is_encrypted_file(path)?)));
+
+    let (buf, encrypt) =
+        match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                pb.write(format!("Error: {}", e).colorize("red"));
+                return Vec::new();
+            }
         };
 
+    let shard_path = |i: usize| path.with_file_name(format!("{}.{}.users.freqs", &name, i));
+
+    // progress on a different input or with other shards no longer counts
+    let input = format!("{:016x}:{}", content_hash(&buf), sharding.key());
+
+    let progress = {
+        let mut state = state.lock().unwrap();
+        let progress = state.files.entry(name.clone()).or_default();
+
+        if progress.input != input {
+            *progress = FileProgress { input, ..Default::default() };
+        }
+
+        progress.clone()
+    };
+
+    if let Some(total) = progress.total {
+        if (0..total).all(|i| is_intact(&shard_path(i), progress.shards.get(&i))) {
+            pb.write(format!("Skipping: {} was already migrated", &name).colorize("green"));
+            return (0..total).map(shard_path).collect();
+        }
+    }
+
     let poo =
         deserialize(
             &buf,
@@ -61,65 +286,230 @@ fn run_for_file(path: &Path, pb: &mut RichProgress) {
                 },
         );
 
-    let pooitems =
+    let mut pooitems =
         poo.iter()
             .map(|(k, v)|
                 (k.clone(), v.clone())
             )
             .collect::<Vec<_>>();
 
-    let pooitems =
+    // sorted so the shards are the same from run to run and finished ones
+    // can be kept
+    pooitems.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    let pooitems = sharding.split(pooitems, buf.len());
+
+    let total = pooitems.len();
+
+    // errors are written through the bar once all shards are done, printing
+    // them directly would garble the other bars
+    let errors =
         pooitems
-            .chunks(poo.len() / 20)
-            .enumerate()
-            .map(|(i, chunk)| (BTreeMap::from_iter(chunk.iter().cloned()), i))
-            .collect::<Vec<(_, _)>>();
-
-    pooitems
-        .par_iter()
-        .for_each(|(poo, i)| {
-            let mut file =
-                File::create(
-                    path
-                        .clone()
-                        .with_file_name(
-                            format!("{}.{}.users.freqs", &name, i),
-                        )
-                ).unwrap();
-
-            let mut encoder = zstd::stream::Encoder::new(&mut file, 10).unwrap();
-
-            serialize_with_writer(
-                poo,
-                &mut encoder,
-                |fb|
-                    match fb {
-                        FnFeedback::Message(msg) => {
-                            println!("Message: {}", format!("{}", msg).colorize("green"));
-                        },
-                        FnFeedback::Total(total) => {
-                            println!("Total: {}", total as usize);
-                        },
-                        FnFeedback::Progress(progress) => {
-                            println!("Progress: {}", progress as usize);
-                        },
-                        _ => {},
+            .par_iter()
+            .filter(|(_, i)| !is_intact(&shard_path(*i), progress.shards.get(i)))
+            .filter_map(|(poo, i)| {
+                let out = shard_path(*i);
+
+                let written =
+                    write_freqs(poo, &out, 1, encrypt)
+                        .and_then(|_| verify_shard(&out, poo.len()));
+
+                match written {
+                    Ok(hash) => {
+                        let mut state = state.lock().unwrap();
+
+                        if let Some(progress) = state.files.get_mut(&name) {
+                            progress.shards.insert(*i, hash);
+                        }
+
+                        state.save(state_dir)
+                            .err()
+                            .map(|e| format!("Error saving progress: {}", e))
+                    }
+                    Err(e) => Some(format!("Error writing shard {}: {}", out.display(), e)),
+                }
+            })
+            .collect::<Vec<_>>();
+
+    for error in errors {
+        pb.write(error.colorize("red"));
+    }
+
+    let mut state = state.lock().unwrap();
+
+    let done =
+        match state.files.get_mut(&name) {
+            Some(progress) => {
+                if (0..total).all(|i| progress.shards.contains_key(&i)) {
+                    progress.total = Some(total);
+                }
+
+                progress.shards.keys().map(|i| shard_path(*i)).collect()
+            }
+            None => Vec::new(),
+        };
+
+    if let Err(e) = state.save(state_dir) {
+        pb.write(format!("Error saving progress: {}", e).colorize("red"));
+    }
+
+    done
+}
+
+/// Rewrites `path` in format `version`, next to it as `<stem>.v<version>.freqs`
+/// or, with `replace`, over it. The rewrite is read back and must hold
+/// exactly what the original did before anything is replaced.
+///
+/// Damaged files are refused unless `recover` is set, then everything
+/// intact is kept and what was lost is listed in `<out>.recovery.json`.
+/// The rewrite is encrypted if the original was or `encrypt` is set.
+/// Returns the files written.
+fn convert_file(path: &Path, pb: &mut RichProgress, version: u32, replace: bool, recover: bool, encrypt: bool) -> std::io::Result<Vec<PathBuf>> {
+    let name = path.file_name().unwrap().to_string_lossy().to_string();
+
+    let buf = read_file(path)?;
+    let encrypt = encrypt || is_encrypted_file(path)?;
+
+    if format_version(&buf) == Some(version) && !recover {
+        pb.write(format!("Skipping: {} is already version {}", &name, version).colorize("green"));
+        return Ok(Vec::new());
+    }
+
+    let (poo, damage) =
+        deserialize_salvaging(
+            &buf,
+            |fb|
+                match fb {
+                    FnFeedback::Total(total) => {
+                        pb.pb.set_total(total as usize);
+                    },
+                    FnFeedback::Progress(progress) => {
+                        pb.update_to(progress as usize);
                     },
+                    _ => {},
+                },
+        );
+
+    let (lost_authors, lost_words) = damage_totals(&damage);
+
+    if !damage.is_empty() && !recover {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "{} has {} damaged parts ({} authors, {} words), convert with --recover to keep the rest",
+                &name,
+                damage.len(),
+                lost_authors,
+                lost_words,
+            ),
+        ));
+    }
+
+    let out =
+        if replace {
+            path.with_file_name(format!("{}.tmp", &name))
+        } else {
+            let stem = path.file_stem().unwrap().to_string_lossy();
+
+            path.with_file_name(format!("{}.v{}.freqs", stem, version))
+        };
+
+    write_freqs(&poo, &out, version, encrypt)?;
+
+    let written = deserialize(&read_file(&out)?, |_| {});
+
+    if written != poo {
+        std::fs::remove_file(&out)?;
+
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} doesn't read back as it was written, left unchanged", &name),
+        ));
+    }
+
+    if replace {
+        std::fs::rename(&out, path)?;
+    }
+
+    let converted = if replace { path } else { out.as_path() };
+    let mut written = vec![converted.to_path_buf()];
+
+    if !damage.is_empty() {
+        let report = converted.with_file_name(format!("{}.recovery.json", converted.file_name().unwrap().to_string_lossy()));
+
+        std::fs::write(&report, serde_json::to_vec_pretty(&damage)?)?;
+
+        pb.write(
+            format!(
+                "Recovered: {} authors of {}, skipped {} damaged authors and {} words, see {}",
+                poo.len(),
+                &name,
+                lost_authors,
+                lost_words,
+                report.display(),
             )
-                .map_err(|x|
-                    eprintln!("Error serializing: {}", x)
-                );
+                .colorize("yellow"),
+        );
 
-            if let Err(e) = encoder.finish() {
-                eprintln!("Error finalizing file: {}", e);
-            }
-        })
+        written.push(report);
+    }
+
+    pb.write(format!("Converted: {} to version {}", &name, version).colorize("green"));
+
+    Ok(written)
 }
 
 fn main() {
+    let args = Args::from_env();
+
+    // opted-out authors aren't copied into shards or rewrites
+    if let Err(e) = optout::init(&args) {
+        eprintln!("Failed to read opt-out list: {}", e);
+        std::process::exit(1);
+    }
+
     // find folder located at first argument
-    let path = std::env::args().nth(1).expect("No path provided");
-    let path = std::path::Path::new(&path);
+    let path = args.positional(0).expect("No path provided");
+    let path = std::path::Path::new(path);
+
+    // --restart forgets the progress of earlier runs
+    let state =
+        if args.flag("restart") {
+            MigrateState::default()
+        } else {
+            MigrateState::load(path)
+        };
+
+    let state = Mutex::new(state);
+
+    // --shards=<n> or --shard-size=<bytes> of input per shard, 20 shards by
+    // default; --partition=contiguous|hash
+    let sharding = Sharding::from_args(&args);
+
+    // --to-version=<1|2> converts files instead of sharding them, --replace
+    // writes over the originals once the conversion checks out
+    // --recover salvages damaged files, into version 2 unless told otherwise
+    let conversion =
+        args.parse_value::<u32>("to-version")
+            .or_else(|| args.flag("recover").then_some(2));
+
+    if let Some(version) = conversion {
+        if !(1..=2).contains(&version) {
+            panic!("unknown format version {}, expected 1 or 2", version);
+        }
+    }
+
+    // --encrypt seals what's written even when the input wasn't encrypted,
+    // encrypted inputs always give encrypted outputs
+    let encrypt = args.flag("encrypt");
+
+    let mut manifest = Manifest::start("migrate");
+
+    manifest.option("to_version", conversion);
+    manifest.option("replace", args.flag("replace"));
+    manifest.option("recover", args.flag("recover"));
+    manifest.option("sharding", sharding.key());
+    manifest.option("encrypted", encrypt);
 
     // find all files in folder
     let files = std::fs::read_dir(path).expect("Could not read directory");
@@ -138,39 +528,94 @@ fn main() {
 
     files.sort_by(|a, b| a.path().file_name().cmp(&b.path().file_name()));
 
-    let mut pb = RichProgress::new(
-        tqdm!(
-            total = 0,
-            unit_scale = true,
-            unit_divisor = 1000
-        ),
-        vec![
-            Column::Spinner(
-                "⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏"
-                    .chars()
-                    .map(|x| x.to_string())
-                    .collect::<Vec<String>>(),
-                80.0,
-                1.0,
-            ),
-            Column::text("[bold blue]?"),
-            Column::Bar,
-            Column::Percentage(1),
-            Column::text("•"),
-            Column::CountTotal,
-            Column::text("•"),
-            Column::Rate,
-            Column::text("•"),
-            Column::RemainingTime,
-        ],
-    );
-
-    files
-        .iter()
-        .for_each(|f| {
-            run_for_file(
-                &f.path(),
-                &mut pb,
-            );
+    // shards of an earlier run sit next to their input, they aren't inputs
+    let names =
+        files.iter()
+            .map(|f| f.file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+
+    files.retain(|f| {
+        let name = f.file_name().to_string_lossy().to_string();
+
+        !names.iter().any(|input| is_shard_of(&name, input))
+    });
+
+    // inputs are migrated concurrently, each fully loaded, so --jobs bounds
+    // memory as much as it does cores
+    let jobs =
+        args.parse_value::<usize>("jobs")
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+            .clamp(1, files.len().max(1));
+
+    let pool =
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build()
+            .expect("failed to start worker threads");
+
+    // the overall bar on the first row, a row below it per running job
+    let overall = Mutex::new(tqdm!(total = files.len(), desc = "total", position = 0));
+    let rows = Mutex::new((1..=jobs as u16).rev().collect::<Vec<_>>());
+
+    // a worker waiting on the nested work of its file can pick up another
+    // file, so more can run than there are jobs; those get rows further down
+    let extra_rows = AtomicU16::new(0);
+
+    for f in files.iter() {
+        manifest.input_path(&f.path().to_string_lossy());
+    }
+
+    let written =
+        pool.install(|| {
+            files
+                .par_iter()
+                .map(|f| {
+                    let row =
+                        rows.lock().unwrap()
+                            .pop()
+                            .unwrap_or_else(|| jobs as u16 + 1 + extra_rows.fetch_add(1, Ordering::Relaxed));
+
+                    let mut pb = progress_bar(row, &f.file_name().to_string_lossy());
+
+                    let written =
+                        match conversion {
+                            Some(version) => {
+                                convert_file(&f.path(), &mut pb, version, args.flag("replace"), args.flag("recover"), encrypt)
+                                    .unwrap_or_else(|e| {
+                                        pb.write(format!("Error converting {}: {}", f.file_name().to_string_lossy(), e).colorize("red"));
+                                        Vec::new()
+                                    })
+                            }
+                            None => {
+                                run_for_file(
+                                    &f.path(),
+                                    &mut pb,
+                                    &sharding,
+                                    &state,
+                                    path,
+                                    encrypt,
+                                )
+                            }
+                        };
+
+                    rows.lock().unwrap().push(row);
+                    overall.lock().unwrap().update(1);
+
+                    written
+                })
+                .collect::<Vec<_>>()
         });
+
+    eprint!("{}", "\n".repeat(jobs + 1 + extra_rows.into_inner() as usize));
+
+    manifest.stage("migrate");
+    manifest.count("files", files.len() as u64);
+
+    for out in written.iter().flatten() {
+        manifest.output(&out.to_string_lossy());
+    }
+
+    if let Err(e) = manifest.write(&path.join("migrate").to_string_lossy()) {
+        eprintln!("Failed to write manifest: {}", e);
+    }
 }
@@ -0,0 +1,54 @@
+use std::collections::HashSet;
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+
+use crate::args::Args;
+
+lazy_static! {
+    /// Authors who asked not to be profiled. Ingestion drops their items and
+    /// every reader of ragegun files skips their blocks, so one list covers
+    /// ingest, analysis and exports alike.
+    static ref OPT_OUT: RwLock<HashSet<Box<[u8]>>> = RwLock::new(HashSet::new());
+}
+
+/// Names in a list file, one per line; blank lines and `#` comments are
+/// ignored.
+pub fn parse_list(text: &str) -> impl Iterator<Item = &[u8]> {
+    text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(str::as_bytes)
+}
+
+pub fn add<'a>(names: impl IntoIterator<Item = &'a [u8]>) {
+    let mut opt_out = OPT_OUT.write().unwrap();
+
+    opt_out.extend(names.into_iter().map(Box::from));
+}
+
+/// Loads the list given by `--opt-out=<file>`, or else by the `POO_OPT_OUT`
+/// environment variable. Returns the number of names loaded.
+pub fn init(args: &Args) -> std::io::Result<usize> {
+    let location =
+        args.value("opt-out")
+            .map(str::to_string)
+            .or_else(|| std::env::var("POO_OPT_OUT").ok());
+
+    let text =
+        match location {
+            Some(location) => std::fs::read_to_string(location)?,
+            None => return Ok(0),
+        };
+
+    add(parse_list(&text));
+
+    Ok(OPT_OUT.read().unwrap().len())
+}
+
+#[inline(always)]
+pub fn is_opted_out(author: &[u8]) -> bool {
+    let opt_out = OPT_OUT.read().unwrap();
+
+    !opt_out.is_empty() && opt_out.contains(author)
+}
@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::{content_hash, hash_path};
+
+/// A `poo pipeline` config file, in JSON.
+///
+/// ```json
+/// {
+///   "input": "data/hn",
+///   "work_dir": "data/pipeline",
+///   "ingest": ["--scrub"],
+///   "segments": 8,
+///   "segment": ["--depth=2"],
+///   "report": { "format": "html", "args": ["-n", "30"] }
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineConfig {
+    /// Database directory or dump to ingest.
+    pub input: String,
+    /// Where artifacts and the pipeline state are kept.
+    #[serde(default = "default_work_dir")]
+    pub work_dir: String,
+    /// Extra arguments to `poo` when ingesting.
+    #[serde(default)]
+    pub ingest: Vec<String>,
+    /// Number of segments, no segmentation stage without it.
+    #[serde(default)]
+    pub segments: Option<usize>,
+    /// Extra arguments to `query segment`.
+    #[serde(default)]
+    pub segment: Vec<String>,
+    /// No report stage without it.
+    #[serde(default)]
+    pub report: Option<ReportStage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportStage {
+    #[serde(default = "default_format")]
+    pub format: String,
+    /// Extra arguments to `query report`.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+fn default_work_dir() -> String {
+    "pipeline".to_string()
+}
+
+fn default_format() -> String {
+    "html".to_string()
+}
+
+impl PipelineConfig {
+    pub fn read(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+
+        serde_json::from_str(&text).map_err(|e| e.to_string())
+    }
+}
+
+/// What a stage was last run with and what it produced.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct StageState {
+    /// Hash of the stage's command and the content of its inputs.
+    key: String,
+    /// Content hash of every output.
+    outputs: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct PipelineState {
+    stages: BTreeMap<String, StageState>,
+}
+
+/// One step: a `poo` or `query` invocation reading `inputs` and writing
+/// `outputs`.
+struct Stage {
+    name: &'static str,
+    program: &'static str,
+    args: Vec<String>,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+}
+
+/// Runs the stages of a config in order, skipping those whose command and
+/// inputs are unchanged since their last run and whose outputs are still
+/// as they left them.
+pub struct Pipeline {
+    config: PipelineConfig,
+    /// Directory holding the `poo` and `query` binaries.
+    bin_dir: PathBuf,
+    state: PipelineState,
+}
+
+impl Pipeline {
+    pub fn new(config: PipelineConfig) -> std::io::Result<Self> {
+        let exe = std::env::current_exe()?;
+        let bin_dir = exe.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        std::fs::create_dir_all(&config.work_dir)?;
+
+        let state =
+            std::fs::read(Path::new(&config.work_dir).join("pipeline.json"))
+                .ok()
+                .and_then(|buf| serde_json::from_slice(&buf).ok())
+                .unwrap_or_default();
+
+        Ok(Self { config, bin_dir, state })
+    }
+
+    fn artifact(&self, name: &str) -> String {
+        Path::new(&self.config.work_dir).join(name).to_string_lossy().to_string()
+    }
+
+    /// ingest → tokenize → aggregate → serialize are one `poo` run,
+    /// vectorize → segment one `query segment` run, then the report.
+    fn stages(&self) -> Vec<Stage> {
+        let corpus = self.artifact("corpus.freqs");
+        let mut stages = Vec::new();
+
+        stages.push(Stage {
+            name: "ingest",
+            program: "poo",
+            args: [self.config.input.clone(), format!("--out={}", corpus)]
+                .into_iter()
+                .chain(self.config.ingest.iter().cloned())
+                .collect(),
+            inputs: vec![self.config.input.clone()],
+            outputs: vec![corpus.clone()],
+        });
+
+        if let Some(k) = self.config.segments {
+            let segments = self.artifact("corpus.freqs.segments");
+
+            stages.push(Stage {
+                name: "segment",
+                program: "query",
+                args: ["segment".to_string(), corpus.clone(), format!("--segments={}", k), format!("--out={}", segments)]
+                    .into_iter()
+                    .chain(self.config.segment.iter().cloned())
+                    .collect(),
+                inputs: vec![corpus.clone()],
+                outputs: vec![segments],
+            });
+        }
+
+        if let Some(report) = &self.config.report {
+            let extension =
+                match report.format.as_str() {
+                    "markdown" => "md",
+                    format => format,
+                };
+
+            let out = self.artifact(&format!("report.{}", extension));
+
+            // reports on the segment stage's assignments rather than
+            // clustering a second time
+            let segments =
+                self.config.segments
+                    .map(|_| self.artifact("corpus.freqs.segments"));
+
+            stages.push(Stage {
+                name: "report",
+                program: "query",
+                args: ["report".to_string(), corpus.clone(), format!("--out={}", out), format!("--format={}", report.format)]
+                    .into_iter()
+                    .chain(segments.iter().map(|segments| format!("--assignments={}", segments)))
+                    .chain(report.args.iter().cloned())
+                    .collect(),
+                inputs: std::iter::once(corpus).chain(segments).collect(),
+                outputs: vec![out],
+            });
+        }
+
+        stages
+    }
+
+    /// Hash of everything a stage's outputs depend on.
+    fn stage_key(stage: &Stage) -> std::io::Result<String> {
+        let mut key = format!("{}\0{}\0", stage.name, stage.program).into_bytes();
+
+        for arg in stage.args.iter() {
+            key.extend_from_slice(arg.as_bytes());
+            key.push(0);
+        }
+
+        for input in stage.inputs.iter() {
+            key.extend_from_slice(hash_path(input)?.as_bytes());
+            key.push(0);
+        }
+
+        Ok(format!("{:016x}", content_hash(&key)))
+    }
+
+    fn is_fresh(&self, stage: &Stage, key: &str) -> bool {
+        let state =
+            match self.state.stages.get(stage.name) {
+                Some(state) if state.key == key => state,
+                _ => return false,
+            };
+
+        stage.outputs.iter().all(|output| {
+            match (state.outputs.get(output), hash_path(output)) {
+                (Some(recorded), Ok(current)) => *recorded == current,
+                _ => false,
+            }
+        })
+    }
+
+    fn save_state(&self) -> std::io::Result<()> {
+        let buf = serde_json::to_vec_pretty(&self.state)?;
+
+        std::fs::write(Path::new(&self.config.work_dir).join("pipeline.json"), buf)
+    }
+
+    /// Runs every stage that's out of date, or all of them with `force`.
+    /// Returns the names of the stages that ran.
+    pub fn run(&mut self, force: bool) -> Result<Vec<&'static str>, String> {
+        let mut ran = Vec::new();
+
+        for stage in self.stages() {
+            // computed before running, as inputs are outputs of earlier stages
+            let key = Self::stage_key(&stage).map_err(|e| format!("{}: failed to hash inputs: {}", stage.name, e))?;
+
+            if !force && self.is_fresh(&stage, &key) {
+                println!("{}: up to date", stage.name);
+
+                continue;
+            }
+
+            println!("{}: running {} {}", stage.name, stage.program, stage.args.join(" "));
+
+            let status =
+                Command::new(self.bin_dir.join(stage.program))
+                    .args(&stage.args)
+                    .status()
+                    .map_err(|e| format!("{}: failed to start {}: {}", stage.name, stage.program, e))?;
+
+            if !status.success() {
+                // a failed stage never counts as done, even if it left outputs
+                self.state.stages.remove(stage.name);
+                self.save_state().map_err(|e| e.to_string())?;
+
+                return Err(format!("{}: {} exited with {}", stage.name, stage.program, status));
+            }
+
+            let outputs =
+                stage.outputs
+                    .iter()
+                    .map(|output| hash_path(output).map(|hash| (output.clone(), hash)))
+                    .collect::<std::io::Result<_>>()
+                    .map_err(|e| format!("{}: failed to hash outputs: {}", stage.name, e))?;
+
+            self.state.stages.insert(stage.name.to_string(), StageState { key, outputs });
+            self.save_state().map_err(|e| e.to_string())?;
+
+            ran.push(stage.name);
+        }
+
+        Ok(ran)
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::BTreeMap;
+
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::analysis::{document_freqs, global_freqs, tf_idf, top_n, total_tokens, word_freqs};
+use crate::categories;
+use crate::domains::{self, domain_freqs};
+use crate::lexicon::{CategoryAffinity, TECH};
+use crate::segment::Segmentation;
+use crate::sentiment::{score_text, Sentiment, Tone};
+use crate::toxicity::{Incivility, Toxicity};
+use crate::text::text_item::{PooMap, PooMapInner};
+use crate::textstats::{Behavior, Readability, TextStats, TextStatsMap};
+
+/// Summary of one author, the unit exported to other tools.
+#[derive(Debug, Clone, Serialize)]
+pub struct UserProfile {
+    pub author: String,
+    pub tokens: u64,
+    pub vocabulary: usize,
+    /// Distinct words per token.
+    pub type_token_ratio: f64,
+    pub top_terms: Vec<(String, u64)>,
+    /// Highest tf-idf words, what sets the author apart.
+    pub distinctive_terms: Vec<(String, f64)>,
+    /// Domains linked to more than the corpus would suggest, with their
+    /// log-odds. Only for corpora ingested with `--domains`.
+    pub domains: Vec<(String, f64)>,
+    /// Languages, frameworks and databases mentioned more than the corpus
+    /// does, per category.
+    pub tech_stack: Vec<CategoryAffinity>,
+    pub sentiment: Sentiment,
+    pub toxicity: Toxicity,
+    /// Share of the tokens in each word category, see `categories`.
+    pub categories: Vec<(String, f64)>,
+    /// Only for corpora ingested with `--text-stats`, and authors with
+    /// enough text.
+    pub readability: Option<Readability>,
+    /// Asker, answerer or lecturer, with the same caveats as `readability`.
+    pub behavior: Option<Behavior>,
+    pub segment: Option<u32>,
+}
+
+/// What profiles are measured against, computed once per corpus.
+#[derive(Debug, Clone)]
+pub struct CorpusStats {
+    pub authors: usize,
+    pub tokens: u64,
+    /// Number of authors using each word.
+    pub doc_freqs: PooMapInner,
+    /// Links per domain.
+    pub domains: PooMapInner,
+    /// Uses of each `TECH` term.
+    pub tech: Vec<u64>,
+}
+
+impl CorpusStats {
+    pub fn new(poo: &PooMap) -> Self {
+        let global = global_freqs(poo);
+
+        Self {
+            authors: poo.len(),
+            tokens: total_tokens(&global),
+            doc_freqs: document_freqs(poo),
+            domains: domains::corpus_domains(poo),
+            tech: TECH.counts(&global),
+        }
+    }
+}
+
+fn lossy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).to_string()
+}
+
+impl UserProfile {
+    pub fn build(
+        author: &[u8],
+        freqs: &PooMapInner,
+        corpus: &CorpusStats,
+        text_stats: Option<&TextStats>,
+        segmentation: Option<&Segmentation>,
+        terms: usize,
+    ) -> Self {
+        let tokens = total_tokens(freqs);
+
+        Self {
+            author: lossy(author),
+            tokens,
+            vocabulary: freqs.len(),
+            type_token_ratio: freqs.len() as f64 / tokens.max(1) as f64,
+            top_terms:
+                top_n(word_freqs(freqs), terms)
+                    .into_iter()
+                    .map(|(word, freq)| (lossy(word), freq))
+                    .collect(),
+            distinctive_terms:
+                top_n(tf_idf(freqs, &corpus.doc_freqs, corpus.authors), terms)
+                    .into_iter()
+                    .map(|(word, score)| (lossy(word), score))
+                    .collect(),
+            domains: domains::affinity(&domain_freqs(freqs), &corpus.domains, terms),
+            tech_stack: TECH.affinity(&TECH.counts(freqs), tokens, &corpus.tech, corpus.tokens, terms),
+            sentiment: Tone::of(freqs).sentiment(),
+            toxicity: Incivility::of(freqs).toxicity(),
+            categories: categories::names().into_iter().zip(categories::vector(freqs)).collect(),
+            readability: text_stats.and_then(TextStats::readability),
+            behavior: text_stats.and_then(TextStats::behavior),
+            segment:
+                segmentation.map(|s| {
+                    s.segment_of(author)
+                        .unwrap_or_else(|| s.assign(freqs))
+                        .0
+                }),
+        }
+    }
+}
+
+/// Profiles of every author with at least `min_tokens` tokens, sorted by
+/// author. `text_stats` adds readability and behavior, see `textstats::load`.
+pub fn profiles(
+    poo: &PooMap,
+    segmentation: Option<&Segmentation>,
+    terms: usize,
+    min_tokens: u64,
+    text_stats: Option<&TextStatsMap>,
+) -> Vec<UserProfile> {
+    let corpus = CorpusStats::new(poo);
+
+    let mut profiles =
+        poo.par_iter()
+            .filter(|(_, freqs)| total_tokens(freqs) >= min_tokens)
+            .map(|(author, freqs)| {
+                let stats = text_stats.and_then(|text_stats| text_stats.get(author));
+
+                UserProfile::build(author, freqs, &corpus, stats, segmentation, terms)
+            })
+            .collect::<Vec<_>>();
+
+    profiles.par_sort_unstable_by(|a, b| a.author.cmp(&b.author));
+    profiles
+}
+
+/// Style measures that only need the word counts.
+#[derive(Debug, Clone, Serialize)]
+pub struct Stylometry {
+    /// Share of the vocabulary used exactly once.
+    pub hapax_ratio: f64,
+    /// Share of the vocabulary used exactly twice.
+    pub dis_legomena_ratio: f64,
+    /// Yule's K, vocabulary richness that doesn't drift with text length.
+    pub yules_k: f64,
+    /// In characters, weighted by use.
+    pub mean_word_length: f64,
+}
+
+impl Stylometry {
+    pub fn build(freqs: &PooMapInner) -> Self {
+        let tokens = total_tokens(freqs).max(1) as f64;
+        let vocabulary = freqs.len().max(1) as f64;
+
+        let used = |n: u64| freqs.values().filter(|f| **f == n).count() as f64;
+
+        let squares = freqs.values().map(|f| (*f as f64).powi(2)).sum::<f64>();
+
+        let chars =
+            freqs.iter()
+                .map(|(word, f)| String::from_utf8_lossy(word).chars().count() as f64 * *f as f64)
+                .sum::<f64>();
+
+        Self {
+            hapax_ratio: used(1) / vocabulary,
+            dis_legomena_ratio: used(2) / vocabulary,
+            yules_k: 1e4 * (squares - tokens) / (tokens * tokens),
+            mean_word_length: chars / tokens,
+        }
+    }
+}
+
+/// When an author posts, from item timestamps (UTC).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TimeProfile {
+    pub items: u64,
+    pub first: Option<i64>,
+    pub last: Option<i64>,
+    pub hours: [u64; 24],
+    /// Monday first.
+    pub weekdays: [u64; 7],
+    /// Sentiment of the items with text, by `YYYY-MM`.
+    pub months: BTreeMap<String, MonthlySentiment>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MonthlySentiment {
+    pub items: u64,
+    /// Mean compound score of the items, -1 to 1.
+    pub compound: f64,
+}
+
+/// `YYYY-MM` of a day counted from the epoch, in the proleptic Gregorian
+/// calendar.
+fn month_of(days: i64) -> String {
+    // Howard Hinnant's civil_from_days, with eras of 400 years starting
+    // on March 1st
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!("{:04}-{:02}", year, month)
+}
+
+impl TimeProfile {
+    pub fn add(&mut self, time: i64) {
+        let days = time.div_euclid(86_400);
+
+        self.items += 1;
+        self.first = Some(self.first.map_or(time, |t| t.min(time)));
+        self.last = Some(self.last.map_or(time, |t| t.max(time)));
+        self.hours[(time.rem_euclid(86_400) / 3600) as usize] += 1;
+        // the epoch was a Thursday
+        self.weekdays[(days + 3).rem_euclid(7) as usize] += 1;
+    }
+
+    /// Counts the sentiment of an item's `text` towards its month.
+    pub fn add_text(&mut self, time: i64, text: &str) {
+        let month = self.months.entry(month_of(time.div_euclid(86_400))).or_default();
+
+        month.items += 1;
+        month.compound += (score_text(text) - month.compound) / month.items as f64;
+    }
+}
+
+/// Everything known about a single author, for individual user views.
+#[derive(Debug, Clone, Serialize)]
+pub struct UserDetail {
+    #[serde(flatten)]
+    pub profile: UserProfile,
+    pub stylometry: Stylometry,
+    /// Only known when the source database was scanned, `.freqs` files
+    /// carry no timestamps.
+    pub time_profile: Option<TimeProfile>,
+    /// Every word used, most frequent first.
+    pub frequencies: Vec<(String, u64)>,
+}
+
+impl UserDetail {
+    pub fn build(
+        author: &[u8],
+        poo: &PooMap,
+        segmentation: Option<&Segmentation>,
+        terms: usize,
+        time_profile: Option<TimeProfile>,
+        text_stats: Option<&TextStats>,
+    ) -> Option<Self> {
+        let freqs = poo.get(author)?;
+
+        Some(Self {
+            profile: UserProfile::build(author, freqs, &CorpusStats::new(poo), text_stats, segmentation, terms),
+            stylometry: Stylometry::build(freqs),
+            time_profile,
+            frequencies:
+                top_n(word_freqs(freqs), freqs.len())
+                    .into_iter()
+                    .map(|(word, freq)| (lossy(word), freq))
+                    .collect(),
+        })
+    }
+}
@@ -0,0 +1,69 @@
+use std::io;
+use std::path::Path;
+
+// bytes of the keyed hash kept, 96 bits make collisions between authors
+// practically impossible
+const PSEUDONYM_LEN: usize = 12;
+
+/// Replaces author names with salted hashes, so frequency files can be
+/// shared without exposing usernames. The same salt maps an author to the
+/// same pseudonym, across runs too if the salt is kept.
+pub struct Pseudonymizer {
+    salt: [u8; 32],
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn parse_hex(text: &str) -> Option<[u8; 32]> {
+    let text = text.trim();
+    let mut salt = [0u8; 32];
+
+    if text.len() != 64 {
+        return None;
+    }
+
+    for (i, byte) in salt.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(text.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+
+    Some(salt)
+}
+
+impl Pseudonymizer {
+    /// A fresh random salt. Unless it's saved, pseudonyms can't be linked
+    /// back to names by anyone, including whoever ran the ingest.
+    pub fn random() -> io::Result<Self> {
+        let mut salt = [0u8; 32];
+
+        getrandom::getrandom(&mut salt).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+        Ok(Self { salt })
+    }
+
+    /// Reads the salt from `path`, creating it with a random one if it
+    /// doesn't exist. Keep it apart from the outputs: with the salt, names
+    /// can be confirmed by hashing candidates.
+    pub fn from_salt_file(path: &Path) -> io::Result<Self> {
+        if path.exists() {
+            let salt =
+                parse_hex(&std::fs::read_to_string(path)?)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "salt file must hold 64 hex digits"))?;
+
+            return Ok(Self { salt });
+        }
+
+        let pseudonymizer = Self::random()?;
+
+        std::fs::write(path, hex(&pseudonymizer.salt) + "\n")?;
+
+        Ok(pseudonymizer)
+    }
+
+    pub fn pseudonym(&self, author: &[u8]) -> Box<[u8]> {
+        let hash = blake3::keyed_hash(&self.salt, author);
+
+        hex(&hash.as_bytes()[..PSEUDONYM_LEN]).into_bytes().into_boxed_slice()
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use pyo3::exceptions::{PyIOError, PyKeyError};
+use pyo3::prelude::*;
+
+use crate::analysis::{cosine_similarity, document_freqs, most_similar, tf_idf, top_n, word_freqs};
+use crate::args::Args;
+use crate::optout;
+use crate::segment::{segment, SegmentOptions, Segmentation};
+use crate::serializer::{deserialize, extract_user as extract, read_file};
+use crate::text::text_item::{PooMap, PooMapInner};
+
+fn lossy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).to_string()
+}
+
+fn to_dict(freqs: &PooMapInner) -> HashMap<String, u64> {
+    freqs
+        .iter()
+        .map(|(word, freq)| (lossy(word), *freq))
+        .collect()
+}
+
+fn to_pairs<S: Copy>(scores: &[(&[u8], S)]) -> Vec<(String, S)> {
+    scores
+        .iter()
+        .map(|(word, score)| (lossy(word), *score))
+        .collect()
+}
+
+/// Reads a file after (re)loading the `POO_OPT_OUT` list, so opted-out
+/// authors are skipped here as in every other reader.
+fn read(path: &str) -> PyResult<Vec<u8>> {
+    optout::init(&Args::default()).map_err(|e| PyIOError::new_err(format!("failed to read opt-out list: {}", e)))?;
+
+    read_file(Path::new(path)).map_err(|e| PyIOError::new_err(e.to_string()))
+}
+
+/// A loaded .freqs file.
+#[pyclass(name = "Corpus")]
+pub struct PyCorpus {
+    poo: PooMap,
+    // built on first use
+    doc_freqs: Option<PooMapInner>,
+}
+
+impl PyCorpus {
+    fn profile(&self, user: &str) -> PyResult<&PooMapInner> {
+        self.poo
+            .get(user.as_bytes())
+            .ok_or_else(|| PyKeyError::new_err(format!("user {} not found", user)))
+    }
+}
+
+#[pymethods]
+impl PyCorpus {
+    #[new]
+    fn new(path: &str) -> PyResult<Self> {
+        Ok(Self {
+            poo: deserialize(&read(path)?, |_| {}),
+            doc_freqs: None,
+        })
+    }
+
+    fn __len__(&self) -> usize {
+        self.poo.len()
+    }
+
+    fn __contains__(&self, user: &str) -> bool {
+        self.poo.contains_key(user.as_bytes())
+    }
+
+    fn authors(&self) -> Vec<String> {
+        self.poo.keys().map(|author| lossy(author)).collect()
+    }
+
+    /// Word frequencies of `user` as a dict.
+    fn freqs(&self, user: &str) -> PyResult<HashMap<String, u64>> {
+        self.profile(user).map(to_dict)
+    }
+
+    #[args(n = "50")]
+    fn top_words(&self, user: &str, n: usize) -> PyResult<Vec<(String, u64)>> {
+        let freqs = self.profile(user)?;
+
+        Ok(to_pairs(&top_n(word_freqs(freqs), n)))
+    }
+
+    #[args(n = "50")]
+    fn tf_idf(&mut self, user: &str, n: usize) -> PyResult<Vec<(String, f64)>> {
+        if self.doc_freqs.is_none() {
+            self.doc_freqs = Some(document_freqs(&self.poo));
+        }
+
+        let freqs = self.profile(user)?;
+        let doc_freqs = self.doc_freqs.as_ref().unwrap();
+
+        Ok(to_pairs(&top_n(tf_idf(freqs, doc_freqs, self.poo.len()), n)))
+    }
+
+    /// Cosine similarity of the vocabularies of two users.
+    fn similarity(&self, a: &str, b: &str) -> PyResult<f64> {
+        Ok(cosine_similarity(self.profile(a)?, self.profile(b)?))
+    }
+
+    /// Users with the most similar vocabulary to `user`.
+    #[args(n = "20")]
+    fn similar(&self, py: Python, user: &str, n: usize) -> PyResult<Vec<(String, f64)>> {
+        let freqs = self.profile(user)?;
+
+        let similar =
+            py.allow_threads(|| most_similar(&self.poo, freqs, n + 1))
+                .into_iter()
+                .filter(|(other, _)| *other != user.as_bytes())
+                .take(n)
+                .collect::<Vec<_>>();
+
+        Ok(to_pairs(&similar))
+    }
+
+    /// Clusters the users into `k` segments by their tf-idf vectors.
+    #[args(k = "16", vocabulary = "4096", min_tokens = "200", iterations = "30", seed = "1513")]
+    fn segment(
+        &self,
+        py: Python,
+        k: usize,
+        vocabulary: usize,
+        min_tokens: u64,
+        iterations: usize,
+        seed: u64,
+    ) -> PySegmentation {
+        let options =
+            SegmentOptions {
+                segments: k,
+                vocabulary,
+                min_tokens,
+                iterations,
+                seed,
+                ..Default::default()
+            };
+
+        PySegmentation(py.allow_threads(|| segment(&self.poo, &options)))
+    }
+}
+
+/// Result of `Corpus.segment`.
+#[pyclass(name = "Segmentation")]
+pub struct PySegmentation(Segmentation);
+
+#[pymethods]
+impl PySegmentation {
+    /// `{author: (segment, similarity to its centroid)}`
+    fn assignments(&self) -> HashMap<String, (u32, f32)> {
+        self.0
+            .assignments
+            .iter()
+            .map(|(author, segment, similarity)| (lossy(author), (*segment, *similarity)))
+            .collect()
+    }
+
+    fn segment_of(&self, user: &str) -> Option<(u32, f32)> {
+        self.0.segment_of(user.as_bytes())
+    }
+
+    fn sizes(&self) -> Vec<usize> {
+        self.0.sizes()
+    }
+
+    #[args(n = "10")]
+    fn top_terms(&self, segment: u32, n: usize) -> Vec<(String, f32)> {
+        to_pairs(&self.0.top_terms(segment, n))
+    }
+}
+
+#[pyfunction]
+fn load(path: &str) -> PyResult<PyCorpus> {
+    PyCorpus::new(path)
+}
+
+/// Word frequencies of a single user, without loading the whole file.
+#[pyfunction]
+fn extract_user(path: &str, user: &str) -> PyResult<Option<HashMap<String, u64>>> {
+    Ok(extract(&read(path)?, user, |_| {}).map(|freqs| to_dict(&freqs)))
+}
+
+#[pymodule]
+fn hn_user_segmenter(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyCorpus>()?;
+    m.add_class::<PySegmentation>()?;
+    m.add_function(wrap_pyfunction!(load, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_user, m)?)?;
+
+    Ok(())
+}
@@ -0,0 +1,255 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::rng::Rng;
+use crate::segment::{dot, kmeans, nearest, Segmentation, SparseVec};
+use crate::text::text_item::PooMap;
+
+#[derive(Debug, Clone)]
+pub struct QualityOptions {
+    /// Authors sampled for the silhouette, which is quadratic in them.
+    pub sample: usize,
+    /// Re-clusterings of random subsamples the stability is averaged over.
+    pub subsamples: usize,
+    /// Share of authors kept in each subsample.
+    pub fraction: f64,
+    pub iterations: usize,
+    pub seed: u64,
+}
+
+impl Default for QualityOptions {
+    fn default() -> Self {
+        Self {
+            sample: 2000,
+            subsamples: 3,
+            fraction: 0.8,
+            iterations: 30,
+            seed: 0x9a1,
+        }
+    }
+}
+
+/// How well separated and reproducible a segmentation is, to choose the
+/// number of segments on evidence rather than by eye.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterQuality {
+    pub segments: usize,
+    /// Mean silhouette on cosine distance, -1 to 1, higher is better.
+    pub silhouette: f64,
+    /// Mean worst-case scatter to separation ratio, lower is better.
+    pub davies_bouldin: f64,
+    pub min_size: usize,
+    pub median_size: usize,
+    pub max_size: usize,
+    /// Standard deviation of the sizes over their mean.
+    pub size_cv: f64,
+    /// Mean adjusted Rand index between the segmentation and re-clusterings
+    /// of subsamples, 1 when the segments come out the same every time.
+    pub stability: f64,
+}
+
+/// Dot product of two sparse vectors sorted by dimension.
+fn sparse_dot(a: &SparseVec, b: &SparseVec) -> f32 {
+    let (mut i, mut j, mut sum) = (0, 0, 0f32);
+
+    while i < a.len() && j < b.len() {
+        match a[i].0.cmp(&b[j].0) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                sum += a[i].1 * b[j].1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+
+    sum
+}
+
+/// `n` distinct indices below `len`, in random order.
+fn sample(rng: &mut Rng, len: usize, n: usize) -> Vec<usize> {
+    let mut indices = (0..len).collect::<Vec<_>>();
+
+    // partial Fisher-Yates, only the first n need shuffling
+    for i in 0..n.min(len) {
+        let j = i + rng.below((len - i) as u64) as usize;
+
+        indices.swap(i, j);
+    }
+
+    indices.truncate(n);
+    indices
+}
+
+fn silhouette(vectors: &[SparseVec], labels: &[u32], k: usize) -> f64 {
+    let scores =
+        (0..vectors.len())
+            .into_par_iter()
+            .filter_map(|i| {
+                let mut sums = vec![0f64; k];
+                let mut counts = vec![0usize; k];
+
+                for (j, v) in vectors.iter().enumerate() {
+                    if i != j {
+                        sums[labels[j] as usize] += 1.0 - sparse_dot(&vectors[i], v) as f64;
+                        counts[labels[j] as usize] += 1;
+                    }
+                }
+
+                let own = labels[i] as usize;
+
+                // singletons have no defined silhouette
+                if counts[own] == 0 {
+                    return None;
+                }
+
+                let a = sums[own] / counts[own] as f64;
+
+                let b =
+                    (0..k)
+                        .filter(|c| *c != own && counts[*c] > 0)
+                        .map(|c| sums[c] / counts[c] as f64)
+                        .fold(f64::INFINITY, f64::min);
+
+                if !b.is_finite() {
+                    return None;
+                }
+
+                Some((b - a) / a.max(b).max(f64::EPSILON))
+            })
+            .collect::<Vec<_>>();
+
+    scores.iter().sum::<f64>() / scores.len().max(1) as f64
+}
+
+fn davies_bouldin(vectors: &[SparseVec], labels: &[u32], centroids: &[Vec<f32>]) -> f64 {
+    let k = centroids.len();
+
+    let mut scatter = vec![0f64; k];
+    let mut counts = vec![0usize; k];
+
+    for (v, label) in vectors.iter().zip(labels.iter()) {
+        scatter[*label as usize] += 1.0 - dot(v, &centroids[*label as usize]) as f64;
+        counts[*label as usize] += 1;
+    }
+
+    let used = (0..k).filter(|i| counts[*i] > 0).collect::<Vec<_>>();
+
+    for i in used.iter() {
+        scatter[*i] /= counts[*i] as f64;
+    }
+
+    let worst =
+        used.iter()
+            .map(|i| {
+                used.iter()
+                    .filter(|j| *j != i)
+                    .map(|j| {
+                        let separation =
+                            1.0 - centroids[*i].iter().zip(centroids[*j].iter()).map(|(a, b)| a * b).sum::<f32>() as f64;
+
+                        (scatter[*i] + scatter[*j]) / separation.max(f64::EPSILON)
+                    })
+                    .fold(0.0, f64::max)
+            })
+            .collect::<Vec<_>>();
+
+    worst.iter().sum::<f64>() / worst.len().max(1) as f64
+}
+
+fn choose2(n: u64) -> f64 {
+    (n * n.saturating_sub(1)) as f64 / 2.0
+}
+
+/// Adjusted Rand index of two labelings of the same items.
+pub fn adjusted_rand(a: &[u32], b: &[u32], k: usize) -> f64 {
+    let mut table = vec![vec![0u64; k]; k];
+
+    for (x, y) in a.iter().zip(b.iter()) {
+        table[*x as usize][*y as usize] += 1;
+    }
+
+    let rows = table.iter().map(|r| choose2(r.iter().sum())).sum::<f64>();
+    let cols = (0..k).map(|j| choose2(table.iter().map(|r| r[j]).sum())).sum::<f64>();
+    let cells = table.iter().flatten().map(|n| choose2(*n)).sum::<f64>();
+
+    let expected = rows * cols / choose2(a.len() as u64).max(1.0);
+    let max = (rows + cols) / 2.0;
+
+    if max == expected {
+        return 1.0;
+    }
+
+    (cells - expected) / (max - expected)
+}
+
+impl ClusterQuality {
+    pub fn measure(poo: &PooMap, segmentation: &Segmentation, options: &QualityOptions) -> Option<Self> {
+        let k = segmentation.centroids.len();
+
+        if k < 2 || segmentation.assignments.len() < k {
+            return None;
+        }
+
+        let vectors =
+            segmentation.assignments
+                .par_iter()
+                .map(|(author, _, _)| segmentation.vectorizer.vectorize(&poo[author]))
+                .collect::<Vec<_>>();
+
+        let labels = segmentation.assignments.iter().map(|(_, s, _)| *s).collect::<Vec<_>>();
+
+        let mut rng = Rng::new(options.seed);
+
+        let picked = sample(&mut rng, vectors.len(), options.sample);
+
+        let silhouette =
+            silhouette(
+                &picked.iter().map(|i| vectors[*i].clone()).collect::<Vec<_>>(),
+                &picked.iter().map(|i| labels[*i]).collect::<Vec<_>>(),
+                k,
+            );
+
+        let stability =
+            (0..options.subsamples)
+                .map(|run| {
+                    let kept = sample(&mut rng, vectors.len(), (vectors.len() as f64 * options.fraction) as usize);
+                    let subset = kept.iter().map(|i| vectors[*i].clone()).collect::<Vec<_>>();
+
+                    let centroids =
+                        kmeans(
+                            &subset,
+                            segmentation.vectorizer.dims(),
+                            k,
+                            options.iterations,
+                            options.seed.wrapping_add(run as u64 + 1),
+                            |_, _, _| {},
+                        );
+
+                    let relabeled = subset.par_iter().map(|v| nearest(&centroids, v).0).collect::<Vec<_>>();
+                    let original = kept.iter().map(|i| labels[*i]).collect::<Vec<_>>();
+
+                    adjusted_rand(&original, &relabeled, k)
+                })
+                .sum::<f64>() / options.subsamples.max(1) as f64;
+
+        let mut sizes = segmentation.sizes();
+
+        sizes.sort_unstable();
+
+        let mean = sizes.iter().sum::<usize>() as f64 / k as f64;
+        let variance = sizes.iter().map(|s| (*s as f64 - mean).powi(2)).sum::<f64>() / k as f64;
+
+        Some(Self {
+            segments: k,
+            silhouette,
+            davies_bouldin: davies_bouldin(&vectors, &labels, &segmentation.centroids),
+            min_size: sizes[0],
+            median_size: sizes[k / 2],
+            max_size: sizes[k - 1],
+            size_cv: variance.sqrt() / mean.max(f64::EPSILON),
+            stability,
+        })
+    }
+}
@@ -0,0 +1,1956 @@
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rayon::prelude::*;
+
+use poo::aggregate;
+use poo::analysis::{document_freqs, merge_freqs, global_freqs, log_odds, most_similar, tf_idf, top_n, total_tokens, word_freqs};
+use poo::anonymity::RareWords;
+use poo::args::Args;
+use poo::audit;
+use poo::cache::{content_hash, ResultCache};
+use poo::categories;
+use poo::compare::{self, ComparisonData};
+use poo::cooccurrence::{self, Cooccurrence, Ppmi};
+use poo::crypto::Sealed;
+use poo::diff::CorpusDiff;
+use poo::events::{spawn_websocket, Event, EVENTS};
+use poo::index::InverseIndex;
+use poo::manifest::Manifest;
+use poo::optout;
+use poo::queue::WorkQueue;
+use poo::matcher::AuthorFold;
+use poo::profile::profiles;
+use poo::report::{render_html, render_markdown, ReportData, ReportOptions};
+use poo::rocks;
+use poo::segment::{parse_labeled_users, parse_seeds, propagate_labels, segment_with, Assignments, SegmentOptions, SizeLimits, Unsegmented};
+use poo::sentiment::Tone;
+use poo::serializer::{deserialize, extract_users_with, read_assignments, remove_authors, serialize_with_writer, write_assignments};
+use poo::server::{Reload, State};
+use poo::site;
+use poo::stability::{Sankey, Stability};
+use poo::storage::{self, is_remote, read_file};
+use poo::text::text_item::{PooMap, PooMapInner};
+use poo::textstats::{self, TextStatsMap};
+use poo::toxicity::{self, Incivility};
+use poo::vocabulary::Vocabulary;
+use poo::wordcloud::{render_svg, CloudOptions};
+use poo::zipf;
+
+const USAGE: &str = "usage: (files may be local paths or s3:// / gs:// URIs with --features s3)
+  commands that load a whole corpus take --exclude-toxic=<share> to leave out that share of the most hostile authors,
+  scored against --toxicity-lexicon=<file> (`word,weight` lines) or the built-in list
+  query top-words <file> [--user=<name> | --global] [-n <count>] [--tfidf] [--ignore-case | --normalize]
+  query word <file> <term> [-n <count>] [--min-tokens=<count>] [--rebuild-index]
+  query users <file> [--sort=tokens|vocab|name] [--min-tokens=<count>] [--min-vocab=<count>] [-n <count>]
+  query diff-users <file> <a> <b> [-n <count>] [--prior=<weight>] [--ignore-case | --normalize]
+  query diff-corpora <file1> <file2> [-n <count>]
+  query repl <file>
+  query lookup <profiles-db> <user> [-n <count>]   (a database written with `poo --profiles-db`)
+  query sql <file>... \"<query>\"   (tables: freqs, plus one per file named after it)
+  query serve <file> [--addr=<host:port>] [--workers=<count>] [--segments=<k> [--seeds=<file>]] [--cache=<entries> [--redis=<url>]] [--events-addr=<host:port>] [--reload-every=<minutes> [--reload-hook=<command>]]
+  query grpc <file> [--addr=<host:port>] [--segments=<k> [--seeds=<file>]] [--events-addr=<host:port>]   (service definition in proto/poo.proto)
+  query report <file> [--out=<file>] [--format=html|markdown|json] [--previous=<report.json>] [--assignments=<segments-file> | --segments=<k> [--seeds=<file>] [--depth=<levels> [--branching=<k>]] [--no-quality]] [-n <count>]
+  query wordcloud <file> [--out=<dir>] [--segments=<k> [--seeds=<file>]] [-n <words>]
+  query zipf <file> [<user>...] [--out=<file.svg>] [--outliers=<count>] [--min-tokens=<count>]
+  query compare <file1> <file2> [--out=<file>] [--format=html|markdown|json] [--segments=<k> [--seeds=<file>]] [-n <count>]   (segments are fit on the first corpus)
+  query site <file> [--out=<dir>] [--segments=<k> [--seeds=<file>] [--depth=<levels> [--branching=<k>]]] [--min-tokens=<count>] [--terms=<count>] [--redact-rare[=<authors>]]
+  query segment <file> (--segments=<k> [--seeds=<file>] [--depth=<levels> [--branching=<k>]] [--min-size=<authors>] [--max-size=<authors> | --balanced] | --labels=<file>) [--out=<file>] [--terms=<count>] [--min-tokens=<count>] [--min-similarity=<cosine>]   (writes <file>.segments without --out; --labels lists `user<TAB>label` lines)
+  query assignments <segments-file> [--segment=<id> | --unsegmented]
+  query segment-freqs <file> <segments-file> [--out=<file>]   (a ragegun file of each segment's summed counts, keyed \"(segment <id>)\")
+  query stability <segments-file> <segments-file>... [--sankey=<file.json>]   (files of consecutive runs, oldest first)
+  query assign <segments-file> <file> [--out=<file>] [--min-tokens=<count>] [--min-similarity=<cosine>]   (keeps the segment ids of <segments-file>, writes <file>.segments without --out)
+  query merge <file>... [--out=<file>] | query merge --queue=<queue> [--out=<file>]   (sums the profiles of shards written by `poo work`, and their .textstats and .cooc sidecars)
+  query remove-users <file> [<name>...] [--list=<file>] [--out=<file>]   (rewrites <file> in place without --out)
+  query flight <file> [--addr=<host:port>] [--vocabulary=<size>]   (tickets: vectors, vocabulary)
+  query sentiment <file> [--out=<file>] [--min-tokens=<count>] [--labels]   (--labels writes tone terciles as `user<TAB>label` for `query segment --labels`)
+  query categories <file> [--categories=<file.dic>] [--out=<file>] [--min-tokens=<count>] [--standardize]   (word category shares per author as TSV, LIWC-style dictionaries)
+  query neighbors <file> <word> [-n <count>]   (words with the most similar PPMI vectors, for corpora ingested with `poo --cooccurrence`)
+  query semantic <file> <user> [<other>] [-n <count>] [--min-tokens=<count>]   (users whose vocabulary is closest in meaning, or how close two users' is)";
+
+const REPL_HELP: &str = "commands:
+  user <name>              stats and top words of a user
+  top [<name>] [n]         top words of a user, or of the whole corpus
+  tfidf <name> [n]         top words of a user by tf-idf
+  similar <name> [n]       users with the most similar vocabulary
+  word <term> [n]          users using a term the most
+  help                     this text
+  quit";
+
+fn author_fold(args: &Args) -> AuthorFold {
+    if args.flag("normalize") {
+        AuthorFold::Normalized
+    } else if args.flag("ignore-case") {
+        AuthorFold::CaseInsensitive
+    } else {
+        AuthorFold::Exact
+    }
+}
+
+/// Options for `k` segments, with the themes of `--seeds`, the tree shape
+/// of `--depth` and `--branching`, the outlier threshold of
+/// `--min-similarity` and the size limits of `--min-size`, `--max-size` and
+/// `--balanced` if given.
+fn segment_options(args: &Args, k: usize) -> SegmentOptions {
+    let seeds =
+        match args.value("seeds") {
+            Some(path) => {
+                std::fs::read_to_string(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|text| parse_seeds(&text))
+                    .unwrap_or_else(|e| {
+                        eprintln!("Failed to read seeds from {}: {}", path, e);
+                        std::process::exit(1);
+                    })
+            }
+            None => Vec::new(),
+        };
+
+    let defaults = SegmentOptions::default();
+
+    SegmentOptions {
+        segments: k,
+        seeds,
+        depth: args.parse_value("depth").unwrap_or(defaults.depth),
+        branching: args.parse_value("branching").unwrap_or(defaults.branching),
+        min_similarity: args.parse_value("min-similarity").unwrap_or(defaults.min_similarity),
+        sizes: SizeLimits {
+            min: args.parse_value("min-size").unwrap_or(defaults.sizes.min),
+            max: args.parse_value("max-size").or(defaults.sizes.max),
+        },
+        balanced: args.flag("balanced"),
+        ..defaults
+    }
+}
+
+fn print_scores<S: std::fmt::Display>(scores: &[(&[u8], S)]) {
+    for (rank, (word, score)) in scores.iter().enumerate() {
+        println!("{:>5}  {:<32} {}", rank + 1, String::from_utf8_lossy(word), score);
+    }
+}
+
+/// Document frequencies of the corpus at `path`, from its vocabulary
+/// sidecar when there is one.
+fn load_doc_freqs(path: &str, poo: &PooMap) -> PooMapInner {
+    let sidecar =
+        read_file(&Vocabulary::sidecar_path(path))
+            .and_then(|buf| Vocabulary::read(&buf[..]));
+
+    match sidecar {
+        Ok(vocabulary) if vocabulary.authors() == poo.len() => vocabulary.doc_freqs(),
+        _ => document_freqs(poo),
+    }
+}
+
+fn top_words(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+    let n = args.parse_value::<usize>("n").unwrap_or(50);
+
+    let buf = read_file(path).expect("failed to read file");
+
+    match args.value("user") {
+        // TF-IDF needs document frequencies, so it has to load everything
+        Some(user) if args.flag("tfidf") => {
+            let poo = deserialize(&buf, |_| {});
+
+            let (author, freqs) =
+                poo.iter()
+                    .find(|(author, _)| {
+                        author_fold(args).fold(author) == author_fold(args).fold(user.as_bytes())
+                    })
+                    .unwrap_or_else(|| panic!("user {} not found", user));
+
+            println!("{} (tf-idf)", String::from_utf8_lossy(author));
+
+            let doc_freqs = load_doc_freqs(path, &poo);
+
+            print_scores(&top_n(tf_idf(freqs, &doc_freqs, poo.len()), n));
+        }
+        Some(user) => {
+            let found = extract_users_with(&buf, &[user], author_fold(args), |_| {});
+
+            if found.is_empty() {
+                panic!("user {} not found", user);
+            }
+
+            for (author, freqs) in found.iter() {
+                println!("{}", String::from_utf8_lossy(author));
+
+                print_scores(&top_n(word_freqs(freqs), n));
+            }
+        }
+        None => {
+            let poo = deserialize(&buf, |_| {});
+            let freqs = global_freqs(&poo);
+
+            println!("global ({} authors)", poo.len());
+
+            print_scores(&top_n(word_freqs(freqs), n));
+        }
+    }
+}
+
+/// Loads the sidecar index of `path`, building and saving it if it is
+/// missing, older than the file, or a rebuild was requested.
+fn load_index(path: &Path, rebuild: bool) -> InverseIndex {
+    let index_path = InverseIndex::sidecar_path(path);
+
+    let modified = |p: &Path| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+
+    let fresh =
+        !rebuild
+            && matches!(
+                (modified(&index_path), modified(path)),
+                (Some(index), Some(file)) if index >= file
+            );
+
+    if fresh {
+        match InverseIndex::load(&index_path) {
+            Ok(index) => return index,
+            Err(e) => eprintln!("Failed to load index, rebuilding: {}", e),
+        }
+    }
+
+    eprintln!("Building index for {}..", path.display());
+
+    let location = path.to_string_lossy();
+
+    let buf = read_file(&location).expect("failed to read file");
+    let index = InverseIndex::build(&deserialize(&buf, |_| {}));
+
+    // there's nowhere to keep a sidecar next to an object
+    if is_remote(&location) {
+        return index;
+    }
+
+    if let Err(e) = index.save(&index_path, storage::is_encrypted(&location).unwrap_or(false)) {
+        eprintln!("Failed to save index: {}", e);
+    }
+
+    index
+}
+
+fn word(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+    let term = args.positional(2).expect(USAGE);
+    let n = args.parse_value::<usize>("n").unwrap_or(50);
+
+    // tiny accounts otherwise dominate the normalized ranking
+    let min_tokens = args.parse_value::<u64>("min-tokens").unwrap_or(100);
+
+    let index = load_index(Path::new(path), args.flag("rebuild-index"));
+
+    let uses = index.users_of(term.to_lowercase().as_bytes());
+
+    println!("{}: used by {} of {} authors", term, uses.len(), index.authors());
+
+    for (rank, u) in uses.iter().filter(|u| u.total >= min_tokens).take(n).enumerate() {
+        println!(
+            "{:>5}  {:<24} {:>8} / {:<10} {:.6}",
+            rank + 1,
+            String::from_utf8_lossy(u.author),
+            u.freq,
+            u.total,
+            u.share(),
+        );
+    }
+}
+
+fn users(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+    let min_tokens = args.parse_value::<u64>("min-tokens").unwrap_or(0);
+    let min_vocab = args.parse_value::<usize>("min-vocab").unwrap_or(0);
+
+    let buf = read_file(path).expect("failed to read file");
+    let poo = deserialize(&buf, |_| {});
+
+    let corpus_tokens = poo.values().map(total_tokens).sum::<u64>().max(1);
+
+    // (author, vocabulary size, total tokens)
+    let mut stats =
+        poo.iter()
+            .map(|(author, freqs)| (author, freqs.len(), total_tokens(freqs)))
+            .filter(|(_, vocab, tokens)| *vocab >= min_vocab && *tokens >= min_tokens)
+            .collect::<Vec<_>>();
+
+    match args.value("sort").unwrap_or("tokens") {
+        "tokens" => stats.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(b.0))),
+        "vocab" => stats.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0))),
+        "name" => stats.sort_by(|a, b| a.0.cmp(b.0)),
+        other => panic!("unknown sort order: {}", other),
+    }
+
+    println!("{} of {} authors, {} tokens", stats.len(), poo.len(), corpus_tokens);
+    println!("{:<24} {:>10} {:>12} {:>9}", "author", "vocab", "tokens", "share");
+
+    for (author, vocab, tokens) in stats.iter().take(args.parse_value("n").unwrap_or(usize::MAX)) {
+        println!(
+            "{:<24} {:>10} {:>12} {:>8.4}%",
+            String::from_utf8_lossy(author),
+            vocab,
+            tokens,
+            *tokens as f64 / corpus_tokens as f64 * 100.0,
+        );
+    }
+}
+
+fn diff_users(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+    let a = args.positional(2).expect(USAGE);
+    let b = args.positional(3).expect(USAGE);
+    let n = args.parse_value::<usize>("n").unwrap_or(30);
+    let prior = args.parse_value::<f64>("prior").unwrap_or(1000.0);
+
+    let buf = read_file(path).expect("failed to read file");
+    let fold = author_fold(args);
+    let found = extract_users_with(&buf, &[a, b], fold, |_| {});
+
+    let profile = |user: &str| {
+        found.iter()
+            .find(|(author, _)| fold.fold(author) == fold.fold(user.as_bytes()))
+            .map(|(_, freqs)| freqs)
+            .unwrap_or_else(|| panic!("user {} not found", user))
+    };
+
+    let (freqs_a, freqs_b) = (profile(a), profile(b));
+
+    let scores = log_odds(freqs_a, freqs_b, prior);
+
+    println!("over-represented in {} (vs {}):", a, b);
+    print_scores(&top_n(scores.iter().map(|(w, z)| (*w, *z)), n));
+
+    println!();
+    println!("over-represented in {} (vs {}):", b, a);
+    print_scores(&top_n(scores.iter().map(|(w, z)| (*w, -*z)), n));
+}
+
+fn diff_corpora(args: &Args) {
+    let path_a = args.positional(1).expect(USAGE);
+    let path_b = args.positional(2).expect(USAGE);
+    let n = args.parse_value::<usize>("n").unwrap_or(20);
+
+    let load = |path: &str| {
+        let buf = read_file(path).expect("failed to read file");
+
+        deserialize(&buf, |_| {})
+    };
+
+    let (a, b) = rayon::join(|| load(path_a), || load(path_b));
+
+    let diff = CorpusDiff::compute(&a, &b, n);
+
+    println!("{:<12} {:>14} {:>14}", "", path_a, path_b);
+    println!("{:<12} {:>14} {:>14}", "authors", diff.authors.0, diff.authors.1);
+    println!("{:<12} {:>14} {:>14}", "tokens", diff.tokens.0, diff.tokens.1);
+    println!("{:<12} {:>14} {:>14}", "vocabulary", diff.vocabulary.0, diff.vocabulary.1);
+
+    let names = |users: &[&[u8]]| {
+        users
+            .iter()
+            .take(n)
+            .map(|u| String::from_utf8_lossy(u).to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    println!();
+    println!("{} new users: {}", diff.new_users.len(), names(&diff.new_users));
+    println!("{} vanished users: {}", diff.vanished_users.len(), names(&diff.vanished_users));
+
+    println!();
+    println!("rising words:");
+    print_scores(&diff.rising.iter().map(|(w, z)| (&w[..], *z)).collect::<Vec<_>>());
+
+    println!();
+    println!("falling words:");
+    print_scores(&diff.falling.iter().map(|(w, z)| (&w[..], *z)).collect::<Vec<_>>());
+
+    println!();
+    println!("biggest vocabulary shifts ({} users in both):", diff.shifts.len());
+    print_scores(&diff.shifts[..n.min(diff.shifts.len())]);
+}
+
+/// Top words of a user, read from the profiles column family of a database
+/// instead of scanning a .freqs file.
+fn lookup(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+    let user = args.positional(2).expect(USAGE);
+    let n = args.parse_value::<usize>("n").unwrap_or(50);
+
+    audit::read(path).expect("failed to write audit log");
+
+    let db = rocks::open(Path::new(path)).expect("failed to open profiles database");
+
+    match rocks::read_profile(&db, user.as_bytes()).expect("failed to read profile") {
+        Some(freqs) => {
+            println!("{}: {} words, {} tokens", user, freqs.len(), total_tokens(&freqs));
+
+            print_scores(&top_n(word_freqs(freqs), n));
+        }
+        None => panic!("user {} not found", user),
+    }
+}
+
+/// Loads the corpus once and answers queries read from stdin.
+fn repl(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+
+    eprintln!("Loading {}..", path);
+
+    let buf = read_file(path).expect("failed to read file");
+    let poo = deserialize(&buf, |_| {});
+
+    drop(buf);
+
+    let fold = author_fold(args);
+
+    // built on first use
+    let mut global = None;
+    let mut doc_freqs = None;
+    let mut index = None;
+
+    eprintln!("{} authors loaded, type 'help' for commands", poo.len());
+
+    let stdin = std::io::stdin();
+
+    loop {
+        print!("> ");
+        std::io::stdout().flush().ok();
+
+        let mut line = String::new();
+
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+
+        let words = line.split_whitespace().collect::<Vec<_>>();
+
+        let count = |i: usize| words.get(i).and_then(|n| n.parse::<usize>().ok()).unwrap_or(20);
+
+        let lookup = |name: &str| {
+            let found =
+                poo.iter()
+                    .find(|(author, _)| fold.fold(author) == fold.fold(name.as_bytes()));
+
+            if found.is_none() {
+                println!("user {} not found", name);
+            }
+
+            found
+        };
+
+        match words.as_slice() {
+            [] => {}
+            ["quit"] | ["exit"] => break,
+            ["help"] => println!("{}", REPL_HELP),
+            ["user", name] => {
+                if let Some((author, freqs)) = lookup(name) {
+                    println!(
+                        "{}: {} words, {} tokens",
+                        String::from_utf8_lossy(author),
+                        freqs.len(),
+                        total_tokens(freqs),
+                    );
+
+                    print_scores(&top_n(word_freqs(freqs), 10));
+                }
+            }
+            ["top"] | ["top", _] if words.get(1).map_or(true, |w| w.parse::<usize>().is_ok()) => {
+                let global = global.get_or_insert_with(|| global_freqs(&poo));
+
+                print_scores(&top_n(global.iter().map(|(w, f)| (&w[..], *f)), count(1)));
+            }
+            ["top", name, ..] => {
+                if let Some((_, freqs)) = lookup(name) {
+                    print_scores(&top_n(word_freqs(freqs), count(2)));
+                }
+            }
+            ["tfidf", name, ..] => {
+                if let Some((_, freqs)) = lookup(name) {
+                    let doc_freqs = doc_freqs.get_or_insert_with(|| load_doc_freqs(path, &poo));
+
+                    print_scores(&top_n(tf_idf(freqs, doc_freqs, poo.len()), count(2)));
+                }
+            }
+            ["similar", name, ..] => {
+                if let Some((author, freqs)) = lookup(name) {
+                    let similar =
+                        most_similar(&poo, freqs, count(2) + 1)
+                            .into_iter()
+                            .filter(|(other, _)| *other != &author[..])
+                            .collect::<Vec<_>>();
+
+                    print_scores(&similar);
+                }
+            }
+            ["word", term, ..] => {
+                let index = index.get_or_insert_with(|| InverseIndex::build(&poo));
+
+                for u in index.users_of(term.to_lowercase().as_bytes()).iter().take(count(2)) {
+                    println!(
+                        "{:<24} {:>8} / {:<10} {:.6}",
+                        String::from_utf8_lossy(u.author),
+                        u.freq,
+                        u.total,
+                        u.share(),
+                    );
+                }
+            }
+            _ => println!("unknown command, type 'help' for commands"),
+        }
+    }
+}
+
+/// Table name for a file, e.g. `2022-10.users.freqs` becomes `t_2022_10_users`.
+#[cfg(feature = "sql")]
+fn table_name(path: &Path) -> String {
+    let stem =
+        path.file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+    let name =
+        stem.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+            .collect::<String>();
+
+    if name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name
+    } else {
+        format!("t_{}", name)
+    }
+}
+
+#[cfg(feature = "sql")]
+fn sql(args: &Args) {
+    use datafusion::prelude::SessionContext;
+
+    let positionals = args.positionals();
+
+    let (query, files) =
+        positionals[1..]
+            .split_last()
+            .filter(|(_, files)| !files.is_empty())
+            .expect(USAGE);
+
+    let ctx = SessionContext::new();
+
+    for (i, file) in files.iter().enumerate() {
+        let path = Path::new(file);
+
+        eprintln!("Loading {}..", file);
+
+        let buf = read_file(file).expect("failed to read file");
+        let corpus = deserialize(&buf, |_| {});
+
+        if i == 0 {
+            poo::sql::register(&ctx, "freqs", &corpus).expect("failed to register table");
+        }
+
+        poo::sql::register(&ctx, &table_name(path), &corpus).expect("failed to register table");
+    }
+
+    let runtime = tokio::runtime::Runtime::new().expect("failed to start runtime");
+
+    if let Err(e) = runtime.block_on(poo::sql::run(&ctx, query)) {
+        eprintln!("Query failed: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "sql"))]
+fn sql(_args: &Args) {
+    eprintln!("SQL support is not compiled in, rebuild with `--features sql`");
+    std::process::exit(1);
+}
+
+/// Loads the corpus for the servers, segmenting it if `--segments` is given
+/// and caching results if `--cache` is.
+fn load_state(path: &str, args: &Args) -> std::sync::Arc<State> {
+    // started first, so clients can follow loading and segmentation
+    if let Some(addr) = args.value("events-addr") {
+        if let Err(e) = spawn_websocket(addr) {
+            eprintln!("Failed to start event stream: {}", e);
+        }
+    }
+
+    std::sync::Arc::new(build_state(path, args).expect("failed to load corpus"))
+}
+
+/// The state `load_state` serves, also rebuilt by `serve --reload-every`.
+/// Cached results are keyed by the content hash, so a changed corpus starts
+/// from an empty cache.
+fn build_state(path: &str, args: &Args) -> std::io::Result<State> {
+    eprintln!("Loading {}..", path);
+
+    EVENTS.publish(&Event::Message { text: format!("Loading {}", path) });
+
+    let buf = read_file(path)?;
+    let corpus = deserialize(&buf, |_| {});
+
+    let cache =
+        args.parse_value::<usize>("cache")
+            .map(|entries| ResultCache::new(content_hash(&buf), entries));
+
+    drop(buf);
+
+    #[cfg(feature = "redis")]
+    let cache =
+        match (cache, args.value("redis")) {
+            (Some(cache), Some(url)) =>
+                Some(
+                    cache.with_redis(url)
+                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("failed to connect to Redis: {}", e)))?
+                ),
+            (cache, _) => cache,
+        };
+
+    let segmentation =
+        args.parse_value::<usize>("segments")
+            .map(|k| {
+                eprintln!("Segmenting {} authors into {} segments..", corpus.len(), k);
+
+                let segmentation =
+                    segment_with(
+                        &corpus,
+                        &segment_options(args, k),
+                        |iteration, changed, sizes| {
+                            EVENTS.publish(&Event::SegmentIteration { iteration, changed, sizes })
+                        },
+                    );
+
+                EVENTS.publish(&Event::Segmented {
+                    assigned: segmentation.assignments.len(),
+                    sizes: segmentation.sizes(),
+                });
+
+                segmentation
+            });
+
+    let state = State::new(corpus, segmentation);
+
+    Ok(
+        match cache {
+            Some(cache) => state.with_cache(cache),
+            None => state,
+        }
+    )
+}
+
+/// Serves the corpus over HTTP, see `poo::server` for the endpoints.
+fn serve(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+    let addr = args.value("addr").unwrap_or("127.0.0.1:8080");
+    let workers = args.parse_value::<usize>("workers").unwrap_or(4);
+
+    let state = load_state(path, args);
+
+    // --reload-every=<minutes> re-reads and re-segments the corpus, running
+    // --reload-hook=<command> after each attempt
+    let reload =
+        args.parse_value::<u64>("reload-every")
+            .map(|minutes| {
+                let path = path.to_string();
+                let load_args = args.clone();
+                let hook = args.value("reload-hook").map(str::to_string);
+
+                Reload {
+                    every: Duration::from_secs(minutes.max(1) * 60),
+                    load: Box::new(move || build_state(&path, &load_args)),
+                    done: Box::new(move |result| {
+                        if let Some(hook) = &hook {
+                            run_reload_hook(hook, result);
+                        }
+                    }),
+                }
+            });
+
+    eprintln!("Listening on http://{}", addr);
+
+    if let Err(e) = poo::server::serve(state, addr, workers, reload) {
+        eprintln!("Server failed: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Runs `hook` through the shell with `POO_RELOAD` set to `ok` or `failed`,
+/// and `POO_RELOAD_ERROR` to the reason if it failed.
+fn run_reload_hook(hook: &str, result: &std::io::Result<()>) {
+    let mut command = std::process::Command::new("sh");
+
+    command.arg("-c").arg(hook);
+
+    match result {
+        Ok(()) => command.env("POO_RELOAD", "ok"),
+        Err(e) => command.env("POO_RELOAD", "failed").env("POO_RELOAD_ERROR", e.to_string()),
+    };
+
+    match command.status() {
+        Ok(status) if !status.success() => eprintln!("Reload hook exited with {}", status),
+        Err(e) => eprintln!("Failed to run reload hook: {}", e),
+        Ok(_) => {}
+    }
+}
+
+#[cfg(feature = "grpc")]
+fn grpc(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+
+    let addr =
+        args.value("addr")
+            .unwrap_or("127.0.0.1:50051")
+            .parse()
+            .expect("invalid address");
+
+    let state = load_state(path, args);
+
+    eprintln!("Listening on {} (gRPC)", addr);
+
+    let runtime = tokio::runtime::Runtime::new().expect("failed to start runtime");
+
+    if let Err(e) = runtime.block_on(poo::grpc::serve(state, addr)) {
+        eprintln!("Server failed: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "grpc"))]
+fn grpc(_args: &Args) {
+    eprintln!("gRPC support is not compiled in, rebuild with `--features grpc`");
+    std::process::exit(1);
+}
+
+/// Streams the tf-idf vectors of the corpus over Arrow Flight.
+#[cfg(feature = "flight")]
+fn flight(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+    let vocabulary = args.parse_value::<usize>("vocabulary").unwrap_or(4096);
+
+    let addr =
+        args.value("addr")
+            .unwrap_or("127.0.0.1:50052")
+            .parse()
+            .expect("invalid address");
+
+    eprintln!("Loading {}..", path);
+
+    let buf = read_file(path).expect("failed to read file");
+    let corpus = deserialize(&buf, |_| {});
+
+    drop(buf);
+
+    eprintln!("Listening on {} (Arrow Flight)", addr);
+
+    let runtime = tokio::runtime::Runtime::new().expect("failed to start runtime");
+
+    if let Err(e) = runtime.block_on(poo::flight::serve(corpus, vocabulary, addr)) {
+        eprintln!("Server failed: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "flight"))]
+fn flight(_args: &Args) {
+    eprintln!("Arrow Flight support is not compiled in, rebuild with `--features flight`");
+    std::process::exit(1);
+}
+
+fn corpus_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string())
+}
+
+/// Reads and deserializes the corpus at `path`, recording it as an input.
+/// `--exclude-toxic=<share>` drops that share of the most hostile authors,
+/// e.g. 0.1 for the top decile, before anything else sees the corpus.
+fn load_corpus(path: &str, args: &Args, manifest: &mut Manifest) -> PooMap {
+    eprintln!("Loading {}..", path);
+
+    let buf = read_file(path).expect("failed to read file");
+
+    manifest.input(path, &buf);
+
+    let mut corpus = deserialize(&buf, |_| {});
+
+    manifest.count("authors", corpus.len() as u64);
+
+    if let Some(share) = args.parse_value::<f64>("exclude-toxic") {
+        let excluded = toxicity::exclude_most_toxic(&mut corpus, share);
+
+        eprintln!("Excluded {} of the most toxic authors", excluded);
+        manifest.option("exclude_toxic", share);
+        manifest.count("excluded_toxic", excluded as u64);
+    }
+
+    manifest.stage("load");
+
+    corpus
+}
+
+/// Records `out` and writes the manifest next to it.
+fn finish_manifest(mut manifest: Manifest, out: &str) {
+    manifest.output(out);
+
+    if let Err(e) = manifest.write(out) {
+        eprintln!("Failed to write manifest: {}", e);
+    }
+}
+
+/// Writes a report of the corpus, a self-contained HTML page by default.
+/// The JSON format is what `--previous` reads to compare against a past run.
+fn report(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+    let format = args.value("format").unwrap_or("html");
+
+    let out =
+        args.value("out")
+            .unwrap_or(match format {
+                "markdown" | "md" => "report.md",
+                "json" => "report.json",
+                _ => "report.html",
+            });
+
+    let mut manifest = Manifest::start("query");
+
+    let previous =
+        args.value("previous")
+            .map(|previous| {
+                let buf = read_file(previous).expect("failed to read previous report");
+
+                manifest.input(previous, &buf);
+
+                serde_json::from_slice::<ReportData>(&buf).expect("previous report is not a JSON report")
+            });
+
+    let corpus = load_corpus(path, args, &mut manifest);
+
+    // --assignments reports on a saved segmentation instead of clustering
+    // again, so the report matches what `query segment` wrote
+    let segmentation =
+        match args.value("assignments") {
+            Some(segments_path) => {
+                let buf = read_file(segments_path).expect("failed to read assignments");
+
+                manifest.input(segments_path, &buf);
+
+                match read_assignments(&buf).map(|a| a.to_segmentation()) {
+                    Ok(Some(segmentation)) => Some(segmentation),
+                    Ok(None) => {
+                        eprintln!("{} has no segment model, segment again with a current version", segments_path);
+                        std::process::exit(1);
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to read assignments from {}: {}", segments_path, e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            None =>
+                args.parse_value::<usize>("segments")
+                    .map(|k| segment_with(&corpus, &segment_options(args, k), |_, _, _| {})),
+        };
+
+    let options =
+        ReportOptions {
+            top_words: args.parse_value("n").unwrap_or(50),
+            quality: !args.flag("no-quality"),
+            ..Default::default()
+        };
+
+    let data = ReportData::build(&corpus_name(path), &corpus, segmentation.as_ref(), &options);
+
+    let written =
+        storage::create(out)
+            .and_then(|mut output| {
+                let rendered =
+                    match format {
+                        "markdown" | "md" => render_markdown(&data, previous.as_ref()),
+                        "json" => serde_json::to_string(&data)?,
+                        _ => render_html(&data),
+                    };
+
+                output.write_all(rendered.as_bytes())?;
+                output.finish()
+            });
+
+    match written {
+        Ok(_) => {
+            eprintln!("Report written to {}", out);
+            finish_manifest(manifest, out);
+        }
+        Err(e) => {
+            eprintln!("Failed to write report: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Writes SVG word clouds of the corpus, scored by tf-idf against the
+/// document frequencies, and of every segment, scored by centroid weight.
+fn wordcloud(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+    let out = Path::new(args.value("out").unwrap_or("wordclouds"));
+    let n = args.parse_value("n").unwrap_or(150);
+
+    let mut manifest = Manifest::start("query");
+    let corpus = load_corpus(path, args, &mut manifest);
+
+    let lossy = |word: &[u8]| String::from_utf8_lossy(word).to_string();
+    let options = CloudOptions::default();
+
+    let global = global_freqs(&corpus);
+    let doc_freqs = load_doc_freqs(path, &corpus);
+
+    let mut clouds =
+        vec![(
+            "corpus".to_string(),
+            top_n(tf_idf(&global, &doc_freqs, corpus.len()), n)
+                .into_iter()
+                .map(|(word, score)| (lossy(word), score))
+                .collect::<Vec<_>>(),
+        )];
+
+    if let Some(k) = args.parse_value::<usize>("segments") {
+        let segmentation = segment_with(&corpus, &segment_options(args, k), |_, _, _| {});
+
+        for i in 0..segmentation.centroids.len() as u32 {
+            let terms =
+                segmentation
+                    .top_terms(i, n)
+                    .into_iter()
+                    .map(|(word, weight)| (lossy(word), weight as f64))
+                    .collect();
+
+            clouds.push((format!("segment-{}", i), terms));
+        }
+    }
+
+    let written =
+        std::fs::create_dir_all(out)
+            .and_then(|_| {
+                clouds
+                    .iter()
+                    .try_for_each(|(name, terms)| {
+                        std::fs::write(out.join(format!("{}.svg", name)), render_svg(terms, &options))
+                    })
+            });
+
+    match written {
+        Ok(()) => {
+            eprintln!("{} word clouds written to {}", clouds.len(), out.display());
+            finish_manifest(manifest, &out.to_string_lossy());
+        }
+        Err(e) => {
+            eprintln!("Failed to write word clouds: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Rank-frequency fits of the corpus and the given users, plotted log-log.
+/// `--outliers` lists the authors whose distribution is the least Zipfian,
+/// which tends to surface bots and copy-pasters.
+fn zipf(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+    let users = args.positionals().iter().skip(2).map(|u| u.as_str()).collect::<Vec<_>>();
+    let out = args.value("out").unwrap_or("zipf.svg");
+
+    let mut manifest = Manifest::start("query");
+    let corpus = load_corpus(path, args, &mut manifest);
+
+    let fold = author_fold(args);
+
+    let mut series = vec![("(corpus)".to_string(), zipf::ranks(&global_freqs(&corpus)))];
+
+    for user in users {
+        match corpus.iter().find(|(author, _)| fold.fold(author) == fold.fold(user.as_bytes())) {
+            Some((author, freqs)) => series.push((String::from_utf8_lossy(author).to_string(), zipf::ranks(freqs))),
+            None => eprintln!("user {} not found", user),
+        }
+    }
+
+    for (label, ranks) in series.iter() {
+        match zipf::fit(ranks) {
+            Some(fit) => println!("{}\texponent {:.3}\tr² {:.3}", label, fit.exponent, fit.r_squared),
+            None => println!("{}\ttoo few words to fit", label),
+        }
+    }
+
+    if let Some(n) = args.parse_value::<usize>("outliers") {
+        let min_tokens = args.parse_value::<u64>("min-tokens").unwrap_or(1000);
+
+        let mut fits =
+            corpus.par_iter()
+                .filter(|(_, freqs)| total_tokens(freqs) >= min_tokens)
+                .filter_map(|(author, freqs)| zipf::fit(&zipf::ranks(freqs)).map(|fit| (&author[..], fit)))
+                .collect::<Vec<_>>();
+
+        fits.sort_by(|a, b| a.1.r_squared.partial_cmp(&b.1.r_squared).unwrap_or(std::cmp::Ordering::Equal));
+        fits.truncate(n);
+
+        println!();
+        println!("least Zipfian authors ({} tokens or more):", min_tokens);
+
+        print_scores(
+            &fits.iter()
+                .map(|(author, fit)| (*author, format!("r² {:.3}  exponent {:.3}", fit.r_squared, fit.exponent)))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    let written =
+        storage::create(out)
+            .and_then(|mut output| {
+                output.write_all(zipf::render_svg(&series, 800.0, 500.0).as_bytes())?;
+                output.finish()
+            });
+
+    match written {
+        Ok(()) => {
+            eprintln!("Plot written to {}", out);
+            finish_manifest(manifest, out);
+        }
+        Err(e) => {
+            eprintln!("Failed to write plot: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Writes a report comparing two corpora, e.g. two years of comments.
+fn compare(args: &Args) {
+    let path_a = args.positional(1).expect(USAGE);
+    let path_b = args.positional(2).expect(USAGE);
+    let format = args.value("format").unwrap_or("html");
+
+    let out =
+        args.value("out")
+            .unwrap_or(match format {
+                "markdown" | "md" => "comparison.md",
+                "json" => "comparison.json",
+                _ => "comparison.html",
+            });
+
+    let mut manifest = Manifest::start("query");
+
+    let read = |path: &str| {
+        eprintln!("Loading {}..", path);
+
+        read_file(path).expect("failed to read file")
+    };
+
+    let (buf_a, buf_b) = (read(path_a), read(path_b));
+
+    manifest.input(path_a, &buf_a);
+    manifest.input(path_b, &buf_b);
+
+    let (a, b) = rayon::join(|| deserialize(&buf_a, |_| {}), || deserialize(&buf_b, |_| {}));
+
+    drop((buf_a, buf_b));
+
+    let segmentation =
+        args.parse_value::<usize>("segments")
+            .map(|k| segment_with(&a, &segment_options(args, k), |_, _, _| {}));
+
+    let data =
+        ComparisonData::build(
+            (&corpus_name(path_a), &corpus_name(path_b)),
+            &a,
+            &b,
+            segmentation.as_ref(),
+            args.parse_value("n").unwrap_or(30),
+        );
+
+    let written =
+        storage::create(out)
+            .and_then(|mut output| {
+                let rendered =
+                    match format {
+                        "markdown" | "md" => compare::render_markdown(&data),
+                        "json" => serde_json::to_string(&data)?,
+                        _ => compare::render_html(&data),
+                    };
+
+                output.write_all(rendered.as_bytes())?;
+                output.finish()
+            });
+
+    match written {
+        Ok(()) => {
+            eprintln!("Comparison written to {}", out);
+            finish_manifest(manifest, out);
+        }
+        Err(e) => {
+            eprintln!("Failed to write comparison: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Writes a static site with a page per segment and per user into a local
+/// directory, ready to be copied to any static host.
+fn site(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+    let out = args.value("out").unwrap_or("site");
+
+    let mut manifest = Manifest::start("query");
+    let mut corpus = load_corpus(path, args, &mut manifest);
+
+    let segmentation =
+        args.parse_value::<usize>("segments")
+            .map(|k| segment_with(&corpus, &segment_options(args, k), |_, _, _| {}));
+
+    let options = ReportOptions::default();
+
+    let data = ReportData::build(&corpus_name(path), &corpus, segmentation.as_ref(), &options);
+
+    // only the user pages are redacted, corpus-wide figures stay exact
+    if args.flag("redact-rare") {
+        let rare =
+            RareWords {
+                max_authors: args.parse_value("redact-rare").unwrap_or(1),
+                ..Default::default()
+            };
+
+        let redacted = rare.apply(&mut corpus);
+
+        eprintln!("Redacted {} rare words", redacted);
+        manifest.count("redacted_words", redacted as u64);
+    }
+
+    let profiles =
+        profiles(
+            &corpus,
+            segmentation.as_ref(),
+            args.parse_value("terms").unwrap_or(25),
+            args.parse_value("min-tokens").unwrap_or(options.min_tokens),
+            textstats::load(path).as_ref(),
+        );
+
+    eprintln!("Writing {} user pages..", profiles.len());
+
+    match site::generate(Path::new(out), &corpus, &data, &profiles) {
+        Ok(()) => {
+            eprintln!("Site written to {}", out);
+            manifest.count("user_pages", profiles.len() as u64);
+            finish_manifest(manifest, out);
+        }
+        Err(e) => {
+            eprintln!("Failed to write site: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Segments a corpus and saves the assignments, so later runs and tools
+/// work from the same segmentation.
+fn segment(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+
+    let labeled =
+        args.value("labels")
+            .map(|labels| {
+                std::fs::read_to_string(labels)
+                    .map_err(|e| e.to_string())
+                    .and_then(|text| parse_labeled_users(&text))
+                    .unwrap_or_else(|e| {
+                        eprintln!("Failed to read labeled users from {}: {}", labels, e);
+                        std::process::exit(1);
+                    })
+            });
+
+    let k =
+        match (args.parse_value::<usize>("segments"), labeled.as_ref()) {
+            (Some(k), _) => k,
+            // one segment per label
+            (None, Some(_)) => 0,
+            (None, None) => {
+                eprintln!("{}", USAGE);
+                std::process::exit(1);
+            }
+        };
+
+    let out =
+        args.value("out")
+            .map(str::to_string)
+            .unwrap_or_else(|| Assignments::sidecar_path(path));
+
+    let mut manifest = Manifest::start("query");
+    let corpus = load_corpus(path, args, &mut manifest);
+
+    let options =
+        SegmentOptions {
+            min_tokens: args.parse_value("min-tokens").unwrap_or(SegmentOptions::default().min_tokens),
+            ..segment_options(args, k)
+        };
+
+    manifest.option("min_tokens", options.min_tokens);
+
+    let segmentation =
+        match labeled {
+            Some(labeled) => {
+                let found = labeled.iter().filter(|(user, _)| corpus.contains_key(user)).count();
+
+                eprintln!("Propagating labels from {} of {} labeled users..", found, labeled.len());
+
+                manifest.option("labels", args.value("labels"));
+                manifest.count("labeled", found as u64);
+
+                propagate_labels(&corpus, &labeled, &options)
+            }
+            None => {
+                eprintln!("Segmenting {} authors into {} segments..", corpus.len(), k);
+
+                manifest.option("segments", k);
+
+                segment_with(&corpus, &options, |_, _, _| {})
+            }
+        };
+
+    let assignments = segmentation.to_assignments(args.parse_value("terms").unwrap_or(20));
+
+    manifest.count("assigned", assignments.authors.len() as u64);
+    manifest.stage("segment");
+
+    save_assignments(&assignments, &out, manifest);
+}
+
+/// Writes the assignments to `out`, exiting on failure.
+fn save_assignments(assignments: &Assignments, out: &str, manifest: Manifest) {
+    let written =
+        storage::create(out)
+            .and_then(|mut output| {
+                write_assignments(&mut output, assignments)?;
+                output.finish()
+            });
+
+    match written {
+        Ok(()) => {
+            eprintln!(
+                "Assigned {} authors, {} left unsegmented, written to {}",
+                assignments.authors.len(),
+                assignments.unsegmented.len(),
+                out,
+            );
+            finish_manifest(manifest, out);
+        }
+        Err(e) => {
+            eprintln!("Failed to write assignments: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn load_assignments(path: &str) -> Assignments {
+    read_file(path)
+        .and_then(|buf| read_assignments(&buf))
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to read {}: {}", path, e);
+            std::process::exit(1);
+        })
+}
+
+/// Assigns the authors of a corpus to the segments of a saved segmentation,
+/// e.g. a new month of comments, so segment ids stay comparable.
+fn assign(args: &Args) {
+    let (model_path, path) =
+        match (args.positional(1), args.positional(2)) {
+            (Some(model_path), Some(path)) => (model_path, path),
+            _ => {
+                eprintln!("{}", USAGE);
+                std::process::exit(1);
+            }
+        };
+
+    let out =
+        args.value("out")
+            .map(str::to_string)
+            .unwrap_or_else(|| Assignments::sidecar_path(path));
+
+    let mut manifest = Manifest::start("query");
+
+    let model = load_assignments(model_path);
+
+    manifest.input_path(model_path);
+
+    let corpus = load_corpus(path, args, &mut manifest);
+    let min_tokens = args.parse_value("min-tokens").unwrap_or(SegmentOptions::default().min_tokens);
+
+    let min_similarity = args.parse_value("min-similarity").unwrap_or(SegmentOptions::default().min_similarity);
+
+    manifest.option("min_tokens", min_tokens);
+    manifest.option("min_similarity", min_similarity);
+
+    let assignments =
+        model.reassign(&corpus, min_tokens, min_similarity)
+            .unwrap_or_else(|| {
+                eprintln!("{} has no model, segment the corpus again to save one", model_path);
+                std::process::exit(1);
+            });
+
+    manifest.count("assigned", assignments.authors.len() as u64);
+    manifest.stage("assign");
+
+    save_assignments(&assignments, &out, manifest);
+}
+
+/// Saves the summed profile of every segment of a saved segmentation, so
+/// segment vocabularies work with everything that reads corpora.
+fn segment_freqs(args: &Args) {
+    let (path, segments_path) =
+        match (args.positional(1), args.positional(2)) {
+            (Some(path), Some(segments_path)) => (path, segments_path),
+            _ => {
+                eprintln!("{}", USAGE);
+                std::process::exit(1);
+            }
+        };
+
+    let out = args.value("out").unwrap_or("segments.freqs");
+
+    let mut manifest = Manifest::start("query");
+    let corpus = load_corpus(path, args, &mut manifest);
+    let assignments = load_assignments(segments_path);
+
+    manifest.input_path(segments_path);
+
+    let freqs = aggregate::segment_freqs(&corpus, &assignments);
+
+    manifest.count("segments", freqs.len() as u64);
+
+    let written =
+        storage::create(out)
+            .and_then(|output| {
+                let mut encoder = zstd::stream::Encoder::new(output, 10)?;
+
+                serialize_with_writer(&freqs, &mut encoder, |_| {})?;
+
+                encoder.finish()?.finish()
+            });
+
+    match written {
+        Ok(()) => {
+            eprintln!("{} segment profiles written to {}", freqs.len(), out);
+            finish_manifest(manifest, out);
+        }
+        Err(e) => {
+            eprintln!("Failed to write segment profiles: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Matches the segments of consecutive runs by the authors they share and
+/// prints how each carried over, to tell real segments from artifacts of
+/// the random initialization.
+fn stability(args: &Args) {
+    let paths = args.positionals().iter().skip(1).map(String::as_str).collect::<Vec<_>>();
+
+    if paths.len() < 2 {
+        eprintln!("{}", USAGE);
+        std::process::exit(1);
+    }
+
+    let runs = paths.iter().map(|path| load_assignments(path)).collect::<Vec<_>>();
+
+    let steps =
+        runs.windows(2)
+            .map(|pair| Stability::measure(&pair[0], &pair[1]))
+            .collect::<Vec<_>>();
+
+    for (i, step) in steps.iter().enumerate() {
+        println!("{} -> {}", paths[i], paths[i + 1]);
+        println!(
+            "  {} authors in both, {} left, {} joined, churn {:.1}%",
+            step.common,
+            step.left,
+            step.joined,
+            step.churn * 100.0,
+        );
+
+        for m in step.matches.iter() {
+            match m.after {
+                Some(after) => println!("  {:>4} -> {:<4} {:>8} shared  jaccard {:.3}", m.before, after, m.overlap, m.jaccard),
+                None => println!("  {:>4} -> -    (no match)", m.before),
+            }
+        }
+    }
+
+    if let Some(out) = args.value("sankey") {
+        let names = paths.iter().map(|path| corpus_name(path)).collect::<Vec<_>>();
+
+        let runs =
+            names.iter()
+                .map(String::as_str)
+                .zip(runs.iter())
+                .collect::<Vec<_>>();
+
+        let written =
+            storage::create(out)
+                .and_then(|mut output| {
+                    serde_json::to_writer(&mut output, &Sankey::build(&runs, &steps))?;
+                    output.finish()
+                });
+
+        match written {
+            Ok(()) => eprintln!("Transitions written to {}", out),
+            Err(e) => {
+                eprintln!("Failed to write transitions: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Prints the segments of a saved segmentation, then its authors as tab
+/// separated `author, segment, similarity`, or with `--unsegmented` the
+/// authors left out and why.
+fn assignments(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+    let only = args.parse_value::<u32>("segment");
+
+    let assignments = load_assignments(path);
+
+    for (i, segment) in assignments.segments.iter().enumerate() {
+        if only.map_or(false, |only| only != i as u32) {
+            continue;
+        }
+
+        let terms = segment.terms.iter().map(|t| String::from_utf8_lossy(t)).collect::<Vec<_>>();
+
+        let parent = segment.parent.map(|p| format!("in {}", p)).unwrap_or_default();
+
+        eprintln!("segment {:>3}  {:<6} {:<20} {:>8} authors  {}", i, parent, segment.label, segment.size, terms.join(" "));
+    }
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+
+    if args.flag("unsegmented") {
+        for (author, reason) in assignments.unsegmented.iter() {
+            out.write_all(author).ok();
+
+            match reason {
+                Unsegmented::TooLittleData => writeln!(out, "\ttoo little data").ok(),
+                Unsegmented::Outlier(similarity) => writeln!(out, "\toutlier\t{:.4}", similarity).ok(),
+            };
+        }
+
+        return;
+    }
+
+    for (author, segment, similarity) in assignments.authors.iter() {
+        if only.map_or(true, |only| only == *segment) {
+            out.write_all(author).ok();
+            writeln!(out, "\t{}\t{:.4}", segment, similarity).ok();
+        }
+    }
+}
+
+/// Sums the profiles of shard files into one corpus. Authors active in
+/// several shards end up with a single profile.
+fn merge(args: &Args) {
+    let paths =
+        match args.value("queue") {
+            Some(location) => {
+                let done =
+                    WorkQueue::open(location)
+                        .and_then(|mut queue| {
+                            let (pending, claimed, _) = queue.counts()?;
+
+                            if pending + claimed > 0 {
+                                eprintln!(
+                                    "{} shards are still pending and {} in progress, `poo requeue` puts back those of dead workers",
+                                    pending,
+                                    claimed,
+                                );
+                                std::process::exit(1);
+                            }
+
+                            queue.done()
+                        });
+
+                match done {
+                    Ok(done) => done.into_iter().map(|d| d.output).collect::<Vec<_>>(),
+                    Err(e) => {
+                        eprintln!("Failed to read queue {}: {}", location, e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            None => args.positionals().iter().skip(1).cloned().collect(),
+        };
+
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
+        std::process::exit(1);
+    }
+
+    let out = args.value("out").unwrap_or("merged.users.freqs");
+
+    let mut manifest = Manifest::start("query");
+    let mut merged = PooMap::default();
+    let mut text_stats = None::<TextStatsMap>;
+    let mut encrypted = false;
+    let mut sealed_stats = false;
+    let mut cooccurrence = None::<Cooccurrence>;
+
+    for path in paths.iter() {
+        // shards of `poo work --encrypt` merge into a sealed corpus
+        encrypted |= storage::is_encrypted(path).unwrap_or(false);
+
+        for (author, freqs) in load_corpus(path, args, &mut manifest) {
+            let sums = merged.remove(&author).unwrap_or_default();
+
+            merged.insert(author, merge_freqs(sums, freqs));
+        }
+
+        // shards ingested with --text-stats, sealed if any of them was
+        sealed_stats |= textstats::is_sealed(path);
+
+        for (author, stats) in textstats::load(path).into_iter().flatten() {
+            *text_stats.get_or_insert_with(Default::default).entry(author).or_default() += stats;
+        }
+
+        // and with --cooccurrence
+        if let Some(more) = cooccurrence::load(path) {
+            match cooccurrence.as_mut() {
+                Some(cooccurrence) => cooccurrence.merge(more),
+                None => cooccurrence = Some(more),
+            }
+        }
+    }
+
+    manifest.count("shards", paths.len() as u64);
+    manifest.count("merged_authors", merged.len() as u64);
+
+    let written =
+        storage::create(out)
+            .and_then(|mut output| {
+                let mut sealed = Sealed::new(&mut output, encrypted)?;
+                let mut encoder = zstd::stream::Encoder::new(&mut sealed, 10)?;
+
+                serialize_with_writer(&merged, &mut encoder, |_| {})?;
+
+                encoder.finish()?;
+                sealed.finish()?;
+                output.finish()
+            })
+            .and_then(|_| {
+                let mut output = storage::create(&Vocabulary::sidecar_path(out))?;
+                let mut sealed = Sealed::new(&mut output, encrypted)?;
+
+                Vocabulary::build(&merged).write(&mut sealed)?;
+
+                sealed.finish()?;
+                output.finish()
+            })
+            .and_then(|_| {
+                match text_stats.as_ref() {
+                    Some(text_stats) => textstats::save(text_stats, out, sealed_stats),
+                    None => Ok(()),
+                }
+            })
+            .and_then(|_| {
+                match cooccurrence.as_ref() {
+                    Some(cooccurrence) => {
+                        let mut output = storage::create(&Cooccurrence::sidecar_path(out))?;
+                        let mut sealed = Sealed::new(&mut output, encrypted)?;
+
+                        cooccurrence.write(&mut sealed)?;
+
+                        sealed.finish()?;
+                        output.finish()
+                    }
+                    None => Ok(()),
+                }
+            });
+
+    match written {
+        Ok(()) => {
+            eprintln!("{} authors from {} shards written to {}", merged.len(), paths.len(), out);
+            finish_manifest(manifest, out);
+        }
+        Err(e) => {
+            eprintln!("Failed to write {}: {}", out, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Rewrites a corpus without some authors, to honor erasure requests
+/// without re-running ingestion. Rewrites the file in place unless `--out`
+/// is given.
+fn remove_users(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+
+    let mut names =
+        args.positionals()
+            .iter()
+            .skip(2)
+            .map(|u| u.as_bytes().to_vec())
+            .collect::<HashSet<_>>();
+
+    if let Some(list) = args.value("list") {
+        let list = std::fs::read_to_string(list).expect("failed to read name list");
+
+        names.extend(optout::parse_list(&list).map(<[u8]>::to_vec));
+    }
+
+    if names.is_empty() {
+        eprintln!("{}", USAGE);
+        std::process::exit(1);
+    }
+
+    if is_remote(path) && args.value("out").is_none() {
+        eprintln!("Remote files can't be rewritten in place, pass --out");
+        std::process::exit(1);
+    }
+
+    eprintln!("Loading {}..", path);
+
+    let buf = read_file(path).expect("failed to read file");
+
+    // an encrypted corpus stays encrypted
+    let encrypted = storage::is_encrypted(path).expect("failed to read file");
+
+    // written next to the original and moved over it once complete
+    let out =
+        args.value("out")
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{}.tmp", path));
+
+    let removed =
+        storage::create(&out)
+            .and_then(|mut output| {
+                let mut sealed = Sealed::new(&mut output, encrypted)?;
+                let mut encoder = zstd::stream::Encoder::new(&mut sealed, 10)?;
+
+                let removed = remove_authors(&buf, |author| names.contains(author), &mut encoder, |_| {})?;
+
+                encoder.finish()?;
+                sealed.finish()?;
+                output.finish()?;
+
+                Ok(removed)
+            });
+
+    let removed =
+        match removed {
+            Ok(removed) => removed,
+            Err(e) => {
+                eprintln!("Failed to write {}: {}", out, e);
+                std::process::exit(1);
+            }
+        };
+
+    if args.value("out").is_none() {
+        if let Err(e) = std::fs::rename(&out, path) {
+            eprintln!("Failed to replace {}: {}", path, e);
+            std::process::exit(1);
+        }
+
+        // the vocabulary still counts the removed authors' words and the
+        // index still lists them, both are rebuilt when next needed
+        let sidecars = [
+            PathBuf::from(Vocabulary::sidecar_path(path)),
+            InverseIndex::sidecar_path(Path::new(path)),
+        ];
+
+        for sidecar in sidecars.iter() {
+            if sidecar.exists() && std::fs::remove_file(sidecar).is_ok() {
+                eprintln!("Removed stale {}", sidecar.display());
+            }
+        }
+    }
+
+    // the text statistics are per author too
+    if let Some(mut text_stats) = textstats::load(path) {
+        let target = args.value("out").unwrap_or(path);
+        let sealed = textstats::is_sealed(path);
+
+        text_stats.retain(|author, _| !names.contains(&author[..]));
+
+        if let Err(e) = textstats::save(&text_stats, target, sealed) {
+            eprintln!("Failed to write {}: {}", textstats::sidecar_path(target), e);
+            std::process::exit(1);
+        }
+    }
+
+    eprintln!("Removed {} of {} authors", removed, names.len());
+}
+
+/// Writes the lines of a per-author table to `out`, or to stdout.
+fn write_table(out: Option<&str>, text: &str, manifest: Manifest) {
+    let text = format!("{}\n", text);
+
+    match out {
+        Some(out) => {
+            let written =
+                storage::create(out)
+                    .and_then(|mut output| {
+                        output.write_all(text.as_bytes())?;
+                        output.finish()
+                    });
+
+            if let Err(e) = written {
+                eprintln!("Failed to write {}: {}", out, e);
+                std::process::exit(1);
+            }
+
+            eprintln!("Written to {}", out);
+            finish_manifest(manifest, out);
+        }
+        None => {
+            std::io::stdout().write_all(text.as_bytes()).ok();
+        }
+    }
+}
+
+/// Writes every author's sentiment and toxicity as TSV, or with `--labels` the tone
+/// terciles as `user<TAB>label` lines, for `query segment --labels`.
+fn sentiment(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+    let min_tokens = args.parse_value::<u64>("min-tokens").unwrap_or(0);
+
+    let mut manifest = Manifest::start("query");
+    let corpus = load_corpus(path, args, &mut manifest);
+
+    let mut scores =
+        corpus.par_iter()
+            .map(|(author, freqs)| (author, Tone::of(freqs), freqs))
+            .filter(|(_, tone, _)| tone.tokens >= min_tokens)
+            .map(|(author, tone, freqs)| {
+                (String::from_utf8_lossy(author).to_string(), tone.tokens, tone.sentiment(), Incivility::of(freqs).score())
+            })
+            .collect::<Vec<_>>();
+
+    scores.par_sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    let lines =
+        if args.flag("labels") {
+            let mut valences = scores.iter().map(|(_, _, sentiment, _)| sentiment.valence).collect::<Vec<_>>();
+
+            valences.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+            // a third of the authors per label
+            let cut = |third: usize| valences.get(valences.len() * third / 3).copied().unwrap_or(0.0);
+            let (low, high) = (cut(1), cut(2));
+
+            scores.iter()
+                .map(|(author, _, sentiment, _)| {
+                    let label =
+                        if sentiment.valence < low {
+                            "negative"
+                        } else if sentiment.valence >= high {
+                            "positive"
+                        } else {
+                            "neutral"
+                        };
+
+                    format!("{}\t{}", author, label)
+                })
+                .collect::<Vec<_>>()
+        } else {
+            std::iter::once("author\ttokens\tvalence\tpositive\tnegative\ttoxicity".to_string())
+                .chain(
+                    scores.iter()
+                        .map(|(author, tokens, sentiment, toxicity)| {
+                            format!(
+                                "{}\t{}\t{:.4}\t{:.6}\t{:.6}\t{:.4}",
+                                author,
+                                tokens,
+                                sentiment.valence,
+                                sentiment.positive,
+                                sentiment.negative,
+                                toxicity,
+                            )
+                        })
+                )
+                .collect::<Vec<_>>()
+        };
+
+    manifest.option("labels", args.flag("labels"));
+    manifest.count("scored", scores.len() as u64);
+
+    write_table(args.value("out"), &lines.join("\n"), manifest);
+}
+
+/// Writes every author's word category vector as TSV, one column per
+/// category of the dictionary in use, ready for clustering elsewhere.
+/// `--standardize` scales each column to zero mean and unit variance.
+fn categories(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+    let min_tokens = args.parse_value::<u64>("min-tokens").unwrap_or(0);
+
+    let mut manifest = Manifest::start("query");
+    let corpus = load_corpus(path, args, &mut manifest);
+    let names = categories::names();
+
+    let mut vectors =
+        corpus.par_iter()
+            .filter(|(_, freqs)| total_tokens(freqs) >= min_tokens)
+            .map(|(author, freqs)| (String::from_utf8_lossy(author).to_string(), categories::vector(freqs)))
+            .collect::<Vec<_>>();
+
+    vectors.par_sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    if args.flag("standardize") && !vectors.is_empty() {
+        let n = vectors.len() as f64;
+
+        for column in 0..names.len() {
+            let mean = vectors.iter().map(|(_, v)| v[column]).sum::<f64>() / n;
+            let sd = (vectors.iter().map(|(_, v)| (v[column] - mean).powi(2)).sum::<f64>() / n).sqrt();
+
+            for (_, vector) in vectors.iter_mut() {
+                // a column nobody varies on carries no information
+                vector[column] = if sd > 0.0 { (vector[column] - mean) / sd } else { 0.0 };
+            }
+        }
+    }
+
+    manifest.option("standardize", args.flag("standardize"));
+    manifest.count("scored", vectors.len() as u64);
+
+    let text =
+        std::iter::once(format!("author\t{}", names.join("\t")))
+            .chain(
+                vectors.iter()
+                    .map(|(author, vector)| {
+                        let values = vector.iter().map(|value| format!("{:.6}", value)).collect::<Vec<_>>();
+
+                        format!("{}\t{}", author, values.join("\t"))
+                    })
+            )
+            .collect::<Vec<_>>()
+            .join("\n");
+
+    write_table(args.value("out"), &text, manifest);
+}
+
+/// PPMI word vectors from the .cooc sidecar of the corpus at `path`.
+fn load_ppmi(path: &str) -> Ppmi {
+    match cooccurrence::load(path) {
+        Some(cooccurrence) => Ppmi::fit(&cooccurrence),
+        None => {
+            eprintln!("No co-occurrence counts for {}, ingest it with `poo --cooccurrence=<file.vocab>`", path);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Words used in the same contexts as a word.
+fn neighbors(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+    let word = args.positional(2).expect(USAGE);
+    let n = args.parse_value::<usize>("n").unwrap_or(20);
+
+    let ppmi = load_ppmi(path);
+
+    match ppmi.neighbors(word.to_lowercase().as_bytes(), n) {
+        Some(neighbors) => print_scores(&neighbors.into_iter().map(|(w, s)| (w, format!("{:.4}", s))).collect::<Vec<_>>()),
+        None => {
+            eprintln!("{} isn't among the {} words with vectors", word, ppmi.len());
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Compares users by the meaning of their words rather than the words
+/// themselves: a profile is the sum of its words' PPMI vectors, so users
+/// writing about the same things in different words still come out close.
+fn semantic(args: &Args) {
+    let path = args.positional(1).expect(USAGE);
+    let user = args.positional(2).expect(USAGE);
+    let n = args.parse_value::<usize>("n").unwrap_or(20);
+    let min_tokens = args.parse_value::<u64>("min-tokens").unwrap_or(0);
+
+    let mut manifest = Manifest::start("query");
+    let ppmi = load_ppmi(path);
+    let corpus = load_corpus(path, args, &mut manifest);
+
+    let embed = |name: &str| {
+        match corpus.get(name.as_bytes()) {
+            Some(freqs) => ppmi.embed(freqs),
+            None => {
+                eprintln!("User {} not found", name);
+                std::process::exit(1);
+            }
+        }
+    };
+
+    let target = embed(user);
+
+    if let Some(other) = args.positional(3) {
+        println!("{:.4}", cooccurrence::similarity(&target, &embed(other)));
+        return;
+    }
+
+    let mut similar =
+        corpus.par_iter()
+            .filter(|(author, freqs)| author.as_ref() != user.as_bytes() && total_tokens(freqs) >= min_tokens)
+            .map(|(author, freqs)| (author.as_ref(), cooccurrence::similarity(&target, &ppmi.embed(freqs))))
+            .collect::<Vec<_>>();
+
+    similar.par_sort_unstable_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal).then_with(|| a.0.cmp(b.0)));
+    similar.truncate(n);
+
+    print_scores(&similar.into_iter().map(|(author, s)| (author, format!("{:.4}", s))).collect::<Vec<_>>());
+}
+
+fn main() {
+    let args = Args::from_env();
+
+    if let Err(e) = optout::init(&args) {
+        eprintln!("Failed to read opt-out list: {}", e);
+        std::process::exit(1);
+    }
+
+    if let Err(e) = toxicity::init(&args) {
+        eprintln!("Failed to read toxicity lexicon: {}", e);
+        std::process::exit(1);
+    }
+
+    if let Err(e) = categories::init(&args) {
+        eprintln!("Failed to read category dictionary: {}", e);
+        std::process::exit(1);
+    }
+
+    match args.positional(0) {
+        Some("top-words") => top_words(&args),
+        Some("word") => word(&args),
+        Some("users") => users(&args),
+        Some("diff-users") => diff_users(&args),
+        Some("diff-corpora") => diff_corpora(&args),
+        Some("repl") => repl(&args),
+        Some("lookup") => lookup(&args),
+        Some("sql") => sql(&args),
+        Some("serve") => serve(&args),
+        Some("grpc") => grpc(&args),
+        Some("flight") => flight(&args),
+        Some("report") => report(&args),
+        Some("wordcloud") => wordcloud(&args),
+        Some("zipf") => zipf(&args),
+        Some("compare") => compare(&args),
+        Some("site") => site(&args),
+        Some("segment") => segment(&args),
+        Some("assignments") => assignments(&args),
+        Some("assign") => assign(&args),
+        Some("stability") => stability(&args),
+        Some("segment-freqs") => segment_freqs(&args),
+        Some("merge") => merge(&args),
+        Some("remove-users") => remove_users(&args),
+        Some("sentiment") => sentiment(&args),
+        Some("categories") => categories(&args),
+        Some("neighbors") => neighbors(&args),
+        Some("semantic") => semantic(&args),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    }
+}
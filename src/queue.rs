@@ -0,0 +1,384 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+/// One shard of a corpus: the items with ids in `from..to`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Task {
+    pub shard: u32,
+    pub from: i64,
+    pub to: i64,
+}
+
+/// A completed task and where its profiles were written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Done {
+    pub task: Task,
+    pub output: String,
+}
+
+/// Splits the ids `from..=last` into `shards` contiguous ranges of about
+/// equal width.
+pub fn split(from: i64, last: i64, shards: u32) -> Vec<Task> {
+    let shards = shards.max(1) as i64;
+    let width = ((last - from + 1).max(1) + shards - 1) / shards;
+
+    (0..shards)
+        .map(|i| Task {
+            shard: i as u32,
+            from: from + i * width,
+            to: (from + (i + 1) * width).min(last + 1),
+        })
+        .filter(|task| task.from < task.to)
+        .collect()
+}
+
+/// Where workers find the shards left to ingest.
+///
+/// A directory, shared between machines over NFS or similar, holds a file per
+/// task in `pending/`, `claimed/` and `done/`; a worker claims a task by
+/// renaming it out of `pending/`, which only one of them can do. With
+/// `--features redis`, a `redis://` location keeps the same three states as
+/// lists under `poo:queue:`.
+///
+/// Claims record when they were made, the file's modification time or a
+/// hash next to the lists, so `requeue` can put back the tasks of workers
+/// that died without releasing them.
+pub enum WorkQueue {
+    Dir(PathBuf),
+    #[cfg(feature = "redis")]
+    Redis(redis::Connection),
+}
+
+#[cfg(feature = "redis")]
+fn to_io<E: std::error::Error + Send + Sync + 'static>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+#[cfg(feature = "redis")]
+const PENDING: &str = "poo:queue:pending";
+#[cfg(feature = "redis")]
+const CLAIMED: &str = "poo:queue:claimed";
+#[cfg(feature = "redis")]
+const DONE: &str = "poo:queue:done";
+/// Task JSON → seconds since the epoch it was claimed at.
+#[cfg(feature = "redis")]
+const CLAIMED_AT: &str = "poo:queue:claimed_at";
+
+#[cfg(feature = "redis")]
+fn since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+impl WorkQueue {
+    pub fn open(location: &str) -> io::Result<Self> {
+        let redis = location.starts_with("redis://") || location.starts_with("rediss://");
+
+        #[cfg(feature = "redis")]
+        if redis {
+            let connection =
+                redis::Client::open(location)
+                    .and_then(|client| client.get_connection())
+                    .map_err(to_io)?;
+
+            return Ok(WorkQueue::Redis(connection));
+        }
+
+        #[cfg(not(feature = "redis"))]
+        if redis {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "Redis queues need --features redis"));
+        }
+
+        let dir = PathBuf::from(location);
+
+        for state in ["pending", "claimed", "done"] {
+            std::fs::create_dir_all(dir.join(state))?;
+        }
+
+        Ok(WorkQueue::Dir(dir))
+    }
+
+    fn task_file(dir: &Path, state: &str, shard: u32) -> PathBuf {
+        dir.join(state).join(format!("{:05}.json", shard))
+    }
+
+    /// Adds `tasks` to the pending ones.
+    pub fn push(&mut self, tasks: &[Task]) -> io::Result<()> {
+        match self {
+            WorkQueue::Dir(dir) => {
+                for task in tasks {
+                    std::fs::write(Self::task_file(dir, "pending", task.shard), serde_json::to_vec(task)?)?;
+                }
+
+                Ok(())
+            }
+            #[cfg(feature = "redis")]
+            WorkQueue::Redis(connection) => {
+                for task in tasks {
+                    redis::cmd("RPUSH")
+                        .arg(PENDING)
+                        .arg(serde_json::to_string(task)?)
+                        .query::<()>(connection)
+                        .map_err(to_io)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Takes a pending task, `None` once there are none left.
+    pub fn claim(&mut self) -> io::Result<Option<Task>> {
+        match self {
+            WorkQueue::Dir(dir) => {
+                let mut pending =
+                    std::fs::read_dir(dir.join("pending"))?
+                        .filter_map(|entry| entry.ok().map(|e| e.path()))
+                        .collect::<Vec<_>>();
+
+                pending.sort();
+
+                for path in pending {
+                    let claimed = dir.join("claimed").join(path.file_name().unwrap());
+
+                    // another worker got there first
+                    if std::fs::rename(&path, &claimed).is_err() {
+                        continue;
+                    }
+
+                    // a rename keeps the old modification time, writing the
+                    // task back stamps the claim
+                    let buf = std::fs::read(&claimed)?;
+
+                    std::fs::write(&claimed, &buf)?;
+
+                    return serde_json::from_slice(&buf).map(Some).map_err(Into::into);
+                }
+
+                Ok(None)
+            }
+            #[cfg(feature = "redis")]
+            WorkQueue::Redis(connection) => {
+                let json =
+                    redis::cmd("LMOVE")
+                        .arg(PENDING)
+                        .arg(CLAIMED)
+                        .arg("LEFT")
+                        .arg("RIGHT")
+                        .query::<Option<String>>(connection)
+                        .map_err(to_io)?;
+
+                if let Some(json) = &json {
+                    redis::cmd("HSET")
+                        .arg(CLAIMED_AT)
+                        .arg(json)
+                        .arg(since_epoch(SystemTime::now()))
+                        .query::<()>(connection)
+                        .map_err(to_io)?;
+                }
+
+                json.map(|json| serde_json::from_str(&json).map_err(Into::into)).transpose()
+            }
+        }
+    }
+
+    /// Records that `task` was written to `output`.
+    pub fn complete(&mut self, task: &Task, output: &str) -> io::Result<()> {
+        let done = Done { task: task.clone(), output: output.to_string() };
+
+        match self {
+            WorkQueue::Dir(dir) => {
+                std::fs::write(Self::task_file(dir, "done", task.shard), serde_json::to_vec(&done)?)?;
+
+                // already requeued if this worker was taken for dead
+                match std::fs::remove_file(Self::task_file(dir, "claimed", task.shard)) {
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                    removed => removed,
+                }
+            }
+            #[cfg(feature = "redis")]
+            WorkQueue::Redis(connection) => {
+                redis::pipe()
+                    .atomic()
+                    .cmd("LREM").arg(CLAIMED).arg(1).arg(serde_json::to_string(task)?).ignore()
+                    .cmd("HDEL").arg(CLAIMED_AT).arg(serde_json::to_string(task)?).ignore()
+                    .cmd("RPUSH").arg(DONE).arg(serde_json::to_string(&done)?).ignore()
+                    .query::<()>(connection)
+                    .map_err(to_io)
+            }
+        }
+    }
+
+    /// Puts a claimed `task` back for another worker.
+    pub fn release(&mut self, task: &Task) -> io::Result<()> {
+        match self {
+            WorkQueue::Dir(dir) => {
+                std::fs::rename(
+                    Self::task_file(dir, "claimed", task.shard),
+                    Self::task_file(dir, "pending", task.shard),
+                )
+            }
+            #[cfg(feature = "redis")]
+            WorkQueue::Redis(connection) => {
+                let json = serde_json::to_string(task)?;
+
+                redis::pipe()
+                    .atomic()
+                    .cmd("LREM").arg(CLAIMED).arg(1).arg(&json).ignore()
+                    .cmd("HDEL").arg(CLAIMED_AT).arg(&json).ignore()
+                    .cmd("LPUSH").arg(PENDING).arg(&json).ignore()
+                    .query::<()>(connection)
+                    .map_err(to_io)
+            }
+        }
+    }
+
+    /// Puts back the tasks claimed longer than `stale_after` ago, whose
+    /// workers are presumed dead, returning them. Claims made before their
+    /// time was recorded get it now, and go back on a later call.
+    pub fn requeue(&mut self, stale_after: Duration) -> io::Result<Vec<Task>> {
+        let now = SystemTime::now();
+        let is_stale = |claimed_at: SystemTime| now.duration_since(claimed_at).map_or(false, |age| age >= stale_after);
+
+        let mut requeued = Vec::new();
+
+        match self {
+            WorkQueue::Dir(dir) => {
+                for entry in std::fs::read_dir(dir.join("claimed"))? {
+                    let path = entry?.path();
+
+                    if !is_stale(std::fs::metadata(&path)?.modified()?) {
+                        continue;
+                    }
+
+                    let task = serde_json::from_slice::<Task>(&std::fs::read(&path)?)?;
+
+                    // the worker finished or released it meanwhile
+                    if std::fs::rename(&path, Self::task_file(dir, "pending", task.shard)).is_ok() {
+                        requeued.push(task);
+                    }
+                }
+            }
+            #[cfg(feature = "redis")]
+            WorkQueue::Redis(connection) => {
+                let claimed =
+                    redis::cmd("LRANGE")
+                        .arg(CLAIMED)
+                        .arg(0)
+                        .arg(-1)
+                        .query::<Vec<String>>(connection)
+                        .map_err(to_io)?;
+
+                for json in claimed {
+                    let claimed_at =
+                        redis::cmd("HGET")
+                            .arg(CLAIMED_AT)
+                            .arg(&json)
+                            .query::<Option<u64>>(connection)
+                            .map_err(to_io)?;
+
+                    let claimed_at =
+                        match claimed_at {
+                            Some(secs) => SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+                            None => {
+                                redis::cmd("HSETNX")
+                                    .arg(CLAIMED_AT)
+                                    .arg(&json)
+                                    .arg(since_epoch(now))
+                                    .query::<()>(connection)
+                                    .map_err(to_io)?;
+
+                                continue;
+                            }
+                        };
+
+                    if !is_stale(claimed_at) {
+                        continue;
+                    }
+
+                    let (removed,) =
+                        redis::pipe()
+                            .atomic()
+                            .cmd("LREM").arg(CLAIMED).arg(1).arg(&json)
+                            .cmd("HDEL").arg(CLAIMED_AT).arg(&json).ignore()
+                            .query::<(usize,)>(connection)
+                            .map_err(to_io)?;
+
+                    // the worker finished or released it meanwhile
+                    if removed > 0 {
+                        redis::cmd("LPUSH")
+                            .arg(PENDING)
+                            .arg(&json)
+                            .query::<()>(connection)
+                            .map_err(to_io)?;
+
+                        requeued.push(serde_json::from_str(&json)?);
+                    }
+                }
+            }
+        }
+
+        requeued.sort_by_key(|task| task.shard);
+
+        Ok(requeued)
+    }
+
+    /// Tasks pending, claimed and done.
+    pub fn counts(&mut self) -> io::Result<(usize, usize, usize)> {
+        match self {
+            WorkQueue::Dir(dir) => {
+                let count = |state: &str| std::fs::read_dir(dir.join(state)).map(Iterator::count);
+
+                Ok((count("pending")?, count("claimed")?, count("done")?))
+            }
+            #[cfg(feature = "redis")]
+            WorkQueue::Redis(connection) => {
+                let (pending, claimed, done) =
+                    redis::pipe()
+                        .cmd("LLEN").arg(PENDING)
+                        .cmd("LLEN").arg(CLAIMED)
+                        .cmd("LLEN").arg(DONE)
+                        .query(connection)
+                        .map_err(to_io)?;
+
+                Ok((pending, claimed, done))
+            }
+        }
+    }
+
+    /// The completed tasks, by shard.
+    pub fn done(&mut self) -> io::Result<Vec<Done>> {
+        let mut done =
+            match self {
+                WorkQueue::Dir(dir) => {
+                    std::fs::read_dir(dir.join("done"))?
+                        .map(|entry| {
+                            let buf = std::fs::read(entry?.path())?;
+
+                            serde_json::from_slice::<Done>(&buf).map_err(Into::into)
+                        })
+                        .collect::<io::Result<Vec<_>>>()?
+                }
+                #[cfg(feature = "redis")]
+                WorkQueue::Redis(connection) => {
+                    redis::cmd("LRANGE")
+                        .arg(DONE)
+                        .arg(0)
+                        .arg(-1)
+                        .query::<Vec<String>>(connection)
+                        .map_err(to_io)?
+                        .iter()
+                        .map(|json| serde_json::from_str::<Done>(json).map_err(Into::into))
+                        .collect::<io::Result<Vec<_>>>()?
+                }
+            };
+
+        // a requeued worker that was only slow completes its task twice
+        done.sort_by_key(|d| d.task.shard);
+        done.dedup_by_key(|d| d.task.shard);
+
+        Ok(done)
+    }
+}
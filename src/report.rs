@@ -0,0 +1,689 @@
+use std::fmt::Write;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::{global_freqs, merge_freqs, top_n, total_tokens, word_freqs};
+use crate::domains::{self, domain_freqs};
+use crate::fingerprint;
+use crate::lexicon::{CategoryAffinity, TECH};
+use crate::quality::{ClusterQuality, QualityOptions};
+use crate::segment::Segmentation;
+use crate::sentiment::{Sentiment, Tone};
+use crate::toxicity::{Incivility, Toxicity};
+use crate::text::text_item::{PooMap, PooMapInner};
+
+#[derive(Debug, Clone)]
+pub struct ReportOptions {
+    pub top_words: usize,
+    pub segment_terms: usize,
+    /// Authors listed per segment and leaderboard.
+    pub leaders: usize,
+    pub fingerprints: usize,
+    /// Authors below this are left off the type-token ratio leaderboard,
+    /// where tiny accounts would otherwise win.
+    pub min_tokens: u64,
+    /// Measure the segmentation's quality, which re-clusters subsamples.
+    pub quality: bool,
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        Self {
+            top_words: 50,
+            segment_terms: 15,
+            leaders: 20,
+            fingerprints: 12,
+            min_tokens: 1000,
+            quality: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SegmentSummary {
+    pub segment: u32,
+    /// Empty unless the segment was named after a seed.
+    #[serde(default)]
+    pub label: String,
+    /// The segment this one splits, in hierarchical segmentations.
+    #[serde(default)]
+    pub parent: Option<u32>,
+    pub size: usize,
+    pub terms: Vec<(String, f32)>,
+    /// Most prolific members.
+    pub members: Vec<String>,
+    /// Domains the members link to more than the corpus does, see
+    /// `domains::affinity`.
+    #[serde(default)]
+    pub domains: Vec<(String, f64)>,
+    /// Languages, frameworks and databases the members mention more than
+    /// the corpus does.
+    #[serde(default)]
+    pub tech_stack: Vec<CategoryAffinity>,
+    /// From the members' word counts, missing in older reports.
+    #[serde(default)]
+    pub sentiment: Option<Sentiment>,
+    /// Likewise.
+    #[serde(default)]
+    pub toxicity: Option<Toxicity>,
+}
+
+impl SegmentSummary {
+    /// The id, followed by the label if there is one.
+    pub fn title(&self) -> String {
+        if self.label.is_empty() {
+            self.segment.to_string()
+        } else {
+            format!("{}: {}", self.segment, self.label)
+        }
+    }
+}
+
+/// Whether `leaf` is `segment` or one of its sub-segments.
+pub fn segment_contains(segments: &[SegmentSummary], segment: u32, leaf: u32) -> bool {
+    let mut current = Some(leaf);
+
+    while let Some(s) = current {
+        if s == segment {
+            return true;
+        }
+
+        current = segments.iter().find(|summary| summary.segment == s).and_then(|summary| summary.parent);
+    }
+
+    false
+}
+
+/// How deep `segment` sits in the segment tree, 0 for the first level.
+pub fn segment_depth(segments: &[SegmentSummary], segment: &SegmentSummary) -> usize {
+    let mut depth = 0;
+    let mut parent = segment.parent;
+
+    while let Some(p) = parent {
+        depth += 1;
+        parent = segments.iter().find(|s| s.segment == p).and_then(|s| s.parent);
+    }
+
+    depth
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Leaderboard {
+    pub title: String,
+    pub entries: Vec<(String, String)>,
+}
+
+/// Everything a corpus report shows, independent of the output format.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportData {
+    pub name: String,
+    pub authors: usize,
+    pub tokens: u64,
+    pub vocabulary: usize,
+    pub top_words: Vec<(String, u64)>,
+    pub segments: Vec<SegmentSummary>,
+    /// Authors too small or too unlike every segment to be in one.
+    #[serde(default)]
+    pub unsegmented: usize,
+    pub quality: Option<ClusterQuality>,
+    pub leaderboards: Vec<Leaderboard>,
+    /// `(author, base64 PNG)`, the first one is the whole corpus.
+    pub fingerprints: Vec<(String, String)>,
+}
+
+/// What segment summaries score, summed over the members.
+struct MemberSums {
+    domains: PooMapInner,
+    /// Uses of each `TECH` term.
+    tech: Vec<u64>,
+    tone: Tone,
+    incivility: Incivility,
+}
+
+impl Default for MemberSums {
+    fn default() -> Self {
+        Self {
+            domains: PooMapInner::default(),
+            tech: vec![0; TECH.terms.len()],
+            tone: Tone::default(),
+            incivility: Incivility::default(),
+        }
+    }
+}
+
+impl MemberSums {
+    fn of(freqs: &PooMapInner) -> Self {
+        Self {
+            domains: domain_freqs(freqs),
+            tech: TECH.counts(freqs),
+            tone: Tone::of(freqs),
+            incivility: Incivility::of(freqs),
+        }
+    }
+
+    fn merge(mut self, other: Self) -> Self {
+        self.tech.iter_mut().zip(other.tech).for_each(|(count, other)| *count += other);
+
+        self.tone += other.tone;
+        self.incivility += other.incivility;
+        self.domains = merge_freqs(self.domains, other.domains);
+        self
+    }
+}
+
+fn lossy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).to_string()
+}
+
+impl ReportData {
+    pub fn build(
+        name: &str,
+        poo: &PooMap,
+        segmentation: Option<&Segmentation>,
+        options: &ReportOptions,
+    ) -> Self {
+        let global = global_freqs(poo);
+
+        // (author, tokens, vocabulary)
+        let mut stats =
+            poo.par_iter()
+                .map(|(author, freqs)| (&author[..], total_tokens(freqs), word_freqs(freqs).count()))
+                .collect::<Vec<_>>();
+
+        stats.par_sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+        let leaderboard = |title: &str, entries: Vec<(String, String)>| Leaderboard {
+            title: title.to_string(),
+            entries,
+        };
+
+        let by_tokens =
+            stats.iter()
+                .take(options.leaders)
+                .map(|(author, tokens, _)| (lossy(author), tokens.to_string()))
+                .collect();
+
+        let mut by_vocabulary = stats.iter().collect::<Vec<_>>();
+
+        by_vocabulary.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(b.0)));
+
+        let by_vocabulary =
+            by_vocabulary
+                .iter()
+                .take(options.leaders)
+                .map(|(author, _, vocabulary)| (lossy(author), vocabulary.to_string()))
+                .collect();
+
+        let by_ratio =
+            top_n(
+                stats.iter()
+                    .filter(|(_, tokens, _)| *tokens >= options.min_tokens)
+                    .map(|(author, tokens, vocabulary)| (*author, *vocabulary as f64 / *tokens as f64)),
+                options.leaders,
+            )
+                .into_iter()
+                .map(|(author, ratio)| (lossy(author), format!("{:.4}", ratio)))
+                .collect();
+
+        let corpus_domains = domains::corpus_domains(poo);
+        let corpus_tech = TECH.counts(&global);
+        let corpus_tokens = total_tokens(&global);
+
+        let segments =
+            segmentation
+                .map(|s| {
+                    s.sizes()
+                        .iter()
+                        .enumerate()
+                        .map(|(i, size)| {
+                            let segment = i as u32;
+
+                            // stats is sorted by tokens, so members come out by prolificness
+                            let members =
+                                stats.iter()
+                                    .filter(|(author, _, _)| s.segment_of(author).map_or(false, |(leaf, _)| s.contains(segment, leaf)))
+                                    .take(options.leaders)
+                                    .map(|(author, _, _)| lossy(author))
+                                    .collect();
+
+                            let sums =
+                                poo.par_iter()
+                                    .filter(|(author, _)| s.segment_of(author).map_or(false, |(leaf, _)| s.contains(segment, leaf)))
+                                    .map(|(_, freqs)| MemberSums::of(freqs))
+                                    .reduce(MemberSums::default, MemberSums::merge);
+
+                            SegmentSummary {
+                                segment,
+                                label: s.labels[i].clone(),
+                                parent: s.parents[i],
+                                size: *size,
+                                terms:
+                                    s.top_terms(segment, options.segment_terms)
+                                        .into_iter()
+                                        .map(|(word, weight)| (lossy(word), weight))
+                                        .collect(),
+                                members,
+                                domains: domains::affinity(&sums.domains, &corpus_domains, options.segment_terms),
+                                tech_stack:
+                                    TECH.affinity(&sums.tech, sums.tone.tokens, &corpus_tech, corpus_tokens, options.segment_terms),
+                                sentiment: Some(sums.tone.sentiment()),
+                                toxicity: Some(sums.incivility.toxicity()),
+                            }
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+
+        let fingerprints =
+            std::iter::once(("(corpus)".to_string(), &global))
+                .chain(
+                    stats.iter()
+                        .take(options.fingerprints)
+                        .map(|(author, _, _)| (lossy(author), &poo[*author])),
+                )
+                .collect::<Vec<_>>()
+                .into_par_iter()
+                .filter_map(|(author, freqs)| {
+                    fingerprint::png(freqs).map(|png| (author, base64::encode(png)))
+                })
+                .collect();
+
+        Self {
+            name: name.to_string(),
+            authors: poo.len(),
+            tokens: global.values().sum(),
+            vocabulary: global.len(),
+            top_words:
+                top_n(global.iter().map(|(w, f)| (&w[..], *f)), options.top_words)
+                    .into_iter()
+                    .map(|(word, freq)| (lossy(word), freq))
+                    .collect(),
+            segments,
+            unsegmented: segmentation.map_or(0, |s| s.unsegmented.len()),
+            quality:
+                segmentation
+                    .filter(|_| options.quality)
+                    .and_then(|s| ClusterQuality::measure(poo, s, &QualityOptions::default())),
+            leaderboards: vec![
+                leaderboard("Most tokens", by_tokens),
+                leaderboard("Largest vocabulary", by_vocabulary),
+                leaderboard("Highest type-token ratio", by_ratio),
+            ],
+            fingerprints,
+        }
+    }
+}
+
+/// `category: term, term; category: term`, each part through `escape`.
+pub fn tech_stack_line(stack: &[CategoryAffinity], escape: fn(&str) -> String) -> String {
+    stack.iter()
+        .map(|affinity| {
+            let terms =
+                affinity.terms
+                    .iter()
+                    .map(|(term, _)| escape(term))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+            format!("{}: {}", escape(&affinity.category), terms)
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Mean valence and the shares of positive and negative words.
+pub fn sentiment_line(sentiment: &Sentiment) -> String {
+    format!(
+        "valence {:.2}, {:.2}% positive and {:.2}% negative words",
+        sentiment.valence,
+        sentiment.positive * 100.0,
+        sentiment.negative * 100.0,
+    )
+}
+
+/// The score and the lexicon hits behind it.
+pub fn toxicity_line(toxicity: &Toxicity) -> String {
+    format!("{:.2} per thousand tokens, {} lexicon words", toxicity.score, toxicity.hits)
+}
+
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+pub(crate) const STYLE: &str = "
+body { font: 14px/1.4 sans-serif; max-width: 1100px; margin: 2em auto; color: #222; }
+table { border-collapse: collapse; margin: 0.5em 0 1.5em; }
+td, th { padding: 2px 10px; border-bottom: 1px solid #eee; text-align: left; }
+td.n { text-align: right; font-variant-numeric: tabular-nums; }
+.grid { display: flex; flex-wrap: wrap; gap: 1em; }
+.grid figure { margin: 0; text-align: center; }
+.grid img { width: 128px; height: 128px; image-rendering: pixelated; }
+.segment { border: 1px solid #ddd; padding: 0 1em; margin-bottom: 1em; }
+";
+
+pub(crate) fn table<A: std::fmt::Display, B: std::fmt::Display>(out: &mut String, head: (&str, &str), rows: &[(A, B)]) {
+    writeln!(out, "<table><tr><th>#</th><th>{}</th><th>{}</th></tr>", head.0, head.1).ok();
+
+    for (i, (a, b)) in rows.iter().enumerate() {
+        writeln!(
+            out,
+            "<tr><td class=\"n\">{}</td><td>{}</td><td class=\"n\">{}</td></tr>",
+            i + 1,
+            escape(&a.to_string()),
+            escape(&b.to_string()),
+        ).ok();
+    }
+
+    writeln!(out, "</table>").ok();
+}
+
+fn quality_rows(quality: &ClusterQuality) -> Vec<(&'static str, String)> {
+    vec![
+        ("silhouette (higher is better)", format!("{:.3}", quality.silhouette)),
+        ("Davies-Bouldin (lower is better)", format!("{:.3}", quality.davies_bouldin)),
+        ("stability, adjusted Rand index", format!("{:.3}", quality.stability)),
+        ("segment sizes min / median / max", format!("{} / {} / {}", quality.min_size, quality.median_size, quality.max_size)),
+        ("size coefficient of variation", format!("{:.3}", quality.size_cv)),
+    ]
+}
+
+/// Renders a self-contained HTML page, images are inlined as data URIs.
+pub fn render_html(data: &ReportData) -> String {
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title><style>{}</style></head><body>",
+        escape(&data.name),
+        STYLE,
+    ).ok();
+
+    writeln!(out, "<h1>{}</h1>", escape(&data.name)).ok();
+    writeln!(
+        out,
+        "<p>{} authors, {} tokens, {} distinct words.</p>",
+        data.authors,
+        data.tokens,
+        data.vocabulary,
+    ).ok();
+
+    writeln!(out, "<h2>Top words</h2>").ok();
+    table(&mut out, ("word", "count"), &data.top_words);
+
+    if !data.segments.is_empty() {
+        writeln!(out, "<h2>Segments</h2>").ok();
+
+        if data.unsegmented > 0 {
+            writeln!(out, "<p>{} authors are in no segment.</p>", data.unsegmented).ok();
+        }
+
+        for segment in data.segments.iter() {
+            let terms =
+                segment.terms
+                    .iter()
+                    .map(|(word, _)| escape(word))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+            let members =
+                segment.members
+                    .iter()
+                    .map(|author| escape(author))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+            let domains =
+                Some(&segment.domains)
+                    .filter(|domains| !domains.is_empty())
+                    .map(|domains| {
+                        let domains =
+                            domains.iter()
+                                .map(|(domain, _)| escape(domain))
+                                .collect::<Vec<_>>()
+                                .join(", ");
+
+                        format!("<p><b>Linked domains:</b> {}</p>", domains)
+                    })
+                    .unwrap_or_default();
+
+            let tech_stack =
+                Some(&segment.tech_stack)
+                    .filter(|stack| !stack.is_empty())
+                    .map(|stack| format!("<p><b>Tech stack:</b> {}</p>", tech_stack_line(stack, escape)))
+                    .unwrap_or_default();
+
+            let sentiment =
+                segment.sentiment
+                    .map(|sentiment| format!("<p><b>Sentiment:</b> {}</p>", sentiment_line(&sentiment)))
+                    .unwrap_or_default();
+
+            let toxicity =
+                segment.toxicity
+                    .map(|toxicity| format!("<p><b>Toxicity:</b> {}</p>", toxicity_line(&toxicity)))
+                    .unwrap_or_default();
+
+            writeln!(
+                out,
+                "<div class=\"segment\" style=\"margin-left: {}em\"><h3>Segment {} ({} authors)</h3><p><b>Terms:</b> {}</p>{}{}{}{}<p><b>Members:</b> {}</p></div>",
+                segment_depth(&data.segments, segment) * 2,
+                escape(&segment.title()),
+                segment.size,
+                terms,
+                domains,
+                tech_stack,
+                sentiment,
+                toxicity,
+                members,
+            ).ok();
+        }
+    }
+
+    if let Some(quality) = data.quality.as_ref() {
+        writeln!(out, "<h3>Segmentation quality</h3>").ok();
+        table(&mut out, ("metric", "value"), &quality_rows(quality));
+    }
+
+    writeln!(out, "<h2>Leaderboards</h2>").ok();
+
+    for board in data.leaderboards.iter() {
+        writeln!(out, "<h3>{}</h3>", escape(&board.title)).ok();
+        table(&mut out, ("author", "value"), &board.entries);
+    }
+
+    writeln!(out, "<h2>Fingerprints</h2><div class=\"grid\">").ok();
+
+    for (author, png) in data.fingerprints.iter() {
+        writeln!(
+            out,
+            "<figure><img src=\"data:image/png;base64,{}\" alt=\"{}\"><figcaption>{}</figcaption></figure>",
+            png,
+            escape(author),
+            escape(author),
+        ).ok();
+    }
+
+    writeln!(out, "</div></body></html>").ok();
+
+    out
+}
+
+pub(crate) fn md_escape(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+/// Words whose share of all tokens changed the most since `previous`, in
+/// occurrences per million tokens `(word, before, after)`. Only words in the
+/// top words of either run are known, others count as zero.
+pub fn top_movers(data: &ReportData, previous: &ReportData, n: usize) -> Vec<(String, f64, f64)> {
+    let per_million = |count: u64, tokens: u64| count as f64 * 1e6 / tokens.max(1) as f64;
+
+    let mut words =
+        data.top_words
+            .iter()
+            .chain(previous.top_words.iter())
+            .map(|(word, _)| word.clone())
+            .collect::<Vec<_>>();
+
+    words.sort();
+    words.dedup();
+
+    let share = |report: &ReportData, word: &str| {
+        report.top_words
+            .iter()
+            .find(|(w, _)| w == word)
+            .map(|(_, count)| per_million(*count, report.tokens))
+            .unwrap_or(0.0)
+    };
+
+    let mut movers =
+        words
+            .into_iter()
+            .map(|word| {
+                let before = share(previous, &word);
+                let after = share(data, &word);
+
+                (word, before, after)
+            })
+            .collect::<Vec<_>>();
+
+    movers.sort_by(|a, b| {
+        (b.2 - b.1).abs()
+            .partial_cmp(&(a.2 - a.1).abs())
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.0.cmp(&b.0))
+    });
+
+    movers.truncate(n);
+    movers
+}
+
+/// Renders a Markdown summary for pasting into issues or wikis, with the
+/// top movers against `previous` when there is an earlier run to compare to.
+pub fn render_markdown(data: &ReportData, previous: Option<&ReportData>) -> String {
+    let mut out = String::new();
+
+    writeln!(out, "# {}\n", md_escape(&data.name)).ok();
+    writeln!(out, "| | |\n|---|---:|").ok();
+    writeln!(out, "| authors | {} |", data.authors).ok();
+    writeln!(out, "| tokens | {} |", data.tokens).ok();
+    writeln!(out, "| distinct words | {} |", data.vocabulary).ok();
+
+    if let Some(previous) = previous {
+        writeln!(out, "\n## Top movers since {}\n", md_escape(&previous.name)).ok();
+        writeln!(out, "Occurrences per million tokens.\n").ok();
+        writeln!(out, "| word | before | after | change |\n|---|---:|---:|---:|").ok();
+
+        for (word, before, after) in top_movers(data, previous, 15) {
+            writeln!(out, "| {} | {:.1} | {:.1} | {:+.1} |", md_escape(&word), before, after, after - before).ok();
+        }
+    }
+
+    if !data.segments.is_empty() {
+        writeln!(out, "\n## Segments\n").ok();
+
+        if data.unsegmented > 0 {
+            writeln!(out, "{} authors are in no segment.\n", data.unsegmented).ok();
+        }
+        writeln!(out, "| segment | authors | terms | members |\n|---:|---:|---|---|").ok();
+
+        for segment in data.segments.iter() {
+            let terms =
+                segment.terms
+                    .iter()
+                    .take(8)
+                    .map(|(word, _)| md_escape(word))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+            let members =
+                segment.members
+                    .iter()
+                    .take(5)
+                    .map(|author| md_escape(author))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+            let title = format!("{}{}", "↳ ".repeat(segment_depth(&data.segments, segment)), segment.title());
+
+            writeln!(out, "| {} | {} | {} | {} |", md_escape(&title), segment.size, terms, members).ok();
+        }
+
+        // corpora ingested without --domains have none
+        if data.segments.iter().any(|segment| !segment.domains.is_empty()) {
+            writeln!(out, "\n### Linked domains\n").ok();
+            writeln!(out, "| segment | domains |\n|---:|---|").ok();
+
+            for segment in data.segments.iter().filter(|segment| !segment.domains.is_empty()) {
+                let domains =
+                    segment.domains
+                        .iter()
+                        .take(8)
+                        .map(|(domain, score)| format!("{} ({:.2})", md_escape(domain), score))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+
+                writeln!(out, "| {} | {} |", md_escape(&segment.title()), domains).ok();
+            }
+        }
+
+        if data.segments.iter().any(|segment| !segment.tech_stack.is_empty()) {
+            writeln!(out, "\n### Tech stacks\n").ok();
+            writeln!(out, "| segment | tech stack |\n|---:|---|").ok();
+
+            for segment in data.segments.iter().filter(|segment| !segment.tech_stack.is_empty()) {
+                writeln!(out, "| {} | {} |", md_escape(&segment.title()), tech_stack_line(&segment.tech_stack, md_escape)).ok();
+            }
+        }
+
+        if data.segments.iter().any(|segment| segment.sentiment.is_some()) {
+            writeln!(out, "\n### Tone\n").ok();
+            writeln!(out, "| segment | valence | positive | negative | toxicity |\n|---:|---:|---:|---:|---:|").ok();
+
+            for segment in data.segments.iter() {
+                if let Some(sentiment) = segment.sentiment {
+                    writeln!(
+                        out,
+                        "| {} | {:.2} | {:.2}% | {:.2}% | {} |",
+                        md_escape(&segment.title()),
+                        sentiment.valence,
+                        sentiment.positive * 100.0,
+                        sentiment.negative * 100.0,
+                        segment.toxicity.map(|toxicity| format!("{:.2}", toxicity.score)).unwrap_or_default(),
+                    ).ok();
+                }
+            }
+        }
+    }
+
+    if let Some(quality) = data.quality.as_ref() {
+        writeln!(out, "\n### Segmentation quality\n").ok();
+        writeln!(out, "| metric | value |\n|---|---:|").ok();
+
+        for (metric, value) in quality_rows(quality) {
+            writeln!(out, "| {} | {} |", metric, value).ok();
+        }
+    }
+
+    writeln!(out, "\n## Top words\n").ok();
+    writeln!(out, "| # | word | count |\n|---:|---|---:|").ok();
+
+    for (i, (word, count)) in data.top_words.iter().enumerate() {
+        writeln!(out, "| {} | {} | {} |", i + 1, md_escape(word), count).ok();
+    }
+
+    out
+}
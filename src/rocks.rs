@@ -0,0 +1,271 @@
+use std::path::Path;
+
+use std::io::Write;
+
+use rocksdb::{ColumnFamilyDescriptor, Direction, IteratorMode, MergeOperands, Options, WriteBatch, DB};
+
+use crate::serializer::{encode_author, try_deserialize_original, write_footer, write_header, FnFeedback, ProgressSink};
+use crate::text::interner::WordKey;
+use crate::text::text_item::{PooMapInner, PooMapRoot};
+
+/// Column family holding one frequency blob per author, keyed by name.
+pub const PROFILES_CF: &str = "profiles";
+
+/// Column family indexing item ids by author: keys are the author, a zero
+/// byte and the big-endian item id, values are empty.
+pub const AUTHOR_ITEMS_CF: &str = "author_items";
+
+const WRITE_BATCH: usize = 10_000;
+
+fn decode_block(block: &[u8]) -> Option<(Box<[u8]>, PooMapInner)> {
+    try_deserialize_original(block, |_| {}).into_iter().next()
+}
+
+/// Sums author blocks, so counts can be merged into the profiles column
+/// family one item at a time instead of aggregating in memory.
+fn merge_blocks(author: &[u8], existing: Option<&[u8]>, operands: &MergeOperands) -> Option<Vec<u8>> {
+    let mut freqs = existing.and_then(decode_block).map(|(_, f)| f).unwrap_or_default();
+
+    for operand in operands {
+        if let Some((_, other)) = decode_block(operand) {
+            for (word, freq) in other {
+                *freqs.entry(word).or_default() += freq;
+            }
+        }
+    }
+
+    let mut block = Vec::new();
+
+    encode_author(&mut block, author, &freqs);
+
+    Some(block)
+}
+
+/// Opens (creating if needed) a database with the column families written
+/// here.
+pub fn open(path: &Path) -> Result<DB, rocksdb::Error> {
+    let mut opts = Options::default();
+
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+
+    let mut cf_opts = Options::default();
+
+    cf_opts.set_merge_operator_associative("ragegun_author_sum", merge_blocks);
+
+    DB::open_cf_descriptors(
+        &opts,
+        path,
+        vec![
+            ColumnFamilyDescriptor::new(PROFILES_CF, cf_opts),
+            ColumnFamilyDescriptor::new(AUTHOR_ITEMS_CF, Options::default()),
+        ],
+    )
+}
+
+fn author_prefix(author: &[u8]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(author.len() + 9);
+
+    key.extend_from_slice(author);
+    key.push(0);
+    key
+}
+
+/// Records that item `id` was written by `author`.
+pub fn index_item(db: &DB, author: &[u8], id: i64) -> Result<(), rocksdb::Error> {
+    let cf = db.cf_handle(AUTHOR_ITEMS_CF).expect("author index column family is missing");
+
+    let mut key = author_prefix(author);
+
+    key.extend_from_slice(&id.to_be_bytes());
+
+    db.put_cf(cf, key, [])
+}
+
+/// Ids of every indexed item by `author`, ascending.
+pub fn items_of(db: &DB, author: &[u8]) -> Result<Vec<i64>, rocksdb::Error> {
+    let cf = db.cf_handle(AUTHOR_ITEMS_CF).expect("author index column family is missing");
+
+    let prefix = author_prefix(author);
+
+    let mut ids = Vec::new();
+
+    for entry in db.iterator_cf(cf, IteratorMode::From(&prefix, Direction::Forward)) {
+        let (key, _) = entry?;
+
+        if !key.starts_with(&prefix) {
+            break;
+        }
+
+        if let Ok(id) = <[u8; 8]>::try_from(&key[prefix.len()..]) {
+            ids.push(i64::from_be_bytes(id));
+        }
+    }
+
+    Ok(ids)
+}
+
+/// Key in the default column family of the last item id `merge_items` got
+/// through.
+const MERGED_THROUGH: &[u8] = b"merged_through";
+
+/// Adds the counts of a batch of items to their authors' profiles, along
+/// with `through`, the id of the batch's last item, in one atomic write.
+/// Aggregation this way needs no memory beyond RocksDB's own and survives
+/// crashes: a batch is either merged and recorded or not merged at all, so
+/// a re-run resumes after `merged_through` without counting anything twice.
+pub fn merge_items<K: WordKey>(
+    db: &DB,
+    items: &[(Box<[u8]>, PooMapRoot<K, u64>)],
+    through: Option<i64>,
+) -> Result<(), rocksdb::Error> {
+    let cf = db.cf_handle(PROFILES_CF).expect("profiles column family is missing");
+
+    let mut batch = WriteBatch::default();
+    let mut block = Vec::new();
+
+    for (author, freqs) in items {
+        block.clear();
+
+        encode_author(&mut block, author, freqs);
+
+        batch.merge_cf(cf, author, &block);
+    }
+
+    if let Some(through) = through {
+        batch.put(MERGED_THROUGH, through.to_be_bytes());
+    }
+
+    db.write(batch)
+}
+
+/// Id of the last item merged by `merge_items`, `None` for a fresh
+/// database.
+pub fn merged_through(db: &DB) -> Result<Option<i64>, rocksdb::Error> {
+    Ok(
+        db.get_pinned(MERGED_THROUGH)?
+            .and_then(|id| <[u8; 8]>::try_from(&id[..]).ok())
+            .map(i64::from_be_bytes)
+    )
+}
+
+/// Writes every profile in the column family as a ragegun file. Keys are
+/// sorted by author already; the header needs the counts up front, so the
+/// family is read twice, like the spiller's runs.
+pub fn write_freqs<W: Write>(
+    db: &DB,
+    writer: &mut W,
+    mut on_author: impl FnMut(&[u8], &PooMapInner),
+    fn_feedback: impl FnMut(FnFeedback) -> (),
+) -> std::io::Result<()> {
+    let cf = db.cf_handle(PROFILES_CF).expect("profiles column family is missing");
+
+    let to_io = |e: rocksdb::Error| std::io::Error::new(std::io::ErrorKind::Other, e);
+
+    let mut sink = ProgressSink::new(fn_feedback);
+
+    sink.message("Saving: Counting profiles..");
+
+    let mut authors = 0u64;
+    let mut words = 0u64;
+
+    for entry in db.iterator_cf(cf, IteratorMode::Start) {
+        let (_, block) = entry.map_err(to_io)?;
+
+        if let Some((_, freqs)) = decode_block(&block) {
+            authors += 1;
+            words += freqs.len() as u64;
+        }
+    }
+
+    sink.message("Saving: Writing authors..");
+    sink.total(authors);
+
+    write_header(writer, authors, words)?;
+
+    let mut abuf = Vec::new();
+    let mut i = 0u64;
+
+    for entry in db.iterator_cf(cf, IteratorMode::Start) {
+        let (_, block) = entry.map_err(to_io)?;
+
+        // re-encoded rather than copied, decoding drops the same words the
+        // count above did
+        if let Some((author, freqs)) = decode_block(&block) {
+            abuf.clear();
+
+            encode_author(&mut abuf, &author, &freqs);
+
+            writer.write_all(&abuf)?;
+            on_author(&author, &freqs);
+
+            i += 1;
+
+            sink.progress(i);
+        }
+    }
+
+    sink.finish(i);
+
+    write_footer(writer)
+}
+
+/// Buffers author profiles and writes them to the profiles column family in
+/// batches. Values are ragegun author blocks, the same encoding as in
+/// .freqs files, so no new format is needed to read them back.
+pub struct ProfileWriter<'a> {
+    db: &'a DB,
+    batch: WriteBatch,
+    buf: Vec<u8>,
+    written: u64,
+}
+
+impl<'a> ProfileWriter<'a> {
+    pub fn new(db: &'a DB) -> Self {
+        Self {
+            db,
+            batch: WriteBatch::default(),
+            buf: Vec::new(),
+            written: 0,
+        }
+    }
+
+    pub fn put<K: WordKey>(&mut self, author: &[u8], freqs: &PooMapRoot<K, u64>) -> Result<(), rocksdb::Error> {
+        let db = self.db;
+        let cf = db.cf_handle(PROFILES_CF).expect("profiles column family is missing");
+
+        self.buf.clear();
+
+        encode_author(&mut self.buf, author, freqs);
+
+        self.batch.put_cf(cf, author, &self.buf);
+        self.written += 1;
+
+        if self.batch.len() >= WRITE_BATCH {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), rocksdb::Error> {
+        self.db.write(std::mem::take(&mut self.batch))
+    }
+
+    /// Flushes what's left, returning the number of profiles written.
+    pub fn finish(mut self) -> Result<u64, rocksdb::Error> {
+        self.flush()?;
+
+        Ok(self.written)
+    }
+}
+
+/// Looks up the profile of `author`.
+pub fn read_profile(db: &DB, author: &[u8]) -> Result<Option<PooMapInner>, rocksdb::Error> {
+    let cf = db.cf_handle(PROFILES_CF).expect("profiles column family is missing");
+
+    Ok(
+        db.get_pinned_cf(cf, author)?
+            .and_then(|block| try_deserialize_original(&block, |_| {}).into_values().next())
+    )
+}
@@ -0,0 +1,65 @@
+use std::borrow::Cow;
+
+use regex::Regex;
+
+/// Patterns removed by default: email addresses, phone numbers and runs of
+/// six or more digits (account, card and ID numbers), separators included.
+const DEFAULT_PATTERNS: [&str; 3] = [
+    r"[A-Za-z0-9._%+-]+(@|&#x40;)[A-Za-z0-9.-]+\.[A-Za-z]{2,}",
+    r"\+?\(?\d{2,4}\)?[\s.-]\d{3,4}[\s.-]\d{3,4}",
+    r"\d(?:[\s.-]?\d){5,}",
+];
+
+/// Removes personal data from comment text before it's tokenized, so it
+/// never reaches the frequency maps.
+#[derive(Debug, Clone)]
+pub struct Scrubber {
+    patterns: Vec<Regex>,
+}
+
+impl Default for Scrubber {
+    fn default() -> Self {
+        Self::new(&DEFAULT_PATTERNS).expect("default patterns are valid")
+    }
+}
+
+impl Scrubber {
+    pub fn new(patterns: &[&str]) -> Result<Self, regex::Error> {
+        Ok(Self {
+            patterns: patterns.iter().map(|p| Regex::new(p)).collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// The defaults plus one pattern per line of `text`; blank lines and
+    /// `#` comments are ignored.
+    pub fn with_patterns(text: &str) -> Result<Self, regex::Error> {
+        let mut scrubber = Self::default();
+
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            scrubber.patterns.push(Regex::new(line)?);
+        }
+
+        Ok(scrubber)
+    }
+
+    /// `text` with every match replaced by a space, so the words around it
+    /// stay apart.
+    pub fn scrub<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+
+        for pattern in self.patterns.iter() {
+            // borrowed when nothing matched
+            let scrubbed =
+                match pattern.replace_all(&text, " ") {
+                    Cow::Owned(scrubbed) => Some(scrubbed),
+                    Cow::Borrowed(_) => None,
+                };
+
+            if let Some(scrubbed) = scrubbed {
+                text = Cow::Owned(scrubbed);
+            }
+        }
+
+        text
+    }
+}
@@ -0,0 +1,1040 @@
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+
+use crate::analysis::{document_freqs, total_tokens};
+use crate::domains::is_domain;
+use crate::rng::Rng;
+use crate::text::STOPWORDS;
+use crate::text::text_item::{PooMap, PooMapInner};
+use crate::vocabulary::Vocabulary;
+
+/// Sparse vector of `(dimension, value)` pairs sorted by dimension.
+pub type SparseVec = Vec<(u32, f32)>;
+
+#[derive(Debug, Clone)]
+pub struct SegmentOptions {
+    pub segments: usize,
+    /// Number of words used as features.
+    pub vocabulary: usize,
+    /// Authors with fewer tokens than this are left unsegmented.
+    pub min_tokens: u64,
+    pub iterations: usize,
+    pub seed: u64,
+    /// Themes the first segments start from and are named after.
+    pub seeds: Vec<Seed>,
+    /// Levels of the segment tree, every level below the first splits each
+    /// segment into `branching` sub-segments.
+    pub depth: usize,
+    pub branching: usize,
+    /// Authors less similar than this to the segment they'd join are left
+    /// unsegmented rather than forced into it.
+    pub min_similarity: f32,
+    /// Bounds on the number of authors per first-level segment.
+    pub sizes: SizeLimits,
+    /// Keeps first-level segments within one author of the same size.
+    pub balanced: bool,
+}
+
+/// Bounds on segment sizes. Unconstrained k-means on HN tends to produce
+/// one giant generic segment and a few tiny ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SizeLimits {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl SizeLimits {
+    /// Limits that make `k` segments of `n` authors as equal as possible.
+    pub fn balanced(n: usize, k: usize) -> Self {
+        let k = k.max(1);
+
+        Self {
+            min: n / k,
+            max: Some((n + k - 1) / k),
+        }
+    }
+
+    pub fn is_unconstrained(&self) -> bool {
+        self.min == 0 && self.max.is_none()
+    }
+
+    /// The limits clamped so `k` segments can hold exactly `n` authors.
+    fn feasible(&self, n: usize, k: usize) -> Self {
+        let k = k.max(1);
+
+        Self {
+            min: self.min.min(n / k),
+            max: self.max.map(|max| max.max((n + k - 1) / k)),
+        }
+    }
+}
+
+impl Default for SegmentOptions {
+    fn default() -> Self {
+        Self {
+            segments: 16,
+            vocabulary: 4096,
+            min_tokens: 200,
+            iterations: 30,
+            seed: 0x5e9,
+            seeds: Vec::new(),
+            depth: 1,
+            branching: 4,
+            min_similarity: 0.0,
+            sizes: SizeLimits::default(),
+            balanced: false,
+        }
+    }
+}
+
+/// A named theme and the words that mark it, e.g. `crypto` and `bitcoin
+/// ethereum blockchain`.
+#[derive(Debug, Clone)]
+pub struct Seed {
+    pub label: String,
+    pub words: Vec<Box<[u8]>>,
+}
+
+/// Reads seeds written one per line as `label: word word ...`; words may
+/// also be separated by commas. Blank lines and `#` comments are ignored.
+pub fn parse_seeds(text: &str) -> Result<Vec<Seed>, String> {
+    text.lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'))
+        .map(|(i, line)| {
+            let (label, words) =
+                line.split_once(':')
+                    .ok_or_else(|| format!("line {}: expected `label: words`", i + 1))?;
+
+            // the tokenizer lowercases, seeds have to match its output
+            let words =
+                words.split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|w| !w.is_empty())
+                    .map(|w| w.to_lowercase().into_bytes().into_boxed_slice())
+                    .collect::<Vec<_>>();
+
+            if label.trim().is_empty() || words.is_empty() {
+                return Err(format!("line {}: seeds need a label and words", i + 1));
+            }
+
+            Ok(Seed { label: label.trim().to_string(), words })
+        })
+        .collect()
+}
+
+fn is_stopword(word: &[u8]) -> bool {
+    std::str::from_utf8(word)
+        .map(|w| STOPWORDS.contains(w))
+        .unwrap_or(false)
+}
+
+fn normalize_sparse(v: &mut SparseVec) {
+    let norm = v.iter().map(|(_, x)| x * x).sum::<f32>().sqrt();
+
+    if norm > 0.0 {
+        v.iter_mut().for_each(|(_, x)| *x /= norm);
+    }
+}
+
+fn normalize_dense(v: &mut [f32]) {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+#[inline(always)]
+pub fn dot(sparse: &SparseVec, dense: &[f32]) -> f32 {
+    sparse.iter().map(|(i, x)| x * dense[*i as usize]).sum()
+}
+
+fn densify(sparse: &SparseVec, dims: usize) -> Vec<f32> {
+    let mut dense = vec![0f32; dims];
+
+    for (i, x) in sparse.iter() {
+        dense[*i as usize] = *x;
+    }
+
+    dense
+}
+
+/// Maps frequency profiles onto L2-normalized tf-idf vectors over a fixed
+/// vocabulary.
+#[derive(Debug, Clone)]
+pub struct Vectorizer {
+    vocabulary: Vec<Box<[u8]>>,
+    idf: Vec<f32>,
+    index: FxHashMap<Box<[u8]>, u32>,
+}
+
+impl Vectorizer {
+    pub fn new(vocabulary: Vec<Box<[u8]>>, idf: Vec<f32>) -> Self {
+        let index =
+            vocabulary
+                .iter()
+                .enumerate()
+                .map(|(i, word)| (word.clone(), i as u32))
+                .collect();
+
+        Self {
+            vocabulary,
+            idf,
+            index,
+        }
+    }
+
+    /// Uses the `size` most widely used words, stopwords excluded.
+    pub fn fit(poo: &PooMap, size: usize) -> Self {
+        Self::from_doc_freqs(&document_freqs(poo), poo.len(), size)
+    }
+
+    /// Same as `fit`, from a vocabulary sidecar instead of the corpus.
+    pub fn fit_vocabulary(vocabulary: &Vocabulary, size: usize) -> Self {
+        Self::from_doc_freqs(&vocabulary.doc_freqs(), vocabulary.authors(), size)
+    }
+
+    fn from_doc_freqs(doc_freqs: &PooMapInner, authors: usize, size: usize) -> Self {
+        let mut words =
+            doc_freqs
+                .iter()
+                .filter(|(word, _)| word.len() > 1 && !is_stopword(word) && !is_domain(word))
+                .collect::<Vec<_>>();
+
+        words.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        words.truncate(size);
+
+        let authors = authors.max(1) as f32;
+
+        Self::new(
+            words.iter().map(|(word, _)| (*word).clone()).collect(),
+            words.iter().map(|(_, df)| (authors / **df as f32).ln() + 1.0).collect(),
+        )
+    }
+
+    pub fn dims(&self) -> usize {
+        self.vocabulary.len()
+    }
+
+    pub fn vocabulary(&self) -> &[Box<[u8]>] {
+        &self.vocabulary
+    }
+
+    pub fn idf(&self) -> &[f32] {
+        &self.idf
+    }
+
+    pub fn vectorize(&self, freqs: &PooMapInner) -> SparseVec {
+        let total = total_tokens(freqs).max(1) as f32;
+
+        let mut v =
+            freqs
+                .iter()
+                .filter_map(|(word, freq)| {
+                    self.index
+                        .get(word)
+                        .map(|&i| (i, *freq as f32 / total * self.idf[i as usize]))
+                })
+                .collect::<SparseVec>();
+
+        v.sort_unstable_by_key(|(i, _)| *i);
+
+        normalize_sparse(&mut v);
+
+        v
+    }
+
+    /// A vector weighting each of `words` by its idf, as if an author had
+    /// used each once. Empty if none of them are in the vocabulary.
+    pub fn vectorize_words(&self, words: &[Box<[u8]>]) -> SparseVec {
+        let mut v =
+            words.iter()
+                .filter_map(|word| self.index.get(word).map(|&i| (i, self.idf[i as usize])))
+                .collect::<SparseVec>();
+
+        v.sort_unstable_by_key(|(i, _)| *i);
+        v.dedup_by_key(|(i, _)| *i);
+
+        normalize_sparse(&mut v);
+
+        v
+    }
+}
+
+/// The centroid most similar to `v` and the cosine similarity to it.
+pub fn nearest(centroids: &[Vec<f32>], v: &SparseVec) -> (u32, f32) {
+    centroids
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (i as u32, dot(v, c)))
+        .fold((0, f32::MIN), |best, cur| if cur.1 > best.1 { cur } else { best })
+}
+
+/// Descends the segment tree from its roots to the leaf most similar to
+/// `v`, returning it and the similarity to it. Flat segmentations are all
+/// roots.
+pub fn descend(centroids: &[Vec<f32>], parents: &[Option<u32>], v: &SparseVec) -> (u32, f32) {
+    let mut parent = None;
+    let mut best = (0, f32::MIN);
+
+    loop {
+        let level =
+            centroids.iter()
+                .zip(parents.iter())
+                .enumerate()
+                .filter(|(_, (_, p))| **p == parent)
+                .map(|(i, (c, _))| (i as u32, dot(v, c)))
+                .fold(None, |best: Option<(u32, f32)>, cur| match best {
+                    Some(best) if best.1 >= cur.1 => Some(best),
+                    _ => Some(cur),
+                });
+
+        match level {
+            Some(next) => {
+                best = next;
+                parent = Some(next.0);
+            }
+            None => return best,
+        }
+    }
+}
+
+/// Per segment, the number of authors in it or any of its descendants.
+fn subtree_sizes(parents: &[Option<u32>], leaves: impl Iterator<Item = u32>) -> Vec<usize> {
+    let mut sizes = vec![0; parents.len()];
+
+    for leaf in leaves {
+        let mut segment = Some(leaf);
+
+        while let Some(s) = segment {
+            sizes[s as usize] += 1;
+            segment = parents[s as usize];
+        }
+    }
+
+    sizes
+}
+
+/// Assigns every vector to its most similar centroid that still has room,
+/// most similar pairs first: first until each centroid has `limits.min`
+/// vectors, then until each has at most `limits.max`.
+pub fn assign_limited(vectors: &[SparseVec], centroids: &[Vec<f32>], limits: SizeLimits) -> Vec<(u32, f32)> {
+    let k = centroids.len();
+    let limits = limits.feasible(vectors.len(), k);
+
+    let mut pairs =
+        vectors
+            .par_iter()
+            .enumerate()
+            .flat_map_iter(|(i, v)| {
+                centroids.iter()
+                    .enumerate()
+                    .map(move |(c, centroid)| (dot(v, centroid), i as u32, c as u32))
+            })
+            .collect::<Vec<_>>();
+
+    pairs.par_sort_unstable_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut assigned = vec![(u32::MAX, 0f32); vectors.len()];
+    let mut sizes = vec![0usize; k];
+
+    for cap in [limits.min, limits.max.unwrap_or(usize::MAX)] {
+        for (similarity, i, c) in pairs.iter() {
+            let (i, c) = (*i as usize, *c as usize);
+
+            if assigned[i].0 == u32::MAX && sizes[c] < cap {
+                assigned[i] = (c as u32, *similarity);
+                sizes[c] += 1;
+            }
+        }
+    }
+
+    assigned
+}
+
+/// Spherical k-means with k-means++ seeding on cosine distance.
+///
+/// `on_iteration` is called after every pass with its index, the number of
+/// vectors that changed segment and the resulting segment sizes.
+pub fn kmeans(
+    vectors: &[SparseVec],
+    dims: usize,
+    k: usize,
+    iterations: usize,
+    seed: u64,
+    on_iteration: impl FnMut(usize, usize, Vec<usize>),
+) -> Vec<Vec<f32>> {
+    kmeans_from(vectors, dims, k, Vec::new(), SizeLimits::default(), iterations, seed, on_iteration)
+}
+
+/// Like `kmeans`, starting from the `initial` centroids and seeding only
+/// the remaining ones, assigning within `limits` on every pass.
+#[allow(clippy::too_many_arguments)]
+pub fn kmeans_from(
+    vectors: &[SparseVec],
+    dims: usize,
+    k: usize,
+    initial: Vec<Vec<f32>>,
+    limits: SizeLimits,
+    iterations: usize,
+    seed: u64,
+    mut on_iteration: impl FnMut(usize, usize, Vec<usize>),
+) -> Vec<Vec<f32>> {
+    let n = vectors.len();
+    let k = k.min(n);
+
+    if k == 0 {
+        return Vec::new();
+    }
+
+    let mut rng = Rng::new(seed);
+
+    let mut centroids = initial;
+
+    centroids.truncate(k);
+
+    if centroids.is_empty() {
+        centroids.push(densify(&vectors[rng.below(n as u64) as usize], dims));
+    }
+
+    let mut distances =
+        vectors
+            .par_iter()
+            .map(|v| centroids.iter().map(|c| 1.0 - dot(v, c)).fold(f32::MAX, f32::min))
+            .collect::<Vec<f32>>();
+
+    while centroids.len() < k {
+        let total = distances.iter().map(|d| (d.max(0.0) as f64).powi(2)).sum::<f64>();
+
+        let pick =
+            if total <= 0.0 {
+                rng.below(n as u64) as usize
+            } else {
+                let mut target = rng.unit() * total;
+
+                distances
+                    .iter()
+                    .position(|d| {
+                        target -= (d.max(0.0) as f64).powi(2);
+                        target <= 0.0
+                    })
+                    .unwrap_or(n - 1)
+            };
+
+        centroids.push(densify(&vectors[pick], dims));
+
+        let centroid = centroids.last().unwrap();
+
+        distances
+            .par_iter_mut()
+            .zip(vectors.par_iter())
+            .for_each(|(d, v)| *d = d.min(1.0 - dot(v, centroid)));
+    }
+
+    let mut assignments = vec![u32::MAX; n];
+
+    for iteration in 0..iterations {
+        let next =
+            if limits.is_unconstrained() {
+                vectors
+                    .par_iter()
+                    .map(|v| nearest(&centroids, v).0)
+                    .collect::<Vec<_>>()
+            } else {
+                assign_limited(vectors, &centroids, limits)
+                    .into_iter()
+                    .map(|(segment, _)| segment)
+                    .collect()
+            };
+
+        let changed =
+            next.iter()
+                .zip(assignments.iter())
+                .filter(|(a, b)| a != b)
+                .count();
+
+        assignments = next;
+
+        let mut sizes = vec![0; k];
+
+        for segment in assignments.iter() {
+            sizes[*segment as usize] += 1;
+        }
+
+        on_iteration(iteration, changed, sizes);
+
+        let mut sums = vec![vec![0f32; dims]; k];
+
+        for (v, segment) in vectors.iter().zip(assignments.iter()) {
+            for (i, x) in v.iter() {
+                sums[*segment as usize][*i as usize] += x;
+            }
+        }
+
+        // empty segments keep their previous centroid
+        for (centroid, mut sum) in centroids.iter_mut().zip(sums) {
+            if sum.iter().any(|x| *x != 0.0) {
+                normalize_dense(&mut sum);
+
+                *centroid = sum;
+            }
+        }
+
+        if changed == 0 {
+            break;
+        }
+    }
+
+    centroids
+}
+
+/// Why an author isn't in any segment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unsegmented {
+    /// Fewer tokens than asked for, or not a single vocabulary word.
+    TooLittleData,
+    /// Too dissimilar to every segment, with the similarity to the closest.
+    Outlier(f32),
+}
+
+/// Vectorizes the authors `keep` accepts, sorted by name. Those it rejects
+/// and those without a single vocabulary word are returned as unsegmented.
+#[allow(clippy::type_complexity)]
+fn vectorize_authors<'a>(
+    poo: &'a PooMap,
+    vectorizer: &Vectorizer,
+    keep: impl Fn(&[u8], &PooMapInner) -> bool + Sync,
+) -> (Vec<&'a Box<[u8]>>, Vec<SparseVec>, Vec<(Box<[u8]>, Unsegmented)>) {
+    let mut all =
+        poo.par_iter()
+            .map(|(author, freqs)| {
+                let v =
+                    if keep(author, freqs) {
+                        vectorizer.vectorize(freqs)
+                    } else {
+                        SparseVec::new()
+                    };
+
+                (author, v)
+            })
+            .collect::<Vec<_>>();
+
+    all.par_sort_unstable_by(|a, b| a.0.cmp(b.0));
+
+    let (mut authors, mut vectors, mut unsegmented) = (Vec::new(), Vec::new(), Vec::new());
+
+    for (author, v) in all {
+        if v.is_empty() {
+            unsegmented.push((author.clone(), Unsegmented::TooLittleData));
+        } else {
+            authors.push(author);
+            vectors.push(v);
+        }
+    }
+
+    (authors, vectors, unsegmented)
+}
+
+/// Moves the assignments less similar than `min_similarity` into
+/// `unsegmented`, which ends up sorted by author.
+fn split_outliers(
+    assignments: &mut Vec<(Box<[u8]>, u32, f32)>,
+    unsegmented: &mut Vec<(Box<[u8]>, Unsegmented)>,
+    min_similarity: f32,
+    exempt: impl Fn(&[u8]) -> bool,
+) {
+    assignments.retain(|(author, _, similarity)| {
+        let outlier = *similarity < min_similarity && !exempt(author);
+
+        if outlier {
+            unsegmented.push((author.clone(), Unsegmented::Outlier(*similarity)));
+        }
+
+        !outlier
+    });
+
+    unsegmented.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+}
+
+#[derive(Debug, Clone)]
+pub struct Segmentation {
+    pub vectorizer: Vectorizer,
+    pub centroids: Vec<Vec<f32>>,
+    /// `(author, segment, similarity to the centroid)`, sorted by author.
+    pub assignments: Vec<(Box<[u8]>, u32, f32)>,
+    /// Per segment, the seed it was named after, or empty.
+    pub labels: Vec<String>,
+    /// Per segment, the segment it splits, `None` for the first level.
+    /// Authors are assigned to leaves.
+    pub parents: Vec<Option<u32>>,
+    /// Authors in no segment and why, sorted by author.
+    pub unsegmented: Vec<(Box<[u8]>, Unsegmented)>,
+}
+
+impl Segmentation {
+    pub fn assign(&self, freqs: &PooMapInner) -> (u32, f32) {
+        descend(&self.centroids, &self.parents, &self.vectorizer.vectorize(freqs))
+    }
+
+    pub fn segment_of(&self, author: &[u8]) -> Option<(u32, f32)> {
+        self.assignments
+            .binary_search_by(|(a, _, _)| a[..].cmp(author))
+            .ok()
+            .map(|i| (self.assignments[i].1, self.assignments[i].2))
+    }
+
+    /// Whether `leaf` is `segment` or one of its sub-segments.
+    pub fn contains(&self, segment: u32, leaf: u32) -> bool {
+        let mut current = Some(leaf);
+
+        while let Some(s) = current {
+            if s == segment {
+                return true;
+            }
+
+            current = self.parents[s as usize];
+        }
+
+        false
+    }
+
+    /// Authors per segment, those of sub-segments included.
+    pub fn sizes(&self) -> Vec<usize> {
+        subtree_sizes(&self.parents, self.assignments.iter().map(|(_, segment, _)| *segment))
+    }
+
+    /// Highest weighted words of a segment's centroid.
+    pub fn top_terms(&self, segment: u32, n: usize) -> Vec<(&[u8], f32)> {
+        let mut terms =
+            self.centroids[segment as usize]
+                .iter()
+                .enumerate()
+                .map(|(i, w)| (&self.vectorizer.vocabulary[i][..], *w))
+                .collect::<Vec<_>>();
+
+        terms.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        terms.truncate(n);
+        terms
+    }
+
+    /// The results to save, with the `terms` top terms of every segment.
+    pub fn to_assignments(&self, terms: usize) -> Assignments {
+        let segments =
+            self.sizes()
+                .into_iter()
+                .enumerate()
+                .map(|(i, size)| {
+                    SegmentInfo {
+                        label: self.labels[i].clone(),
+                        parent: self.parents[i],
+                        size: size as u64,
+                        terms: self.top_terms(i as u32, terms).into_iter().map(|(word, _)| word.into()).collect(),
+                    }
+                })
+                .collect();
+
+        Assignments {
+            segments,
+            authors: self.assignments.clone(),
+            unsegmented: self.unsegmented.clone(),
+            model: Some(SegmentModel {
+                vectorizer: self.vectorizer.clone(),
+                centroids: self.centroids.clone(),
+                parents: self.parents.clone(),
+            }),
+        }
+    }
+}
+
+/// What it takes to assign authors to the segments of a past run.
+#[derive(Debug, Clone)]
+pub struct SegmentModel {
+    pub vectorizer: Vectorizer,
+    pub centroids: Vec<Vec<f32>>,
+    pub parents: Vec<Option<u32>>,
+}
+
+impl SegmentModel {
+    pub fn assign(&self, freqs: &PooMapInner) -> (u32, f32) {
+        descend(&self.centroids, &self.parents, &self.vectorizer.vectorize(freqs))
+    }
+}
+
+/// What a saved segmentation records about one of its segments.
+#[derive(Debug, Clone, Default)]
+pub struct SegmentInfo {
+    /// Empty unless the segment was named.
+    pub label: String,
+    /// The segment this one splits, if any.
+    pub parent: Option<u32>,
+    /// Authors in the segment or its sub-segments.
+    pub size: u64,
+    /// Highest weighted words of the centroid.
+    pub terms: Vec<Box<[u8]>>,
+}
+
+/// The results of a segmentation, as written by
+/// `serializer::write_assignments`. Segment ids index `segments`.
+#[derive(Debug, Clone, Default)]
+pub struct Assignments {
+    pub segments: Vec<SegmentInfo>,
+    /// `(author, segment, similarity to the centroid)`, sorted by author.
+    pub authors: Vec<(Box<[u8]>, u32, f32)>,
+    /// Authors in no segment and why, sorted by author. Empty in files
+    /// written before version 4.
+    pub unsegmented: Vec<(Box<[u8]>, Unsegmented)>,
+    /// Missing from files written before version 2.
+    pub model: Option<SegmentModel>,
+}
+
+impl Assignments {
+    /// Assigns the authors of `poo` with at least `min_tokens` tokens to
+    /// these segments without re-clustering, so segment ids and labels carry
+    /// over from run to run. Authors less similar than `min_similarity` to
+    /// their segment are left unsegmented. `None` without a model.
+    pub fn reassign(&self, poo: &PooMap, min_tokens: u64, min_similarity: f32) -> Option<Assignments> {
+        let model = self.model.as_ref()?;
+
+        let (authors, vectors, mut unsegmented) =
+            vectorize_authors(poo, &model.vectorizer, |_, freqs| {
+                !model.centroids.is_empty() && total_tokens(freqs) >= min_tokens
+            });
+
+        let mut authors =
+            authors
+                .par_iter()
+                .zip(vectors.par_iter())
+                .map(|(author, v)| {
+                    let (segment, similarity) = descend(&model.centroids, &model.parents, v);
+
+                    ((*author).clone(), segment, similarity)
+                })
+                .collect::<Vec<_>>();
+
+        split_outliers(&mut authors, &mut unsegmented, min_similarity, |_| false);
+
+        let mut segments = self.segments.clone();
+
+        let sizes = subtree_sizes(&model.parents, authors.iter().map(|(_, segment, _)| *segment));
+
+        for (segment, size) in segments.iter_mut().zip(sizes) {
+            segment.size = size as u64;
+        }
+
+        Some(Assignments {
+            segments,
+            authors,
+            unsegmented,
+            model: Some(model.clone()),
+        })
+    }
+
+    /// `corpus.freqs` is segmented into `corpus.freqs.segments`.
+    pub fn sidecar_path(location: &str) -> String {
+        format!("{}.segments", location)
+    }
+
+    pub fn segment_of(&self, author: &[u8]) -> Option<(u32, f32)> {
+        self.authors
+            .binary_search_by(|(a, _, _)| a[..].cmp(author))
+            .ok()
+            .map(|i| (self.authors[i].1, self.authors[i].2))
+    }
+
+    /// The segmentation these were saved from, so reports and the like can
+    /// use it without re-clustering. `None` without a model.
+    pub fn to_segmentation(&self) -> Option<Segmentation> {
+        let model = self.model.clone()?;
+
+        Some(Segmentation {
+            vectorizer: model.vectorizer,
+            centroids: model.centroids,
+            assignments: self.authors.clone(),
+            labels: self.segments.iter().map(|segment| segment.label.clone()).collect(),
+            parents: model.parents,
+            unsegmented: self.unsegmented.clone(),
+        })
+    }
+}
+
+pub fn segment(poo: &PooMap, options: &SegmentOptions) -> Segmentation {
+    segment_with(poo, options, |_, _, _| {})
+}
+
+/// Like `segment`, reporting each k-means pass as `kmeans` does.
+pub fn segment_with(
+    poo: &PooMap,
+    options: &SegmentOptions,
+    on_iteration: impl FnMut(usize, usize, Vec<usize>),
+) -> Segmentation {
+    let vectorizer = Vectorizer::fit(poo, options.vocabulary);
+
+    let (authors, vectors, mut unsegmented) =
+        vectorize_authors(poo, &vectorizer, |_, freqs| total_tokens(freqs) >= options.min_tokens);
+
+    // seeds without a word in the vocabulary can't steer anything
+    let seeds =
+        options.seeds
+            .iter()
+            .map(|seed| (seed, vectorizer.vectorize_words(&seed.words)))
+            .filter(|(_, v)| !v.is_empty())
+            .collect::<Vec<_>>();
+
+    let k = options.segments.max(seeds.len());
+
+    let limits =
+        if options.balanced {
+            SizeLimits::balanced(vectors.len(), k)
+        } else {
+            options.sizes
+        };
+
+    let mut centroids =
+        kmeans_from(
+            &vectors,
+            vectorizer.dims(),
+            k,
+            seeds.iter().map(|(_, v)| densify(v, vectorizer.dims())).collect(),
+            limits,
+            options.iterations,
+            options.seed,
+            on_iteration,
+        );
+
+    let mut labels = label_segments(&centroids, &seeds);
+
+    let mut parents = vec![None; centroids.len()];
+
+    // sub-segments are split without limits
+    let mut leaves =
+        if limits.is_unconstrained() || centroids.is_empty() {
+            vectors
+                .par_iter()
+                .map(|v| nearest(&centroids, v))
+                .collect::<Vec<_>>()
+        } else {
+            assign_limited(&vectors, &centroids, limits)
+        };
+
+    // every further level splits the segments of the one above
+    let mut level = 0..centroids.len();
+
+    for depth in 1..options.depth {
+        let next = centroids.len();
+
+        for parent in level.clone() {
+            let members =
+                (0..vectors.len())
+                    .filter(|i| leaves[*i].0 == parent as u32)
+                    .collect::<Vec<_>>();
+
+            if members.len() <= options.branching {
+                continue;
+            }
+
+            let children =
+                kmeans(
+                    &members.iter().map(|i| vectors[*i].clone()).collect::<Vec<_>>(),
+                    vectorizer.dims(),
+                    options.branching,
+                    options.iterations,
+                    options.seed.wrapping_add((depth * next + parent) as u64),
+                    |_, _, _| {},
+                );
+
+            let first = centroids.len() as u32;
+
+            for i in members {
+                let (child, similarity) = nearest(&children, &vectors[i]);
+
+                leaves[i] = (first + child, similarity);
+            }
+
+            parents.extend(children.iter().map(|_| Some(parent as u32)));
+            centroids.extend(children);
+        }
+
+        level = next..centroids.len();
+    }
+
+    labels.resize(centroids.len(), String::new());
+
+    let mut assignments =
+        authors
+            .iter()
+            .zip(leaves)
+            .map(|(author, (segment, similarity))| ((*author).clone(), segment, similarity))
+            .collect();
+
+    split_outliers(&mut assignments, &mut unsegmented, options.min_similarity, |_| false);
+
+    Segmentation {
+        vectorizer,
+        centroids,
+        assignments,
+        labels,
+        parents,
+        unsegmented,
+    }
+}
+
+/// Names segments after the seeds closest to their centroids, closest pairs
+/// first and each seed at most once, since segments drift away from the
+/// seeds they started from.
+fn label_segments(centroids: &[Vec<f32>], seeds: &[(&Seed, SparseVec)]) -> Vec<String> {
+    let mut pairs =
+        centroids.iter()
+            .enumerate()
+            .flat_map(|(segment, c)| {
+                seeds.iter()
+                    .enumerate()
+                    .map(move |(seed, (_, v))| (segment, seed, dot(v, c)))
+            })
+            .filter(|(_, _, similarity)| *similarity > 0.0)
+            .collect::<Vec<_>>();
+
+    pairs.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut labels = vec![String::new(); centroids.len()];
+    let mut used = vec![false; seeds.len()];
+
+    for (segment, seed, _) in pairs {
+        if labels[segment].is_empty() && !used[seed] {
+            labels[segment] = seeds[seed].0.label.clone();
+            used[seed] = true;
+        }
+    }
+
+    labels
+}
+
+/// Reads hand-labeled users, one `user<TAB>label` or `user,label` per line.
+/// Blank lines and `#` comments are ignored.
+pub fn parse_labeled_users(text: &str) -> Result<Vec<(Box<[u8]>, String)>, String> {
+    text.lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'))
+        .map(|(i, line)| {
+            let (user, label) =
+                line.split_once('\t')
+                    .or_else(|| line.split_once(','))
+                    .ok_or_else(|| format!("line {}: expected `user<TAB>label`", i + 1))?;
+
+            let (user, label) = (user.trim(), label.trim());
+
+            if user.is_empty() || label.is_empty() {
+                return Err(format!("line {}: expected `user<TAB>label`", i + 1));
+            }
+
+            Ok((user.as_bytes().into(), label.to_string()))
+        })
+        .collect()
+}
+
+/// Segments by example: every label's centroid starts as the mean of the
+/// users labeled with it, then all authors are assigned to the nearest
+/// label and the centroids recomputed, labeled users staying put, for up to
+/// `options.iterations` passes. `options.segments` and `options.seeds` are
+/// ignored, there's a segment per label.
+pub fn propagate_labels(
+    poo: &PooMap,
+    labeled: &[(Box<[u8]>, String)],
+    options: &SegmentOptions,
+) -> Segmentation {
+    let vectorizer = Vectorizer::fit(poo, options.vocabulary);
+    let dims = vectorizer.dims();
+
+    let mut labels = Vec::<String>::new();
+
+    // labeled users are used however little they wrote
+    let pinned =
+        labeled.iter()
+            .filter(|(user, _)| poo.contains_key(user))
+            .map(|(user, label)| {
+                let segment =
+                    match labels.iter().position(|l| l == label) {
+                        Some(i) => i,
+                        None => {
+                            labels.push(label.clone());
+                            labels.len() - 1
+                        }
+                    };
+
+                (user.clone(), segment as u32)
+            })
+            .collect::<FxHashMap<_, _>>();
+
+    let (authors, vectors, mut unsegmented) =
+        vectorize_authors(poo, &vectorizer, |author, freqs| {
+            pinned.contains_key(author) || total_tokens(freqs) >= options.min_tokens
+        });
+
+    let centroids_of = |assignments: &[u32]| {
+        let mut sums = vec![vec![0f32; dims]; labels.len()];
+
+        for (v, segment) in vectors.iter().zip(assignments) {
+            if *segment != u32::MAX {
+                for (i, x) in v.iter() {
+                    sums[*segment as usize][*i as usize] += x;
+                }
+            }
+        }
+
+        sums.iter_mut().for_each(|c| normalize_dense(c));
+        sums
+    };
+
+    let mut assignments =
+        authors.iter()
+            .map(|author| pinned.get(*author).copied().unwrap_or(u32::MAX))
+            .collect::<Vec<_>>();
+
+    let mut centroids = centroids_of(&assignments);
+
+    for _ in 0..options.iterations {
+        let next =
+            authors
+                .par_iter()
+                .zip(vectors.par_iter())
+                .map(|(author, v)| {
+                    pinned.get(*author).copied().unwrap_or_else(|| nearest(&centroids, v).0)
+                })
+                .collect::<Vec<_>>();
+
+        let changed = next != assignments;
+
+        assignments = next;
+        centroids = centroids_of(&assignments);
+
+        if !changed {
+            break;
+        }
+    }
+
+    let mut assignments =
+        authors
+            .iter()
+            .zip(vectors.iter())
+            .zip(assignments)
+            .filter(|_| !centroids.is_empty())
+            .map(|((author, v), segment)| {
+                let segment = if segment == u32::MAX { nearest(&centroids, v).0 } else { segment };
+
+                ((*author).clone(), segment, dot(v, &centroids[segment as usize]))
+            })
+            .collect();
+
+    // labeled users are where they were put, however far from the rest
+    split_outliers(&mut assignments, &mut unsegmented, options.min_similarity, |author| pinned.contains_key(author));
+
+    Segmentation {
+        vectorizer,
+        parents: vec![None; centroids.len()],
+        centroids,
+        assignments,
+        labels,
+        unsegmented,
+    }
+}
@@ -0,0 +1,155 @@
+use std::ops::AddAssign;
+
+use lazy_static::lazy_static;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::total_tokens;
+use crate::text::text_item::PooMapInner;
+
+/// Normalizes a text's summed valence into -1..1, as VADER does.
+const ALPHA: f64 = 15.0;
+
+/// Valence scale of a following word after a negation.
+const NEGATION_SCALAR: f64 = -0.74;
+
+/// How far back a negation or booster reaches.
+const WINDOW: usize = 3;
+
+lazy_static! {
+    /// Word to valence, -4 to 4.
+    pub static ref VALENCES: FxHashMap<Box<[u8]>, f64> =
+        include_str!("./text/sentimentwords.txt")
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (word, valence) = line.split_once(',').expect("lexicon lines are word,valence");
+
+                (Box::from(word.as_bytes()), valence.parse().expect("valences are numbers"))
+            })
+            .collect();
+
+    static ref NEGATIONS: FxHashSet<&'static str> =
+        [
+            "not", "no", "never", "nothing", "nobody", "none", "neither", "nor", "hardly", "without",
+            "dont", "doesnt", "didnt", "isnt", "wasnt", "arent", "werent", "cant", "cannot",
+            "couldnt", "wont", "wouldnt", "shouldnt", "aint",
+        ]
+        .into_iter()
+        .collect();
+
+    // what a preceding word adds to the intensity of a sentiment word
+    static ref BOOSTERS: FxHashMap<&'static str, f64> =
+        [
+            ("very", 0.293), ("really", 0.293), ("extremely", 0.293), ("incredibly", 0.293),
+            ("absolutely", 0.293), ("totally", 0.293), ("completely", 0.293), ("so", 0.293),
+            ("super", 0.293), ("truly", 0.293), ("utterly", 0.293), ("quite", 0.2),
+            ("slightly", -0.293), ("somewhat", -0.293), ("barely", -0.293), ("kinda", -0.293),
+            ("marginally", -0.293), ("almost", -0.293),
+        ]
+        .into_iter()
+        .collect();
+}
+
+/// Additive sentiment counts of a profile, so segments can sum their
+/// members' before scoring.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tone {
+    pub tokens: u64,
+    /// Uses of words with a valence.
+    pub rated: u64,
+    pub positive: u64,
+    pub negative: u64,
+    /// Sum of the valences of every use.
+    pub valence: f64,
+}
+
+impl Tone {
+    pub fn of(freqs: &PooMapInner) -> Self {
+        let mut tone = Self { tokens: total_tokens(freqs), ..Default::default() };
+
+        for (word, freq) in freqs {
+            if let Some(valence) = VALENCES.get(word) {
+                tone.rated += freq;
+                tone.valence += valence * *freq as f64;
+
+                if *valence > 0.0 {
+                    tone.positive += freq;
+                } else if *valence < 0.0 {
+                    tone.negative += freq;
+                }
+            }
+        }
+
+        tone
+    }
+
+    pub fn sentiment(&self) -> Sentiment {
+        let tokens = self.tokens.max(1) as f64;
+
+        Sentiment {
+            valence: self.valence / self.rated.max(1) as f64,
+            positive: self.positive as f64 / tokens,
+            negative: self.negative as f64 / tokens,
+        }
+    }
+}
+
+impl AddAssign for Tone {
+    fn add_assign(&mut self, other: Self) {
+        self.tokens += other.tokens;
+        self.rated += other.rated;
+        self.positive += other.positive;
+        self.negative += other.negative;
+        self.valence += other.valence;
+    }
+}
+
+/// Average sentiment from word counts alone. Without word order there are
+/// no negations or boosters, so it measures word choice more than stance.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Sentiment {
+    /// Mean valence of the words that have one, -4 to 4.
+    pub valence: f64,
+    /// Share of all tokens that are positive words.
+    pub positive: f64,
+    /// Share of all tokens that are negative words.
+    pub negative: f64,
+}
+
+/// VADER-style compound score of a single text, -1 to 1. Sentiment words
+/// are scaled by boosters and flipped by negations up to three words
+/// before them.
+pub fn score_text(text: &str) -> f64 {
+    let text =
+        text.chars()
+            .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+            .flat_map(char::to_lowercase)
+            .collect::<String>();
+
+    let words = text.split_whitespace().collect::<Vec<_>>();
+
+    let sum =
+        words.iter()
+            .enumerate()
+            .filter_map(|(i, word)| VALENCES.get(word.as_bytes()).map(|valence| (i, *valence)))
+            .map(|(i, valence)| {
+                let before = &words[i.saturating_sub(WINDOW)..i];
+
+                let boost =
+                    before.iter()
+                        .filter_map(|word| BOOSTERS.get(word))
+                        .sum::<f64>();
+
+                let valence = valence + valence.signum() * boost;
+
+                if before.iter().any(|word| NEGATIONS.contains(word)) {
+                    valence * NEGATION_SCALAR
+                } else {
+                    valence
+                }
+            })
+            .sum::<f64>();
+
+    sum / (sum * sum + ALPHA).sqrt()
+}
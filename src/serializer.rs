@@ -1,8 +1,18 @@
+use std::collections::HashSet;
 use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
 
-use zstd::zstd_safe::WriteBuf;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::text::text_item::{PooMap, PooMapInner};
+use crate::matcher::AuthorFold;
+use crate::optout::is_opted_out;
+use crate::segment::{Assignments, SegmentInfo, SegmentModel, Unsegmented, Vectorizer};
+use crate::text::interner::WordKey;
+use crate::text::text_item::{PooMap, PooMapBase, PooMapInner, PooMapRoot};
 
 pub enum FnFeedback {
     Message(String),
@@ -11,89 +21,278 @@ pub enum FnFeedback {
     Tick,
 }
 
+/// Wraps a feedback closure and throttles `Progress` events.
+///
+/// Progress is reported at most once per `interval`, and the clock is only
+/// consulted every `CHECK_EVERY` calls, so calling `progress` from a hot
+/// loop costs a counter increment most of the time.
+pub struct ProgressSink<F: FnMut(FnFeedback)> {
+    fn_feedback: F,
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    interval: Duration,
+    // browsers have no `Instant`, so there progress is throttled by the call
+    // count alone
+    #[cfg(not(target_arch = "wasm32"))]
+    last: Instant,
+    calls: u32,
+}
+
+impl<F: FnMut(FnFeedback)> ProgressSink<F> {
+    const CHECK_EVERY: u32 = 1024;
+
+    pub fn new(fn_feedback: F) -> Self {
+        Self::with_interval(fn_feedback, Duration::from_millis(100))
+    }
+
+    pub fn with_interval(fn_feedback: F, interval: Duration) -> Self {
+        Self {
+            fn_feedback,
+            interval,
+            #[cfg(not(target_arch = "wasm32"))]
+            last: Instant::now(),
+            calls: 0,
+        }
+    }
+
+    pub fn message(&mut self, msg: impl Into<String>) {
+        (self.fn_feedback)(FnFeedback::Message(msg.into()));
+    }
+
+    pub fn total(&mut self, total: u64) {
+        (self.fn_feedback)(FnFeedback::Total(total));
+    }
+
+    #[inline(always)]
+    pub fn progress(&mut self, progress: u64) {
+        self.calls += 1;
+
+        if self.calls < Self::CHECK_EVERY {
+            return;
+        }
+
+        self.calls = 0;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if self.last.elapsed() < self.interval {
+                return;
+            }
+
+            self.last = Instant::now();
+        }
+
+        (self.fn_feedback)(FnFeedback::Progress(progress));
+    }
+
+    /// Reports `progress` unconditionally, e.g. once a stage is done.
+    pub fn finish(&mut self, progress: u64) {
+        self.calls = 0;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.last = Instant::now();
+        }
+
+        (self.fn_feedback)(FnFeedback::Progress(progress));
+    }
+}
+
+// number of authors encoded in parallel per batch
+const SERIALIZE_CHUNK: usize = 4096;
+
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Reads a ragegun file, decrypting and decompressing it as needed.
+pub fn read_file(path: &Path) -> std::io::Result<Vec<u8>> {
+    #[cfg(feature = "native")]
+    crate::audit::read(&path.to_string_lossy())?;
+
+    let buf = std::fs::read(path)?;
+
+    #[cfg(feature = "native")]
+    let buf = crate::crypto::decrypt(buf)?;
+
+    decompress(buf)
+}
+
+/// Decompresses `buf` if it is zstd-compressed, otherwise returns it as is.
+pub fn decompress(buf: Vec<u8>) -> std::io::Result<Vec<u8>> {
+    if !buf.starts_with(&ZSTD_MAGIC) {
+        return Ok(buf);
+    }
+
+    #[cfg(feature = "native")]
+    return zstd::decode_all(buf.as_slice());
+
+    // the pure Rust decoder is slower, but builds for wasm
+    #[cfg(not(feature = "native"))]
+    {
+        use std::io::Read;
+
+        let mut source = buf.as_slice();
+        let mut out = Vec::new();
+
+        ruzstd::StreamingDecoder::new(&mut source)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
+            .read_to_end(&mut out)?;
+
+        Ok(out)
+    }
+}
+
 #[inline(always)]
-pub fn serialize_with_writer<W: Write>(
-    data: &PooMap,
+pub fn serialize_with_writer<W: Write + Send, K: WordKey + Sync>(
+    data: &PooMapBase<PooMapRoot<K, u64>>,
     writer: &mut W,
-    mut fn_feedback: impl FnMut(FnFeedback) -> (),
+    fn_feedback: impl FnMut(FnFeedback) -> (),
 ) -> std::io::Result<()> {
-    let serbuf = data.iter().collect::<Vec<_>>();
+    let mut sink = ProgressSink::new(fn_feedback);
+
+    // the maps are unordered, sort authors and words so the output is stable
+    let mut serbuf = data.iter().collect::<Vec<_>>();
+    serbuf.par_sort_unstable_by(|a, b| a.0.cmp(b.0));
 
     let mut i = 0u64;
 
-    fn_feedback(FnFeedback::Message("Saving: Writing authors..".into()));
-    fn_feedback(FnFeedback::Total(serbuf.len() as u64));
+    sink.message("Saving: Writing authors..");
+    sink.total(serbuf.len() as u64);
 
-    // write magic
-    writer.write_all(b"ragegun")?;
+    let word_count = serbuf.iter().map(|(_, v)| v.len()).sum::<usize>() as u64;
 
-    // write version (1u32)
-    writer.write_all(&1u32.to_be_bytes())?;
+    write_header(writer, serbuf.len() as u64, word_count)?;
 
-    // write author count (u64)
-    writer.write_all(&(serbuf.len() as u64).to_be_bytes())?;
+    // encode the next batch of author blocks while the current one is
+    // being written, so encoding overlaps with the (compressing) writer
+    let mut chunks = serbuf.chunks(SERIALIZE_CHUNK);
+    let mut pending = chunks.next().map(encode_authors);
 
-    // write word count
-    let word_count = serbuf.iter().map(|(_, v)| v.len()).sum::<usize>() as u64;
-    writer.write_all(&word_count.to_be_bytes())?;
+    while let Some(blocks) = pending {
+        let (written, next) =
+            rayon::join(
+                || -> std::io::Result<()> {
+                    for block in blocks.iter() {
+                        writer.write_all(block.as_slice())?;
+                    }
 
-    for (author, freqs) in serbuf {
-        let mut abuf = Vec::new();
+                    Ok(())
+                },
+                || chunks.next().map(encode_authors),
+            );
 
-        abuf.extend_from_slice(&[author.as_slice(), &[245, 0]].concat());
+        written?;
 
-        for (word, freq) in freqs {
-            abuf.extend_from_slice(word.as_slice());
+        i += blocks.len() as u64;
 
-            match *freq {
-                x if freq <= &255u64 => {
-                    abuf.extend_from_slice(
-                        &[
-                            (x as u8).to_be_bytes().as_slice(),
-                            [255u8, 0u8].as_slice(),
-                        ]
-                            .concat(),
-                    );
-                }
-                x if freq <= &(u32::MAX as u64) => {
-                    abuf.extend_from_slice(
-                        &[
-                            (x as u32).to_be_bytes().as_slice(),
-                            [254, 0].as_slice(),
-                        ]
-                            .concat(),
-                    );
-                }
-                x => {
-                    abuf.extend_from_slice(
-                        &[
-                            (x as u64).to_be_bytes().as_slice(),
-                            [253, 0].as_slice(),
-                        ]
-                            .concat(),
-                    );
-                }
-            }
-        }
+        sink.progress(i);
 
-        abuf.extend_from_slice(&[244, 0]);
+        pending = next;
+    }
 
-        writer.write_all(abuf.as_slice())?;
+    sink.finish(i);
 
-        i += 1;
+    write_footer(writer)
+}
+
+fn encode_authors<K: WordKey + Sync>(
+    authors: &[(&Box<[u8]>, &PooMapRoot<K, u64>)],
+) -> Vec<Vec<u8>> {
+    authors
+        .par_iter()
+        .map(|(author, freqs)| {
+            let mut abuf = Vec::new();
+
+            encode_author(&mut abuf, author, freqs);
+
+            abuf
+        })
+        .collect()
+}
+
+pub fn write_header<W: Write>(
+    writer: &mut W,
+    authors: u64,
+    words: u64,
+) -> std::io::Result<()> {
+    write_header_version(writer, 1, authors, words)
+}
+
+fn write_header_version<W: Write>(
+    writer: &mut W,
+    version: u32,
+    authors: u64,
+    words: u64,
+) -> std::io::Result<()> {
+    // write magic
+    writer.write_all(b"ragegun")?;
 
-        if i % 1000 == 0 {
-            fn_feedback(FnFeedback::Progress(i as u64));
+    // write version
+    writer.write_all(&version.to_be_bytes())?;
+
+    // write author count (u64)
+    writer.write_all(&authors.to_be_bytes())?;
+
+    // write word count
+    writer.write_all(&words.to_be_bytes())
+}
+
+/// Appends the block of a single author to `abuf`, words sorted bytewise.
+#[inline(always)]
+pub fn encode_author<K: WordKey>(
+    abuf: &mut Vec<u8>,
+    author: &[u8],
+    freqs: &PooMapRoot<K, u64>,
+) {
+    abuf.extend_from_slice(&[author, &[245, 0]].concat());
+
+    let mut freqs = freqs.iter().collect::<Vec<_>>();
+    freqs.sort_unstable_by(|a, b| a.0.word().cmp(b.0.word()));
+
+    for (word, freq) in freqs {
+        abuf.extend_from_slice(word.word());
+
+        match *freq {
+            x if freq <= &255u64 => {
+                abuf.extend_from_slice(
+                    &[
+                        (x as u8).to_be_bytes().as_slice(),
+                        [255u8, 0u8].as_slice(),
+                    ]
+                        .concat(),
+                );
+            }
+            x if freq <= &(u32::MAX as u64) => {
+                abuf.extend_from_slice(
+                    &[
+                        (x as u32).to_be_bytes().as_slice(),
+                        [254, 0].as_slice(),
+                    ]
+                        .concat(),
+                );
+            }
+            x => {
+                abuf.extend_from_slice(
+                    &[
+                        (x as u64).to_be_bytes().as_slice(),
+                        [253, 0].as_slice(),
+                    ]
+                        .concat(),
+                );
+            }
         }
     }
 
-    writer.write_all(&[243, 0])?;
+    abuf.extend_from_slice(&[244, 0]);
+}
 
-    Ok(())
+pub fn write_footer<W: Write>(writer: &mut W) -> std::io::Result<()> {
+    writer.write_all(&[243, 0])
 }
 
 const DEBUG: bool = true;
 
+// magic (7) + version (4) + author count (8) + word count (8)
+const HEADER_LEN: usize = 27;
+
 /*
 file format:
 ragegun
@@ -136,30 +335,31 @@ fn establish_freqs(
     frame: &[u8],
 ) -> Action {
     match marker {
-        Marker::FreqU8 => {
+        // the frame is the word immediately followed by its frequency
+        Marker::FreqU8 if frame.len() >= 1 => {
             Action::FreqWordOffset(
-                frame[frame.len() - 2] as u64,
+                frame[frame.len() - 1] as u64,
                 1,
             )
         }
-        Marker::FreqU32 => {
+        Marker::FreqU32 if frame.len() >= 4 => {
             let mut buf = [0u8; 4];
 
-            buf.copy_from_slice(&frame[frame.len() - 5..frame.len() - 1]);
+            buf.copy_from_slice(&frame[frame.len() - 4..]);
 
             Action::FreqWordOffset(
                 u32::from_be_bytes(buf) as u64,
-                5,
+                4,
             )
         }
-        Marker::FreqU64 => {
+        Marker::FreqU64 if frame.len() >= 8 => {
             let mut buf = [0u8; 8];
 
-            buf.copy_from_slice(&frame[frame.len() - 9..frame.len() - 1]);
+            buf.copy_from_slice(&frame[frame.len() - 8..]);
 
             Action::FreqWordOffset(
                 u64::from_be_bytes(buf),
-                9,
+                8,
             )
         }
         _ => Action::Continue
@@ -168,7 +368,7 @@ fn establish_freqs(
 
 enum DeState {
     FindAuthor,
-    Author(Vec<u8>, PooMapInner, bool),
+    Author(Box<[u8]>, PooMapInner, bool),
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -215,6 +415,7 @@ impl From<&[u8]> for Marker {
 #[derive(Debug)]
 enum RGFileFormat {
     Nov2022A(u64, u64),
+    Indexed(u64, u64),
     Unknown,
     TooShort,
 }
@@ -232,12 +433,13 @@ impl RGFileFormat {
 
         match version {
             1 => Self::Nov2022A(authors, words),
+            2 => Self::Indexed(authors, words),
             _ => Self::Unknown,
         }
     }
 
     fn from_buf(data: &[u8]) -> Self {
-        if data.len() < 10 {
+        if data.len() < HEADER_LEN {
             return Self::TooShort;
         }
 
@@ -264,6 +466,21 @@ impl RGFileFormat {
 
 const HTTP_NEEDLE: &'static [u8] = b"http";
 
+#[inline(always)]
+fn should_skip_word(word: &[u8]) -> bool {
+    // links and plain numbers
+    word.windows(HTTP_NEEDLE.len()).any(|w| w == HTTP_NEEDLE)
+        || !word.iter().any(|w| !(*w as char).is_ascii_digit())
+}
+
+/// Strips the header, if any, returning the author blocks.
+fn body(data: &[u8]) -> &[u8] {
+    match RGFileFormat::from_buf(data) {
+        RGFileFormat::Nov2022A(_, _) => &data[HEADER_LEN..],
+        _ => data,
+    }
+}
+
 pub fn deserialize(
     data: &[u8],
     mut fn_feedback: impl FnMut(FnFeedback) -> (),
@@ -281,6 +498,15 @@ pub fn deserialize(
                 fn_feedback,
             )
         }
+        RGFileFormat::Indexed(authors, words) => {
+            fn_feedback(FnFeedback::Message(
+                format!("Loading: File format is indexed ({} authors, {} words)", authors, words)
+            ));
+
+            fn_feedback(FnFeedback::Total(authors as u64));
+
+            salvage_indexed(data, fn_feedback).0
+        }
         RGFileFormat::Unknown => {
             fn_feedback(FnFeedback::Message("Loading: File format is unknown, assuming classic".into()));
 
@@ -291,7 +517,7 @@ pub fn deserialize(
         }
         RGFileFormat::TooShort => {
             fn_feedback(FnFeedback::Message("Loading: File is too short".into()));
-            return PooMap::new();
+            return PooMap::default();
         }
     }
 }
@@ -301,24 +527,373 @@ pub fn try_deserialize_Nov2022A(
     fn_feedback: impl FnMut(FnFeedback) -> (),
 ) -> PooMap {
     try_deserialize_original(
-        &data[28..],
+        &data[HEADER_LEN..],
         fn_feedback,
     )
 }
 
 pub fn try_deserialize_original(
     data: &[u8],
-    mut fn_feedback: impl FnMut(FnFeedback) -> (),
+    fn_feedback: impl FnMut(FnFeedback) -> (),
+) -> PooMap {
+    salvage_original(data, fn_feedback).0
+}
+
+fn salvage_original(
+    data: &[u8],
+    fn_feedback: impl FnMut(FnFeedback) -> (),
+) -> (PooMap, Vec<Damage>) {
+    let mut freq_vec = PooMap::default();
+    let mut damage = Vec::new();
+
+    scan_authors(
+        data,
+        |_| true,
+        |author, freqs| {
+            freq_vec.insert(author, freqs);
+
+            true
+        },
+        |d| damage.push(d),
+        fn_feedback,
+    );
+
+    (freq_vec, damage)
+}
+
+/// Like `deserialize`, also returning what had to be skipped. Every intact
+/// author is loaded, however much of the rest of the file is damaged.
+pub fn deserialize_salvaging(
+    data: &[u8],
+    fn_feedback: impl FnMut(FnFeedback) -> (),
+) -> (PooMap, Vec<Damage>) {
+    match RGFileFormat::from_buf(data) {
+        RGFileFormat::Indexed(_, _) => salvage_indexed(data, fn_feedback),
+        _ => salvage_original(body(data), fn_feedback),
+    }
+}
+
+/// The version of the ragegun file `data`, `None` for headerless files.
+pub fn format_version(data: &[u8]) -> Option<u32> {
+    match RGFileFormat::from_buf(data) {
+        RGFileFormat::Nov2022A(_, _) => Some(1),
+        RGFileFormat::Indexed(_, _) => Some(2),
+        _ => None,
+    }
+}
+
+pub fn extract_user(
+    data: &[u8],
+    user: &str,
+    fn_feedback: impl FnMut(FnFeedback) -> (),
+) -> Option<PooMapInner> {
+    extract_users(data, &[user], fn_feedback)
+        .remove(user.as_bytes())
+}
+
+/// Extracts the profiles of all `users` in a single pass over `data`,
+/// stopping as soon as every one of them has been found.
+pub fn extract_users(
+    data: &[u8],
+    users: &[&str],
+    fn_feedback: impl FnMut(FnFeedback) -> (),
+) -> PooMap {
+    extract_users_with(data, users, AuthorFold::Exact, fn_feedback)
+}
+
+/// Like `extract_users`, comparing names after folding them with `fold`.
+///
+/// The returned map is keyed by the names as stored in the file, which may
+/// differ from the requested ones.
+pub fn extract_users_with(
+    data: &[u8],
+    users: &[&str],
+    fold: AuthorFold,
+    fn_feedback: impl FnMut(FnFeedback) -> (),
 ) -> PooMap {
-    let mut freq_vec = PooMap::new();
+    let needles =
+        users
+            .iter()
+            .map(|u| fold.fold(u.as_bytes()).into_owned())
+            .collect::<HashSet<_>>();
+
+    let mut found = PooMap::default();
+
+    if let RGFileFormat::Indexed(_, _) = RGFileFormat::from_buf(data) {
+        // exact names are looked up in the index instead of scanning
+        if fold == AuthorFold::Exact {
+            if let Some(index) = read_index(data) {
+                for user in users {
+                    let found_block =
+                        index
+                            .binary_search_by(|(author, _)| (*author).cmp(user.as_bytes()))
+                            .ok()
+                            .and_then(|i| decode_indexed_block(data, index[i].1 as usize).ok());
+
+                    if let Some((author, freqs, _)) = found_block {
+                        if !is_opted_out(&author) {
+                            found.insert(author, freqs);
+                        }
+                    }
+                }
+
+                return found;
+            }
+        }
+
+        scan_indexed(
+            data,
+            |author| needles.contains(fold.fold(author).as_ref()),
+            |author, freqs| {
+                found.insert(author, freqs);
+
+                true
+            },
+            |_| {},
+            fn_feedback,
+        );
+
+        return found;
+    }
+
+    let mut matched = HashSet::new();
+
+    scan_authors(
+        body(data),
+        |author| needles.contains(fold.fold(author).as_ref()),
+        |author, freqs| {
+            matched.insert(fold.fold(&author).into_owned());
+            found.insert(author, freqs);
+
+            // with folding several stored names may match the same user,
+            // so only stop early for exact lookups
+            fold != AuthorFold::Exact || matched.len() < needles.len()
+        },
+        |_| {},
+        fn_feedback,
+    );
+
+    found
+}
+
+/// Extracts every author accepted by `matches`, e.g. an `AuthorMatcher`.
+pub fn extract_matching(
+    data: &[u8],
+    matches: impl Fn(&[u8]) -> bool,
+    fn_feedback: impl FnMut(FnFeedback) -> (),
+) -> PooMap {
+    let mut found = PooMap::default();
+
+    if let RGFileFormat::Indexed(_, _) = RGFileFormat::from_buf(data) {
+        scan_indexed(
+            data,
+            matches,
+            |author, freqs| {
+                found.insert(author, freqs);
+
+                true
+            },
+            |_| {},
+            fn_feedback,
+        );
+
+        return found;
+    }
+
+    scan_authors(
+        body(data),
+        matches,
+        |author, freqs| {
+            found.insert(author, freqs);
+
+            true
+        },
+        |_| {},
+        fn_feedback,
+    );
+
+    found
+}
+
+/// Walks the raw author blocks in `data`, handing each block's author, its
+/// bytes including the end marker and its number of words to `on_block`.
+/// Nothing is decoded, so blocks can be copied through unchanged.
+fn scan_blocks<'a>(data: &'a [u8], mut on_block: impl FnMut(&'a [u8], &'a [u8], u64)) {
+    let mut author: Option<&[u8]> = None;
+    let mut block_start = 0;
+    let mut frame_start = 0;
+    let mut words = 0u64;
+
+    for i in 1..data.len() {
+        if data[i] != 0 {
+            continue;
+        }
+
+        match Marker::from_byte(data[i - 1]) {
+            Marker::Unknown => continue,
+            Marker::Author => {
+                author = Some(&data[frame_start..i - 1]);
+                block_start = frame_start;
+                words = 0;
+            }
+            Marker::FreqU8 | Marker::FreqU32 | Marker::FreqU64 => {
+                words += 1;
+            }
+            Marker::AuthorEnd => {
+                if let Some(author) = author.take() {
+                    on_block(author, &data[block_start..=i], words);
+                }
+            }
+            Marker::End => return,
+        }
+
+        frame_start = i + 1;
+    }
+}
+
+/// Copies the ragegun file `data` to `writer` without the authors matched by
+/// `remove`, with the header counts adjusted. Kept authors are copied byte
+/// for byte rather than decoded and re-encoded. Returns how many authors
+/// were removed.
+pub fn remove_authors<W: Write>(
+    data: &[u8],
+    remove: impl Fn(&[u8]) -> bool,
+    writer: &mut W,
+    fn_feedback: impl FnMut(FnFeedback) -> (),
+) -> std::io::Result<u64> {
+    // blocks are found through the index, which changes with them, so
+    // indexed files are rewritten whole. A damaged one is refused rather
+    // than rewritten without every author that couldn't be read
+    if let RGFileFormat::Indexed(_, _) = RGFileFormat::from_buf(data) {
+        let (mut poo, damage) = salvage_indexed(data, |_| {});
+
+        if !damage.is_empty() {
+            let (lost_authors, lost_words) = damage_totals(&damage);
+
+            return Err(invalid_data(format!(
+                "file has {} damaged parts ({} authors, {} words), recover it with migrate --recover first",
+                damage.len(),
+                lost_authors,
+                lost_words,
+            )));
+        }
+
+        let before = poo.len();
+
+        poo.retain(|author, _| !remove(author));
+
+        serialize_indexed_with_writer(&poo, writer, fn_feedback)?;
+
+        return Ok((before - poo.len()) as u64);
+    }
+
+    let data = body(data);
+
+    let mut sink = ProgressSink::new(fn_feedback);
+
+    let (mut kept, mut removed, mut words) = (0u64, 0u64, 0u64);
+
+    // the header needs the counts, the blocks are walked twice
+    scan_blocks(data, |author, _, block_words| {
+        if remove(author) {
+            removed += 1;
+        } else {
+            kept += 1;
+            words += block_words;
+        }
+    });
+
+    sink.message("Saving: Writing authors..");
+    sink.total(kept);
+
+    write_header(writer, kept, words)?;
+
+    let mut written = 0u64;
+    let mut result = Ok(());
+
+    scan_blocks(data, |author, block, _| {
+        if result.is_ok() && !remove(author) {
+            result = writer.write_all(block);
+            written += 1;
+
+            sink.progress(written);
+        }
+    });
+
+    result?;
+
+    sink.finish(written);
+
+    write_footer(writer)?;
 
+    Ok(removed)
+}
+
+/// A part of a ragegun file that couldn't be read and was skipped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Damage {
+    /// Byte offset into the author blocks.
+    pub offset: usize,
+    /// The author whose block it's in, whose profile is then dropped.
+    pub author: Option<String>,
+    /// Words lost with it, as far as they could be told apart.
+    #[serde(default)]
+    pub words: u64,
+    pub problem: String,
+}
+
+/// Authors and words lost to `damage`.
+pub fn damage_totals(damage: &[Damage]) -> (u64, u64) {
+    (
+        damage.iter().filter(|d| d.author.is_some()).count() as u64,
+        damage.iter().map(|d| d.words).sum(),
+    )
+}
+
+/// Walks the author blocks in `data`.
+///
+/// Words are only decoded for authors accepted by `wants`; each decoded
+/// author is handed to `on_author`, which returns whether to keep going.
+/// Damaged blocks are handed to `on_damage` and skipped whole, so a bad
+/// frame never leaves a partial profile behind. Reading picks up again at
+/// the next author marker, frames before it are reported together.
+fn scan_authors(
+    data: &[u8],
+    wants: impl Fn(&[u8]) -> bool,
+    mut on_author: impl FnMut(Box<[u8]>, PooMapInner) -> bool,
+    mut on_damage: impl FnMut(Damage),
+    fn_feedback: impl FnMut(FnFeedback) -> (),
+) {
     let mut state = DeState::FindAuthor;
 
     let mut i = 0;
-    let mut last_marker_pos = 0;
+    let mut frame_start = 0;
+    let mut authors = 0u64;
+    let mut damaged = 0u64;
+    let mut lost = 0u64;
+
+    // frames of the current block, and where and why it went bad
+    let mut block_words = 0u64;
+    let mut block_damage: Option<(usize, String)> = None;
+
+    // frames outside any block since the last one: where they start, how many
+    let mut stray: Option<(usize, u64)> = None;
+
+    let mut sink = ProgressSink::new(fn_feedback);
+
+    let mut damage = |offset: usize, author: Option<&[u8]>, words: u64, problem: String| {
+        damaged += 1;
+        lost += words;
+
+        on_damage(Damage {
+            offset,
+            author: author.map(|a| String::from_utf8_lossy(a).into_owned()),
+            words,
+            problem,
+        });
+    };
 
-    fn_feedback(FnFeedback::Message("Reading: Loading authors..".into()));
-    //fn_feedback(FnFeedback::Total(data.len() as u64));
+    sink.message("Reading: Loading authors..");
 
     while i < data.len() {
         let marker =
@@ -330,274 +905,712 @@ pub fn try_deserialize_original(
                 Marker::Unknown
             };
 
-        if i % 1000 == 0 {
-            fn_feedback(FnFeedback::Progress(i as u64));
-        }
-
         if marker == Marker::Unknown {
             i += 1;
 
             continue;
         }
 
+        // the frame between the previous marker and this one
+        let frame = &data[frame_start..i - 1];
+
         match state {
             DeState::FindAuthor => {
+                if marker == Marker::Author || marker == Marker::End {
+                    if let Some((offset, words)) = stray.take() {
+                        damage(offset, None, words, format!("{} frames outside any author block", words));
+                    }
+                }
+
                 match marker {
                     Marker::Author => {
                         state =
                             DeState::Author(
-                                data[last_marker_pos..i - 1].to_vec(),
-                                PooMapInner::new(),
-                                false,
+                                frame.into(),
+                                PooMapInner::default(),
+                                !wants(frame) || is_opted_out(frame),
                             );
+
+                        block_words = 0;
+                        block_damage = None;
                     }
                     Marker::End => {
-                        last_marker_pos = i;
-
-                        return freq_vec;
+                        break;
                     }
                     _ => {
-                        println!("Invalid author marker at {}: expected 245.", i);
+                        let (_, words) = stray.get_or_insert((frame_start, 0));
+
+                        *words += 1;
                     }
                 }
             }
-            DeState::Author(ref author, ref mut freqs, _) => {
-                let frame = &data[last_marker_pos + 1..i - 1];
-
+            DeState::Author(ref author, ref mut freqs, skip) => {
                 match marker {
                     Marker::FreqU8
                     | Marker::FreqU32
                     | Marker::FreqU64 => {
-                        last_marker_pos = i;
-
-                        match establish_freqs(&marker, frame) {
-                            Action::FreqWordOffset(freq, word_offset) => {
-                                let word = frame[..frame.len() - word_offset as usize].to_vec();
-
-                                let mut should_skip = false;
-
-                                should_skip |=
-                                    word.windows(HTTP_NEEDLE.len())
-                                        .any(|w| w == HTTP_NEEDLE);
-
-                                should_skip |=
-                                    !word.iter()
-                                        .any(|w| !(*w as char).is_ascii_digit());
-
-                                if !should_skip {
-                                    freqs.insert(
-                                        word,
-                                        freq,
-                                    );
+                        block_words += 1;
+
+                        if !skip && block_damage.is_none() {
+                            match establish_freqs(&marker, frame) {
+                                Action::FreqWordOffset(freq, word_offset) => {
+                                    let word = &frame[..frame.len() - word_offset as usize];
+
+                                    if !should_skip_word(word) {
+                                        freqs.insert(
+                                            word.into(),
+                                            freq,
+                                        );
+                                    }
+                                }
+                                Action::Continue => {
+                                    block_damage =
+                                        Some((
+                                            frame_start,
+                                            format!("{}-byte frame too short for a {:?} frequency", frame.len(), marker),
+                                        ));
                                 }
-                            }
-                            Action::Continue => {
-                                println!(
-                                    "Invalid frame at [{} - {}] with len {}: should be 1, 4 or 8 bytes.",
-                                    last_marker_pos,
-                                    i,
-                                    frame.len(),
-                                );
                             }
                         }
                     }
                     Marker::Author => {
+                        damage(frame_start, Some(author), block_words, "author block never ended".to_string());
+
                         state =
                             DeState::Author(
-                                data[last_marker_pos..i - 1].to_vec(),
-                                PooMapInner::new(),
-                                false,
+                                frame.into(),
+                                PooMapInner::default(),
+                                !wants(frame) || is_opted_out(frame),
                             );
+
+                        block_words = 0;
+                        block_damage = None;
                     }
                     Marker::AuthorEnd => {
-                        last_marker_pos = i;
+                        authors += 1;
+
+                        if let Some((offset, problem)) = block_damage.take() {
+                            damage(offset, Some(author), block_words, problem);
+                        } else if !skip && !on_author(author.clone(), std::mem::take(freqs)) {
+                            sink.finish(authors);
 
-                        freq_vec.insert(
-                            author.clone(),
-                            freqs.clone(),
-                        );
+                            return;
+                        }
 
                         state = DeState::FindAuthor;
 
-                        fn_feedback(FnFeedback::Progress(freq_vec.len() as u64));
+                        sink.progress(authors);
                     }
                     Marker::End => {
-                        last_marker_pos = i;
+                        damage(frame_start, Some(author), block_words, "file ended inside the author block".to_string());
 
-                        return freq_vec;
-                    }
-                    _ => {
-                        println!(
-                            "({}/{:?})): Invalid frequency marker at {}: expected 255, 254 or 253.",
-                            String::from_utf8(author.clone())
-                                .unwrap_or(
-                                    "invalid author".to_string(),
-                                ),
-                            marker,
-                            i,
-                        );
+                        state = DeState::FindAuthor;
+
+                        break;
                     }
+                    Marker::Unknown => unreachable!(),
                 }
             }
         }
 
+        frame_start = i + 1;
+
         i += 1;
     }
 
-    println!("Warning: reached end of file without finding end marker.");
+    match state {
+        DeState::Author(ref author, _, _) => {
+            damage(frame_start, Some(author), block_words, "file truncated inside the author block".to_string());
+        }
+        DeState::FindAuthor => {
+            if let Some((offset, words)) = stray.take() {
+                damage(offset, None, words, format!("{} frames outside any author block", words));
+            }
+        }
+    }
+
+    sink.finish(authors);
 
-    freq_vec
+    if damaged > 0 {
+        sink.message(format!("Warning: skipped {} damaged parts holding {} words", damaged, lost));
+    }
 }
 
-pub fn extract_user(
-    data: &[u8],
-    user: &str,
-    mut fn_feedback: impl FnMut(FnFeedback) -> (),
-) -> Option<PooMapInner> {
-    let mut freq_vec = PooMap::new();
+const INDEX_TRAILER: &[u8] = b"rgindex";
 
-    let mut state = DeState::FindAuthor;
+/*
+version 2 (indexed) file format:
+ragegun
+version (u32, 2)
+author count (u64)
+word count (u64)
+--
+per author, sorted by name:
+author length (u16), author
+word count (u32)
+per word, sorted bytewise:
+word length (u16), word
+frequency (LEB128)
+--
+index, per author in the same order:
+author length (u16), author
+offset of the author's block from the start of the file (u64)
+--
+offset of the index (u64)
+rgindex
+*/
 
-    let mut i = 0;
-    let mut last_marker_pos = 0;
+fn write_varint<W: Write>(writer: &mut W, mut value: u64) -> std::io::Result<()> {
+    let mut buf = [0u8; 10];
+    let mut len = 0;
 
-    fn_feedback(FnFeedback::Message("Reading: Loading authors..".into()));
-    fn_feedback(FnFeedback::Total(data.len() as u64));
+    loop {
+        let byte = (value & 0x7f) as u8;
 
-    let user_needle = user.as_bytes();
+        value >>= 7;
 
-    while i < data.len() {
-        let marker =
-            if data[i] == 0 && i != 0 {
-                Marker::from_byte(
-                    data[i - 1]
-                )
-            } else {
-                Marker::Unknown
-            };
+        if value == 0 {
+            buf[len] = byte;
+            len += 1;
 
-        if i % 1000 == 0 {
-            fn_feedback(FnFeedback::Progress(i as u64));
+            break;
         }
 
-        if marker == Marker::Unknown {
-            i += 1;
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
 
-            continue;
+    writer.write_all(&buf[..len])
+}
+
+fn encode_indexed_author<K: WordKey>(
+    author: &[u8],
+    freqs: &PooMapRoot<K, u64>,
+) -> std::io::Result<Vec<u8>> {
+    let mut abuf = Vec::new();
+
+    write_field(&mut abuf, author)?;
+
+    abuf.extend_from_slice(&(freqs.len() as u32).to_be_bytes());
+
+    let mut freqs = freqs.iter().collect::<Vec<_>>();
+    freqs.sort_unstable_by(|a, b| a.0.word().cmp(b.0.word()));
+
+    for (word, freq) in freqs {
+        write_field(&mut abuf, word.word())?;
+        write_varint(&mut abuf, *freq)?;
+    }
+
+    Ok(abuf)
+}
+
+/// Writes `data` in the indexed version 2 format: length-prefixed fields
+/// instead of markers, so names and words may hold any byte, and an index
+/// of every author's block at the end for lookups without a full scan.
+pub fn serialize_indexed_with_writer<W: Write + Send, K: WordKey + Sync>(
+    data: &PooMapBase<PooMapRoot<K, u64>>,
+    writer: &mut W,
+    fn_feedback: impl FnMut(FnFeedback) -> (),
+) -> std::io::Result<()> {
+    let mut sink = ProgressSink::new(fn_feedback);
+
+    let mut serbuf = data.iter().collect::<Vec<_>>();
+    serbuf.par_sort_unstable_by(|a, b| a.0.cmp(b.0));
+
+    sink.message("Saving: Writing authors..");
+    sink.total(serbuf.len() as u64);
+
+    let word_count = serbuf.iter().map(|(_, v)| v.len()).sum::<usize>() as u64;
+
+    write_header_version(writer, 2, serbuf.len() as u64, word_count)?;
+
+    let mut offset = HEADER_LEN as u64;
+    let mut offsets = Vec::with_capacity(serbuf.len());
+    let mut i = 0u64;
+
+    for chunk in serbuf.chunks(SERIALIZE_CHUNK) {
+        let blocks =
+            chunk.par_iter()
+                .map(|(author, freqs)| encode_indexed_author(author, freqs))
+                .collect::<std::io::Result<Vec<_>>>()?;
+
+        for ((author, _), block) in chunk.iter().zip(blocks.iter()) {
+            offsets.push((*author, offset));
+
+            writer.write_all(block)?;
+
+            offset += block.len() as u64;
         }
 
-        match state {
-            DeState::FindAuthor => {
-                match marker {
-                    Marker::Author => {
-                        state =
-                            DeState::Author(
-                                data[last_marker_pos + 1..i - 1].to_vec(),
-                                PooMapInner::new(),
-                                &data[last_marker_pos + 1..i - 1] != user_needle,
-                            );
-                    }
-                    Marker::End => {
-                        last_marker_pos = i;
+        i += chunk.len() as u64;
 
-                        return Default::default();
-                    }
-                    _ => {
-                        println!("Invalid author marker at {}: expected 245.", i);
-                    }
+        sink.progress(i);
+    }
+
+    sink.finish(i);
+
+    let index_offset = offset;
+
+    for (author, offset) in offsets {
+        write_field(writer, author)?;
+        writer.write_all(&offset.to_be_bytes())?;
+    }
+
+    writer.write_all(&index_offset.to_be_bytes())?;
+    writer.write_all(INDEX_TRAILER)
+}
+
+/// Where the blocks of an indexed file end and its index starts, `None`
+/// when the trailer is missing, e.g. in a truncated file.
+fn index_offset(data: &[u8]) -> Option<usize> {
+    let trailer = data.len().checked_sub(8 + INDEX_TRAILER.len())?;
+
+    if &data[trailer + 8..] != INDEX_TRAILER {
+        return None;
+    }
+
+    let offset = u64::from_be_bytes(data[trailer..trailer + 8].try_into().ok()?) as usize;
+
+    (HEADER_LEN..=trailer).contains(&offset).then_some(offset)
+}
+
+/// The author names of an indexed file with their block offsets, sorted.
+fn read_index(data: &[u8]) -> Option<Vec<(&[u8], u64)>> {
+    let start = index_offset(data)?;
+    let end = data.len() - 8 - INDEX_TRAILER.len();
+
+    let mut fields = Fields { data: &data[..end], pos: start };
+    let mut index = Vec::new();
+
+    while fields.pos < end {
+        let author = fields.field().ok()?;
+        let offset = fields.u64().ok()?;
+
+        if offset as usize >= start {
+            return None;
+        }
+
+        index.push((author, offset));
+    }
+
+    Some(index)
+}
+
+/// Decodes the block at `pos`, returning its author, words and where the
+/// next block starts.
+fn decode_indexed_block(data: &[u8], pos: usize) -> std::io::Result<(Box<[u8]>, PooMapInner, usize)> {
+    let mut fields = Fields { data, pos };
+
+    let author = fields.field()?;
+    let count = fields.u32()?;
+
+    let mut freqs = PooMapInner::default();
+
+    for _ in 0..count {
+        let word = fields.field()?;
+        let freq = fields.varint()?;
+
+        if !should_skip_word(word) {
+            freqs.insert(word.into(), freq);
+        }
+    }
+
+    Ok((author.into(), freqs, fields.pos))
+}
+
+/// Like `scan_authors`, for indexed files. A damaged block is skipped by
+/// picking up at the next block in the index; without an index, reading
+/// stops there.
+fn scan_indexed(
+    data: &[u8],
+    wants: impl Fn(&[u8]) -> bool,
+    mut on_author: impl FnMut(Box<[u8]>, PooMapInner) -> bool,
+    mut on_damage: impl FnMut(Damage),
+    fn_feedback: impl FnMut(FnFeedback) -> (),
+) {
+    let mut sink = ProgressSink::new(fn_feedback);
+
+    sink.message("Reading: Loading authors..");
+
+    let index = read_index(data);
+
+    let end =
+        match index_offset(data) {
+            Some(end) => end,
+            None => {
+                on_damage(Damage {
+                    offset: data.len(),
+                    author: None,
+                    words: 0,
+                    problem: "index missing, the file is likely truncated".to_string(),
+                });
+
+                data.len()
+            }
+        };
+
+    let mut pos = HEADER_LEN;
+    let mut authors = 0u64;
+
+    while pos < end {
+        match decode_indexed_block(&data[..end], pos) {
+            Ok((author, freqs, next)) => {
+                authors += 1;
+
+                if wants(&author) && !is_opted_out(&author) && !on_author(author, freqs) {
+                    break;
+                }
+
+                sink.progress(authors);
+
+                pos = next;
+            }
+            Err(e) => {
+                let resume =
+                    index.as_ref()
+                        .and_then(|index| {
+                            index.iter()
+                                .map(|(_, offset)| *offset as usize)
+                                .find(|offset| *offset > pos)
+                        });
+
+                // the block's own word count, when that much of it is intact
+                let mut fields = Fields { data, pos };
+                let author = fields.field().ok().map(|a| String::from_utf8_lossy(a).into_owned());
+                let words = author.as_ref().and_then(|_| fields.u32().ok()).unwrap_or(0) as u64;
+
+                on_damage(Damage {
+                    offset: pos,
+                    author,
+                    words,
+                    problem: e.to_string(),
+                });
+
+                match resume {
+                    Some(next) => pos = next,
+                    None => break,
                 }
             }
-            DeState::Author(ref author, ref mut freqs, skip) => {
-                let frame = &data[last_marker_pos + 1..i - 1];
+        }
+    }
 
-                match marker {
-                    Marker::FreqU8
-                    | Marker::FreqU32
-                    | Marker::FreqU64 => {
-                        last_marker_pos = i;
+    sink.finish(authors);
+}
 
-                        if skip {
-                            i += 1;
-                            continue;
-                        }
+fn salvage_indexed(
+    data: &[u8],
+    fn_feedback: impl FnMut(FnFeedback) -> (),
+) -> (PooMap, Vec<Damage>) {
+    let mut poo = PooMap::default();
+    let mut damage = Vec::new();
+
+    scan_indexed(
+        data,
+        |_| true,
+        |author, freqs| {
+            poo.insert(author, freqs);
+
+            true
+        },
+        |d| damage.push(d),
+        fn_feedback,
+    );
 
-                        match establish_freqs(&marker, frame) {
-                            Action::FreqWordOffset(freq, word_offset) => {
-                                let word = frame[..frame.len() - word_offset as usize].to_vec();
+    (poo, damage)
+}
 
-                                let mut should_skip = false;
+const SEGMENTS_MAGIC: &[u8] = b"ragesegs";
 
-                                should_skip |=
-                                    word.windows(HTTP_NEEDLE.len())
-                                        .any(|w| w == HTTP_NEEDLE);
+/*
+segment assignment file format:
+ragesegs
+version (u32)
+segment count (u32)
+author count (u64)
+--
+per segment, in id order:
+size (u64)
+since version 3, parent segment (u32, u32::MAX for none)
+label length (u16), label
+term count (u16), per term: length (u16), term
+--
+since version 2, the model:
+dimension count (u32)
+per dimension: word length (u16), word, idf (f32)
+per segment, in id order: centroid (dimension count * f32)
+--
+per author, sorted by name:
+name length (u16), name
+segment (u32)
+similarity (f32)
+--
+since version 4:
+unsegmented author count (u64)
+per unsegmented author, sorted by name:
+name length (u16), name
+reason (u8, 0 for too little data, 1 for outliers)
+similarity to the closest segment (f32, 0 unless an outlier)
+*/
 
-                                should_skip |=
-                                    !word.iter()
-                                        .any(|w| !(*w as char).is_ascii_digit());
+fn invalid_data(msg: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg.into())
+}
 
-                                if !should_skip {
-                                    freqs.insert(
-                                        word,
-                                        freq,
-                                    );
-                                }
-                            }
-                            Action::Continue => {
-                                dbg!(frame, i, last_marker_pos);
-
-                                println!(
-                                    "Invalid frame at [{} - {}] with len {}: should be 1, 4 or 8 bytes.",
-                                    last_marker_pos,
-                                    i,
-                                    frame.len(),
-                                );
-                            }
-                        }
-                    }
-                    Marker::AuthorEnd => {
-                        if !skip {
-                            println!("Found user: {}", user);
+fn write_field<W: Write>(writer: &mut W, field: &[u8]) -> std::io::Result<()> {
+    let len =
+        u16::try_from(field.len())
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "field longer than 65535 bytes"))?;
 
-                            for (word, freq) in freqs.iter() {
-                                println!("{}: {}", String::from_utf8(word.clone()).unwrap(), freq);
-                            }
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(field)
+}
 
-                            return Some(freqs.clone());
-                        }
+/// Writes segment assignments and metadata, authors sorted by name.
+pub fn write_assignments<W: Write>(writer: &mut W, assignments: &Assignments) -> std::io::Result<()> {
+    writer.write_all(SEGMENTS_MAGIC)?;
+    writer.write_all(&4u32.to_be_bytes())?;
+    writer.write_all(&(assignments.segments.len() as u32).to_be_bytes())?;
+    writer.write_all(&(assignments.authors.len() as u64).to_be_bytes())?;
 
-                        last_marker_pos = i;
+    for segment in assignments.segments.iter() {
+        writer.write_all(&segment.size.to_be_bytes())?;
+        writer.write_all(&segment.parent.unwrap_or(u32::MAX).to_be_bytes())?;
 
-                        freq_vec.insert(
-                            author.clone(),
-                            freqs.clone(),
-                        );
+        write_field(writer, segment.label.as_bytes())?;
 
-                        state = DeState::FindAuthor;
-                    }
-                    Marker::End => {
-                        last_marker_pos = i;
+        writer.write_all(&(segment.terms.len().min(u16::MAX as usize) as u16).to_be_bytes())?;
 
-                        return Default::default();
-                    }
-                    _ => {
-                        println!(
-                            "({}/{:?})): Invalid frequency marker at {}: expected 255, 254 or 253.",
-                            String::from_utf8(author.clone())
-                                .unwrap_or(
-                                    "invalid author".to_string(),
-                                ),
-                            marker,
-                            i,
-                        );
-                    }
-                }
+        for term in segment.terms.iter().take(u16::MAX as usize) {
+            write_field(writer, term)?;
+        }
+    }
+
+    // files are always written with a model, an empty one if there is none
+    let (vocabulary, idf) =
+        match assignments.model.as_ref() {
+            Some(model) => (model.vectorizer.vocabulary(), model.vectorizer.idf()),
+            None => (&[][..], &[][..]),
+        };
+
+    writer.write_all(&(vocabulary.len() as u32).to_be_bytes())?;
+
+    for (word, idf) in vocabulary.iter().zip(idf) {
+        write_field(writer, word)?;
+
+        writer.write_all(&idf.to_be_bytes())?;
+    }
+
+    for i in 0..assignments.segments.len() {
+        let centroid = assignments.model.as_ref().map_or(&[][..], |model| &model.centroids[i][..]);
+
+        for x in centroid {
+            writer.write_all(&x.to_be_bytes())?;
+        }
+    }
+
+    let mut authors = assignments.authors.iter().collect::<Vec<_>>();
+    authors.par_sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    for (author, segment, similarity) in authors {
+        write_field(writer, author)?;
+
+        writer.write_all(&segment.to_be_bytes())?;
+        writer.write_all(&similarity.to_be_bytes())?;
+    }
+
+    let mut unsegmented = assignments.unsegmented.iter().collect::<Vec<_>>();
+    unsegmented.par_sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    writer.write_all(&(unsegmented.len() as u64).to_be_bytes())?;
+
+    for (author, reason) in unsegmented {
+        write_field(writer, author)?;
+
+        let (reason, similarity) =
+            match reason {
+                Unsegmented::TooLittleData => (0u8, 0f32),
+                Unsegmented::Outlier(similarity) => (1, *similarity),
+            };
+
+        writer.write_all(&[reason])?;
+        writer.write_all(&similarity.to_be_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Reads fixed-size and length-prefixed fields off a buffer.
+struct Fields<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn take(&mut self, len: usize) -> std::io::Result<&'a [u8]> {
+        let bytes =
+            self.data
+                .get(self.pos..self.pos + len)
+                .ok_or_else(|| invalid_data(format!("truncated at byte {}", self.pos)))?;
+
+        self.pos += len;
+
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> std::io::Result<[u8; N]> {
+        let mut buf = [0u8; N];
+
+        buf.copy_from_slice(self.take(N)?);
+
+        Ok(buf)
+    }
+
+    fn u16(&mut self) -> std::io::Result<u16> {
+        self.array().map(u16::from_be_bytes)
+    }
+
+    fn u32(&mut self) -> std::io::Result<u32> {
+        self.array().map(u32::from_be_bytes)
+    }
+
+    fn u64(&mut self) -> std::io::Result<u64> {
+        self.array().map(u64::from_be_bytes)
+    }
+
+    fn f32(&mut self) -> std::io::Result<f32> {
+        self.array().map(f32::from_be_bytes)
+    }
+
+    fn field(&mut self) -> std::io::Result<&'a [u8]> {
+        let len = self.u16()?;
+
+        self.take(len as usize)
+    }
+
+    fn varint(&mut self) -> std::io::Result<u64> {
+        let mut value = 0u64;
+
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+
+            value |= ((byte & 0x7f) as u64) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
             }
         }
 
-        i += 1;
+        Err(invalid_data(format!("overlong varint at byte {}", self.pos)))
+    }
+}
+
+/// Reads a file written by `write_assignments`.
+pub fn read_assignments(data: &[u8]) -> std::io::Result<Assignments> {
+    if !data.starts_with(SEGMENTS_MAGIC) {
+        return Err(invalid_data("not a segment assignment file"));
+    }
+
+    let mut fields = Fields { data, pos: SEGMENTS_MAGIC.len() };
+
+    let version = fields.u32()?;
+
+    if !(1..=4).contains(&version) {
+        return Err(invalid_data(format!("unsupported segment file version {}", version)));
+    }
+
+    let segment_count = fields.u32()?;
+    let author_count = fields.u64()?;
+
+    // the counts come from the file, don't trust them for allocations
+    let mut segments = Vec::with_capacity((segment_count as usize).min(data.len()));
+
+    for _ in 0..segment_count {
+        let size = fields.u64()?;
+
+        let parent =
+            match version {
+                1 | 2 => None,
+                _ => Some(fields.u32()?).filter(|p| *p != u32::MAX),
+            };
+
+        // parents come first, which also rules out cycles
+        if parent.map_or(false, |p| p as usize >= segments.len()) {
+            return Err(invalid_data(format!("segment {} listed before its parent", segments.len())));
+        }
+
+        let label = String::from_utf8_lossy(fields.field()?).to_string();
+
+        let terms =
+            (0..fields.u16()?)
+                .map(|_| fields.field().map(Box::from))
+                .collect::<std::io::Result<_>>()?;
+
+        segments.push(SegmentInfo { label, parent, size, terms });
+    }
+
+    let model =
+        if version >= 2 {
+            read_model(&mut fields, &segments)?
+        } else {
+            None
+        };
+
+    let mut authors = Vec::with_capacity((author_count as usize).min(data.len() / 10));
+
+    for _ in 0..author_count {
+        let author = Box::from(fields.field()?);
+        let segment = fields.u32()?;
+
+        if segment >= segment_count {
+            return Err(invalid_data(format!("author assigned to unknown segment {}", segment)));
+        }
+
+        authors.push((author, segment, fields.f32()?));
     }
 
-    println!("Warning: reached end of file without finding end marker.");
+    let mut unsegmented = Vec::new();
 
-    Default::default()
-}
\ No newline at end of file
+    if version >= 4 {
+        for _ in 0..fields.u64()? {
+            let author = Box::from(fields.field()?);
+
+            let reason =
+                match (fields.array::<1>()?[0], fields.f32()?) {
+                    (0, _) => Unsegmented::TooLittleData,
+                    (1, similarity) => Unsegmented::Outlier(similarity),
+                    (reason, _) => return Err(invalid_data(format!("unknown unsegmented reason {}", reason))),
+                };
+
+            unsegmented.push((author, reason));
+        }
+    }
+
+    Ok(Assignments { segments, authors, unsegmented, model })
+}
+
+/// The model section of a segment file, `None` if it's empty.
+fn read_model(fields: &mut Fields, segments: &[SegmentInfo]) -> std::io::Result<Option<SegmentModel>> {
+    let dims = fields.u32()? as usize;
+
+    if dims == 0 {
+        return Ok(None);
+    }
+
+    let (mut vocabulary, mut idf) = (Vec::new(), Vec::new());
+
+    for _ in 0..dims {
+        vocabulary.push(Box::from(fields.field()?));
+        idf.push(fields.f32()?);
+    }
+
+    let centroids =
+        (0..segments.len())
+            .map(|_| (0..dims).map(|_| fields.f32()).collect::<std::io::Result<Vec<f32>>>())
+            .collect::<std::io::Result<_>>()?;
+
+    Ok(Some(SegmentModel {
+        vectorizer: Vectorizer::new(vocabulary, idf),
+        centroids,
+        parents: segments.iter().map(|s| s.parent).collect(),
+    }))
+}
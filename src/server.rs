@@ -0,0 +1,374 @@
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::analysis::{document_freqs, global_freqs, most_similar, tf_idf, top_n, total_tokens, word_freqs};
+use crate::cache::ResultCache;
+use crate::matcher::AuthorFold;
+use crate::metrics::METRICS;
+use crate::segment::Segmentation;
+use crate::text::text_item::{PooMap, PooMapInner};
+
+/// Everything the endpoints answer from, loaded at startup and on every
+/// `Reload`.
+pub struct State {
+    pub poo: PooMap,
+    pub global: PooMapInner,
+    pub doc_freqs: PooMapInner,
+    pub segmentation: Option<Segmentation>,
+    pub cache: Option<ResultCache>,
+}
+
+impl State {
+    pub fn new(poo: PooMap, segmentation: Option<Segmentation>) -> Self {
+        let (global, doc_freqs) = rayon::join(|| global_freqs(&poo), || document_freqs(&poo));
+
+        Self {
+            poo,
+            global,
+            doc_freqs,
+            segmentation,
+            cache: None,
+        }
+    }
+
+    pub fn with_cache(mut self, cache: ResultCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Looks up an author, scanning the corpus unless the match is exact.
+    pub fn find(&self, name: &[u8], fold: AuthorFold) -> Option<(&[u8], &PooMapInner)> {
+        if fold == AuthorFold::Exact {
+            return self.poo.get_key_value(name).map(|(author, freqs)| (&author[..], freqs));
+        }
+
+        let name = fold.fold(name);
+
+        self.poo
+            .par_iter()
+            .find_any(|(author, _)| fold.fold(author) == name)
+            .map(|(author, freqs)| (&author[..], freqs))
+    }
+}
+
+fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = |b: u8| (b as char).to_digit(16);
+
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                    (Some(hi), Some(lo)) => {
+                        out.push((hi * 16 + lo) as u8);
+                        i += 3;
+                        continue;
+                    }
+                    _ => out.push(b'%'),
+                }
+            }
+            b'+' => out.push(b' '),
+            b => out.push(b),
+        }
+
+        i += 1;
+    }
+
+    out
+}
+
+fn param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+fn parse_param<T: std::str::FromStr>(query: &str, name: &str, default: T) -> T {
+    param(query, name)
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+fn lossy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).to_string()
+}
+
+fn scores<S: Into<Value> + Copy>(scores: &[(&[u8], S)]) -> Value {
+    scores
+        .iter()
+        .map(|(word, score)| json!([lossy(word), (*score).into()]))
+        .collect()
+}
+
+fn not_found(what: &str) -> (u16, Value) {
+    (404, json!({ "error": format!("{} not found", what) }))
+}
+
+/// Answers from the cache if there is one, caching successful responses.
+fn cached(state: &State, key: String, compute: impl FnOnce() -> (u16, Value)) -> (u16, Value) {
+    let Some(cache) = &state.cache else {
+        return compute();
+    };
+
+    if let Some(body) = cache.get(&key) {
+        return (200, body);
+    }
+
+    let (status, body) = compute();
+
+    if status == 200 {
+        cache.insert(&key, &body);
+    }
+
+    (status, body)
+}
+
+fn user_freqs(state: &State, name: &[u8], query: &str) -> (u16, Value) {
+    let Some(freqs) = state.poo.get(name) else {
+        return not_found("user");
+    };
+
+    let limit = parse_param(query, "limit", 100);
+
+    (200, json!({
+        "author": lossy(name),
+        "vocabulary": freqs.len(),
+        "tokens": total_tokens(freqs),
+        "freqs": scores(&top_n(word_freqs(freqs), limit)),
+    }))
+}
+
+fn user_similar(state: &State, name: &[u8], query: &str) -> (u16, Value) {
+    let n = parse_param(query, "n", 20);
+
+    cached(state, format!("similar:{}:{}", lossy(name), n), || {
+        let Some(freqs) = state.poo.get(name) else {
+            return not_found("user");
+        };
+
+        let similar =
+            most_similar(&state.poo, freqs, n + 1)
+                .into_iter()
+                .filter(|(other, _)| *other != name)
+                .take(n)
+                .collect::<Vec<_>>();
+
+        (200, json!({
+            "author": lossy(name),
+            "similar": scores(&similar),
+        }))
+    })
+}
+
+fn user_tfidf(state: &State, name: &[u8], query: &str) -> (u16, Value) {
+    let n = parse_param(query, "n", 50);
+
+    cached(state, format!("tfidf:{}:{}", lossy(name), n), || {
+        let Some(freqs) = state.poo.get(name) else {
+            return not_found("user");
+        };
+
+        (200, json!({
+            "author": lossy(name),
+            "tfidf": scores(&top_n(tf_idf(freqs, &state.doc_freqs, state.poo.len()), n)),
+        }))
+    })
+}
+
+fn segments(state: &State, query: &str) -> (u16, Value) {
+    let Some(segmentation) = &state.segmentation else {
+        return not_found("segmentation");
+    };
+
+    let terms = parse_param(query, "terms", 10);
+
+    let segments =
+        segmentation
+            .sizes()
+            .iter()
+            .enumerate()
+            .map(|(i, size)| json!({
+                "segment": i,
+                "size": size,
+                "terms": scores(&segmentation.top_terms(i as u32, terms)),
+            }))
+            .collect::<Vec<_>>();
+
+    (200, json!({
+        "assigned": segmentation.assignments.len(),
+        "segments": segments,
+    }))
+}
+
+fn user_segment(state: &State, name: &[u8]) -> (u16, Value) {
+    let Some(segmentation) = &state.segmentation else {
+        return not_found("segmentation");
+    };
+
+    // authors below the token threshold aren't in the assignments, so fall
+    // back to the nearest centroid
+    let found =
+        segmentation
+            .segment_of(name)
+            .or_else(|| state.poo.get(name).map(|freqs| segmentation.assign(freqs)));
+
+    match found {
+        Some((segment, similarity)) => (200, json!({
+            "author": lossy(name),
+            "segment": segment,
+            "similarity": similarity,
+        })),
+        None => not_found("user"),
+    }
+}
+
+fn query_top_words(state: &State, query: &str) -> (u16, Value) {
+    let n = parse_param(query, "n", 50);
+
+    match param(query, "user").map(percent_decode) {
+        Some(user) => match state.poo.get(&user[..]) {
+            Some(freqs) => (200, json!({
+                "author": lossy(&user),
+                "words": scores(&top_n(word_freqs(freqs), n)),
+            })),
+            None => not_found("user"),
+        },
+        None => (200, json!({
+            "authors": state.poo.len(),
+            "words": scores(&top_n(state.global.iter().map(|(w, f)| (&w[..], *f)), n)),
+        })),
+    }
+}
+
+/// Routes a `GET` request, returning the route and the status code and JSON
+/// body.
+pub fn handle(state: &State, url: &str) -> (&'static str, (u16, Value)) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+
+    let parts =
+        path.trim_matches('/')
+            .split('/')
+            .collect::<Vec<_>>();
+
+    match parts.as_slice() {
+        ["users", name, "freqs"] => ("user_freqs", user_freqs(state, &percent_decode(name), query)),
+        ["users", name, "similar"] => ("user_similar", user_similar(state, &percent_decode(name), query)),
+        ["users", name, "tfidf"] => ("user_tfidf", user_tfidf(state, &percent_decode(name), query)),
+        ["users", name, "segment"] => ("user_segment", user_segment(state, &percent_decode(name))),
+        ["segments"] => ("segments", segments(state, query)),
+        ["query", "top-words"] => ("top_words", query_top_words(state, query)),
+        _ => ("unknown", not_found("endpoint")),
+    }
+}
+
+fn respond(state: &State, request: Request) {
+    if request.url() == "/metrics" {
+        let response = Response::from_string(METRICS.render());
+
+        if let Err(e) = request.respond(response) {
+            eprintln!("Failed to respond: {}", e);
+        }
+
+        return;
+    }
+
+    let start = Instant::now();
+
+    let (route, (status, body)) =
+        match request.method() {
+            Method::Get => handle(state, request.url()),
+            _ => ("unknown", (405, json!({ "error": "only GET is supported" }))),
+        };
+
+    let response =
+        Response::from_string(body.to_string())
+            .with_status_code(status)
+            .with_header(
+                Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+                    .expect("static header is valid"),
+            );
+
+    if let Err(e) = request.respond(response) {
+        eprintln!("Failed to respond: {}", e);
+    }
+
+    METRICS.observe(route, start.elapsed());
+}
+
+/// Replaces the served state every `every` with what `load` returns, for
+/// corpora that are re-ingested in place. One reload runs at a time: the
+/// next is only scheduled once the last is done, however long it took, and
+/// requests are answered from the previous state meanwhile. `done` hears
+/// how each went, e.g. to notify someone.
+pub struct Reload {
+    pub every: Duration,
+    pub load: Box<dyn Fn() -> std::io::Result<State> + Send>,
+    pub done: Box<dyn Fn(&std::io::Result<()>) + Send>,
+}
+
+/// Serves `state` on `addr` with `workers` threads until the process exits,
+/// swapping it out on `reload`'s schedule if given.
+pub fn serve(state: Arc<State>, addr: &str, workers: usize, reload: Option<Reload>) -> std::io::Result<()> {
+    let server =
+        Server::http(addr)
+            .map(Arc::new)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+
+    let current = Arc::new(RwLock::new(state));
+
+    if let Some(reload) = reload {
+        let current = current.clone();
+
+        std::thread::spawn(move || loop {
+            std::thread::sleep(reload.every);
+
+            let start = Instant::now();
+
+            // loaded outside the lock, requests only wait for the swap; a
+            // corpus caught mid-write can panic the reader, which must not
+            // end the schedule
+            let reloaded =
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| (reload.load)()))
+                    .unwrap_or_else(|_| Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "loading panicked")))
+                    .map(|state| *current.write().unwrap() = Arc::new(state));
+
+            match &reloaded {
+                Ok(()) => eprintln!("Reloaded in {:.1?}", start.elapsed()),
+                Err(e) => eprintln!("Reload failed, still serving the previous state: {}", e),
+            }
+
+            (reload.done)(&reloaded);
+        });
+    }
+
+    let handles =
+        (0..workers.max(1))
+            .map(|_| {
+                let server = server.clone();
+                let current = current.clone();
+
+                std::thread::spawn(move || {
+                    for request in server.incoming_requests() {
+                        let state = current.read().unwrap().clone();
+
+                        respond(&state, request);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.join().ok();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,248 @@
+use std::fmt::Write as _;
+use std::path::Path;
+
+use rayon::prelude::*;
+
+use crate::audit;
+use crate::fingerprint;
+use crate::lexicon::CategoryAffinity;
+use crate::profile::UserProfile;
+use crate::report::{escape, segment_contains, segment_depth, sentiment_line, toxicity_line, ReportData};
+use crate::text::text_item::PooMap;
+
+const STYLE: &str = "
+body { font: 14px/1.4 sans-serif; max-width: 1100px; margin: 2em auto; color: #222; }
+table { border-collapse: collapse; }
+td, th { padding: 2px 10px; border-bottom: 1px solid #eee; text-align: left; }
+img.fp { width: 256px; height: 256px; image-rendering: pixelated; }
+";
+
+/// File name safe form of an author name; anything outside `[A-Za-z0-9_-]`
+/// is hex escaped so distinct names never collide.
+pub fn slug(author: &str) -> String {
+    let mut out = String::with_capacity(author.len());
+
+    for b in author.bytes() {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-' => out.push(b as char),
+            _ => { write!(out, "~{:02x}", b).ok(); }
+        }
+    }
+
+    out
+}
+
+fn page(title: &str, root: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title><style>{}</style></head><body>\n<p><a href=\"{}index.html\">index</a></p>\n<h1>{}</h1>\n{}</body></html>\n",
+        escape(title),
+        STYLE,
+        root,
+        escape(title),
+        body,
+    )
+}
+
+fn user_link(author: &str, root: &str) -> String {
+    format!("<a href=\"{}users/{}.html\">{}</a>", root, slug(author), escape(author))
+}
+
+fn terms_list<S: std::fmt::Display>(terms: &[(String, S)]) -> String {
+    let mut out = String::from("<table>");
+
+    for (word, score) in terms {
+        write!(out, "<tr><td>{}</td><td>{}</td></tr>", escape(word), score).ok();
+    }
+
+    out.push_str("</table>");
+    out
+}
+
+/// A heading and terms table per category.
+fn stack_list(stack: &[CategoryAffinity]) -> String {
+    stack.iter()
+        .map(|affinity| format!("<h3>{}</h3>{}", escape(&affinity.category), terms_list(&affinity.terms)))
+        .collect()
+}
+
+fn index_page(data: &ReportData, profiles: &[UserProfile]) -> String {
+    let mut body = String::new();
+
+    writeln!(body, "<p>{} authors, {} tokens, {} distinct words.</p>", data.authors, data.tokens, data.vocabulary).ok();
+
+    if !data.segments.is_empty() {
+        writeln!(body, "<h2>Segments</h2><ul>").ok();
+
+        for segment in data.segments.iter() {
+            let terms =
+                segment.terms
+                    .iter()
+                    .take(8)
+                    .map(|(word, _)| escape(word))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+            writeln!(
+                body,
+                "<li style=\"margin-left: {}em\"><a href=\"segments/{}.html\">Segment {}</a> ({} authors): {}</li>",
+                segment_depth(&data.segments, segment) * 2,
+                segment.segment,
+                escape(&segment.title()),
+                segment.size,
+                terms,
+            ).ok();
+        }
+
+        writeln!(body, "</ul>").ok();
+    }
+
+    writeln!(body, "<h2>Top words</h2>{}", terms_list(&data.top_words)).ok();
+    writeln!(body, "<h2>Users</h2><p>").ok();
+
+    let links = profiles.iter().map(|p| user_link(&p.author, "")).collect::<Vec<_>>();
+
+    writeln!(body, "{}</p>", links.join(" · ")).ok();
+
+    page(&data.name, "", &body)
+}
+
+fn segment_page(data: &ReportData, segment: usize, profiles: &[UserProfile]) -> String {
+    let summary = &data.segments[segment];
+
+    let mut members =
+        profiles
+            .iter()
+            .filter(|p| p.segment.map_or(false, |leaf| segment_contains(&data.segments, summary.segment, leaf)))
+            .collect::<Vec<_>>();
+
+    members.sort_by(|a, b| b.tokens.cmp(&a.tokens));
+
+    let mut body = String::new();
+
+    writeln!(body, "<p>{} authors.</p><h2>Terms</h2>{}", summary.size, terms_list(&summary.terms)).ok();
+
+    if !summary.domains.is_empty() {
+        writeln!(body, "<h2>Linked domains</h2>{}", terms_list(&summary.domains)).ok();
+    }
+
+    if !summary.tech_stack.is_empty() {
+        writeln!(body, "<h2>Tech stack</h2>{}", stack_list(&summary.tech_stack)).ok();
+    }
+
+    if let Some(sentiment) = summary.sentiment {
+        writeln!(body, "<p>Sentiment: {}.</p>", sentiment_line(&sentiment)).ok();
+    }
+
+    if let Some(toxicity) = summary.toxicity {
+        writeln!(body, "<p>Toxicity: {}.</p>", toxicity_line(&toxicity)).ok();
+    }
+
+    writeln!(body, "<h2>Members</h2><table><tr><th>author</th><th>tokens</th></tr>").ok();
+
+    for member in members {
+        writeln!(body, "<tr><td>{}</td><td>{}</td></tr>", user_link(&member.author, "../"), member.tokens).ok();
+    }
+
+    writeln!(body, "</table>").ok();
+
+    page(&format!("{}: segment {}", data.name, summary.title()), "../", &body)
+}
+
+fn user_page(profile: &UserProfile) -> String {
+    let mut body = String::new();
+
+    writeln!(
+        body,
+        "<img class=\"fp\" src=\"{}.png\" alt=\"fingerprint\">\n<p>{} tokens, {} distinct words, type-token ratio {:.4}.</p>",
+        slug(&profile.author),
+        profile.tokens,
+        profile.vocabulary,
+        profile.type_token_ratio,
+    ).ok();
+
+    writeln!(body, "<p>Sentiment: {}.</p>", sentiment_line(&profile.sentiment)).ok();
+    writeln!(body, "<p>Toxicity: {}.</p>", toxicity_line(&profile.toxicity)).ok();
+
+    if let Some(readability) = profile.readability {
+        writeln!(
+            body,
+            "<p>Readability: Flesch reading ease {:.1}, Flesch-Kincaid grade {:.1}, SMOG {:.1}, {:.1} words per sentence.</p>",
+            readability.flesch_reading_ease,
+            readability.flesch_kincaid_grade,
+            readability.smog,
+            readability.words_per_sentence,
+        ).ok();
+    }
+
+    if let Some(behavior) = profile.behavior {
+        let depth =
+            behavior.mean_depth
+                .map(|depth| format!(", mean reply depth {:.1}", depth))
+                .unwrap_or_default();
+
+        writeln!(
+            body,
+            "<p>Behavior: {:?}, {:.1}% questions, {:.1}% question openings, {:.0} words and {:.1} replies per item{}.</p>",
+            behavior.role,
+            behavior.question_rate * 100.0,
+            behavior.question_openings * 100.0,
+            behavior.words_per_item,
+            behavior.replies_received,
+            depth,
+        ).ok();
+    }
+
+    if let Some(segment) = profile.segment {
+        writeln!(body, "<p>Segment <a href=\"../segments/{}.html\">{}</a></p>", segment, segment).ok();
+    }
+
+    writeln!(body, "<h2>Distinctive terms</h2>{}", terms_list(&profile.distinctive_terms)).ok();
+    writeln!(body, "<h2>Top terms</h2>{}", terms_list(&profile.top_terms)).ok();
+
+    if !profile.domains.is_empty() {
+        writeln!(body, "<h2>Linked domains</h2>{}", terms_list(&profile.domains)).ok();
+    }
+
+    if !profile.tech_stack.is_empty() {
+        writeln!(body, "<h2>Tech stack</h2>{}", stack_list(&profile.tech_stack)).ok();
+    }
+
+    page(&profile.author, "../", &body)
+}
+
+fn write_json<T: serde::Serialize>(path: &Path, value: &T) -> std::io::Result<()> {
+    std::fs::write(path, serde_json::to_vec(value)?)
+}
+
+/// Writes a static site into `dir`: an index, a page per segment and per
+/// profiled user, and the JSON they are built from under `data/`.
+pub fn generate(dir: &Path, poo: &PooMap, data: &ReportData, profiles: &[UserProfile]) -> std::io::Result<()> {
+    // one entry for the whole site rather than one per page
+    audit::write(&dir.to_string_lossy())?;
+
+    for sub in ["segments", "users", "data/users"] {
+        std::fs::create_dir_all(dir.join(sub))?;
+    }
+
+    write_json(&dir.join("data/report.json"), data)?;
+    std::fs::write(dir.join("index.html"), index_page(data, profiles))?;
+
+    for i in 0..data.segments.len() {
+        std::fs::write(dir.join(format!("segments/{}.html", data.segments[i].segment)), segment_page(data, i, profiles))?;
+    }
+
+    profiles
+        .par_iter()
+        .try_for_each(|profile| {
+            let slug = slug(&profile.author);
+
+            write_json(&dir.join(format!("data/users/{}.json", slug)), profile)?;
+            std::fs::write(dir.join(format!("users/{}.html", slug)), user_page(profile))?;
+
+            if let Some(png) = poo.get(profile.author.as_bytes()).and_then(fingerprint::png) {
+                std::fs::write(dir.join(format!("users/{}.png", slug)), png)?;
+            }
+
+            Ok(())
+        })
+}
@@ -0,0 +1,222 @@
+use std::io;
+
+use rocksdb::{Direction, IteratorMode, ReadOptions, Snapshot};
+
+/// Item ids to process, `from` inclusive and `to` exclusive, and how to read
+/// them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdRange {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    /// Bytes to read ahead, large values help full scans on spinning disks.
+    /// Only RocksDB uses it.
+    pub readahead: Option<usize>,
+}
+
+impl IdRange {
+    fn contains(&self, id: i64) -> bool {
+        self.from.map_or(true, |from| id >= from) && self.to.map_or(true, |to| id < to)
+    }
+}
+
+/// A key and its value, owned.
+pub type Entry = (Box<[u8]>, Box<[u8]>);
+
+pub type Entries<'a> = Box<dyn Iterator<Item = io::Result<Entry>> + Send + 'a>;
+
+/// A key-value store items are read from. Keys are big-endian item ids and
+/// values are the items' JSON, possibly compressed.
+pub trait KvSource {
+    /// The entries in `range`, by ascending id.
+    fn items(&self, range: IdRange) -> Entries<'_>;
+
+    /// Id of the last item in `range`.
+    fn last_id(&self, range: IdRange) -> io::Result<Option<i64>>;
+}
+
+/// Item id of a key.
+pub fn decode_id(key: &[u8]) -> Option<i64> {
+    key.get(..8)
+        .and_then(|k| <[u8; 8]>::try_from(k).ok())
+        .map(i64::from_be_bytes)
+}
+
+fn to_io<E: std::error::Error + Send + Sync + 'static>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+fn read_options(range: IdRange) -> ReadOptions {
+    let mut opts = ReadOptions::default();
+
+    if let Some(readahead) = range.readahead {
+        opts.set_readahead_size(readahead);
+    }
+
+    if let Some(to) = range.to {
+        opts.set_iterate_upper_bound(to.to_be_bytes().to_vec());
+    }
+
+    opts
+}
+
+/// RocksDB is read through a snapshot, so a database still being written to
+/// yields a consistent corpus.
+impl KvSource for Snapshot<'_> {
+    fn items(&self, range: IdRange) -> Entries<'_> {
+        let opts = read_options(range);
+
+        // the range maps onto a seek and an upper bound instead of a full scan
+        let items =
+            match range.from.map(i64::to_be_bytes) {
+                Some(key) => self.iterator_opt(IteratorMode::From(&key, Direction::Forward), opts),
+                None => self.iterator_opt(IteratorMode::Start, opts),
+            };
+
+        Box::new(items.map(|entry| entry.map_err(to_io)))
+    }
+
+    fn last_id(&self, range: IdRange) -> io::Result<Option<i64>> {
+        match self.iterator_opt(IteratorMode::End, read_options(range)).next() {
+            Some(entry) => Ok(decode_id(&entry.map_err(to_io)?.0).filter(|id| range.contains(*id))),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(feature = "sled")]
+fn bounds(range: IdRange) -> (std::ops::Bound<[u8; 8]>, std::ops::Bound<[u8; 8]>) {
+    use std::ops::Bound;
+
+    (
+        range.from.map_or(Bound::Unbounded, |from| Bound::Included(from.to_be_bytes())),
+        range.to.map_or(Bound::Unbounded, |to| Bound::Excluded(to.to_be_bytes())),
+    )
+}
+
+/// sled has no snapshots, items written during the scan may or may not be
+/// seen.
+#[cfg(feature = "sled")]
+impl KvSource for sled::Db {
+    fn items(&self, range: IdRange) -> Entries<'_> {
+        Box::new(
+            self.range(bounds(range))
+                .map(|entry| {
+                    let (k, v) = entry?;
+
+                    Ok((Box::from(&k[..]), Box::from(&v[..])))
+                })
+        )
+    }
+
+    fn last_id(&self, range: IdRange) -> io::Result<Option<i64>> {
+        match self.range(bounds(range)).next_back() {
+            Some(entry) => Ok(decode_id(&entry?.0)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(feature = "lmdb")]
+pub use self::lmdb_source::LmdbSource;
+
+#[cfg(feature = "lmdb")]
+mod lmdb_source {
+    use std::io;
+    use std::path::Path;
+    use std::sync::mpsc::{sync_channel, SyncSender};
+    use std::sync::Arc;
+
+    use lmdb::{Cursor, Database, Environment, EnvironmentFlags, Transaction};
+
+    use super::{decode_id, to_io, Entries, Entry, IdRange, KvSource};
+
+    // entries buffered between the reading thread and the consumer
+    const CHANNEL_BOUND: usize = 4096;
+
+    /// The default database of an LMDB environment.
+    pub struct LmdbSource {
+        env: Arc<Environment>,
+        db: Database,
+    }
+
+    impl LmdbSource {
+        pub fn open(path: &Path, writable: bool) -> io::Result<Self> {
+            let mut builder = Environment::new();
+
+            if !writable {
+                builder.set_flags(EnvironmentFlags::READ_ONLY);
+            }
+
+            let env = builder.open(path).map_err(to_io)?;
+            let db = env.open_db(None).map_err(to_io)?;
+
+            Ok(Self { env: Arc::new(env), db })
+        }
+    }
+
+    fn scan(env: &Environment, db: Database, range: IdRange, tx: &SyncSender<io::Result<Entry>>) -> lmdb::Result<()> {
+        let txn = env.begin_ro_txn()?;
+        let mut cursor = txn.open_ro_cursor(db)?;
+
+        let items =
+            match range.from {
+                Some(from) => cursor.iter_from(from.to_be_bytes()),
+                None => cursor.iter_start(),
+            };
+
+        for entry in items {
+            let (k, v) = entry?;
+
+            if range.to.map_or(false, |to| k >= &to.to_be_bytes()[..]) {
+                break;
+            }
+
+            // the consumer stopped reading
+            if tx.send(Ok((Box::from(k), Box::from(v)))).is_err() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    impl KvSource for LmdbSource {
+        /// Read transactions are bound to their thread, so one thread reads
+        /// the whole range inside a single transaction, which also makes it a
+        /// consistent snapshot, and hands entries over through a channel.
+        fn items(&self, range: IdRange) -> Entries<'_> {
+            let (tx, rx) = sync_channel(CHANNEL_BOUND);
+            let (env, db) = (self.env.clone(), self.db);
+
+            std::thread::spawn(move || {
+                if let Err(e) = scan(&env, db, range, &tx) {
+                    tx.send(Err(to_io(e))).ok();
+                }
+            });
+
+            Box::new(rx.into_iter())
+        }
+
+        fn last_id(&self, range: IdRange) -> io::Result<Option<i64>> {
+            let txn = self.env.begin_ro_txn().map_err(to_io)?;
+            let cursor = txn.open_ro_cursor(self.db).map_err(to_io)?;
+
+            // the first key at or past the end of the range, then one back
+            let found =
+                match range.to {
+                    Some(to) => match cursor.get(Some(&to.to_be_bytes()[..]), None, lmdb_sys::MDB_SET_RANGE) {
+                        Ok(_) => cursor.get(None, None, lmdb_sys::MDB_PREV),
+                        Err(lmdb::Error::NotFound) => cursor.get(None, None, lmdb_sys::MDB_LAST),
+                        Err(e) => Err(e),
+                    },
+                    None => cursor.get(None, None, lmdb_sys::MDB_LAST),
+                };
+
+            match found {
+                Ok((key, _)) => Ok(key.and_then(decode_id).filter(|id| range.contains(*id))),
+                Err(lmdb::Error::NotFound) => Ok(None),
+                Err(e) => Err(to_io(e)),
+            }
+        }
+    }
+}
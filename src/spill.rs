@@ -0,0 +1,245 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+
+use crate::serializer::{encode_author, FnFeedback, ProgressSink, write_footer, write_header};
+use crate::text::interner::Sym;
+use crate::text::text_item::{SymMap, SymMapInner, TextItem};
+
+/// Rough in-memory footprint of an aggregation, including hash map overhead.
+pub fn estimate_size(map: &SymMap) -> usize {
+    map.iter()
+        .map(|(author, freqs)|
+            author.len()
+                + size_of::<(Box<[u8]>, SymMapInner)>() + 1
+                + freqs.capacity() * (size_of::<(Sym, u64)>() + 1)
+        )
+        .sum()
+}
+
+/// Writes partial aggregations to sorted run files once they outgrow the
+/// memory budget and merges them back together when saving.
+///
+/// Runs are zstd-compressed bincode streams of `(author, [(word, freq)])`
+/// records sorted by author. Words stay interned, so runs are only valid
+/// within the process that wrote them.
+pub struct Spiller {
+    budget: usize,
+    dir: PathBuf,
+    runs: Vec<PathBuf>,
+}
+
+impl Spiller {
+    pub fn new(budget: usize, dir: &Path) -> Self {
+        Self {
+            budget,
+            dir: dir.to_path_buf(),
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Spills `map` to disk if it exceeds the budget, returning whether it did.
+    pub fn maybe_spill(&mut self, map: &mut SymMap) -> std::io::Result<bool> {
+        if estimate_size(map) < self.budget {
+            return Ok(false);
+        }
+
+        self.spill(std::mem::take(map))?;
+
+        Ok(true)
+    }
+
+    pub fn spill(&mut self, map: SymMap) -> std::io::Result<()> {
+        let path =
+            self.dir.join(
+                format!("poo.{}.{}.run", std::process::id(), self.runs.len())
+            );
+
+        let mut authors = map.into_iter().collect::<Vec<_>>();
+        authors.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        let mut encoder =
+            zstd::stream::Encoder::new(
+                BufWriter::new(File::create(&path)?),
+                1,
+            )?;
+
+        bincode::serialize_into(&mut encoder, &(authors.len() as u64))
+            .map_err(to_io_error)?;
+
+        for (author, freqs) in authors {
+            let freqs = freqs.into_iter().collect::<Vec<_>>();
+
+            bincode::serialize_into(&mut encoder, &(author, freqs))
+                .map_err(to_io_error)?;
+        }
+
+        encoder.finish()?.flush()?;
+
+        self.runs.push(path);
+
+        Ok(())
+    }
+
+    /// Merges all runs and writes the result as a ragegun file, handing
+    /// every merged author to `on_author` on the way.
+    ///
+    /// The header needs the author and word counts up front, so the runs
+    /// are merged twice: once to count and once to write.
+    pub fn merge_into<W: Write>(
+        &self,
+        writer: &mut W,
+        mut on_author: impl FnMut(&[u8], &SymMapInner),
+        fn_feedback: impl FnMut(FnFeedback) -> (),
+    ) -> std::io::Result<()> {
+        let mut sink = ProgressSink::new(fn_feedback);
+
+        sink.message(format!("Saving: Counting {} runs..", self.runs.len()));
+
+        let mut authors = 0u64;
+        let mut words = 0u64;
+
+        self.merge_runs(|_, freqs| {
+            authors += 1;
+            words += freqs.len() as u64;
+
+            Ok(())
+        })?;
+
+        sink.message("Saving: Writing authors..");
+        sink.total(authors);
+
+        write_header(writer, authors, words)?;
+
+        let mut abuf = Vec::new();
+        let mut i = 0u64;
+
+        self.merge_runs(|author, freqs| {
+            abuf.clear();
+
+            encode_author(&mut abuf, &author, &freqs);
+            on_author(&author, &freqs);
+
+            writer.write_all(abuf.as_slice())?;
+
+            i += 1;
+
+            sink.progress(i);
+
+            Ok(())
+        })?;
+
+        sink.finish(i);
+
+        write_footer(writer)
+    }
+
+    fn merge_runs(
+        &self,
+        mut f: impl FnMut(Box<[u8]>, SymMapInner) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        let mut readers =
+            self.runs
+                .iter()
+                .map(|path| RunReader::open(path))
+                .collect::<std::io::Result<Vec<_>>>()?;
+
+        let mut heads = Vec::with_capacity(readers.len());
+        let mut heap = BinaryHeap::new();
+
+        for (i, reader) in readers.iter_mut().enumerate() {
+            let head = reader.next()?;
+
+            if let Some((ref author, _)) = head {
+                heap.push(Reverse((author.clone(), i)));
+            }
+
+            heads.push(head);
+        }
+
+        while let Some(Reverse((author, i))) = heap.pop() {
+            let mut freqs = SymMapInner::default();
+            let mut sources = vec![i];
+
+            while let Some(Reverse((next, _))) = heap.peek() {
+                if *next != author {
+                    break;
+                }
+
+                if let Some(Reverse((_, j))) = heap.pop() {
+                    sources.push(j);
+                }
+            }
+
+            for j in sources {
+                if let Some((_, run_freqs)) = heads[j].take() {
+                    TextItem::merge_freqs(
+                        &mut freqs,
+                        run_freqs.into_iter().collect(),
+                    );
+                }
+
+                heads[j] = readers[j].next()?;
+
+                if let Some((ref next, _)) = heads[j] {
+                    heap.push(Reverse((next.clone(), j)));
+                }
+            }
+
+            f(author, freqs)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Spiller {
+    fn drop(&mut self) {
+        for run in self.runs.iter() {
+            let _ = std::fs::remove_file(run);
+        }
+    }
+}
+
+struct RunReader {
+    decoder: zstd::stream::Decoder<'static, BufReader<File>>,
+    remaining: u64,
+}
+
+impl RunReader {
+    fn open(path: &Path) -> std::io::Result<Self> {
+        let mut decoder = zstd::stream::Decoder::new(File::open(path)?)?;
+
+        let remaining =
+            bincode::deserialize_from(&mut decoder)
+                .map_err(to_io_error)?;
+
+        Ok(Self {
+            decoder,
+            remaining,
+        })
+    }
+
+    fn next(&mut self) -> std::io::Result<Option<(Box<[u8]>, Vec<(Sym, u64)>)>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        self.remaining -= 1;
+
+        bincode::deserialize_from(&mut self.decoder)
+            .map(Some)
+            .map_err(to_io_error)
+    }
+}
+
+fn to_io_error(e: bincode::Error) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e)
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{StringBuilder, UInt64Builder};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::datasource::MemTable;
+use datafusion::error::Result;
+use datafusion::prelude::SessionContext;
+
+use crate::text::text_item::PooMap;
+
+// rows per record batch
+const BATCH_ROWS: usize = 1 << 20;
+
+pub fn schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("author", DataType::Utf8, false),
+        Field::new("word", DataType::Utf8, false),
+        Field::new("count", DataType::UInt64, false),
+    ]))
+}
+
+/// Flattens a corpus into `(author, word, count)` record batches.
+pub fn to_batches(poo: &PooMap) -> Result<Vec<RecordBatch>> {
+    let schema = schema();
+
+    let mut batches = Vec::new();
+
+    let mut authors = StringBuilder::new();
+    let mut words = StringBuilder::new();
+    let mut counts = UInt64Builder::new();
+    let mut rows = 0;
+
+    for (author, freqs) in poo.iter() {
+        let author = String::from_utf8_lossy(author);
+
+        for (word, count) in freqs.iter() {
+            authors.append_value(&author);
+            words.append_value(String::from_utf8_lossy(word));
+            counts.append_value(*count);
+
+            rows += 1;
+
+            if rows == BATCH_ROWS {
+                batches.push(RecordBatch::try_new(
+                    schema.clone(),
+                    vec![
+                        Arc::new(authors.finish()),
+                        Arc::new(words.finish()),
+                        Arc::new(counts.finish()),
+                    ],
+                )?);
+
+                rows = 0;
+            }
+        }
+    }
+
+    if rows > 0 || batches.is_empty() {
+        batches.push(RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(authors.finish()),
+                Arc::new(words.finish()),
+                Arc::new(counts.finish()),
+            ],
+        )?);
+    }
+
+    Ok(batches)
+}
+
+/// Registers `poo` as an in-memory table called `name`.
+pub fn register(ctx: &SessionContext, name: &str, poo: &PooMap) -> Result<()> {
+    let table = MemTable::try_new(schema(), vec![to_batches(poo)?])?;
+
+    ctx.register_table(name, Arc::new(table))?;
+
+    Ok(())
+}
+
+/// Runs `query` against the registered tables and prints the result.
+pub async fn run(ctx: &SessionContext, query: &str) -> Result<()> {
+    ctx.sql(query).await?.show().await
+}
@@ -0,0 +1,253 @@
+use serde::Serialize;
+
+use crate::segment::Assignments;
+
+/// Matches rows to columns maximizing the summed `weights`, with the
+/// Hungarian algorithm. Returns the column of every row, `None` for rows
+/// left over when there are more rows than columns.
+pub fn max_weight_matching(weights: &[Vec<u64>]) -> Vec<Option<usize>> {
+    let rows = weights.len();
+    let cols = weights.iter().map(Vec::len).max().unwrap_or(0);
+    let n = rows.max(cols);
+
+    if n == 0 {
+        return Vec::new();
+    }
+
+    let max = weights.iter().flatten().copied().max().unwrap_or(0) as i64;
+
+    // square, minimizing, 1-based with row/column 0 as sentinels
+    let cost = |i: usize, j: usize| {
+        max - weights.get(i - 1).and_then(|row| row.get(j - 1)).copied().unwrap_or(0) as i64
+    };
+
+    let (mut u, mut v) = (vec![0i64; n + 1], vec![0i64; n + 1]);
+    let mut matched = vec![0usize; n + 1];
+    let mut way = vec![0usize; n + 1];
+
+    for i in 1..=n {
+        matched[0] = i;
+
+        let mut j0 = 0;
+        let mut min = vec![i64::MAX; n + 1];
+        let mut used = vec![false; n + 1];
+
+        loop {
+            used[j0] = true;
+
+            let i0 = matched[j0];
+            let mut delta = i64::MAX;
+            let mut j1 = 0;
+
+            for j in 1..=n {
+                if used[j] {
+                    continue;
+                }
+
+                let reduced = cost(i0, j) - u[i0] - v[j];
+
+                if reduced < min[j] {
+                    min[j] = reduced;
+                    way[j] = j0;
+                }
+
+                if min[j] < delta {
+                    delta = min[j];
+                    j1 = j;
+                }
+            }
+
+            for j in 0..=n {
+                if used[j] {
+                    u[matched[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min[j] -= delta;
+                }
+            }
+
+            j0 = j1;
+
+            if matched[j0] == 0 {
+                break;
+            }
+        }
+
+        // flip the augmenting path
+        loop {
+            let j1 = way[j0];
+
+            matched[j0] = matched[j1];
+            j0 = j1;
+
+            if j0 == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut columns = vec![None; rows];
+
+    for j in 1..=cols {
+        if matched[j] >= 1 && matched[j] <= rows {
+            columns[matched[j] - 1] = Some(j - 1);
+        }
+    }
+
+    columns
+}
+
+/// A segment of one run and the segment of the next that corresponds to it.
+#[derive(Debug, Clone, Serialize)]
+pub struct SegmentMatch {
+    pub before: u32,
+    pub after: Option<u32>,
+    /// Authors in both.
+    pub overlap: u64,
+    /// `overlap` over the authors in either, among those in both runs.
+    pub jaccard: f64,
+}
+
+/// How the segments of one run carry over to the next.
+#[derive(Debug, Clone, Serialize)]
+pub struct Stability {
+    pub matches: Vec<SegmentMatch>,
+    /// Authors assigned in both runs.
+    pub common: u64,
+    /// Authors only assigned in the earlier run.
+    pub left: u64,
+    /// Authors only assigned in the later run.
+    pub joined: u64,
+    /// Share of the common authors not in the segment matching their old one.
+    pub churn: f64,
+    /// `(before, after, authors)` for every pair of segments sharing authors.
+    pub transitions: Vec<(u32, u32, u64)>,
+}
+
+impl Stability {
+    /// Compares the leaf assignments of two runs. Segment ids are arbitrary
+    /// from run to run, so segments are first matched by their overlap.
+    pub fn measure(before: &Assignments, after: &Assignments) -> Self {
+        let mut overlap = vec![vec![0u64; after.segments.len()]; before.segments.len()];
+        let (mut common, mut left) = (0, 0);
+
+        for (author, segment, _) in before.authors.iter() {
+            match after.segment_of(author) {
+                Some((next, _)) => {
+                    overlap[*segment as usize][next as usize] += 1;
+                    common += 1;
+                }
+                None => left += 1,
+            }
+        }
+
+        let joined = after.authors.len() as u64 - common;
+
+        let row_sums = overlap.iter().map(|row| row.iter().sum::<u64>()).collect::<Vec<_>>();
+
+        let col_sums =
+            (0..after.segments.len())
+                .map(|j| overlap.iter().map(|row| row[j]).sum::<u64>())
+                .collect::<Vec<_>>();
+
+        let matching = max_weight_matching(&overlap);
+
+        let matches =
+            matching
+                .iter()
+                .enumerate()
+                .map(|(i, j)| {
+                    // a segment paired with one it shares nobody with has no match
+                    let j = j.filter(|j| overlap[i][*j] > 0);
+                    let shared = j.map_or(0, |j| overlap[i][j]);
+                    let union = row_sums[i] + j.map_or(0, |j| col_sums[j]) - shared;
+
+                    SegmentMatch {
+                        before: i as u32,
+                        after: j.map(|j| j as u32),
+                        overlap: shared,
+                        jaccard: if union == 0 { 0.0 } else { shared as f64 / union as f64 },
+                    }
+                })
+                .collect::<Vec<_>>();
+
+        let stayed = matches.iter().map(|m| m.overlap).sum::<u64>();
+
+        let transitions =
+            overlap
+                .iter()
+                .enumerate()
+                .flat_map(|(i, row)| {
+                    row.iter()
+                        .enumerate()
+                        .filter(|(_, n)| **n > 0)
+                        .map(move |(j, n)| (i as u32, j as u32, *n))
+                })
+                .collect();
+
+        Self {
+            matches,
+            common,
+            left,
+            joined,
+            churn: if common == 0 { 0.0 } else { 1.0 - stayed as f64 / common as f64 },
+            transitions,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SankeyNode {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SankeyLink {
+    pub source: usize,
+    pub target: usize,
+    pub value: u64,
+}
+
+/// Segment transitions across consecutive runs, as the `nodes` and `links`
+/// d3-sankey and most other sankey renderers take.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Sankey {
+    pub nodes: Vec<SankeyNode>,
+    pub links: Vec<SankeyLink>,
+}
+
+impl Sankey {
+    /// `runs` are the names and assignments of consecutive runs, `steps`
+    /// the stability between each run and the next.
+    pub fn build(runs: &[(&str, &Assignments)], steps: &[Stability]) -> Self {
+        let mut sankey = Self::default();
+        let mut offsets = Vec::new();
+
+        for (name, assignments) in runs.iter() {
+            offsets.push(sankey.nodes.len());
+
+            for (i, segment) in assignments.segments.iter().enumerate() {
+                let name =
+                    if segment.label.is_empty() {
+                        format!("{}: {}", name, i)
+                    } else {
+                        format!("{}: {} {}", name, i, segment.label)
+                    };
+
+                sankey.nodes.push(SankeyNode { name });
+            }
+        }
+
+        for (step, stability) in steps.iter().enumerate() {
+            for (before, after, value) in stability.transitions.iter() {
+                sankey.links.push(SankeyLink {
+                    source: offsets[step] + *before as usize,
+                    target: offsets[step + 1] + *after as usize,
+                    value: *value,
+                });
+            }
+        }
+
+        sankey
+    }
+}
@@ -0,0 +1,230 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::audit;
+use crate::crypto::{self, decrypt};
+use crate::serializer::decompress;
+
+/// Whether `location` is an object store URI such as `s3://bucket/key`
+/// rather than a local path.
+pub fn is_remote(location: &str) -> bool {
+    matches!(location.split_once("://"), Some((scheme, _)) if scheme != "file")
+}
+
+fn local_path(location: &str) -> &str {
+    location.strip_prefix("file://").unwrap_or(location)
+}
+
+/// Reads a local file or object.
+pub fn read(location: &str) -> std::io::Result<Vec<u8>> {
+    audit::read(location)?;
+
+    if is_remote(location) {
+        remote::read(location)
+    } else {
+        std::fs::read(local_path(location))
+    }
+}
+
+/// Like `serializer::read_file`, for local paths and object store URIs.
+pub fn read_file(location: &str) -> std::io::Result<Vec<u8>> {
+    decompress(decrypt(read(location)?)?)
+}
+
+/// Whether the file or object at `location` is age-encrypted, so what's
+/// derived from it can be sealed as well.
+pub fn is_encrypted(location: &str) -> std::io::Result<bool> {
+    if is_remote(location) {
+        read(location).map(|raw| crypto::is_encrypted(&raw))
+    } else {
+        crypto::is_encrypted_file(Path::new(local_path(location)))
+    }
+}
+
+/// A local file or an object being uploaded.
+///
+/// Objects are streamed up as a multipart upload, which is only completed by
+/// `finish`; the object doesn't exist until then.
+pub enum Output {
+    Local(BufWriter<File>),
+    #[cfg(feature = "s3")]
+    Remote(remote::Upload),
+}
+
+pub fn create(location: &str) -> std::io::Result<Output> {
+    audit::write(location)?;
+
+    if is_remote(location) {
+        remote::create(location)
+    } else {
+        Ok(Output::Local(BufWriter::new(File::create(local_path(location))?)))
+    }
+}
+
+impl Output {
+    pub fn finish(self) -> std::io::Result<()> {
+        match self {
+            Output::Local(mut writer) => writer.flush(),
+            #[cfg(feature = "s3")]
+            Output::Remote(upload) => upload.finish(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Output::Local(writer) => writer.write(buf),
+            #[cfg(feature = "s3")]
+            Output::Remote(upload) => upload.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Output::Local(writer) => writer.flush(),
+            #[cfg(feature = "s3")]
+            Output::Remote(upload) => upload.flush(),
+        }
+    }
+}
+
+#[cfg(not(feature = "s3"))]
+mod remote {
+    use super::Output;
+
+    fn unsupported(location: &str) -> std::io::Error {
+        std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("can't access {}, rebuild with `--features s3` for object store support", location),
+        )
+    }
+
+    pub fn read(location: &str) -> std::io::Result<Vec<u8>> {
+        Err(unsupported(location))
+    }
+
+    pub fn create(location: &str) -> std::io::Result<Output> {
+        Err(unsupported(location))
+    }
+}
+
+#[cfg(feature = "s3")]
+mod remote {
+    use std::io::ErrorKind;
+    use std::sync::Arc;
+
+    use lazy_static::lazy_static;
+    use object_store::aws::AmazonS3Builder;
+    use object_store::gcp::GoogleCloudStorageBuilder;
+    use object_store::path::Path;
+    use object_store::{MultipartId, ObjectStore};
+    use tokio::io::{AsyncWrite, AsyncWriteExt};
+    use tokio::runtime::Runtime;
+
+    use super::Output;
+
+    lazy_static! {
+        static ref RUNTIME: Runtime = Runtime::new().expect("failed to start runtime");
+    }
+
+    fn to_io(e: object_store::Error) -> std::io::Error {
+        let kind =
+            match e {
+                object_store::Error::NotFound { .. } => ErrorKind::NotFound,
+                _ => ErrorKind::Other,
+            };
+
+        std::io::Error::new(kind, e)
+    }
+
+    /// Opens the store for `location`, configured from the usual environment
+    /// variables (`AWS_ACCESS_KEY_ID`, `GOOGLE_SERVICE_ACCOUNT`, ..).
+    fn open(location: &str) -> std::io::Result<(Arc<dyn ObjectStore>, Path)> {
+        let invalid = || std::io::Error::new(ErrorKind::InvalidInput, format!("invalid location {}", location));
+
+        let (scheme, rest) = location.split_once("://").ok_or_else(invalid)?;
+        let (bucket, key) = rest.split_once('/').ok_or_else(invalid)?;
+
+        let store: Arc<dyn ObjectStore> =
+            match scheme {
+                "s3" => Arc::new(
+                    AmazonS3Builder::from_env()
+                        .with_bucket_name(bucket)
+                        .build()
+                        .map_err(to_io)?
+                ),
+                "gs" => Arc::new(
+                    GoogleCloudStorageBuilder::from_env()
+                        .with_bucket_name(bucket)
+                        .build()
+                        .map_err(to_io)?
+                ),
+                _ => return Err(std::io::Error::new(
+                    ErrorKind::Unsupported,
+                    format!("unsupported scheme {}://, expected s3:// or gs://", scheme),
+                )),
+            };
+
+        let path = Path::parse(key).map_err(|e| std::io::Error::new(ErrorKind::InvalidInput, e))?;
+
+        Ok((store, path))
+    }
+
+    pub fn read(location: &str) -> std::io::Result<Vec<u8>> {
+        let (store, path) = open(location)?;
+
+        RUNTIME.block_on(async {
+            let bytes =
+                store.get(&path).await.map_err(to_io)?
+                    .bytes().await.map_err(to_io)?;
+
+            Ok(bytes.to_vec())
+        })
+    }
+
+    pub fn create(location: &str) -> std::io::Result<Output> {
+        let (store, path) = open(location)?;
+
+        let (id, writer) = RUNTIME.block_on(store.put_multipart(&path)).map_err(to_io)?;
+
+        Ok(Output::Remote(Upload {
+            store,
+            path,
+            id,
+            writer,
+        }))
+    }
+
+    /// A multipart upload; the writer buffers and sends parts as they fill.
+    pub struct Upload {
+        store: Arc<dyn ObjectStore>,
+        path: Path,
+        id: MultipartId,
+        writer: Box<dyn AsyncWrite + Unpin + Send>,
+    }
+
+    impl Upload {
+        pub fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            RUNTIME.block_on(self.writer.write(buf))
+        }
+
+        pub fn flush(&mut self) -> std::io::Result<()> {
+            RUNTIME.block_on(self.writer.flush())
+        }
+
+        /// Completes the upload, aborting it if the last parts fail.
+        pub fn finish(mut self) -> std::io::Result<()> {
+            RUNTIME.block_on(async {
+                if let Err(e) = self.writer.shutdown().await {
+                    self.store.abort_multipart(&self.path, &self.id).await.ok();
+
+                    return Err(e);
+                }
+
+                Ok(())
+            })
+        }
+    }
+}
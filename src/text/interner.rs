@@ -0,0 +1,84 @@
+use std::hash::BuildHasherDefault;
+use std::sync::RwLock;
+
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use rustc_hash::FxHasher;
+use serde::{Deserialize, Serialize};
+
+lazy_static! {
+    pub static ref INTERNER: Interner = Interner::new();
+}
+
+/// Interned word, only meaningful together with the [`INTERNER`] of the
+/// process that created it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Sym(u32);
+
+/// Global symbol table mapping words to `Sym`s.
+///
+/// Words are leaked on insertion so that resolving a symbol can hand out
+/// `'static` slices; the table lives for the whole process anyway.
+pub struct Interner {
+    ids: DashMap<&'static [u8], Sym, BuildHasherDefault<FxHasher>>,
+    words: RwLock<Vec<&'static [u8]>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self {
+            ids: DashMap::default(),
+            words: RwLock::new(Vec::new()),
+        }
+    }
+
+    #[inline(always)]
+    pub fn intern(&self, word: &[u8]) -> Sym {
+        if let Some(sym) = self.ids.get(word) {
+            return *sym;
+        }
+
+        let mut words = self.words.write().unwrap();
+
+        // another thread may have inserted the word while we were waiting
+        if let Some(sym) = self.ids.get(word) {
+            return *sym;
+        }
+
+        let word: &'static [u8] = Box::leak(word.to_vec().into_boxed_slice());
+        let sym = Sym(words.len() as u32);
+
+        words.push(word);
+        self.ids.insert(word, sym);
+
+        sym
+    }
+
+    #[inline(always)]
+    pub fn resolve(&self, sym: Sym) -> &'static [u8] {
+        self.words.read().unwrap()[sym.0 as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.read().unwrap().len()
+    }
+}
+
+/// Anything that can be used as the word key of a frequency map.
+pub trait WordKey {
+    fn word(&self) -> &[u8];
+}
+
+impl WordKey for Box<[u8]> {
+    #[inline(always)]
+    fn word(&self) -> &[u8] {
+        self
+    }
+}
+
+impl WordKey for Sym {
+    #[inline(always)]
+    fn word(&self) -> &[u8] {
+        INTERNER.resolve(*self)
+    }
+}
@@ -1,20 +1,27 @@
 use std::collections::{HashMap, HashSet};
+#[cfg(feature = "native")]
 use std::io::BufReader;
 
 
 
 use lazy_static::lazy_static;
+#[cfg(feature = "native")]
 use nlprule::tokenizer::Tokenizer;
 
+pub mod interner;
 pub mod text_item;
 
+// nlprule links onig, which doesn't build for wasm
+#[cfg(feature = "native")]
 lazy_static! {
     pub(crate) static ref EN_TOKENIZER: Tokenizer = {
         let model = include_bytes!(concat!(env!("ASSET_DIR"), "/en_tokenizer.bin"));
 
         Tokenizer::from_reader(BufReader::new(&*model.to_vec())).unwrap()
     };
+}
 
+lazy_static! {
     pub static ref STOPWORDS: HashSet<String> =
         include_str!("./stopwords.txt")
             .lines()
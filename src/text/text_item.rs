@@ -1,77 +1,150 @@
-use std::collections::BTreeMap;
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
 use std::ops::AddAssign;
 
 use rayon::iter::ParallelIterator;
 use rayon::prelude::*;
+use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
 
-use crate::serializer::FnFeedback;
+use crate::serializer::{FnFeedback, ProgressSink};
+use crate::text::interner::{INTERNER, Sym};
 
-pub type PooMapRoot<K, V> = BTreeMap<K, V>;
-pub type PooMapBase<T> = BTreeMap<Vec<u8>, T>;
+// hash maps keep aggregation fast; anything that needs a stable order
+// (e.g. the serializer) sorts the keys itself.
+pub type PooMapRoot<K, V> = FxHashMap<K, V>;
+pub type PooMapBase<T> = PooMapRoot<Box<[u8]>, T>;
 pub type PooMapInner = PooMapBase<u64>;
 pub type PooMap = PooMapBase<PooMapInner>;
 
+// aggregation maps, words are interned into `Sym`s
+pub type SymMapInner = PooMapRoot<Sym, u64>;
+pub type SymMap = PooMapBase<SymMapInner>;
+
+thread_local! {
+    // per-thread token buffer reused across comments; words are sliced out
+    // of it and interned, so tokenizing a comment doesn't allocate per word
+    static SCRATCH: RefCell<String> = RefCell::new(String::new());
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TextItem {
-    pub word_freqs: PooMap,
+    pub word_freqs: SymMap,
 }
 
 impl TextItem {
     pub fn new() -> Self {
         Self {
-            word_freqs: PooMap::new(),
+            word_freqs: SymMap::default(),
         }
     }
 
     pub fn ingest(
         &mut self,
-        other: &PooMap,
-        mut fn_feedback: impl FnMut(FnFeedback) -> (),
+        other: SymMap,
+        fn_feedback: impl FnMut(FnFeedback) -> (),
     ) {
-        fn_feedback(FnFeedback::Message("Process: Processing authors..".into()));
-        fn_feedback(FnFeedback::Total(other.len() as u64));
-
-        for (author, freqs) in other.iter() {
-            let author_freqs =
-                self.word_freqs
-                    .entry(author.clone())
-                    .or_insert_with(PooMapInner::new);
-
-            for (word, freq) in freqs.iter() {
-                author_freqs
-                    .entry(word.clone())
-                    .or_insert(0)
-                    .add_assign(*freq);
-            }
+        let mut sink = ProgressSink::new(fn_feedback);
+
+        sink.message("Process: Processing authors..");
+        sink.total(other.len() as u64);
 
-            fn_feedback(FnFeedback::Tick);
+        let mut i = 0u64;
+
+        for (author, freqs) in other {
+            Self::merge_author(&mut self.word_freqs, author, freqs);
+
+            i += 1;
+
+            sink.progress(i);
         }
+
+        sink.finish(i);
     }
 
-    #[inline(always)]
-    pub fn process_alt(text: &str) -> PooMapInner {
-        text
-            .chars()
-            .filter(|c| c.is_alphanumeric() || c.is_whitespace())
-            .collect::<String>()
-            .to_lowercase()
-            .split_whitespace()
+    /// Folds tokenized `(author, freqs)` pairs into a single aggregation.
+    pub fn aggregate(items: impl ParallelIterator<Item=(Box<[u8]>, SymMapInner)>) -> SymMap {
+        items
             .fold(
-                PooMapInner::new(),
-                |mut acc, word| {
-                    acc
-                        .entry(
-                            word.trim()
-                                .as_bytes()
-                                .to_vec()
-                        )
-                        .or_insert(0)
-                        .add_assign(1u64);
+                || SymMap::default(),
+                |mut acc, (author, freqs)| {
+                    Self::merge_author(&mut acc, author, freqs);
 
                     acc
                 },
             )
+            .reduce(
+                || SymMap::default(),
+                Self::merge,
+            )
+    }
+
+    /// Merges two aggregations, moving the smaller one into the larger one.
+    pub fn merge(mut acc: SymMap, mut other: SymMap) -> SymMap {
+        if acc.len() < other.len() {
+            std::mem::swap(&mut acc, &mut other);
+        }
+
+        for (author, freqs) in other {
+            Self::merge_author(&mut acc, author, freqs);
+        }
+
+        acc
+    }
+
+    #[inline(always)]
+    pub fn merge_author(acc: &mut SymMap, author: Box<[u8]>, freqs: SymMapInner) {
+        match acc.entry(author) {
+            Entry::Occupied(mut entry) => {
+                Self::merge_freqs(entry.get_mut(), freqs);
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(freqs);
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub fn merge_freqs(acc: &mut SymMapInner, mut freqs: SymMapInner) {
+        if acc.len() < freqs.len() {
+            std::mem::swap(acc, &mut freqs);
+        }
+
+        for (word, freq) in freqs {
+            acc
+                .entry(word)
+                .or_insert(0)
+                .add_assign(freq);
+        }
+    }
+
+    #[inline(always)]
+    pub fn process_alt(text: &str) -> SymMapInner {
+        SCRATCH.with(|scratch| {
+            let mut buf = scratch.borrow_mut();
+
+            buf.clear();
+            buf.extend(
+                text
+                    .chars()
+                    .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+                    .flat_map(char::to_lowercase)
+            );
+
+            buf
+                .split_whitespace()
+                .fold(
+                    SymMapInner::default(),
+                    |mut acc, word| {
+                        acc
+                            .entry(INTERNER.intern(word.as_bytes()))
+                            .or_insert(0)
+                            .add_assign(1u64);
+
+                        acc
+                    },
+                )
+        })
     }
 }
 
@@ -0,0 +1,357 @@
+use std::io::{self, BufRead, Write};
+use std::ops::AddAssign;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+/// A sidecar written next to a .freqs file by `poo --text-stats`.
+pub type TextStatsMap = FxHashMap<Box<[u8]>, TextStats>;
+
+/// Sentences needed before readability scores mean anything.
+const MIN_SENTENCES: u64 = 10;
+
+lazy_static! {
+    // code blocks would read as one endless sentence of odd words
+    static ref CODE: Regex = Regex::new(r"(?s)<pre>.*?</pre>").unwrap();
+    static ref PARAGRAPH: Regex = Regex::new(r"<p>").unwrap();
+    static ref TAG: Regex = Regex::new(r"<[^>]*>").unwrap();
+    // a run of terminators, then the next sentence or the end
+    static ref SENTENCE_END: Regex = Regex::new(r"[.!?]+(\s|$)").unwrap();
+}
+
+/// Words a question usually opens with.
+const QUESTION_WORDS: [&str; 20] = [
+    "who", "what", "when", "where", "why", "how", "which", "whose",
+    "is", "are", "was", "were", "do", "does", "did", "can", "could", "should", "would", "will",
+];
+
+/// Counts from the raw text of an author's items, which word counts alone
+/// can't give. Additive, so items and shards are simply summed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextStats {
+    pub items: u64,
+    pub sentences: u64,
+    pub words: u64,
+    pub syllables: u64,
+    /// Words of three or more syllables.
+    pub polysyllables: u64,
+    /// Sentences ending in a question mark.
+    pub questions: u64,
+    /// Sentences opening with a question word, question mark or not.
+    pub question_openings: u64,
+    /// Replies to the author's items.
+    pub replies_received: u64,
+    /// Items starting a thread, with `--reply-depth` only.
+    pub posts: u64,
+    /// Comments on a post, with `--reply-depth` only.
+    pub top_level: u64,
+    /// Comments on a comment, with `--reply-depth` only.
+    pub replies: u64,
+    /// Sum of the depth of every comment, 1 for top level ones.
+    pub depth: u64,
+}
+
+/// HN item text as plain text: paragraphs on their own lines, code blocks,
+/// tags and the common entities gone.
+fn plain_text(html: &str) -> String {
+    let text = CODE.replace_all(html, "\n");
+    let text = PARAGRAPH.replace_all(&text, "\n");
+    let text = TAG.replace_all(&text, "");
+
+    text.replace("&#x27;", "'")
+        .replace("&#x2F;", "/")
+        .replace("&quot;", "\"")
+        .replace("&gt;", ">")
+        .replace("&lt;", "<")
+        .replace("&amp;", "&")
+}
+
+/// The sentences of a line, and whether each is a question. A line
+/// without a terminator still ends its last sentence.
+fn sentences(line: &str) -> Vec<(&str, bool)> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+
+    for end in SENTENCE_END.find_iter(line) {
+        sentences.push((&line[start..end.start()], end.as_str().contains('?')));
+        start = end.end();
+    }
+
+    sentences.push((&line[start..], false));
+    sentences
+}
+
+/// Vowel groups, less a silent final e, at least one.
+fn syllables(word: &str) -> u64 {
+    let mut count = 0;
+    let mut in_group = false;
+
+    for c in word.chars() {
+        let vowel = matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'y');
+
+        if vowel && !in_group {
+            count += 1;
+        }
+
+        in_group = vowel;
+    }
+
+    if word.ends_with('e') && !word.ends_with("le") && count > 1 {
+        count -= 1;
+    }
+
+    count.max(1)
+}
+
+impl TextStats {
+    pub fn of_text(html: &str) -> Self {
+        let text = plain_text(html);
+        let mut stats = Self { items: 1, ..Default::default() };
+
+        for (sentence, question) in text.lines().flat_map(sentences) {
+            let words =
+                sentence.split_whitespace()
+                    // links and numbers aren't words anyone reads
+                    .filter(|word| !word.contains("://"))
+                    .map(|word| word.chars().filter(|c| c.is_alphabetic()).flat_map(char::to_lowercase).collect::<String>())
+                    .filter(|word| !word.is_empty())
+                    .collect::<Vec<_>>();
+
+            if words.is_empty() {
+                continue;
+            }
+
+            for word in words.iter() {
+                let syllables = syllables(word);
+
+                stats.words += 1;
+                stats.syllables += syllables;
+                stats.polysyllables += (syllables >= 3) as u64;
+            }
+
+            stats.sentences += 1;
+            stats.questions += question as u64;
+            stats.question_openings += QUESTION_WORDS.contains(&words[0].as_str()) as u64;
+        }
+
+        stats
+    }
+
+    /// Also counts how many replies an item got.
+    pub fn of_item(html: &str, replies_received: u64) -> Self {
+        let mut stats = Self::of_text(html);
+
+        stats.replies_received = replies_received;
+        stats
+    }
+
+    /// Counts an item sitting `depth` deep in its thread, 0 for a post.
+    pub fn count_depth(&mut self, depth: u64) {
+        match depth {
+            0 => self.posts += 1,
+            1 => self.top_level += 1,
+            _ => self.replies += 1,
+        }
+
+        self.depth += depth;
+    }
+
+    /// `None` until there's enough text to score.
+    pub fn readability(&self) -> Option<Readability> {
+        if self.sentences < MIN_SENTENCES {
+            return None;
+        }
+
+        let words_per_sentence = self.words as f64 / self.sentences as f64;
+        let syllables_per_word = self.syllables as f64 / self.words.max(1) as f64;
+
+        Some(Readability {
+            flesch_reading_ease: 206.835 - 1.015 * words_per_sentence - 84.6 * syllables_per_word,
+            flesch_kincaid_grade: 0.39 * words_per_sentence + 11.8 * syllables_per_word - 15.59,
+            smog: 1.043 * (self.polysyllables as f64 * 30.0 / self.sentences as f64).sqrt() + 3.1291,
+            words_per_sentence,
+        })
+    }
+
+    /// How the author takes part in threads, `None` until there's enough
+    /// text to tell.
+    pub fn behavior(&self) -> Option<Behavior> {
+        if self.sentences < MIN_SENTENCES {
+            return None;
+        }
+
+        let sentences = self.sentences as f64;
+        let items = self.items.max(1) as f64;
+        let comments = self.top_level + self.replies;
+
+        let mut behavior =
+            Behavior {
+                question_rate: self.questions as f64 / sentences,
+                question_openings: self.question_openings as f64 / sentences,
+                replies_received: self.replies_received as f64 / items,
+                words_per_item: self.words as f64 / items,
+                mean_depth: (comments > 0).then(|| self.depth as f64 / comments as f64),
+                role: Role::Mixed,
+            };
+
+        behavior.role = Role::of(&behavior);
+
+        Some(behavior)
+    }
+}
+
+impl AddAssign for TextStats {
+    fn add_assign(&mut self, other: Self) {
+        self.items += other.items;
+        self.sentences += other.sentences;
+        self.words += other.words;
+        self.syllables += other.syllables;
+        self.polysyllables += other.polysyllables;
+        self.questions += other.questions;
+        self.question_openings += other.question_openings;
+        self.replies_received += other.replies_received;
+        self.posts += other.posts;
+        self.top_level += other.top_level;
+        self.replies += other.replies;
+        self.depth += other.depth;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Readability {
+    /// 0 to 100, higher is easier.
+    pub flesch_reading_ease: f64,
+    /// US school grade.
+    pub flesch_kincaid_grade: f64,
+    /// Years of education needed, from the share of polysyllables.
+    pub smog: f64,
+    pub words_per_sentence: f64,
+}
+
+/// Question and thread features, complementing the word counts.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Behavior {
+    /// Share of sentences that are questions.
+    pub question_rate: f64,
+    /// Share of sentences opening with a question word.
+    pub question_openings: f64,
+    /// Replies per item.
+    pub replies_received: f64,
+    pub words_per_item: f64,
+    /// Mean depth of the author's comments, 1 for top level ones. Only for
+    /// corpora ingested with `--reply-depth`.
+    pub mean_depth: Option<f64>,
+    pub role: Role,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Asks a lot.
+    Asker,
+    /// Mostly states things deep in threads.
+    Answerer,
+    /// Writes long statements near the top of threads.
+    Lecturer,
+    Mixed,
+}
+
+impl Role {
+    fn of(behavior: &Behavior) -> Self {
+        let depth = behavior.mean_depth;
+
+        if behavior.question_rate >= 0.2 {
+            Role::Asker
+        } else if behavior.question_rate < 0.1 && behavior.words_per_item >= 100.0 && depth.map_or(true, |d| d < 2.0) {
+            Role::Lecturer
+        } else if behavior.question_rate < 0.2 && depth.map_or(false, |d| d >= 2.0) {
+            Role::Answerer
+        } else {
+            Role::Mixed
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Line {
+    author: String,
+    #[serde(flatten)]
+    stats: TextStats,
+}
+
+/// Where the sidecar of the .freqs file at `location` goes.
+pub fn sidecar_path(location: &str) -> String {
+    format!("{}.textstats", location)
+}
+
+/// One JSON object per author, sorted by author.
+pub fn write(stats: &TextStatsMap, mut writer: impl Write) -> io::Result<()> {
+    let mut authors = stats.keys().collect::<Vec<_>>();
+
+    authors.sort();
+
+    for author in authors {
+        let line = Line { author: String::from_utf8_lossy(author).to_string(), stats: stats[author] };
+
+        serde_json::to_writer(&mut writer, &line)?;
+        writer.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+pub fn read(reader: impl BufRead) -> io::Result<TextStatsMap> {
+    let mut stats = TextStatsMap::default();
+
+    for line in reader.lines() {
+        let line = line?;
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let Line { author, stats: more } = serde_json::from_str(&line)?;
+
+        *stats.entry(author.into_bytes().into_boxed_slice()).or_default() += more;
+    }
+
+    Ok(stats)
+}
+
+/// The sidecar of the .freqs file at `location`, `None` if it was ingested
+/// without `--text-stats`.
+#[cfg(feature = "native")]
+pub fn load(location: &str) -> Option<TextStatsMap> {
+    let buf = crate::storage::read_file(&sidecar_path(location)).ok()?;
+
+    match read(&buf[..]) {
+        Ok(stats) => Some(stats),
+        Err(e) => {
+            eprintln!("Ignoring unreadable {}: {}", sidecar_path(location), e);
+            None
+        }
+    }
+}
+
+/// Writes `stats` as the sidecar of the .freqs file at `location`, sealed
+/// like it when `encrypt`.
+#[cfg(feature = "native")]
+pub fn save(stats: &TextStatsMap, location: &str, encrypt: bool) -> io::Result<()> {
+    let mut output = crate::storage::create(&sidecar_path(location))?;
+    let mut sealed = crate::crypto::Sealed::new(&mut output, encrypt)?;
+
+    write(stats, &mut sealed)?;
+
+    sealed.finish()?;
+    output.finish()
+}
+
+/// Whether the sidecar of the .freqs file at `location` is encrypted, so a
+/// rewrite can keep it that way.
+#[cfg(feature = "native")]
+pub fn is_sealed(location: &str) -> bool {
+    crate::storage::read(&sidecar_path(location)).map_or(false, |raw| crate::crypto::is_encrypted(&raw))
+}
@@ -0,0 +1,134 @@
+use std::ops::AddAssign;
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::total_tokens;
+use crate::args::Args;
+use crate::text::text_item::{PooMap, PooMapInner};
+
+/// Tokens added to every history before scoring, so a single insult in a
+/// handful of comments doesn't rank its author among the most hostile.
+const PRIOR_TOKENS: f64 = 1000.0;
+
+lazy_static! {
+    /// Word to weight, the built-in list unless `init` loaded another.
+    static ref LEXICON: RwLock<FxHashMap<Box<[u8]>, f64>> =
+        RwLock::new(parse(include_str!("./text/toxicwords.txt")).expect("built-in lexicon is valid"));
+}
+
+/// One `word,weight` pair per line; blank lines and `#` comments are
+/// ignored. Words are matched as tokenized: lowercased, without
+/// punctuation.
+pub fn parse(text: &str) -> Result<FxHashMap<Box<[u8]>, f64>, String> {
+    text.lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| {
+            let (word, weight) =
+                line.split_once(',')
+                    .and_then(|(word, weight)| Some((word.trim(), weight.trim().parse::<f64>().ok()?)))
+                    .ok_or_else(|| format!("line {}: expected `word,weight`", i + 1))?;
+
+            Ok((Box::from(word.to_lowercase().as_bytes()), weight))
+        })
+        .collect()
+}
+
+/// Replaces the built-in lexicon with the one given by
+/// `--toxicity-lexicon=<file>`, if any. Returns the number of words in use.
+pub fn init(args: &Args) -> std::io::Result<usize> {
+    if let Some(location) = args.value("toxicity-lexicon") {
+        let text = std::fs::read_to_string(location)?;
+        let lexicon = parse(&text).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+        *LEXICON.write().unwrap() = lexicon;
+    }
+
+    Ok(LEXICON.read().unwrap().len())
+}
+
+/// Additive lexicon hits of a profile, so segments can sum their members'
+/// before scoring.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Incivility {
+    pub tokens: u64,
+    /// Uses of lexicon words.
+    pub hits: u64,
+    /// Sum of the weights of every use.
+    pub weight: f64,
+}
+
+impl Incivility {
+    pub fn of(freqs: &PooMapInner) -> Self {
+        let lexicon = LEXICON.read().unwrap();
+        let mut incivility = Self { tokens: total_tokens(freqs), ..Default::default() };
+
+        // the lexicon is usually the smaller map
+        for (word, weight) in lexicon.iter() {
+            if let Some(freq) = freqs.get(word) {
+                incivility.hits += freq;
+                incivility.weight += weight * *freq as f64;
+            }
+        }
+
+        incivility
+    }
+
+    /// Weighted hits per thousand tokens.
+    pub fn score(&self) -> f64 {
+        self.weight * 1000.0 / (self.tokens as f64 + PRIOR_TOKENS)
+    }
+
+    pub fn toxicity(&self) -> Toxicity {
+        Toxicity {
+            hits: self.hits,
+            score: self.score(),
+        }
+    }
+}
+
+impl AddAssign for Incivility {
+    fn add_assign(&mut self, other: Self) {
+        self.tokens += other.tokens;
+        self.hits += other.hits;
+        self.weight += other.weight;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Toxicity {
+    /// Uses of lexicon words.
+    pub hits: u64,
+    /// Weighted uses per thousand tokens, damped for short histories.
+    pub score: f64,
+}
+
+/// Removes the `share` of authors with the highest toxicity score, e.g. 0.1
+/// for the top decile, leaving out authors without any hits. Returns the
+/// number removed.
+pub fn exclude_most_toxic(poo: &mut PooMap, share: f64) -> usize {
+    let mut scores =
+        poo.par_iter()
+            .map(|(author, freqs)| (author.clone(), Incivility::of(freqs).score()))
+            .filter(|(_, score)| *score > 0.0)
+            .collect::<Vec<_>>();
+
+    scores.par_sort_unstable_by(|a, b| {
+        b.1.partial_cmp(&a.1)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.0.cmp(&b.0))
+    });
+
+    let count = ((poo.len() as f64 * share.clamp(0.0, 1.0)).round() as usize).min(scores.len());
+
+    for (author, _) in scores.iter().take(count) {
+        poo.remove(author);
+    }
+
+    count
+}
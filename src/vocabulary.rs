@@ -0,0 +1,180 @@
+use std::io::{BufRead, Write};
+
+use rayon::prelude::*;
+
+use crate::domains::is_domain;
+use crate::text::interner::WordKey;
+use crate::text::text_item::{PooMapBase, PooMapInner, PooMapRoot};
+
+/// Corpus-wide word statistics, written next to a .freqs file as
+/// `corpus.freqs.vocab` so document frequencies don't need a full pass.
+///
+/// The file is tab separated `word, total count, authors using it`, most
+/// used words first, after a `#authors` line with the corpus size.
+#[derive(Debug, Clone, Default)]
+pub struct Vocabulary {
+    authors: u64,
+    /// `(total count, authors)` per word.
+    words: PooMapBase<(u64, u64)>,
+}
+
+impl Vocabulary {
+    /// Counts in one author.
+    pub fn observe<K: WordKey>(&mut self, freqs: &PooMapRoot<K, u64>) {
+        self.authors += 1;
+
+        for (word, freq) in freqs.iter() {
+            let word = word.word();
+
+            // linked domains aren't words
+            if is_domain(word) {
+                continue;
+            }
+
+            match self.words.get_mut(word) {
+                Some((count, authors)) => {
+                    *count += freq;
+                    *authors += 1;
+                }
+                None => {
+                    self.words.insert(word.into(), (*freq, 1));
+                }
+            }
+        }
+    }
+
+    pub fn merge(mut self, other: Self) -> Self {
+        self.authors += other.authors;
+
+        for (word, (count, authors)) in other.words {
+            let entry = self.words.entry(word).or_default();
+
+            entry.0 += count;
+            entry.1 += authors;
+        }
+
+        self
+    }
+
+    pub fn build<K: WordKey + Sync>(data: &PooMapBase<PooMapRoot<K, u64>>) -> Self {
+        data.par_iter()
+            .fold(
+                Self::default,
+                |mut acc, (_, freqs)| {
+                    acc.observe(freqs);
+
+                    acc
+                },
+            )
+            .reduce(Self::default, Self::merge)
+    }
+
+    pub fn authors(&self) -> usize {
+        self.authors as usize
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// `(total count, authors)` of `word`.
+    pub fn get(&self, word: &[u8]) -> Option<(u64, u64)> {
+        self.words.get(word).copied()
+    }
+
+    /// Same as `analysis::document_freqs` on the corpus.
+    pub fn doc_freqs(&self) -> PooMapInner {
+        self.words
+            .iter()
+            .map(|(word, (_, authors))| (word.clone(), *authors))
+            .collect()
+    }
+
+    /// Same as `analysis::global_freqs` on the corpus.
+    pub fn global_freqs(&self) -> PooMapInner {
+        self.words
+            .iter()
+            .map(|(word, (count, _))| (word.clone(), *count))
+            .collect()
+    }
+
+    /// The `n` most used words, most used first.
+    pub fn top(&self, n: usize) -> Vec<Box<[u8]>> {
+        let mut words = self.words.iter().collect::<Vec<_>>();
+
+        words.par_sort_unstable_by(|a, b| b.1.0.cmp(&a.1.0).then_with(|| a.0.cmp(b.0)));
+
+        words.into_iter().take(n).map(|(word, _)| word.clone()).collect()
+    }
+
+    /// Drops words used by fewer than `min_authors`.
+    pub fn prune(&mut self, min_authors: u64) {
+        self.words.retain(|_, (_, authors)| *authors >= min_authors);
+    }
+
+    /// `corpus.freqs` keeps its vocabulary in `corpus.freqs.vocab`.
+    pub fn sidecar_path(location: &str) -> String {
+        format!("{}.vocab", location)
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut words = self.words.iter().collect::<Vec<_>>();
+
+        words.par_sort_unstable_by(|a, b| b.1.0.cmp(&a.1.0).then_with(|| a.0.cmp(b.0)));
+
+        writeln!(writer, "#authors\t{}", self.authors)?;
+
+        for (word, (count, authors)) in words {
+            // words never contain whitespace, the tokenizer splits on it
+            writer.write_all(word)?;
+            writeln!(writer, "\t{}\t{}", count, authors)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> std::io::Result<Self> {
+        let invalid = |line: usize| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, format!("malformed vocabulary line {}", line + 1))
+        };
+
+        let mut vocabulary = Self::default();
+
+        for (i, line) in reader.split(b'\n').enumerate() {
+            let line = line?;
+
+            if let Some(authors) = line.strip_prefix(b"#authors\t") {
+                vocabulary.authors =
+                    std::str::from_utf8(authors)
+                        .ok()
+                        .and_then(|a| a.trim().parse().ok())
+                        .ok_or_else(|| invalid(i))?;
+
+                continue;
+            }
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut fields = line.rsplitn(3, |b| *b == b'\t');
+
+            let mut number = || {
+                fields.next()
+                    .and_then(|f| std::str::from_utf8(f).ok())
+                    .and_then(|f| f.parse::<u64>().ok())
+            };
+
+            let (authors, count) = (number().ok_or_else(|| invalid(i))?, number().ok_or_else(|| invalid(i))?);
+            let word = fields.next().ok_or_else(|| invalid(i))?;
+
+            vocabulary.words.insert(word.into(), (count, authors));
+        }
+
+        Ok(vocabulary)
+    }
+}
@@ -0,0 +1,93 @@
+use wasm_bindgen::prelude::*;
+
+use crate::analysis::{top_n, total_tokens, word_freqs};
+use crate::fingerprint;
+use crate::serializer::{decompress, deserialize, extract_user};
+use crate::text::text_item::{PooMap, PooMapInner};
+
+fn load(data: Vec<u8>) -> Result<Vec<u8>, JsError> {
+    decompress(data).map_err(|e| JsError::new(&e.to_string()))
+}
+
+fn freqs_json(freqs: &PooMapInner, n: usize) -> String {
+    let top =
+        top_n(word_freqs(freqs), n)
+            .into_iter()
+            .map(|(word, freq)| (String::from_utf8_lossy(word), freq))
+            .collect::<Vec<_>>();
+
+    serde_json::to_string(&top).unwrap_or_default()
+}
+
+/// Side length in pixels of the images returned by the fingerprint functions.
+#[wasm_bindgen(js_name = fingerprintSide)]
+pub fn fingerprint_side() -> usize {
+    fingerprint::SIDE
+}
+
+/// Top `n` words of `user` in a .freqs file as `[[word, freq], ..]` JSON,
+/// without loading the other authors.
+#[wasm_bindgen(js_name = extractUser)]
+pub fn extract_user_json(data: Vec<u8>, user: &str, n: usize) -> Result<Option<String>, JsError> {
+    let data = load(data)?;
+
+    Ok(extract_user(&data, user, |_| {}).map(|freqs| freqs_json(&freqs, n)))
+}
+
+/// RGBA pixels of the fingerprint of `user` in a .freqs file, for an
+/// `ImageData` of `fingerprintSide()` squared.
+#[wasm_bindgen(js_name = userFingerprint)]
+pub fn user_fingerprint(data: Vec<u8>, user: &str) -> Result<Option<Vec<u8>>, JsError> {
+    let data = load(data)?;
+
+    Ok(extract_user(&data, user, |_| {}).map(|freqs| fingerprint::rgba(&freqs)))
+}
+
+/// A fully loaded corpus, for pages querying many users of a small shard.
+#[wasm_bindgen]
+pub struct Corpus {
+    poo: PooMap,
+}
+
+#[wasm_bindgen]
+impl Corpus {
+    #[wasm_bindgen(constructor)]
+    pub fn new(data: Vec<u8>) -> Result<Corpus, JsError> {
+        let data = load(data)?;
+
+        Ok(Self {
+            poo: deserialize(&data, |_| {}),
+        })
+    }
+
+    pub fn authors(&self) -> usize {
+        self.poo.len()
+    }
+
+    /// Author names, sorted.
+    pub fn names(&self) -> Box<[JsValue]> {
+        let mut names =
+            self.poo
+                .keys()
+                .map(|author| String::from_utf8_lossy(author).to_string())
+                .collect::<Vec<_>>();
+
+        names.sort_unstable();
+
+        names.into_iter().map(JsValue::from).collect()
+    }
+
+    pub fn tokens(&self, user: &str) -> Option<u64> {
+        self.poo.get(user.as_bytes()).map(total_tokens)
+    }
+
+    /// Top `n` words of `user` as `[[word, freq], ..]` JSON.
+    #[wasm_bindgen(js_name = topWords)]
+    pub fn top_words(&self, user: &str, n: usize) -> Option<String> {
+        self.poo.get(user.as_bytes()).map(|freqs| freqs_json(freqs, n))
+    }
+
+    pub fn fingerprint(&self, user: &str) -> Option<Vec<u8>> {
+        self.poo.get(user.as_bytes()).map(fingerprint::rgba)
+    }
+}
@@ -0,0 +1,128 @@
+use std::fmt::Write;
+
+use crate::report::escape;
+
+const PALETTE: [&str; 6] = ["#1f4e79", "#2e75b6", "#c55a11", "#548235", "#7030a0", "#bf9000"];
+
+#[derive(Debug, Clone)]
+pub struct CloudOptions {
+    pub width: f64,
+    pub height: f64,
+    pub min_font: f64,
+    pub max_font: f64,
+}
+
+impl Default for CloudOptions {
+    fn default() -> Self {
+        Self {
+            width: 800.0,
+            height: 500.0,
+            min_font: 10.0,
+            max_font: 72.0,
+        }
+    }
+}
+
+/// A placed word, `x`/`y` is the center of its box.
+#[derive(Debug, Clone)]
+pub struct Placed {
+    pub word: String,
+    pub size: f64,
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+impl Placed {
+    fn overlaps(&self, other: &Placed) -> bool {
+        (self.x - other.x).abs() * 2.0 < self.w + other.w
+            && (self.y - other.y).abs() * 2.0 < self.h + other.h
+    }
+}
+
+/// Lays out `words` by walking an Archimedean spiral out from the center,
+/// heaviest first; words that don't fit anywhere are dropped.
+///
+/// Text extents are estimated from the character count, which is close
+/// enough for the sans-serif fonts browsers fall back to.
+pub fn layout(words: &[(String, f64)], options: &CloudOptions) -> Vec<Placed> {
+    let mut words = words.iter().filter(|(_, w)| *w > 0.0).collect::<Vec<_>>();
+
+    words.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+    let max = match words.first() {
+        Some((_, w)) => *w,
+        None => return vec![],
+    };
+
+    let mut placed: Vec<Placed> = Vec::with_capacity(words.len());
+
+    for (word, weight) in words {
+        // sqrt so the area, not the height, follows the weight
+        let size = options.min_font + (options.max_font - options.min_font) * (weight / max).sqrt();
+
+        let mut candidate = Placed {
+            word: word.clone(),
+            size,
+            x: 0.0,
+            y: 0.0,
+            w: size * 0.6 * word.chars().count() as f64,
+            h: size,
+        };
+
+        let mut t = 0.0f64;
+
+        while t < 200.0 {
+            candidate.x = options.width / 2.0 + 2.0 * t * t.cos();
+            candidate.y = options.height / 2.0 + 1.2 * t * t.sin();
+
+            let inside =
+                candidate.x - candidate.w / 2.0 >= 0.0
+                    && candidate.x + candidate.w / 2.0 <= options.width
+                    && candidate.y - candidate.h / 2.0 >= 0.0
+                    && candidate.y + candidate.h / 2.0 <= options.height;
+
+            if inside && !placed.iter().any(|p| p.overlaps(&candidate)) {
+                placed.push(candidate);
+                break;
+            }
+
+            t += 0.1;
+        }
+    }
+
+    placed
+}
+
+/// Renders `words` (word, score) as an SVG word cloud.
+pub fn render_svg(words: &[(String, f64)], options: &CloudOptions) -> String {
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" font-family=\"sans-serif\" text-anchor=\"middle\" dominant-baseline=\"central\">",
+        options.width,
+        options.height,
+        options.width,
+        options.height,
+    ).ok();
+
+    writeln!(out, "<rect width=\"100%\" height=\"100%\" fill=\"#fff\"/>").ok();
+
+    for (i, p) in layout(words, options).iter().enumerate() {
+        writeln!(
+            out,
+            "<text x=\"{:.1}\" y=\"{:.1}\" font-size=\"{:.1}\" fill=\"{}\">{}</text>",
+            p.x,
+            p.y,
+            p.size,
+            PALETTE[i % PALETTE.len()],
+            escape(&p.word),
+        ).ok();
+    }
+
+    writeln!(out, "</svg>").ok();
+
+    out
+}
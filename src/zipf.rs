@@ -0,0 +1,166 @@
+use std::fmt::Write;
+
+use crate::domains::is_domain;
+use crate::text::text_item::PooMapInner;
+
+const PALETTE: [&str; 6] = ["#1f4e79", "#c55a11", "#548235", "#7030a0", "#bf9000", "#2e75b6"];
+
+/// Word frequencies sorted from most to least used; index `i` is rank `i + 1`.
+pub fn ranks(freqs: &PooMapInner) -> Vec<u64> {
+    let mut ranks =
+        freqs.iter()
+            .filter(|(word, _)| !is_domain(word))
+            .map(|(_, freq)| *freq)
+            .collect::<Vec<_>>();
+
+    ranks.sort_unstable_by(|a, b| b.cmp(a));
+    ranks
+}
+
+/// Ranks spaced evenly on a log scale, so the long tail of rare words doesn't
+/// drown out the head when fitting or plotting.
+fn log_spaced(len: usize) -> Vec<usize> {
+    let mut points = Vec::new();
+    let mut rank = 1.0f64;
+
+    while (rank as usize) <= len {
+        if points.last() != Some(&(rank as usize)) {
+            points.push(rank as usize);
+        }
+
+        rank *= 1.1;
+    }
+
+    points
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct ZipfFit {
+    /// Slope of the log-log rank-frequency line, about 1 for natural text.
+    pub exponent: f64,
+    /// How well a straight line fits at all; low values mean the author's
+    /// distribution isn't Zipfian, e.g. templated or repetitive posts.
+    pub r_squared: f64,
+}
+
+/// Least squares fit of `log f = c - s log r` over log-spaced ranks.
+pub fn fit(ranks: &[u64]) -> Option<ZipfFit> {
+    let points =
+        log_spaced(ranks.len())
+            .into_iter()
+            .map(|r| ((r as f64).ln(), (ranks[r - 1].max(1) as f64).ln()))
+            .collect::<Vec<_>>();
+
+    if points.len() < 3 {
+        return None;
+    }
+
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+
+    let sxy = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum::<f64>();
+    let sxx = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum::<f64>();
+    let syy = points.iter().map(|(_, y)| (y - mean_y).powi(2)).sum::<f64>();
+
+    if sxx == 0.0 || syy == 0.0 {
+        return None;
+    }
+
+    Some(ZipfFit {
+        exponent: -sxy / sxx,
+        r_squared: sxy * sxy / (sxx * syy),
+    })
+}
+
+/// Log-log rank-frequency plot of every `(label, ranks)` series as SVG.
+pub fn render_svg(series: &[(String, Vec<u64>)], width: f64, height: f64) -> String {
+    let margin = 50.0;
+
+    let max_rank = series.iter().map(|(_, r)| r.len()).max().unwrap_or(1).max(10) as f64;
+    let max_freq = series.iter().filter_map(|(_, r)| r.first()).copied().max().unwrap_or(1).max(10) as f64;
+
+    let x = |rank: f64| margin + rank.log10() / max_rank.log10() * (width - 2.0 * margin);
+    let y = |freq: f64| height - margin - freq.log10() / max_freq.log10() * (height - 2.0 * margin);
+
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" font-family=\"sans-serif\" font-size=\"11\">",
+        width,
+        height,
+        width,
+        height,
+    ).ok();
+
+    writeln!(out, "<rect width=\"100%\" height=\"100%\" fill=\"#fff\"/>").ok();
+
+    // decade grid lines
+    let mut decade = 1.0;
+
+    while decade <= max_rank {
+        writeln!(
+            out,
+            "<line x1=\"{0:.1}\" x2=\"{0:.1}\" y1=\"{1}\" y2=\"{2}\" stroke=\"#eee\"/><text x=\"{0:.1}\" y=\"{3}\" text-anchor=\"middle\">{4}</text>",
+            x(decade),
+            margin,
+            height - margin,
+            height - margin + 15.0,
+            decade,
+        ).ok();
+
+        decade *= 10.0;
+    }
+
+    decade = 1.0;
+
+    while decade <= max_freq {
+        writeln!(
+            out,
+            "<line x1=\"{0}\" x2=\"{1}\" y1=\"{2:.1}\" y2=\"{2:.1}\" stroke=\"#eee\"/><text x=\"{3}\" y=\"{2:.1}\" text-anchor=\"end\">{4}</text>",
+            margin,
+            width - margin,
+            y(decade),
+            margin - 5.0,
+            decade,
+        ).ok();
+
+        decade *= 10.0;
+    }
+
+    writeln!(out, "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">rank</text>", width / 2.0, height - 10.0).ok();
+    writeln!(out, "<text x=\"12\" y=\"{}\" transform=\"rotate(-90 12 {})\" text-anchor=\"middle\">frequency</text>", height / 2.0, height / 2.0).ok();
+
+    for (i, (label, ranks)) in series.iter().enumerate() {
+        let color = PALETTE[i % PALETTE.len()];
+
+        let points =
+            log_spaced(ranks.len())
+                .into_iter()
+                .map(|r| format!("{:.1},{:.1}", x(r as f64), y(ranks[r - 1].max(1) as f64)))
+                .collect::<Vec<_>>()
+                .join(" ");
+
+        writeln!(out, "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\" points=\"{}\"/>", color, points).ok();
+
+        let exponent =
+            fit(ranks)
+                .map(|f| format!(" (s = {:.2})", f.exponent))
+                .unwrap_or_default();
+
+        writeln!(
+            out,
+            "<text x=\"{}\" y=\"{}\" fill=\"{}\" text-anchor=\"end\">{}{}</text>",
+            width - margin,
+            margin + 14.0 * i as f64,
+            color,
+            crate::report::escape(label),
+            exponent,
+        ).ok();
+    }
+
+    writeln!(out, "</svg>").ok();
+
+    out
+}